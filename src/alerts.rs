@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::models::{AnalyzeTxResponse, HighRiskStats};
+
+/// Sliding-window count of high-risk analyses. When the count inside the
+/// window exceeds the threshold an alert fires once; it re-arms after the
+/// rate drops back to the threshold or below.
+pub struct HighRiskMonitor {
+    window: Duration,
+    threshold: Option<usize>,
+    webhook: Option<(reqwest::Client, String)>,
+    inner: Mutex<Window>,
+}
+
+#[derive(Default)]
+struct Window {
+    events: VecDeque<Instant>,
+    alerting: bool,
+}
+
+impl Window {
+    fn prune(&mut self, now: Instant, window: Duration) {
+        while self
+            .events
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            self.events.pop_front();
+        }
+    }
+}
+
+impl HighRiskMonitor {
+    pub fn new(
+        window: Duration,
+        threshold: Option<usize>,
+        webhook: Option<(reqwest::Client, String)>,
+    ) -> Self {
+        Self {
+            window,
+            threshold,
+            webhook,
+            inner: Mutex::new(Window::default()),
+        }
+    }
+
+    /// Counts a high-risk analysis, alerting if it pushes the rate over the threshold.
+    pub fn record(&self, analysis: &AnalyzeTxResponse) {
+        let now = Instant::now();
+        let count = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.events.push_back(now);
+            inner.prune(now, self.window);
+            let count = inner.events.len();
+            match self.threshold {
+                Some(threshold) if count > threshold && !inner.alerting => {
+                    inner.alerting = true;
+                    count
+                }
+                Some(threshold) if count <= threshold => {
+                    inner.alerting = false;
+                    return;
+                }
+                _ => return,
+            }
+        };
+        self.alert(count, analysis);
+    }
+
+    pub fn stats(&self) -> HighRiskStats {
+        let mut inner = self.inner.lock().unwrap();
+        inner.prune(Instant::now(), self.window);
+        // Also re-arm here, so a quiet period clears the alert without a new event
+        if self.threshold.is_some_and(|t| inner.events.len() <= t) {
+            inner.alerting = false;
+        }
+        HighRiskStats {
+            window_secs: self.window.as_secs(),
+            count: inner.events.len(),
+            alert_threshold: self.threshold,
+            alerting: inner.alerting,
+        }
+    }
+
+    fn alert(&self, count: usize, analysis: &AnalyzeTxResponse) {
+        tracing::error!(
+            "High-risk alert: {} high-risk analyses in the last {}s (threshold {}); latest {} on {}",
+            count,
+            self.window.as_secs(),
+            self.threshold.unwrap_or_default(),
+            analysis.tx_hash,
+            analysis.network
+        );
+        let Some((http, url)) = self.webhook.clone() else {
+            return;
+        };
+        let body = json!({
+            "event": "high_risk_rate_exceeded",
+            "count": count,
+            "window_secs": self.window.as_secs(),
+            "threshold": self.threshold,
+            "latest": { "network": analysis.network, "tx_hash": analysis.tx_hash },
+        });
+        // Best effort: an alert that can't be sent is already in the log
+        tokio::spawn(async move {
+            let sent = http.post(&url).timeout(Duration::from_secs(10)).json(&body).send().await;
+            match sent {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => tracing::warn!("Alert webhook returned {}", response.status()),
+                Err(e) => tracing::warn!("Alert webhook failed: {}", e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::routing::post;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::testing;
+
+    // A monitor alerting past `threshold` in a minute, and the webhook bodies it sends
+    async fn monitor(threshold: usize) -> (HighRiskMonitor, Arc<Mutex<Vec<Value>>>) {
+        let received: Arc<Mutex<Vec<Value>>> = Arc::default();
+        let sink = received.clone();
+        let app = axum::Router::new().route(
+            "/",
+            post(move |axum::Json(body): axum::Json<Value>| async move {
+                sink.lock().unwrap().push(body);
+            }),
+        );
+        let webhook = (reqwest::Client::new(), testing::serve(app).await);
+        let monitor = HighRiskMonitor::new(Duration::from_secs(60), Some(threshold), Some(webhook));
+        (monitor, received)
+    }
+
+    #[tokio::test]
+    async fn a_burst_past_the_threshold_alerts_once() {
+        let (monitor, received) = monitor(2).await;
+        let analysis = testing::analysis(testing::TRANSFER_HASH).await;
+        for _ in 0..5 {
+            monitor.record(&analysis);
+        }
+
+        let stats = monitor.stats();
+        assert_eq!((stats.count, stats.alerting), (5, true));
+        for _ in 0..100 {
+            if !received.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        // Give a stray second alert time to arrive too
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["event"], "high_risk_rate_exceeded");
+        // Fired by the third analysis, the first over the threshold
+        assert_eq!(received[0]["count"], 3);
+        assert_eq!(received[0]["threshold"], 2);
+        assert_eq!(received[0]["latest"]["tx_hash"], testing::TRANSFER_HASH);
+    }
+
+    #[tokio::test]
+    async fn a_rate_at_the_threshold_does_not_alert() {
+        let (monitor, received) = monitor(2).await;
+        let analysis = testing::analysis(testing::TRANSFER_HASH).await;
+        monitor.record(&analysis);
+        monitor.record(&analysis);
+
+        assert!(!monitor.stats().alerting);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(received.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,520 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::emitter::EmitterBackend;
+use crate::encoding::FieldCase;
+use crate::history::QueueFull;
+use crate::network::Network;
+use crate::redact::{RedactionMode, Redactor};
+use crate::secrets;
+use crate::services::blockchain::{ReceiptWait, TraceLimits};
+use crate::services::categories::{self, CustomCategory};
+use crate::shedding::ShedThresholds;
+
+// Keeps pooled connections from being dropped silently by NATs and load balancers
+const HTTP_TCP_KEEPALIVE_SECS: u64 = 60;
+
+// Smallest per-request `max_tokens`; fewer can't fit a verdict
+const MIN_MAX_TOKENS: u32 = 100;
+
+thread_local! {
+    // Bad env values met while reading the config, for `Config::warnings`
+    static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    // Likewise for ones the service can't run with, for `Config::errors`
+    static ERRORS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    // OTLP/HTTP collector base URL; trace export is disabled when unset
+    pub otlp_endpoint: Option<String>,
+
+    // Registry of supported networks and their settings
+    pub networks: HashMap<Network, NetworkConfig>,
+    // Network for analysis requests that omit `network` (single-chain deployments)
+    pub default_network: Option<Network>,
+    // How long a request waits for a free per-network slot before getting a 503
+    pub concurrency_wait_ms: u64,
+    // Load shedding: while the p99 latency or error rate of the analyses in the
+    // window is over its threshold (unset = not checked), requests without
+    // `X-Priority: high` get a 503. Needs LOAD_SHED_MIN_SAMPLES in the window.
+    pub load_shed_p99_ms: Option<u64>,
+    pub load_shed_error_rate: Option<f64>,
+    pub load_shed_window_secs: u64,
+    pub load_shed_min_samples: usize,
+
+    // Connection pool of the HTTP client shared by RPC, explorer, LLM and webhook
+    // calls: idle keep-alive connections kept per host, and how long they're kept
+    pub http_pool_max_idle_per_host: usize,
+    pub http_pool_idle_timeout_secs: u64,
+
+    // Decode the top-level call and every log by name, with each contract's
+    // verified ABI from the explorer (cached) or else the bundled signatures
+    pub abi_decoding: bool,
+
+    // Address labels API (`{address}`/`{chain_id}` placeholders in the URL);
+    // enrichment is off when unset. Labels are cached for LABELS_CACHE_TTL_SECS.
+    pub labels_api_url: Option<String>,
+    pub labels_api_key: Option<String>,
+    pub labels_cache_ttl_secs: u64,
+
+    // LLM (OpenAI-compatible chat completions); heuristic-only when no key is set
+    pub llm_api_key: Option<String>,
+    pub llm_base_url: String,
+    pub llm_model: String,
+    // Models a request may pick via `model` (the default is always allowed)
+    pub llm_allowed_models: Vec<String>,
+    // Fraction of requests (0.0-1.0, chosen deterministically by tx hash) that get the LLM
+    pub llm_sample_rate: f64,
+    // `consensus` requests: LLM calls per analysis, and the sampling temperature
+    // that makes them differ
+    pub llm_consensus_samples: usize,
+    pub llm_consensus_temperature: f32,
+    // Share of the heuristic score in the blended risk score when the LLM is used
+    // (the LLM gets the rest); rule floors apply on top
+    pub risk_heuristic_weight: f32,
+    // Neutralize on-chain strings in the prompt, and reject LLM output that
+    // echoes instructions (prompt-injection defense)
+    pub prompt_sanitization: bool,
+    // LLM token budget: completion tokens per call (a request's `max_tokens` may
+    // pick up to the ceiling), and the model's context window, which the prompt
+    // is trimmed to fit alongside the completion
+    pub llm_max_tokens: u32,
+    pub llm_max_tokens_ceiling: u32,
+    pub llm_context_tokens: usize,
+
+    // SQLite file holding analysis history (":memory:" for a throwaway store)
+    pub history_db_path: String,
+    // Analyses are written to history in the background: at most
+    // HISTORY_QUEUE_CAPACITY wait, HISTORY_BATCH_SIZE go per transaction, and
+    // HISTORY_QUEUE_FULL says whether a request waits for room ("wait") or its
+    // analysis goes unrecorded ("drop") when the queue is full
+    pub history_queue_capacity: usize,
+    pub history_batch_size: usize,
+    pub history_queue_full: QueueFull,
+
+    // Upper bound on a single analysis; the in-flight work is dropped on expiry
+    pub analysis_timeout_secs: u64,
+    // Soft deadline for a single-tx response: optional stages (LLM, token
+    // metadata, labels, ...) are dropped to return a degraded result in time
+    pub response_deadline_ms: Option<u64>,
+    // Single-tx analyses slower than this log their per-stage timings at info
+    pub slow_request_ms: Option<u64>,
+
+    // Re-polls for a receipt that lags its tx, before analyzing from the tx alone
+    pub receipt_poll_attempts: u32,
+    pub receipt_poll_interval_ms: u64,
+
+    // Bounds on the deep-mode call trace; deeper or further frames are left out
+    // and the tx is flagged COMPLEX_CALL_TREE
+    pub trace_max_depth: usize,
+    pub trace_max_calls: usize,
+
+    // Analyses of txs with fewer confirmations are marked provisional, and (unless
+    // the interval is 0) re-checked until final or invalidated when reorged out
+    pub finality_confirmations: u64,
+    pub reorg_check_interval_secs: u64,
+    // Cache-Control max-age of analyses of final txs, and of confirmed but
+    // provisional ones (0 = no-store)
+    pub cache_max_age_final_secs: u64,
+    pub cache_max_age_recent_secs: u64,
+
+    // Key naming of analysis responses: snake_case (default) or camelCase for JS clients
+    pub response_field_case: FieldCase,
+
+    // Maximum number of tx hashes accepted by /analyze_batch
+    pub max_batch_size: usize,
+    // How often a streamed (NDJSON) batch emits a progress event
+    pub progress_interval_ms: u64,
+    // LLM-analyzed items per batch; the rest get heuristics only (unset = no cap)
+    pub batch_llm_budget: Option<usize>,
+
+    // Background analysis jobs (POST /jobs): worker pool size and queued-job limit
+    pub job_workers: usize,
+    pub job_queue_capacity: usize,
+
+    // User-defined tx_type categories from CUSTOM_CATEGORIES_PATH (a JSON array),
+    // checked in order before the built-in classification
+    pub custom_categories: Vec<CustomCategory>,
+
+    // Opt-in embedding of each analysis via the LLM provider, for `/similar`
+    pub embeddings_enabled: bool,
+    pub embedding_model: String,
+
+    // Webhook for high-risk analyses, delivered at least once through a persistent
+    // outbox; a delivery is dead-lettered after WEBHOOK_MAX_ATTEMPTS failures
+    pub webhook_url: Option<String>,
+    pub webhook_min_risk: f32,
+    pub webhook_max_attempts: u32,
+
+    // Message bus each completed analysis is also published to ("kafka" or "nats",
+    // given the matching cargo feature): broker list or server URL, topic or subject,
+    // and how many analyses may wait to be published before new ones are dropped
+    pub emitter_backend: EmitterBackend,
+    pub emitter_url: String,
+    pub emitter_topic: String,
+    pub emitter_queue_capacity: usize,
+
+    // High-risk rate alerting: more than HIGH_RISK_ALERT_THRESHOLD high-risk analyses
+    // within the window is logged and POSTed to ALERT_WEBHOOK_URL; unset disables it
+    pub high_risk_window_secs: u64,
+    pub high_risk_alert_threshold: Option<usize>,
+    pub alert_webhook_url: Option<String>,
+
+    // Address redaction in responses, history, and logs ("off", "mask" or "hash");
+    // hashing is keyed with REDACTION_KEY so values can't be reversed by lookup
+    pub address_redaction: RedactionMode,
+    pub redaction_key: Option<String>,
+
+    // Bearer token for admin endpoints (feedback export); those are disabled when unset
+    pub admin_api_key: Option<String>,
+
+    // Safe mode: unverified counterparty contracts add a risk reason and cap confidence
+    pub safe_mode: bool,
+    pub safe_mode_max_confidence: f32,
+
+    // Env values that didn't parse and fell back to their defaults; logged by
+    // main once tracing is up, since setting it up takes the config
+    pub warnings: Vec<String>,
+    // Settings the service can't run with; main reports them and exits
+    pub errors: Vec<String>,
+}
+
+/// Per-network settings, from `<VAR>_<NETWORK>` env vars (e.g. RPC_URL_ETHEREUM_MAINNET).
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    // JSON-RPC endpoints in failover order; networks without any are served
+    // from mocked data
+    pub rpc_urls: Vec<String>,
+    // `rpc_urls` are archive nodes, so they serve state at any block
+    pub rpc_archival: bool,
+    // Archive nodes for historical-state reads (contract age, state at the tx's
+    // block), when `rpc_urls` are full nodes
+    pub archive_rpc_urls: Vec<String>,
+    // Analyses allowed in flight at once, so one slow chain can't starve the others
+    pub max_concurrency: usize,
+    // Audited contracts (lowercase) whose txs skip the LLM and start from a low risk score
+    pub known_safe_contracts: HashSet<String>,
+    // Block explorer (Etherscan-compatible API) used for contract verification
+    pub explorer_api_url: String,
+    pub explorer_api_key: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let emitter_backend = env_parse("EMITTER_BACKEND", EmitterBackend::Off);
+        let mut config = Self {
+            otlp_endpoint: env_opt("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            networks: Network::ALL
+                .iter()
+                .map(|&network| (network, NetworkConfig::from_env(network)))
+                .collect(),
+            // A typo here would otherwise surface as 400s on every request without `network`
+            default_network: env_opt("DEFAULT_NETWORK")
+                .map(|v| v.parse().unwrap_or_else(|e| panic!("DEFAULT_NETWORK: {}", e))),
+            concurrency_wait_ms: env_parse("CONCURRENCY_WAIT_MS", 250),
+            load_shed_p99_ms: env_opt("LOAD_SHED_P99_MS").and_then(|v| v.trim().parse().ok()),
+            load_shed_error_rate: env_opt("LOAD_SHED_ERROR_RATE")
+                .and_then(|v| v.trim().parse().ok()),
+            load_shed_window_secs: env_parse("LOAD_SHED_WINDOW_SECS", 60),
+            load_shed_min_samples: env_parse("LOAD_SHED_MIN_SAMPLES", 20),
+            http_pool_max_idle_per_host: env_parse("HTTP_POOL_MAX_IDLE_PER_HOST", 32),
+            http_pool_idle_timeout_secs: env_parse("HTTP_POOL_IDLE_TIMEOUT_SECS", 90),
+            abi_decoding: env_parse("ABI_DECODING", false),
+            labels_api_url: env_opt("LABELS_API_URL"),
+            labels_api_key: env_opt("LABELS_API_KEY"),
+            labels_cache_ttl_secs: env_parse("LABELS_CACHE_TTL_SECS", 86_400),
+            llm_api_key: env_opt("LLM_API_KEY"),
+            llm_base_url: env_or("LLM_BASE_URL", "https://api.openai.com/v1"),
+            llm_model: env_or("LLM_MODEL", "gpt-4o-mini"),
+            llm_allowed_models: env_opt("LLM_ALLOWED_MODELS")
+                .map(|v| {
+                    v.split(',')
+                        .map(str::trim)
+                        .filter(|m| !m.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            llm_sample_rate: env_parse("LLM_SAMPLE_RATE", 1.0),
+            llm_consensus_samples: env_parse("LLM_CONSENSUS_SAMPLES", 3),
+            llm_consensus_temperature: env_parse("LLM_CONSENSUS_TEMPERATURE", 0.7),
+            risk_heuristic_weight: env_parse("RISK_HEURISTIC_WEIGHT", 0.3_f32).clamp(0.0, 1.0),
+            prompt_sanitization: env_parse("PROMPT_SANITIZATION", true),
+            llm_max_tokens: env_parse("LLM_MAX_TOKENS", 500),
+            llm_max_tokens_ceiling: env_parse("LLM_MAX_TOKENS_CEILING", 2000),
+            llm_context_tokens: env_parse("LLM_CONTEXT_TOKENS", 128_000),
+            history_db_path: env_or("HISTORY_DB_PATH", "analyzer.db"),
+            history_queue_capacity: env_parse("HISTORY_QUEUE_CAPACITY", 1000),
+            history_batch_size: env_parse("HISTORY_BATCH_SIZE", 50),
+            history_queue_full: env_parse("HISTORY_QUEUE_FULL", QueueFull::Wait),
+            analysis_timeout_secs: env_parse("ANALYSIS_TIMEOUT_SECS", 30),
+            response_deadline_ms: env_opt("RESPONSE_DEADLINE_MS")
+                .and_then(|v| v.trim().parse().ok()),
+            slow_request_ms: env_opt("SLOW_REQUEST_MS").and_then(|v| v.trim().parse().ok()),
+            receipt_poll_attempts: env_parse("RECEIPT_POLL_ATTEMPTS", 3),
+            receipt_poll_interval_ms: env_parse("RECEIPT_POLL_INTERVAL_MS", 500),
+            trace_max_depth: env_parse("TRACE_MAX_DEPTH", 32),
+            trace_max_calls: env_parse("TRACE_MAX_CALLS", 2000),
+            finality_confirmations: env_parse("FINALITY_CONFIRMATIONS", 12),
+            reorg_check_interval_secs: env_parse("REORG_CHECK_INTERVAL_SECS", 30),
+            cache_max_age_final_secs: env_parse("CACHE_MAX_AGE_FINAL_SECS", 86400),
+            cache_max_age_recent_secs: env_parse("CACHE_MAX_AGE_RECENT_SECS", 12),
+            response_field_case: env_parse("RESPONSE_FIELD_CASE", FieldCase::Snake),
+            max_batch_size: env_parse("MAX_BATCH_SIZE", 100),
+            progress_interval_ms: env_parse("PROGRESS_INTERVAL_MS", 1000),
+            batch_llm_budget: env_opt("BATCH_LLM_BUDGET").and_then(|v| v.trim().parse().ok()),
+            job_workers: env_parse("JOB_WORKERS", 4),
+            job_queue_capacity: env_parse("JOB_QUEUE_CAPACITY", 1000),
+            // A broken categories file is a deploy bug; main refuses to start
+            // rather than classify without it
+            custom_categories: env_opt("CUSTOM_CATEGORIES_PATH")
+                .map(|path| {
+                    categories::load(&path).unwrap_or_else(|e| {
+                        ERRORS.with_borrow_mut(|errors| errors.push(e.to_string()));
+                        Vec::new()
+                    })
+                })
+                .unwrap_or_default(),
+            embeddings_enabled: env_parse("EMBEDDINGS_ENABLED", false),
+            embedding_model: env_or("EMBEDDING_MODEL", "text-embedding-3-small"),
+            webhook_url: env_opt("WEBHOOK_URL"),
+            webhook_min_risk: env_parse("WEBHOOK_MIN_RISK", 0.75),
+            webhook_max_attempts: env_parse("WEBHOOK_MAX_ATTEMPTS", 8),
+            emitter_backend,
+            emitter_url: env_opt("EMITTER_URL")
+                .unwrap_or_else(|| emitter_backend.default_url().to_string()),
+            emitter_topic: env_or("EMITTER_TOPIC", "analyses"),
+            emitter_queue_capacity: env_parse("EMITTER_QUEUE_CAPACITY", 1000),
+            high_risk_window_secs: env_parse("HIGH_RISK_WINDOW_SECS", 300),
+            high_risk_alert_threshold: env_opt("HIGH_RISK_ALERT_THRESHOLD")
+                .and_then(|v| v.trim().parse().ok()),
+            alert_webhook_url: env_opt("ALERT_WEBHOOK_URL"),
+            address_redaction: env_parse("ADDRESS_REDACTION", RedactionMode::Off),
+            redaction_key: env_opt("REDACTION_KEY"),
+            admin_api_key: env_opt("ADMIN_API_KEY"),
+            safe_mode: env_parse("SAFE_MODE", false),
+            safe_mode_max_confidence: env_parse("SAFE_MODE_MAX_CONFIDENCE", 0.3),
+            // Last, after every other field has been read
+            warnings: WARNINGS.take(),
+            errors: ERRORS.take(),
+        };
+        if let Err(e) = Redactor::from_config(&config) {
+            config.errors.push(e.to_string());
+        }
+        config
+    }
+}
+
+impl Config {
+    pub fn shed_thresholds(&self) -> ShedThresholds {
+        ShedThresholds {
+            p99_latency: self.load_shed_p99_ms.map(Duration::from_millis),
+            error_rate: self.load_shed_error_rate,
+            window: Duration::from_secs(self.load_shed_window_secs),
+            min_samples: self.load_shed_min_samples,
+        }
+    }
+
+    /// The one HTTP client the process uses; clones share its connection pool.
+    pub fn http_client(&self) -> reqwest::Client {
+        reqwest::Client::builder()
+            .pool_max_idle_per_host(self.http_pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(self.http_pool_idle_timeout_secs))
+            .tcp_keepalive(Duration::from_secs(HTTP_TCP_KEEPALIVE_SECS))
+            .build()
+            .expect("failed to build HTTP client")
+    }
+
+    pub fn response_deadline(&self) -> Option<Duration> {
+        self.response_deadline_ms.map(Duration::from_millis)
+    }
+
+    pub fn receipt_wait(&self) -> ReceiptWait {
+        ReceiptWait {
+            attempts: self.receipt_poll_attempts,
+            interval: Duration::from_millis(self.receipt_poll_interval_ms),
+        }
+    }
+
+    pub fn trace_limits(&self) -> TraceLimits {
+        TraceLimits {
+            max_depth: self.trace_max_depth,
+            max_calls: self.trace_max_calls.max(1),
+        }
+    }
+
+    /// The request's network, or DEFAULT_NETWORK when the request omitted it.
+    pub fn resolve_network(&self, network: Option<Network>) -> Result<Network, String> {
+        network
+            .or(self.default_network)
+            .ok_or_else(|| "Missing `network` and no DEFAULT_NETWORK is configured".to_string())
+    }
+
+    /// Validates a per-request model override against the allowlist.
+    pub fn check_model(&self, model: Option<&str>) -> Result<(), String> {
+        match model {
+            Some(m) if m != self.llm_model && !self.llm_allowed_models.iter().any(|a| a == m) => {
+                let mut allowed = vec![self.llm_model.as_str()];
+                allowed.extend(self.llm_allowed_models.iter().map(String::as_str));
+                Err(format!("Model '{}' is not allowed; allowed models: {}", m, allowed.join(", ")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Configured credentials, and RPC URLs (which often carry a provider key in
+    /// their path), for scrubbing from anything echoed back to clients.
+    pub fn secrets(&self) -> Vec<&str> {
+        let keys = [
+            &self.llm_api_key,
+            &self.labels_api_key,
+            &self.admin_api_key,
+            &self.redaction_key,
+        ];
+        let explorer_keys = self.networks.values().map(|network| &network.explorer_api_key);
+        let urls = self
+            .networks
+            .values()
+            .flat_map(|network| network.rpc_urls.iter().chain(&network.archive_rpc_urls));
+        keys.into_iter()
+            .chain(explorer_keys)
+            .flatten()
+            .chain(urls)
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Validates a per-request `max_tokens` against the allowed range.
+    pub fn check_max_tokens(&self, max_tokens: Option<u32>) -> Result<(), String> {
+        match max_tokens {
+            Some(n) if !(MIN_MAX_TOKENS..=self.llm_max_tokens_ceiling).contains(&n) => Err(format!(
+                "max_tokens {} is out of range; allowed: {}-{}",
+                n, MIN_MAX_TOKENS, self.llm_max_tokens_ceiling
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl NetworkConfig {
+    fn from_env(network: Network) -> Self {
+        let suffix = network.env_suffix();
+        // The unsuffixed EXPLORER_API_URL/KEY predate other networks and stay
+        // Ethereum mainnet's, so they're never sent to another chain's explorer
+        let unsuffixed = |key: &str| env_opt(key).filter(|_| network == Network::EthereumMainnet);
+        Self {
+            rpc_urls: env_list(&format!("RPC_URL_{}", suffix)),
+            rpc_archival: env_parse(&format!("RPC_ARCHIVAL_{}", suffix), false),
+            archive_rpc_urls: env_list(&format!("ARCHIVE_RPC_URL_{}", suffix)),
+            // MAX_CONCURRENCY sets the default for networks without their own value
+            max_concurrency: env_parse(
+                &format!("MAX_CONCURRENCY_{}", suffix),
+                env_parse("MAX_CONCURRENCY", 32),
+            ),
+            known_safe_contracts: env_opt(&format!("KNOWN_SAFE_CONTRACTS_{}", suffix))
+                .map(|v| {
+                    v.split(',')
+                        .map(|a| a.trim().to_lowercase())
+                        .filter(|a| !a.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            explorer_api_url: env_opt(&format!("EXPLORER_API_URL_{}", suffix))
+                .or_else(|| unsuffixed("EXPLORER_API_URL"))
+                .unwrap_or_else(|| network.explorer_api_url().to_string()),
+            explorer_api_key: env_opt(&format!("EXPLORER_API_KEY_{}", suffix))
+                .or_else(|| unsuffixed("EXPLORER_API_KEY")),
+        }
+    }
+
+    pub fn is_known_safe(&self, address: &str) -> bool {
+        self.known_safe_contracts.contains(&address.to_lowercase())
+    }
+}
+
+// Through `secrets`, so `secret://` references read as their resolved value
+fn env_opt(key: &str) -> Option<String> {
+    secrets::var(key).filter(|v| !v.trim().is_empty())
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    env_opt(key).unwrap_or_else(|| default.to_string())
+}
+
+// Comma-separated values, in order
+fn env_list(key: &str) -> Vec<String> {
+    env_opt(key)
+        .map(|v| {
+            v.split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn env_parse<T: FromStr>(key: &str, default: T) -> T {
+    match env_opt(key) {
+        Some(raw) => raw.trim().parse().unwrap_or_else(|_| {
+            let warning = if secrets::is_secret(key) {
+                format!("Invalid value for {} (from a secret), using default", key)
+            } else {
+                format!("Invalid value for {}: {:?}, using default", key, raw)
+            };
+            WARNINGS.with_borrow_mut(|warnings| warnings.push(warning));
+            default
+        }),
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use axum::Router;
+    use axum::extract::ConnectInfo;
+    use axum::routing::get;
+
+    use crate::testing;
+
+    // Client ports seen by a local server, one per connection the client opened
+    async fn connections(http: reqwest::Client, requests: usize) -> HashSet<u16> {
+        let peers: Arc<Mutex<HashSet<u16>>> = Arc::default();
+        let seen = peers.clone();
+        let app = Router::new().route(
+            "/",
+            get(move |ConnectInfo(peer): ConnectInfo<SocketAddr>| async move {
+                seen.lock().unwrap().insert(peer.port());
+                "ok"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let service = app.into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, service).await });
+
+        for _ in 0..requests {
+            http.get(&url).send().await.unwrap().error_for_status().unwrap();
+        }
+        std::mem::take(&mut *peers.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn http_client_reuses_connections() {
+        let http = testing::config().http_client();
+        assert_eq!(connections(http, 5).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn http_client_without_idle_connections_reconnects() {
+        let mut config = testing::config();
+        config.http_pool_max_idle_per_host = 0;
+        assert_eq!(connections(config.http_client(), 3).await.len(), 3);
+    }
+}
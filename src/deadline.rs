@@ -0,0 +1,133 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Optional parts of an analysis, which a response deadline may drop. Each
+/// is named after the response field it fills (`llm` for the LLM verdict).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    // The deep-mode call trace, fetched with the tx
+    Trace,
+    TokenSafety,
+    TokenTransfers,
+    // Pool reserves and tokens, for the price impact of `swaps`
+    Swaps,
+    // Balances before and after the tx's block, for `balance_changes`
+    BalanceChanges,
+    // Buyer and seller history on the explorer, for `wash_trades`
+    WashTrades,
+    // The call and logs decoded with fetched ABIs
+    DecodedEvents,
+    // The network's current gas price, for `gas_context`
+    GasContext,
+    Contracts,
+    AddressLabels,
+    Llm,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Trace => "trace",
+            Stage::TokenSafety => "token_safety",
+            Stage::TokenTransfers => "token_transfers",
+            Stage::Swaps => "swaps",
+            Stage::BalanceChanges => "balance_changes",
+            Stage::WashTrades => "wash_trades",
+            Stage::DecodedEvents => "decoded_events",
+            Stage::GasContext => "gas_context",
+            Stage::Contracts => "contracts",
+            Stage::AddressLabels => "address_labels",
+            Stage::Llm => "llm",
+        }
+    }
+
+    // Least time that must be left to start the stage; slower stages are
+    // dropped first as the deadline nears
+    fn min_time(&self) -> Duration {
+        Duration::from_millis(match self {
+            Stage::Llm => 1000,
+            Stage::Trace | Stage::TokenSafety => 500,
+            Stage::Contracts | Stage::DecodedEvents | Stage::WashTrades => 300,
+            Stage::TokenTransfers
+            | Stage::Swaps
+            | Stage::BalanceChanges
+            | Stage::GasContext
+            | Stage::AddressLabels => 200,
+        })
+    }
+}
+
+// Share of the deadline kept back for scoring and recording the result
+const FINISH_SHARE: u32 = 10;
+
+/// A response deadline (`RESPONSE_DEADLINE_MS`) for one analysis. Optional
+/// stages start only while enough time is left for them and are cut off
+/// when it runs out, so a degraded result still goes out in time. Fetching
+/// the tx itself isn't optional, and isn't cut off.
+pub struct Deadline {
+    // Past this, optional stages are dropped; the rest is kept for finishing
+    cutoff: Instant,
+    skipped: Mutex<Vec<Stage>>,
+}
+
+impl Deadline {
+    /// Starts the clock.
+    pub fn after(deadline: Duration) -> Self {
+        Self {
+            cutoff: Instant::now() + deadline - deadline / FINISH_SHARE,
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Time left for optional stages.
+    pub fn remaining(&self) -> Duration {
+        self.cutoff.saturating_duration_since(Instant::now())
+    }
+
+    /// Whether `stage` can still start; if not, it's recorded as skipped.
+    pub fn allows(&self, stage: Stage) -> bool {
+        let allowed = self.remaining() >= stage.min_time();
+        if !allowed {
+            self.skip(stage);
+        }
+        allowed
+    }
+
+    /// Runs `stage` if it can still start, cut off when time runs out. `None`
+    /// (and recorded as skipped) when it was dropped or cut off.
+    pub async fn run<T>(&self, stage: Stage, work: impl Future<Output = T>) -> Option<T> {
+        if !self.allows(stage) {
+            return None;
+        }
+        let result = tokio::time::timeout(self.remaining(), work).await.ok();
+        if result.is_none() {
+            self.skip(stage);
+        }
+        result
+    }
+
+    pub fn skip(&self, stage: Stage) {
+        let mut skipped = self.skipped.lock().unwrap();
+        if !skipped.contains(&stage) {
+            skipped.push(stage);
+        }
+    }
+
+    /// Stages dropped so far, in the order they were.
+    pub fn skipped(&self) -> Vec<&'static str> {
+        self.skipped.lock().unwrap().iter().map(Stage::as_str).collect()
+    }
+}
+
+/// Runs `work` under `deadline` when there is one, or just runs it.
+pub async fn within<T>(
+    deadline: Option<&Deadline>,
+    stage: Stage,
+    work: impl Future<Output = T>,
+) -> Option<T> {
+    match deadline {
+        Some(deadline) => deadline.run(stage, work).await,
+        None => Some(work.await),
+    }
+}
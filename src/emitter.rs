@@ -0,0 +1,250 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use futures::future::BoxFuture;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::models::AnalyzeTxResponse;
+use crate::state::SharedState;
+
+#[derive(Debug, Error)]
+pub enum EmitError {
+    #[error("Failed to serialize analysis: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Publish failed: {0}")]
+    #[cfg_attr(not(any(feature = "kafka", feature = "nats")), allow(dead_code))]
+    Publish(String),
+    #[error("{0} support isn't compiled in; build with `--features {0}`")]
+    #[cfg_attr(all(feature = "kafka", feature = "nats"), allow(dead_code))]
+    NotCompiled(&'static str),
+}
+
+/// Where completed analyses are published besides the HTTP response
+/// (EMITTER_BACKEND).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmitterBackend {
+    #[default]
+    Off,
+    Kafka,
+    Nats,
+}
+
+impl EmitterBackend {
+    // Used when EMITTER_URL is unset
+    pub fn default_url(&self) -> &'static str {
+        match self {
+            EmitterBackend::Off => "",
+            EmitterBackend::Kafka => "localhost:9092",
+            EmitterBackend::Nats => "nats://localhost:4222",
+        }
+    }
+}
+
+impl FromStr for EmitterBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(EmitterBackend::Off),
+            "kafka" => Ok(EmitterBackend::Kafka),
+            "nats" => Ok(EmitterBackend::Nats),
+            other => Err(format!("unknown emitter backend '{}'", other)),
+        }
+    }
+}
+
+/// A message bus that completed analyses are published to, as JSON (keyed by
+/// tx hash where the bus has keys).
+pub trait AnalysisEmitter: Send + Sync {
+    fn emit<'a>(&'a self, analysis: &'a AnalyzeTxResponse) -> BoxFuture<'a, Result<(), EmitError>>;
+}
+
+/// The configured backend's emitter; `None` when emitting is off.
+pub fn from_config(config: &Config) -> Result<Option<Box<dyn AnalysisEmitter>>, EmitError> {
+    match config.emitter_backend {
+        EmitterBackend::Off => Ok(None),
+        #[cfg(feature = "kafka")]
+        EmitterBackend::Kafka => Ok(Some(Box::new(kafka::KafkaEmitter::new(
+            &config.emitter_url,
+            &config.emitter_topic,
+        )?))),
+        #[cfg(not(feature = "kafka"))]
+        EmitterBackend::Kafka => Err(EmitError::NotCompiled("kafka")),
+        #[cfg(feature = "nats")]
+        EmitterBackend::Nats => Ok(Some(Box::new(nats::NatsEmitter::new(
+            &config.emitter_url,
+            &config.emitter_topic,
+        )))),
+        #[cfg(not(feature = "nats"))]
+        EmitterBackend::Nats => Err(EmitError::NotCompiled("nats")),
+    }
+}
+
+/// Analyses waiting to be published. Queuing never waits: when the queue is
+/// full (the bus is down or slow), the analysis is dropped and counted, so
+/// the bus can't hold up a request.
+pub struct EmitQueue {
+    emitter: Box<dyn AnalysisEmitter>,
+    sender: mpsc::Sender<AnalyzeTxResponse>,
+    // Taken by the publisher task
+    receiver: Mutex<Option<mpsc::Receiver<AnalyzeTxResponse>>>,
+}
+
+impl EmitQueue {
+    pub fn new(emitter: Box<dyn AnalysisEmitter>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        Self {
+            emitter,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    pub fn publish(&self, analysis: &AnalyzeTxResponse, metrics: &Metrics) {
+        if self.sender.try_send(analysis.clone()).is_err() {
+            tracing::warn!("Emitter queue is full; dropped the analysis of {}", analysis.tx_hash);
+            Metrics::inc(&metrics.emit_failures);
+        }
+    }
+}
+
+/// Spawns the publisher when an emitter is configured. Publish failures are
+/// logged and counted; the analysis isn't retried.
+pub fn start_publisher(state: &SharedState) {
+    let Some(queue) = &state.emitter else {
+        return;
+    };
+    let Some(mut receiver) = queue.receiver.lock().unwrap().take() else {
+        return;
+    };
+    tracing::info!(
+        "Publishing analyses to {:?} at {}, topic {}",
+        state.config.emitter_backend,
+        state.config.emitter_url,
+        state.config.emitter_topic
+    );
+    let state = state.clone();
+    tokio::spawn(async move {
+        let Some(queue) = &state.emitter else {
+            return;
+        };
+        while let Some(analysis) = receiver.recv().await {
+            match queue.emitter.emit(&analysis).await {
+                Ok(()) => Metrics::inc(&state.metrics.emitted),
+                Err(e) => {
+                    tracing::warn!("Failed to publish the analysis of {}: {}", analysis.tx_hash, e);
+                    Metrics::inc(&state.metrics.emit_failures);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::time::Duration;
+
+    use futures::future::BoxFuture;
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+
+    use super::{AnalysisEmitter, EmitError};
+    use crate::models::AnalyzeTxResponse;
+
+    // How long librdkafka keeps retrying a message before giving up on it
+    const MESSAGE_TIMEOUT_MS: &str = "10000";
+
+    pub struct KafkaEmitter {
+        producer: FutureProducer,
+        topic: String,
+    }
+
+    impl KafkaEmitter {
+        // `brokers` is Kafka's comma-separated bootstrap server list
+        pub fn new(brokers: &str, topic: &str) -> Result<Self, EmitError> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("message.timeout.ms", MESSAGE_TIMEOUT_MS)
+                .create()
+                .map_err(|e| EmitError::Publish(e.to_string()))?;
+            Ok(Self {
+                producer,
+                topic: topic.to_string(),
+            })
+        }
+    }
+
+    impl AnalysisEmitter for KafkaEmitter {
+        fn emit<'a>(
+            &'a self,
+            analysis: &'a AnalyzeTxResponse,
+        ) -> BoxFuture<'a, Result<(), EmitError>> {
+            Box::pin(async move {
+                let payload = serde_json::to_vec(analysis)?;
+                let record = FutureRecord::to(&self.topic)
+                    .key(&analysis.tx_hash)
+                    .payload(&payload);
+                self.producer
+                    .send(record, Duration::ZERO)
+                    .await
+                    .map(|_| ())
+                    .map_err(|(e, _)| EmitError::Publish(e.to_string()))
+            })
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use futures::future::BoxFuture;
+    use tokio::sync::OnceCell;
+
+    use super::{AnalysisEmitter, EmitError};
+    use crate::models::AnalyzeTxResponse;
+
+    pub struct NatsEmitter {
+        url: String,
+        subject: String,
+        // Connected on first use; the client reconnects by itself after that
+        client: OnceCell<async_nats::Client>,
+    }
+
+    impl NatsEmitter {
+        pub fn new(url: &str, subject: &str) -> Self {
+            Self {
+                url: url.to_string(),
+                subject: subject.to_string(),
+                client: OnceCell::new(),
+            }
+        }
+
+        async fn client(&self) -> Result<&async_nats::Client, EmitError> {
+            self.client
+                .get_or_try_init(|| async {
+                    async_nats::connect(self.url.as_str())
+                        .await
+                        .map_err(|e| EmitError::Publish(e.to_string()))
+                })
+                .await
+        }
+    }
+
+    impl AnalysisEmitter for NatsEmitter {
+        fn emit<'a>(
+            &'a self,
+            analysis: &'a AnalyzeTxResponse,
+        ) -> BoxFuture<'a, Result<(), EmitError>> {
+            Box::pin(async move {
+                let payload = serde_json::to_vec(analysis)?;
+                self.client()
+                    .await?
+                    .publish(self.subject.clone(), payload.into())
+                    .await
+                    .map_err(|e| EmitError::Publish(e.to_string()))
+            })
+        }
+    }
+}
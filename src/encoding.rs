@@ -0,0 +1,380 @@
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer, ser::Error as _};
+use serde_json::Value;
+
+use crate::error::ApiError;
+use crate::util::fnv1a;
+
+const MSGPACK_TYPES: &[&str] = &[
+    "application/msgpack",
+    "application/x-msgpack",
+    "application/vnd.msgpack",
+];
+
+/// Wire format for response bodies, negotiated from the `Accept` header.
+/// JSON unless the client explicitly asks for MessagePack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+}
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Whether the client lists `media_type` in its `Accept` header.
+pub fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    accepted(headers).any(|media| media.eq_ignore_ascii_case(media_type))
+}
+
+fn accepted(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|media| media.split(';').next().unwrap_or("").trim())
+}
+
+impl Encoding {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let wants_msgpack = MSGPACK_TYPES.iter().any(|t| accepts(headers, t));
+
+        if wants_msgpack {
+            Encoding::MessagePack
+        } else {
+            Encoding::Json
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Encoding::Json => "application/json",
+            Encoding::MessagePack => "application/msgpack",
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Response {
+        match self.to_bytes(value) {
+            Ok(bytes) => (
+                [(header::CONTENT_TYPE, HeaderValue::from_static(self.content_type()))],
+                bytes,
+            )
+                .into_response(),
+            Err(e) => serialize_error(e),
+        }
+    }
+
+    /// Like `encode`, with a content-hash `ETag`. Answers `304 Not Modified`
+    /// when the request's `If-None-Match` already names the current body.
+    pub fn encode_cacheable<T: Serialize>(self, request: &HeaderMap, value: &T) -> Response {
+        let bytes = match self.to_bytes(value) {
+            Ok(bytes) => bytes,
+            Err(e) => return serialize_error(e),
+        };
+
+        // The hash covers the encoded bytes, so JSON and MessagePack get distinct tags
+        let etag = format!("\"{:016x}\"", fnv1a(&bytes));
+        let etag_header = HeaderValue::from_str(&etag).expect("hex etag is a valid header");
+        let vary = (header::VARY, HeaderValue::from_static("accept"));
+
+        if if_none_match(request, &etag) {
+            return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag_header), vary]).into_response();
+        }
+        (
+            [
+                (header::CONTENT_TYPE, HeaderValue::from_static(self.content_type())),
+                (header::ETAG, etag_header),
+                vary,
+            ],
+            bytes,
+        )
+            .into_response()
+    }
+
+    // MessagePack is written as a map (field names kept) so it mirrors the JSON shape
+    fn to_bytes<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Encoding::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+const SCHEMA_MEDIA_PREFIX: &str = "application/vnd.analyzer.v";
+const SCHEMA_MEDIA_SUFFIX: &str = "+json";
+
+/// Shape of a serialized analysis, picked by `?schema_version=N` or
+/// `Accept: application/vnd.analyzer.vN+json`. Old versions stay frozen so
+/// existing integrations keep working as fields are added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaVersion {
+    // The original response: type, protocol, risk and explanation only
+    V1,
+    #[default]
+    V2,
+}
+
+impl SchemaVersion {
+    /// The query param wins over the `Accept` header; neither means the latest.
+    pub fn negotiate(headers: &HeaderMap, param: Option<&str>) -> Result<Self, String> {
+        if let Some(param) = param {
+            let param = param.trim();
+            return Self::parse(param.trim_start_matches(['v', 'V']))
+                .ok_or_else(|| format!("Unknown schema_version '{}'; supported: 1, 2", param));
+        }
+        match schema_media(headers) {
+            Some(version) => Self::parse(version).ok_or_else(|| {
+                format!(
+                    "Unsupported media type {}{}{}; supported versions: 1, 2",
+                    SCHEMA_MEDIA_PREFIX, version, SCHEMA_MEDIA_SUFFIX
+                )
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn parse(version: &str) -> Option<Self> {
+        match version {
+            "1" => Some(SchemaVersion::V1),
+            "2" => Some(SchemaVersion::V2),
+            _ => None,
+        }
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            SchemaVersion::V1 => "application/vnd.analyzer.v1+json",
+            SchemaVersion::V2 => "application/vnd.analyzer.v2+json",
+        }
+    }
+}
+
+/// Whether the client asked for a versioned media type, in which case a JSON
+/// response is labeled with it.
+pub fn accepts_schema_media(headers: &HeaderMap) -> bool {
+    schema_media(headers).is_some()
+}
+
+// The "N" of the first `application/vnd.analyzer.vN+json` in `Accept`
+fn schema_media(headers: &HeaderMap) -> Option<&str> {
+    accepted(headers).find_map(|media| {
+        media
+            .strip_prefix(SCHEMA_MEDIA_PREFIX)?
+            .strip_suffix(SCHEMA_MEDIA_SUFFIX)
+    })
+}
+
+/// Key naming of serialized analyses (RESPONSE_FIELD_CASE). Snake case is
+/// the Rust field naming and the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldCase {
+    #[default]
+    Snake,
+    Camel,
+}
+
+impl FromStr for FieldCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "snake" | "snake_case" => Ok(FieldCase::Snake),
+            "camel" | "camelcase" => Ok(FieldCase::Camel),
+            other => Err(format!("unknown field case '{}'", other)),
+        }
+    }
+}
+
+/// Serializes the wrapped value with its object keys renamed to `case`.
+pub struct Cased<'a, T>(pub &'a T, pub FieldCase);
+
+impl<T: Serialize> Serialize for Cased<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.1 {
+            FieldCase::Snake => self.0.serialize(serializer),
+            FieldCase::Camel => {
+                // Through text, as in `FieldSelection::apply`, so f32s don't widen
+                let text = serde_json::to_string(self.0).map_err(S::Error::custom)?;
+                let mut value: Value = serde_json::from_str(&text).map_err(S::Error::custom)?;
+                camel_keys(&mut value);
+                value.serialize(serializer)
+            }
+        }
+    }
+}
+
+// Fields whose values are data rather than more fields: map keys (the
+// tx_types of `ConsensusInfo.votes`) and decoded arguments (the tuples and
+// structs of `DecodedParam.value`). Renamed themselves, but not inside.
+const DATA_FIELDS: &[&str] = &["votes", "value"];
+
+fn camel_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    if !DATA_FIELDS.contains(&key.as_str()) {
+                        camel_keys(&mut value);
+                    }
+                    (to_camel(&key), value)
+                })
+                .collect();
+        }
+        Value::Array(items) => items.iter_mut().for_each(camel_keys),
+        _ => {}
+    }
+}
+
+fn to_camel(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn serialize_error(e: String) -> Response {
+    ApiError::internal(format!("Failed to serialize response: {}", e)).into_response()
+}
+
+// If-None-Match uses weak comparison, so a `W/` prefix on the client's tag is ignored
+fn if_none_match(request: &HeaderMap, etag: &str) -> bool {
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    fn analysis() -> Value {
+        json!({
+            "tx_type": "DEX_SWAP",
+            "risk_score": 0.25,
+            "value": { "wei": "1000", "formatted": "0.000000000000001" },
+            "consensus": {
+                "valid_samples": 3,
+                "votes": { "DEX_SWAP": 2, "TOKEN_TRANSFER": 1 }
+            },
+            "call": {
+                "signature_source": "abi",
+                "params": [{ "type": "tuple", "value": { "min_amount_out": "5" } }]
+            }
+        })
+    }
+
+    fn render(case: FieldCase) -> Value {
+        serde_json::to_value(Cased(&analysis(), case)).unwrap()
+    }
+
+    #[test]
+    fn snake_case_is_unchanged() {
+        assert_eq!(render(FieldCase::Snake), analysis());
+    }
+
+    #[test]
+    fn camel_case_renames_fields_only() {
+        assert_eq!(
+            render(FieldCase::Camel),
+            json!({
+                "txType": "DEX_SWAP",
+                "riskScore": 0.25,
+                "value": { "wei": "1000", "formatted": "0.000000000000001" },
+                "consensus": {
+                    "validSamples": 3,
+                    "votes": { "DEX_SWAP": 2, "TOKEN_TRANSFER": 1 }
+                },
+                "call": {
+                    "signatureSource": "abi",
+                    "params": [{ "type": "tuple", "value": { "min_amount_out": "5" } }]
+                }
+            })
+        );
+    }
+
+    fn conditional(if_none_match: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(tag) = if_none_match {
+            headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(tag).unwrap());
+        }
+        headers
+    }
+
+    fn etag(response: &Response) -> String {
+        response.headers()[header::ETAG].to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn cacheable_responses_carry_a_content_etag() {
+        let first = Encoding::Json.encode_cacheable(&conditional(None), &analysis());
+        let again = Encoding::Json.encode_cacheable(&conditional(None), &analysis());
+        let other = Encoding::Json.encode_cacheable(&conditional(None), &json!({}));
+        let packed = Encoding::MessagePack.encode_cacheable(&conditional(None), &analysis());
+
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()[header::VARY], "accept");
+        assert_eq!(etag(&first), etag(&again));
+        assert_ne!(etag(&first), etag(&other));
+        assert_ne!(etag(&first), etag(&packed));
+    }
+
+    #[test]
+    fn matching_if_none_match_is_not_modified() {
+        let tag = etag(&Encoding::Json.encode_cacheable(&conditional(None), &analysis()));
+        let matching = [
+            tag.clone(),
+            format!("W/{}", tag),
+            format!("\"other\", {}", tag),
+            "*".to_string(),
+        ];
+        for header in matching {
+            let headers = conditional(Some(&header));
+            let response = Encoding::Json.encode_cacheable(&headers, &analysis());
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{}", header);
+            assert_eq!(etag(&response), tag);
+        }
+
+        let stale = Encoding::Json.encode_cacheable(&conditional(Some("\"stale\"")), &analysis());
+        assert_eq!(stale.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn message_pack_round_trips_to_the_json_shape() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/x-msgpack"));
+        let encoding = Encoding::from_headers(&headers);
+        assert_eq!(encoding, Encoding::MessagePack);
+
+        let analysis = testing::analysis(testing::TRANSFER_HASH).await;
+        let response = encoding.encode(&analysis);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/msgpack");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        // Named fields, so it decodes to the same document the JSON body carries
+        let decoded: Value = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, serde_json::to_value(&analysis).unwrap());
+        assert_eq!(decoded["tx_hash"], testing::TRANSFER_HASH);
+    }
+}
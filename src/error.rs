@@ -0,0 +1,210 @@
+use axum::{
+    Json, async_trait,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    extract::{FromRequest, FromRequestParts, Path, Query, Request},
+    http::{StatusCode, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::jobs::JobError;
+use crate::pipeline::AnalysisError;
+use crate::services::blockchain::BlockchainError;
+
+/// Body of every error response.
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    // Stable, machine-readable code (e.g. "INVALID_REQUEST")
+    pub error_code: &'static str,
+    pub message: String,
+}
+
+/// Handler error: an HTTP status plus the structured body.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub error_code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, error_code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error_code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "INVALID_REQUEST", message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            error_code: self.error_code,
+            message: self.message,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<AnalysisError> for ApiError {
+    fn from(e: AnalysisError) -> Self {
+        let (status, code) = match &e {
+            AnalysisError::Fetch(BlockchainError::NotFound(_)) => {
+                (StatusCode::BAD_REQUEST, "TX_NOT_FOUND")
+            }
+            AnalysisError::Fetch(_) => (StatusCode::BAD_REQUEST, "FETCH_FAILED"),
+            AnalysisError::Ai(_) => (StatusCode::INTERNAL_SERVER_ERROR, "ANALYSIS_FAILED"),
+        };
+        Self::new(status, code, e.to_string())
+    }
+}
+
+impl From<JobError> for ApiError {
+    fn from(e: JobError) -> Self {
+        match e {
+            JobError::QueueFull => {
+                Self::new(StatusCode::SERVICE_UNAVAILABLE, "OVERLOADED", e.to_string())
+            }
+            JobError::ReanalysisRunning(_) => {
+                Self::new(StatusCode::CONFLICT, "REANALYSIS_RUNNING", e.to_string())
+            }
+            JobError::Store(e) => Self::internal(e.to_string()),
+        }
+    }
+}
+
+/// `Json` extractor whose rejections (bad syntax, missing fields, wrong types)
+/// come back as an `ErrorResponse` instead of axum's plain-text body.
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            // body_text names the offending field, e.g. "network: unknown network ..."
+            Err(rejection) => Err(ApiError::new(
+                rejection.status(),
+                "INVALID_REQUEST",
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+/// `Query` counterpart of `ApiJson`.
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiQuery<T>
+where
+    Query<T>: FromRequestParts<S, Rejection = QueryRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(value)) => Ok(Self(value)),
+            Err(rejection) => Err(ApiError::new(
+                rejection.status(),
+                "INVALID_REQUEST",
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+/// `Path` counterpart of `ApiJson`.
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiPath<T>
+where
+    Path<T>: FromRequestParts<S, Rejection = PathRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(Self(value)),
+            Err(rejection) => Err(ApiError::new(
+                rejection.status(),
+                "INVALID_REQUEST",
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, MockChain, TRANSFER_HASH};
+
+    async fn rejected(request: Request<Body>) -> (StatusCode, serde_json::Value) {
+        let state = testing::state(MockChain::default(), None);
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(body["error_code"], "INVALID_REQUEST", "{}", body);
+        assert!(body["message"].as_str().is_some_and(|m| !m.is_empty()), "{}", body);
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_structured_error() {
+        let request = Request::post("/analyze_tx")
+            .header("content-type", "application/json")
+            .body(Body::from("{\"tx_hash\": "))
+            .unwrap();
+        let (status, _) = rejected(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn missing_content_type_is_a_structured_error() {
+        let request = Request::post("/analyze_tx")
+            .body(Body::from(json!({ "tx_hash": TRANSFER_HASH }).to_string()))
+            .unwrap();
+        let (status, _) = rejected(request).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn wrong_field_types_name_the_field() {
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "deep": "yes" });
+        let request = testing::post_json("/analyze_tx", payload);
+        let (status, body) = rejected(request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["message"].as_str().unwrap().contains("deep"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn bad_query_and_path_params_are_structured_errors() {
+        let (status, _) = rejected(testing::get("/history?network=no-such-chain")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let uri = format!("/analyze_tx/no-such-chain/{}", TRANSFER_HASH);
+        let (status, _) = rejected(testing::get(&uri)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
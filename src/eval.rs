@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, HashMap};
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::fields::FieldSelection;
+use crate::history;
+use crate::models::AnalyzeTxRequest;
+use crate::network::Network;
+use crate::pipeline;
+use crate::services::risk;
+use crate::services::rpc::{ChainClient, RpcError};
+use crate::state::{AppState, Services};
+use crate::store::Store;
+
+/// A labeled set of txs for `--eval`, with the node responses to analyze them
+/// from, so runs are repeatable and need no node.
+#[derive(Debug, Deserialize)]
+struct Dataset {
+    fixtures: Vec<Fixture>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    // Shown in the mismatch report; defaults to the tx hash
+    #[serde(default)]
+    name: Option<String>,
+    network: Network,
+    tx_hash: String,
+    #[serde(default)]
+    deep: bool,
+    // The node's answers for this tx (tx, receipt, trace, ...)
+    #[serde(default)]
+    rpc: Vec<RecordedCall>,
+    expected: Expected,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordedCall {
+    method: String,
+    // Omitted to answer the method whatever its params (e.g. eth_blockNumber)
+    #[serde(default)]
+    params: Option<Value>,
+    result: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Expected {
+    tx_type: String,
+    // Whether the score should reach HIGH_RISK_SCORE; unchecked when omitted
+    #[serde(default)]
+    high_risk: Option<bool>,
+}
+
+/// Answers calls from recorded responses, matched on method and params, then
+/// on method alone. Anything not recorded gets `null`, as a node answers for
+/// an unknown tx.
+#[derive(Default)]
+struct RecordedChain {
+    exact: HashMap<(String, String), Value>,
+    any_params: HashMap<String, Value>,
+}
+
+impl RecordedChain {
+    fn record(&mut self, call: &RecordedCall) {
+        match &call.params {
+            Some(params) => {
+                let key = (call.method.clone(), params.to_string());
+                self.exact.entry(key).or_insert_with(|| call.result.clone());
+            }
+            None => {
+                self.any_params
+                    .entry(call.method.clone())
+                    .or_insert_with(|| call.result.clone());
+            }
+        }
+    }
+
+    fn answer(&self, method: &str, params: &Value) -> Value {
+        self.exact
+            .get(&(method.to_string(), params.to_string()))
+            .or_else(|| self.any_params.get(method))
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+}
+
+impl ChainClient for RecordedChain {
+    fn call<'a>(&'a self, method: &'a str, params: Value) -> BoxFuture<'a, Result<Value, RpcError>> {
+        Box::pin(async move { Ok(self.answer(method, &params)) })
+    }
+
+    fn batch<'a>(
+        &'a self,
+        calls: &'a [(&'a str, Value)],
+    ) -> BoxFuture<'a, Vec<Result<Value, RpcError>>> {
+        Box::pin(async move {
+            calls
+                .iter()
+                .map(|(method, params)| Ok(self.answer(method, params)))
+                .collect()
+        })
+    }
+}
+
+struct Outcome {
+    name: String,
+    expected: Expected,
+    // Label and score, or the analysis error
+    actual: Result<(String, f32), String>,
+}
+
+impl Outcome {
+    fn type_matches(&self) -> bool {
+        self.actual.as_ref().is_ok_and(|(tx_type, _)| *tx_type == self.expected.tx_type)
+    }
+
+    fn high_risk(&self) -> Option<bool> {
+        let (_, score) = self.actual.as_ref().ok()?;
+        Some(*score >= risk::HIGH_RISK_SCORE)
+    }
+}
+
+/// One-shot `--eval <dataset.json>`: analyzes each labeled fixture through the
+/// normal pipeline, against its recorded node responses and the configured
+/// LLM, and prints accuracy, per-category precision and recall, and every
+/// mismatch. For comparing models and prompts before switching. Returns
+/// whether the dataset could be run.
+pub async fn run(config: Config, path: &str) -> bool {
+    let dataset: Dataset = match std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+    {
+        Ok(dataset) => dataset,
+        Err(e) => {
+            eprintln!("Can't read eval dataset {}: {}", path, e);
+            return false;
+        }
+    };
+
+    let mut chains: HashMap<Network, RecordedChain> = HashMap::new();
+    for fixture in &dataset.fixtures {
+        let chain = chains.entry(fixture.network).or_default();
+        fixture.rpc.iter().for_each(|call| chain.record(call));
+    }
+    let http = config.http_client();
+    let mut services = Services::from_config(&config, &http);
+    services.rpc_clients = chains
+        .into_iter()
+        .map(|(network, chain)| (network, Box::new(chain) as Box<dyn ChainClient>))
+        .collect();
+    services.archive_rpc_clients = HashMap::new();
+    // Eval analyses aren't published anywhere
+    services.emitter = None;
+    let model = config.llm_model.clone();
+    let store = Store::open(":memory:").expect("failed to open history store");
+    let state = AppState::with_services(config, store, http, services);
+    history::start_writer(&state);
+
+    let mut outcomes = Vec::with_capacity(dataset.fixtures.len());
+    for fixture in dataset.fixtures {
+        let request = AnalyzeTxRequest {
+            network: Some(fixture.network),
+            tx_hash: fixture.tx_hash.clone(),
+            deep: fixture.deep,
+            ..AnalyzeTxRequest::default()
+        };
+        let actual = pipeline::analyze(&state, &request, &FieldSelection::default(), None)
+            .await
+            .map(|analysis| (analysis.tx_type, analysis.risk_score))
+            .map_err(|e| e.to_string());
+        outcomes.push(Outcome {
+            name: fixture.name.unwrap_or(fixture.tx_hash),
+            expected: fixture.expected,
+            actual,
+        });
+    }
+
+    let analyzer = if state.llm.is_some() { model.as_str() } else { "heuristics only" };
+    println!("Eval of {} ({} fixtures, {})", path, outcomes.len(), analyzer);
+    report(&outcomes);
+    true
+}
+
+fn report(outcomes: &[Outcome]) {
+    let correct = outcomes.iter().filter(|o| o.type_matches()).count();
+    println!("\ntx_type accuracy: {}", ratio(correct, outcomes.len()));
+
+    // (true positives, predicted, expected) per category
+    let mut categories: BTreeMap<&str, (usize, usize, usize)> = BTreeMap::new();
+    for outcome in outcomes {
+        categories.entry(&outcome.expected.tx_type).or_default().2 += 1;
+        if let Ok((tx_type, _)) = &outcome.actual {
+            categories.entry(tx_type).or_default().1 += 1;
+            if outcome.type_matches() {
+                categories.entry(tx_type).or_default().0 += 1;
+            }
+        }
+    }
+    println!("\n{:<24} {:>16} {:>16}", "category", "precision", "recall");
+    for (category, (hits, predicted, expected)) in &categories {
+        println!(
+            "{:<24} {:>16} {:>16}",
+            category,
+            ratio(*hits, *predicted),
+            ratio(*hits, *expected)
+        );
+    }
+
+    let labeled: Vec<(bool, bool)> = outcomes
+        .iter()
+        .filter_map(|o| Some((o.expected.high_risk?, o.high_risk()?)))
+        .collect();
+    if !labeled.is_empty() {
+        let hits = labeled.iter().filter(|(expected, actual)| *expected && *actual).count();
+        let agree = labeled.iter().filter(|(expected, actual)| expected == actual).count();
+        println!(
+            "\nhigh risk (score >= {}): accuracy {}, precision {}, recall {}",
+            risk::HIGH_RISK_SCORE,
+            ratio(agree, labeled.len()),
+            ratio(hits, labeled.iter().filter(|(_, actual)| *actual).count()),
+            ratio(hits, labeled.iter().filter(|(expected, _)| *expected).count()),
+        );
+    }
+
+    let mismatches: Vec<String> = outcomes.iter().filter_map(mismatch).collect();
+    if mismatches.is_empty() {
+        println!("\nNo mismatches");
+    } else {
+        println!("\nMismatches ({}):", mismatches.len());
+        mismatches.iter().for_each(|line| println!("  {}", line));
+    }
+}
+
+// What differs from the label, if anything
+fn mismatch(outcome: &Outcome) -> Option<String> {
+    let (tx_type, score) = match &outcome.actual {
+        Ok(actual) => actual,
+        Err(e) => return Some(format!("{}: analysis failed: {}", outcome.name, e)),
+    };
+    let mut diffs = Vec::new();
+    if *tx_type != outcome.expected.tx_type {
+        diffs.push(format!("tx_type expected {}, got {}", outcome.expected.tx_type, tx_type));
+    }
+    if let (Some(expected), Some(actual)) = (outcome.expected.high_risk, outcome.high_risk())
+        && expected != actual
+    {
+        let level = |high| if high { "high" } else { "not high" };
+        diffs.push(format!(
+            "risk expected {}, got {} ({:.2})",
+            level(expected),
+            level(actual),
+            score
+        ));
+    }
+    (!diffs.is_empty()).then(|| format!("{}: {}", outcome.name, diffs.join("; ")))
+}
+
+// "3/4 (75.0%)"; "-" when nothing was counted
+fn ratio(part: usize, whole: usize) -> String {
+    if whole == 0 {
+        return "-".to_string();
+    }
+    format!("{}/{} ({:.1}%)", part, whole, part as f64 * 100.0 / whole as f64)
+}
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use serde_json::Value;
+
+// Serialized field names of `AnalyzeTxResponse`; keep in sync with the struct.
+pub const RESPONSE_FIELDS: &[&str] = &[
+    "tx_hash",
+    "network",
+    "tx_type",
+    "protocol",
+    "method",
+    "proxy_address",
+    "implementation_address",
+    "bridge_details",
+    "multisig",
+    "user_operations",
+    "value",
+    "token_transfers",
+    "swaps",
+    "balance_changes",
+    "wash_trades",
+    "decoded_call",
+    "decoded_events",
+    "gas_context",
+    "risk_score",
+    "score_components",
+    "risk_reasons",
+    "recommendations",
+    "confidence",
+    "ai_available",
+    "analyzer_version",
+    "contracts",
+    "address_labels",
+    "permits",
+    "token_safety",
+    "natural_language_explanation",
+    "format",
+    "verbosity",
+    "persona",
+    "source",
+    "confirmations",
+    "provisional",
+    "failure_reason",
+    "revert_reason",
+    "receipt_available",
+    "consensus",
+    "decision_trace",
+    "deadline_skipped",
+    "timing",
+    "prompt",
+    "notes",
+];
+
+// Fields whose value comes from (or is shaped by) the LLM
+pub const LLM_FIELDS: &[&str] = &[
+    "tx_type",
+    "protocol",
+    "risk_score",
+    "score_components",
+    "risk_reasons",
+    "confidence",
+    "natural_language_explanation",
+    "consensus",
+];
+
+/// Response fields requested via `?fields=a,b,c`. No selection means all fields.
+#[derive(Debug, Clone, Default)]
+pub struct FieldSelection(Option<HashSet<String>>);
+
+impl FieldSelection {
+    pub fn parse(raw: Option<&str>) -> Result<Self, String> {
+        let Some(raw) = raw.filter(|r| !r.trim().is_empty()) else {
+            return Ok(Self(None));
+        };
+
+        let mut fields = HashSet::new();
+        for field in raw.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            // camelCase names (as rendered with RESPONSE_FIELD_CASE=camel) work too
+            let field = to_snake(field);
+            let field = field.as_str();
+            if !RESPONSE_FIELDS.contains(&field) {
+                return Err(format!(
+                    "Unknown field '{}'; valid fields: {}",
+                    field,
+                    RESPONSE_FIELDS.join(", ")
+                ));
+            }
+            fields.insert(field.to_string());
+        }
+        Ok(Self(Some(fields)))
+    }
+
+    pub fn wants(&self, field: &str) -> bool {
+        self.0.as_ref().is_none_or(|fields| fields.contains(field))
+    }
+
+    pub fn wants_any(&self, fields: &[&str]) -> bool {
+        fields.iter().any(|f| self.wants(f))
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Serializes `response` keeping only the requested top-level keys.
+    pub fn apply<T: Serialize>(&self, response: &T) -> Result<Value, serde_json::Error> {
+        // Round-trip through text rather than `to_value`, which widens f32 to f64
+        // and turns 0.1 into 0.10000000149011612.
+        let mut value: Value = serde_json::from_str(&serde_json::to_string(response)?)?;
+        if let (Some(fields), Value::Object(map)) = (&self.0, &mut value) {
+            map.retain(|key, _| fields.contains(key));
+        }
+        Ok(value)
+    }
+}
+
+fn to_snake(field: &str) -> String {
+    let mut out = String::with_capacity(field.len() + 4);
+    for c in field.chars() {
+        if c.is_ascii_uppercase() {
+            out.push('_');
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
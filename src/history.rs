@@ -0,0 +1,280 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::metrics::Metrics;
+use crate::models::AnalyzeTxResponse;
+use crate::services::similarity;
+use crate::state::{AppState, SharedState};
+
+/// What recording an analysis does when the history queue is full
+/// (HISTORY_QUEUE_FULL).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueFull {
+    // The request waits for room, so nothing is lost but a slow disk slows requests
+    #[default]
+    Wait,
+    // The analysis isn't recorded (nor its webhook queued), and is counted
+    Drop,
+}
+
+impl FromStr for QueueFull {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "wait" | "block" => Ok(QueueFull::Wait),
+            "drop" => Ok(QueueFull::Drop),
+            other => Err(format!("unknown history queue mode '{}'", other)),
+        }
+    }
+}
+
+/// An analysis waiting to be recorded, with what follows the write.
+struct PendingWrite {
+    analysis: AnalyzeTxResponse,
+    // Queue a webhook delivery with it
+    notify: bool,
+    // The block (hash and number) of a provisional result, to re-check
+    // against the chain
+    block: Option<(String, u64)>,
+}
+
+/// Analyses waiting to be written to history. A single writer drains it,
+/// inserting up to `batch_size` analyses per transaction, so request latency
+/// doesn't follow SQLite's write speed until the queue fills up.
+pub struct HistoryQueue {
+    sender: mpsc::Sender<PendingWrite>,
+    // Taken by the writer task
+    receiver: Mutex<Option<mpsc::Receiver<PendingWrite>>>,
+    full: QueueFull,
+    batch_size: usize,
+    // Queued or being written; `flush` waits for it to reach zero
+    pending: AtomicUsize,
+    // Recorded analyses waiting for their `/similar` embedding, by id
+    embeds: mpsc::Sender<(i64, AnalyzeTxResponse)>,
+    // Taken by the embedder task
+    embed_receiver: Mutex<Option<mpsc::Receiver<(i64, AnalyzeTxResponse)>>>,
+}
+
+impl HistoryQueue {
+    pub fn new(capacity: usize, batch_size: usize, full: QueueFull) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let (embeds, embed_receiver) = mpsc::channel(capacity.max(1));
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            full,
+            batch_size: batch_size.max(1),
+            pending: AtomicUsize::new(0),
+            embeds,
+            embed_receiver: Mutex::new(Some(embed_receiver)),
+        }
+    }
+
+    async fn enqueue(&self, write: PendingWrite, metrics: &Metrics) {
+        let tx_hash = write.analysis.tx_hash.clone();
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let queued = match self.full {
+            QueueFull::Wait => self.sender.send(write).await.is_ok(),
+            QueueFull::Drop => self.sender.try_send(write).is_ok(),
+        };
+        if !queued {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            tracing::warn!("History queue is full; dropped the analysis of {}", tx_hash);
+            Metrics::inc(&metrics.history_dropped);
+        }
+    }
+
+    /// Waits until everything queued so far is written, for at most `timeout`.
+    /// Returns whether it was.
+    pub async fn flush(&self, timeout: Duration) -> bool {
+        let drained = async {
+            while self.pending.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(timeout, drained).await.is_ok()
+    }
+}
+
+/// Records the analysis in history. `block` (hash and number) marks a
+/// provisional result to track for reorgs once it's stored. An analysis that queues a webhook
+/// is written before this returns, with its outbox row in the same
+/// transaction, so its delivery is never dropped by a full queue nor lost
+/// in a crash while queued. The rest go through the queue.
+pub async fn record(
+    state: &AppState,
+    analysis: &AnalyzeTxResponse,
+    notify: bool,
+    block: Option<(&str, u64)>,
+) {
+    let write = PendingWrite {
+        analysis: analysis.clone(),
+        notify,
+        block: block.map(|(hash, number)| (hash.to_string(), number)),
+    };
+    if notify {
+        write_batch(state, std::slice::from_ref(&write)).await;
+    } else {
+        state.history.enqueue(write, &state.metrics).await;
+    }
+}
+
+/// Spawns the history writer, and the embedder behind it. Each batch is one
+/// store transaction; a failed batch is logged and counted, and not retried
+/// (it holds no webhook deliveries, which `record` writes itself).
+pub fn start_writer(state: &SharedState) {
+    let Some(mut receiver) = state.history.receiver.lock().unwrap().take() else {
+        return;
+    };
+    let writer_state = state.clone();
+    tokio::spawn(async move {
+        let state = writer_state;
+        let queue = &state.history;
+        let mut batch = Vec::with_capacity(queue.batch_size);
+        while receiver.recv_many(&mut batch, queue.batch_size).await > 0 {
+            write_batch(&state, &batch).await;
+            queue.pending.fetch_sub(batch.len(), Ordering::SeqCst);
+            batch.clear();
+        }
+    });
+
+    // An LLM call per analysis, so on a task of its own: the writer moves on
+    // to the next batch without waiting for the model
+    let Some(mut embeds) = state.history.embed_receiver.lock().unwrap().take() else {
+        return;
+    };
+    let state = state.clone();
+    tokio::spawn(async move {
+        while let Some((id, analysis)) = embeds.recv().await {
+            similarity::embed_analysis(&state, id, &analysis).await;
+        }
+    });
+}
+
+async fn write_batch(state: &AppState, batch: &[PendingWrite]) {
+    let rows: Vec<(&AnalyzeTxResponse, bool)> =
+        batch.iter().map(|write| (&write.analysis, write.notify)).collect();
+    let ids = match state.store.record_analyses(&rows).await {
+        Ok(ids) => ids,
+        Err(e) => {
+            tracing::warn!("Failed to record a batch of {} analyses: {}", batch.len(), e);
+            state.metrics.history_dropped.fetch_add(batch.len() as u64, Ordering::Relaxed);
+            return;
+        }
+    };
+
+    for (write, id) in batch.iter().zip(ids) {
+        let analysis = &write.analysis;
+        if let Some((block_hash, block_number)) = &write.block {
+            let tracked = state
+                .store
+                .track_provisional(
+                    id,
+                    analysis.network,
+                    &analysis.tx_hash,
+                    block_hash,
+                    *block_number,
+                )
+                .await;
+            if let Err(e) = tracked {
+                tracing::warn!("Failed to track provisional analysis {}: {}", id, e);
+            }
+        }
+        // Best effort: a backed-up model only costs `/similar` some matches
+        if similarity::enabled(state)
+            && state.history.embeds.try_send((id, analysis.clone())).is_err()
+        {
+            tracing::warn!("Embedding queue is full; skipped the analysis of {}", analysis.tx_hash);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::{self, MockChain, MockLlm};
+
+    fn config(capacity: usize, batch_size: usize, full: QueueFull) -> crate::config::Config {
+        let mut config = testing::config();
+        config.history_queue_capacity = capacity;
+        config.history_batch_size = batch_size;
+        config.history_queue_full = full;
+        config
+    }
+
+    async fn stored(state: &AppState) -> i64 {
+        state.store.history_extent().await.unwrap().0
+    }
+
+    #[tokio::test]
+    async fn writes_queued_analyses_in_batches() {
+        let config = config(10, 3, QueueFull::Wait);
+        let state = testing::state_without_writer(config, MockChain::default(), None);
+        for i in 0..7 {
+            let analysis = testing::analysis(&format!("0x{:064x}", i)).await;
+            record(&state, &analysis, false, None).await;
+        }
+        assert_eq!(stored(&state).await, 0);
+
+        start_writer(&state);
+        assert!(state.history.flush(Duration::from_secs(5)).await);
+        assert_eq!(stored(&state).await, 7);
+        assert_eq!(state.metrics.history_dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn drops_and_counts_overflow() {
+        let config = config(2, 50, QueueFull::Drop);
+        let state = testing::state_without_writer(config, MockChain::default(), None);
+        for i in 0..5 {
+            let analysis = testing::analysis(&format!("0x{:064x}", i)).await;
+            record(&state, &analysis, false, None).await;
+        }
+        assert_eq!(state.metrics.history_dropped.load(Ordering::Relaxed), 3);
+
+        start_writer(&state);
+        assert!(state.history.flush(Duration::from_secs(5)).await);
+        assert_eq!(stored(&state).await, 2);
+    }
+
+    #[tokio::test]
+    async fn never_drops_webhook_deliveries() {
+        let config = config(1, 50, QueueFull::Drop);
+        let state = testing::state_without_writer(config, MockChain::default(), None);
+        for i in 0..3 {
+            let analysis = testing::analysis(&format!("0x{:064x}", i)).await;
+            record(&state, &analysis, true, None).await;
+        }
+        // Written without the writer, each with its outbox row
+        assert_eq!(stored(&state).await, 3);
+        assert_eq!(state.store.outbox_counts().await.unwrap(), (3, 0));
+        assert_eq!(state.metrics.history_dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn embeds_recorded_analyses() {
+        let mut config = config(10, 50, QueueFull::Wait);
+        config.embeddings_enabled = true;
+        let llm = MockLlm::transfer();
+        let embeddings = llm.embeddings.clone();
+        let state = testing::state_with(config, MockChain::default(), Some(llm));
+        record(&state, &testing::analysis(&format!("0x{:064x}", 1)).await, false, None).await;
+        assert!(state.history.flush(Duration::from_secs(5)).await);
+
+        for _ in 0..100 {
+            if embeddings.load(Ordering::SeqCst) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("analysis wasn't embedded");
+    }
+}
@@ -0,0 +1,282 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use thiserror::Error;
+use tokio::sync::{Mutex, mpsc};
+
+use crate::fields::FieldSelection;
+use crate::models::{AnalyzeTxRequest, JobProgress, JobStatus, ReanalyzeHistoryRequest};
+use crate::network::Network;
+use crate::pipeline::{self, Replay};
+use crate::state::SharedState;
+use crate::store::StoreError;
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("Job queue is full; retry shortly")]
+    QueueFull,
+    #[error("History re-analysis job {0} is already running")]
+    ReanalysisRunning(i64),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+// Stored analyses fetched per store round-trip during a history re-analysis
+const REANALYSIS_PAGE_SIZE: usize = 100;
+
+struct Job {
+    id: i64,
+    request: AnalyzeTxRequest,
+}
+
+/// In-memory queue of analysis jobs, drained by a fixed pool of workers.
+/// Job status and results are persisted in the store, so polling doesn't
+/// depend on which worker picked a job up.
+pub struct JobQueue {
+    sender: mpsc::Sender<Job>,
+    // Workers take turns receiving; only the one holding the lock waits on the channel
+    receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+    // The running history re-analysis, if any: its job id and cancel flag
+    reanalysis: Mutex<Option<(i64, Arc<AtomicBool>)>>,
+}
+
+impl JobQueue {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        Self {
+            sender,
+            receiver: Arc::new(Mutex::new(receiver)),
+            reanalysis: Mutex::new(None),
+        }
+    }
+
+    /// Records the job as `queued` and hands it to the workers.
+    pub async fn submit(
+        &self,
+        state: &SharedState,
+        request: AnalyzeTxRequest,
+    ) -> Result<i64, JobError> {
+        // Reserve first so a full queue doesn't leave a job row that never runs
+        let permit = self.sender.try_reserve().map_err(|_| JobError::QueueFull)?;
+        let id = state.store.create_job(&request).await?;
+        permit.send(Job { id, request });
+        Ok(id)
+    }
+
+    /// Starts re-analyzing the latest stored analysis of every tx in history
+    /// with the current pipeline, in the background, and returns the job id.
+    /// One runs at a time.
+    pub async fn start_reanalysis(
+        &self,
+        state: &SharedState,
+        request: ReanalyzeHistoryRequest,
+    ) -> Result<i64, JobError> {
+        let mut running = self.reanalysis.lock().await;
+        if let Some((id, _)) = running.as_ref() {
+            return Err(JobError::ReanalysisRunning(*id));
+        }
+        let (until, total) = state.store.history_extent().await?;
+        let id = state.store.create_reanalysis_job(total).await?;
+        let cancel = Arc::new(AtomicBool::new(false));
+        *running = Some((id, cancel.clone()));
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            reanalyze_history(&state, id, request, until, total, &cancel).await;
+            *state.jobs.reanalysis.lock().await = None;
+        });
+        Ok(id)
+    }
+
+    /// Asks the running history re-analysis `id` to stop after the tx in
+    /// flight. False when no such job is running.
+    pub async fn cancel(&self, id: i64) -> bool {
+        match self.reanalysis.lock().await.as_ref() {
+            Some((running, cancel)) if *running == id => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Spawns `workers` background tasks processing the job queue.
+pub async fn start_workers(state: &SharedState, workers: usize) {
+    match state.store.fail_interrupted_jobs().await {
+        Ok(0) => {}
+        Ok(n) => tracing::warn!(
+            "Marked {} job(s) left over from a previous run as failed",
+            n
+        ),
+        Err(e) => tracing::warn!("Failed to clean up interrupted jobs: {}", e),
+    }
+
+    for worker in 0..workers.max(1) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let job = state.jobs.receiver.lock().await.recv().await;
+                let Some(job) = job else { break };
+                run(&state, worker, job).await;
+            }
+        });
+    }
+}
+
+#[tracing::instrument(
+    name = "job.run",
+    skip_all,
+    fields(
+        worker,
+        job_id = job.id,
+        network = %job.request.network(),
+        tx_hash = %job.request.tx_hash,
+    )
+)]
+async fn run(state: &SharedState, worker: usize, job: Job) {
+    let store = &state.store;
+    if let Err(e) = store
+        .update_job(job.id, JobStatus::Running, None, None)
+        .await
+    {
+        tracing::warn!("Failed to mark job {} running: {}", job.id, e);
+    }
+
+    // No timeout: jobs exist for analyses too slow for a synchronous request
+    let fields = FieldSelection::default();
+    let update = match pipeline::analyze(state, &job.request, &fields, None).await {
+        Ok(mut analysis) => {
+            // Job results are stored, and the prompt never is
+            analysis.prompt = None;
+            store
+                .update_job(job.id, JobStatus::Done, Some(&analysis), None)
+                .await
+        }
+        Err(e) => {
+            tracing::info!("Job {} failed on worker {}: {}", job.id, worker, e);
+            store
+                .update_job(job.id, JobStatus::Failed, None, Some(e.to_string()))
+                .await
+        }
+    };
+    if let Err(e) = update {
+        tracing::warn!("Failed to record result of job {}: {}", job.id, e);
+    }
+}
+
+// Re-analyzes txs one at a time (it's background work, so it leaves the
+// network's capacity to live traffic), recording progress after each.
+#[tracing::instrument(name = "job.reanalyze_history", skip_all, fields(job_id = id))]
+async fn reanalyze_history(
+    state: &SharedState,
+    id: i64,
+    request: ReanalyzeHistoryRequest,
+    until: i64,
+    total: u64,
+    cancel: &AtomicBool,
+) {
+    let store = &state.store;
+    let replay = Replay {
+        heuristic_only: request.heuristic_only,
+    };
+    let mut progress = JobProgress {
+        total,
+        ..JobProgress::default()
+    };
+    let mut after = 0;
+    let mut status = JobStatus::Done;
+    let mut error = None;
+
+    'pages: loop {
+        let page = match store.history_targets(after, until, REANALYSIS_PAGE_SIZE).await {
+            Ok(page) if page.is_empty() => break,
+            Ok(page) => page,
+            Err(e) => {
+                status = JobStatus::Failed;
+                error = Some(e.to_string());
+                break;
+            }
+        };
+        for target in page {
+            if cancel.load(Ordering::Relaxed) {
+                status = JobStatus::Cancelled;
+                break 'pages;
+            }
+            after = target.analysis_id;
+
+            let result = match target.network.parse::<Network>() {
+                Ok(network) => {
+                    pipeline::reanalyze(state, network, &target.tx_hash, request.deep, replay)
+                        .await
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            match result {
+                Ok(analysis) => {
+                    let changed = analysis.tx_type != target.tx_type
+                        || analysis.risk_score != target.risk_score;
+                    if changed {
+                        progress.changed += 1;
+                    }
+                    if let Err(e) = store.replace_analysis(target.analysis_id, &analysis).await {
+                        tracing::warn!("Failed to update analysis {}: {}", target.analysis_id, e);
+                        progress.failed += 1;
+                    }
+                }
+                Err(e) => {
+                    tracing::info!("Re-analysis of {} failed: {}", target.tx_hash, e);
+                    progress.failed += 1;
+                }
+            }
+            progress.processed += 1;
+            if let Err(e) = store.update_job_progress(id, progress).await {
+                tracing::warn!("Failed to record progress of job {}: {}", id, e);
+            }
+        }
+    }
+
+    tracing::info!(
+        "History re-analysis {}: {} of {} processed, {} changed, {} failed",
+        status.as_str(),
+        progress.processed,
+        progress.total,
+        progress.changed,
+        progress.failed
+    );
+    if let Err(e) = store.update_job(id, status, None, error).await {
+        tracing::warn!("Failed to record result of job {}: {}", id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use crate::testing::{self, MockChain, MockLlm, TRANSFER_HASH};
+
+    #[tokio::test]
+    async fn job_results_leave_out_the_prompt() {
+        let state = testing::state(MockChain::transfer(), Some(MockLlm::transfer()));
+        super::start_workers(&state, 1).await;
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "include_prompt": true });
+        let (status, accepted) = testing::send(&state, testing::post_json("/jobs", payload)).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", accepted);
+
+        let uri = format!("/jobs/{}", accepted["job_id"]);
+        for _ in 0..100 {
+            let (_, job) = testing::send(&state, testing::get(&uri)).await;
+            if job["status"] == "done" {
+                assert_eq!(job["result"]["tx_type"], "TRANSFER");
+                assert!(job["result"].get("prompt").is_none(), "{}", job);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("job didn't finish");
+    }
+}
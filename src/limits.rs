@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::NetworkConfig;
+use crate::network::Network;
+
+/// The network has no free analysis slot within the wait window.
+#[derive(Debug)]
+pub struct Saturated;
+
+struct Limit {
+    // Shared so permits can be owned, e.g. by a streaming response that outlives the handler
+    semaphore: Arc<Semaphore>,
+    max: usize,
+}
+
+/// One semaphore per network, so backpressure on one chain's RPC only
+/// queues requests for that chain.
+pub struct NetworkLimits {
+    limits: HashMap<Network, Limit>,
+    wait: Duration,
+}
+
+impl NetworkLimits {
+    pub fn new(networks: &HashMap<Network, NetworkConfig>, wait: Duration) -> Self {
+        let limits = networks
+            .iter()
+            .map(|(network, config)| {
+                let max = config.max_concurrency.max(1);
+                (*network, Limit { semaphore: Arc::new(Semaphore::new(max)), max })
+            })
+            .collect();
+        Self { limits, wait }
+    }
+
+    /// Takes `slots` in-flight slots on `network` (capped at the network's limit),
+    /// waiting up to the configured window.
+    pub async fn acquire(
+        &self,
+        network: Network,
+        slots: usize,
+    ) -> Result<Option<OwnedSemaphorePermit>, Saturated> {
+        let Some(limit) = self.limits.get(&network) else {
+            return Ok(None);
+        };
+
+        let slots = slots.clamp(1, limit.max) as u32;
+        match tokio::time::timeout(self.wait, limit.semaphore.clone().acquire_many_owned(slots)).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            // Timed out (the semaphore is never closed)
+            _ => Err(Saturated),
+        }
+    }
+
+    /// In-flight slot count per network, sorted by network.
+    pub fn in_flight(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<(&'static str, u64)> = self
+            .limits
+            .iter()
+            .map(|(network, limit)| {
+                let used = limit.max - limit.semaphore.available_permits();
+                (network.as_str(), used as u64)
+            })
+            .collect();
+        counts.sort();
+        counts
+    }
+}
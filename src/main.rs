@@ -1,26 +1,108 @@
-use axum::{
-    routing::{get, post},
-    Router,
-};
-
 use std::net::SocketAddr;
-use tracing_subscriber::EnvFilter;
+use std::time::Duration;
 
+mod alerts;
+mod config;
+mod deadline;
+mod emitter;
+mod encoding;
+mod error;
+mod eval;
+mod fields;
+mod history;
+mod jobs;
+mod limits;
+mod metrics;
+mod network;
 mod routes;
 mod models;
+mod pipeline;
+mod redact;
+mod reorg;
+mod secrets;
+mod selftest;
 mod services;
+mod shedding;
+mod state;
+mod store;
+mod telemetry;
+#[cfg(test)]
+mod testing;
+mod timing;
+mod util;
+mod webhook;
+
+// How long shutdown waits for queued analyses to be written to history
+const HISTORY_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() {
-    // Setup tracing / logging
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+    dotenvy::dotenv().ok();
+    // Before the config reads them; logging isn't set up yet
+    let resolved_secrets = match secrets::resolve_env().await {
+        Ok(count) => count,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let config = config::Config::from_env();
+    if !config.errors.is_empty() {
+        for error in &config.errors {
+            eprintln!("{}", error);
+        }
+        std::process::exit(1);
+    }
+
+    // Setup tracing / logging (+ optional OTLP export)
+    let redactor = redact::Redactor::from_config(&config).ok().flatten();
+    let tracer_provider = telemetry::init(config.otlp_endpoint.as_deref(), redactor);
+    if resolved_secrets > 0 {
+        tracing::info!("Resolved {} secret reference(s)", resolved_secrets);
+    }
+    for warning in &config.warnings {
+        tracing::warn!("{}", warning);
+    }
+
+    if config.safe_mode {
+        tracing::info!("Safe mode enabled: unverified contracts cap confidence");
+    }
+    // --self-test: one-shot check for CI/deploys, without starting the server
+    if std::env::args().any(|arg| arg == "--self-test") {
+        // Fixture analyses shouldn't land in the real history
+        let store = store::Store::open(":memory:").expect("failed to open history store");
+        let state = state::AppState::new(config, store);
+        history::start_writer(&state);
+        let passed = selftest::run(&state).await;
+        if let Some(provider) = tracer_provider {
+            let _ = provider.shutdown();
+        }
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    // --eval <dataset.json>: labeled accuracy report, without starting the server
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--eval") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("Usage: --eval <dataset.json>");
+            std::process::exit(2);
+        };
+        let ran = eval::run(config, path).await;
+        if let Some(provider) = tracer_provider {
+            let _ = provider.shutdown();
+        }
+        std::process::exit(if ran { 0 } else { 1 });
+    }
+
+    let store = store::Store::open(&config.history_db_path).expect("failed to open history store");
+    let state = state::AppState::new(config, store);
+    jobs::start_workers(&state, state.config.job_workers).await;
+    webhook::start_dispatcher(&state);
+    history::start_writer(&state);
+    emitter::start_publisher(&state);
+    reorg::start_monitor(&state);
 
-    // Build router
-    let app = Router::new()
-        .route("/health", get(routes::health))
-        .route("/analyze_tx", post(routes::analyze_tx));
+    let app = routes::router(state.clone());
 
     // Bind address
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
@@ -31,6 +113,26 @@ async fn main() {
         .expect("failed to bind");
 
     axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .expect("server failed");
+
+    // Analyses of the last requests may still be waiting to be recorded
+    if !state.history.flush(HISTORY_FLUSH_TIMEOUT).await {
+        tracing::warn!("Shut down before every analysis was recorded in history");
+    }
+
+    // Flush any spans still buffered in the batch exporter
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        eprintln!("Failed to shut down tracer provider: {}", e);
+    }
+}
+
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("failed to listen for shutdown signal");
+    tracing::info!("Shutting down");
 }
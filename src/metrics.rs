@@ -0,0 +1,183 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Process-wide counters, rendered in Prometheus text format on `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    pub llm_sampled_in: AtomicU64,
+    pub llm_sampled_out: AtomicU64,
+    pub llm_skipped_known_safe: AtomicU64,
+    pub deadline_degraded: AtomicU64,
+    pub requests_cancelled: AtomicU64,
+    pub requests_saturated: AtomicU64,
+    pub requests_shed: AtomicU64,
+    // 1 while shedding; refreshed when `/metrics` is scraped
+    pub load_shedding: AtomicU64,
+    pub reorgs_detected: AtomicU64,
+    pub emitted: AtomicU64,
+    // Dropped on a full queue, or failed to publish
+    pub emit_failures: AtomicU64,
+    // Dropped on a full history queue, or failed to write
+    pub history_dropped: AtomicU64,
+    // Refreshed by the webhook dispatcher on each poll
+    pub webhook_outbox_depth: AtomicU64,
+    pub webhook_dead_letters: AtomicU64,
+}
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Per-network and per-endpoint series owned elsewhere (limits, RPC
+    // clients) are passed in
+    pub fn render(&self, networks: &[NetworkSeries], endpoints: &[EndpointSeries]) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "analyzer_llm_sampled_in_total",
+            "Requests selected for LLM analysis by sampling",
+            &self.llm_sampled_in,
+        );
+        counter(
+            &mut out,
+            "analyzer_llm_sampled_out_total",
+            "Requests served heuristic-only by sampling",
+            &self.llm_sampled_out,
+        );
+        counter(
+            &mut out,
+            "analyzer_llm_skipped_known_safe_total",
+            "Requests served heuristic-only because the counterparty is allowlisted",
+            &self.llm_skipped_known_safe,
+        );
+        counter(
+            &mut out,
+            "analyzer_deadline_degraded_total",
+            "Analyses that dropped optional stages to meet RESPONSE_DEADLINE_MS",
+            &self.deadline_degraded,
+        );
+        counter(
+            &mut out,
+            "analyzer_requests_cancelled_total",
+            "Analyses abandoned because the client disconnected",
+            &self.requests_cancelled,
+        );
+        counter(
+            &mut out,
+            "analyzer_requests_saturated_total",
+            "Requests rejected with 503 because their network was at its concurrency limit",
+            &self.requests_saturated,
+        );
+        counter(
+            &mut out,
+            "analyzer_requests_shed_total",
+            "Requests rejected with 503 by load shedding",
+            &self.requests_shed,
+        );
+        gauge(
+            &mut out,
+            "analyzer_load_shedding",
+            "1 while recent latency or errors are over the load-shedding thresholds",
+            &self.load_shedding,
+        );
+        counter(
+            &mut out,
+            "analyzer_reorgs_detected_total",
+            "Provisional analyses invalidated because their tx was reorged out",
+            &self.reorgs_detected,
+        );
+        counter(
+            &mut out,
+            "analyzer_emitted_total",
+            "Analyses published to the message bus (EMITTER_BACKEND)",
+            &self.emitted,
+        );
+        counter(
+            &mut out,
+            "analyzer_emit_failures_total",
+            "Analyses not published: dropped on a full emitter queue, or the publish failed",
+            &self.emit_failures,
+        );
+        counter(
+            &mut out,
+            "analyzer_history_dropped_total",
+            "Analyses not recorded in history: dropped on a full queue, or the write failed",
+            &self.history_dropped,
+        );
+        gauge(
+            &mut out,
+            "analyzer_webhook_outbox_depth",
+            "Webhook deliveries waiting to be sent or retried",
+            &self.webhook_outbox_depth,
+        );
+        gauge(
+            &mut out,
+            "analyzer_webhook_dead_letters",
+            "Webhook deliveries given up on after the maximum number of attempts",
+            &self.webhook_dead_letters,
+        );
+        for series in networks {
+            series.render(&mut out);
+        }
+        for series in endpoints {
+            series.render(&mut out);
+        }
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+}
+
+/// A metric with one value per network.
+pub struct NetworkSeries<'a> {
+    pub name: &'static str,
+    pub help: &'static str,
+    // "gauge" or "counter"
+    pub kind: &'static str,
+    pub values: Vec<(&'a str, u64)>,
+}
+
+impl NetworkSeries<'_> {
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (network, value) in &self.values {
+            let _ = writeln!(out, "{}{{network=\"{}\"}} {}", self.name, network, value);
+        }
+    }
+}
+
+/// A metric with one value per RPC endpoint, labelled by network and the
+/// endpoint's position in its RPC_URL list.
+pub struct EndpointSeries<'a> {
+    pub name: &'static str,
+    pub help: &'static str,
+    // "gauge" or "counter"
+    pub kind: &'static str,
+    pub values: Vec<(&'a str, usize, u64)>,
+}
+
+impl EndpointSeries<'_> {
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (network, endpoint, value) in &self.values {
+            let _ = writeln!(
+                out,
+                "{}{{network=\"{}\",endpoint=\"{}\"}} {}",
+                self.name, network, endpoint, value
+            );
+        }
+    }
+}
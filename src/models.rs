@@ -1,15 +1,592 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+use crate::network::{Network, UnknownNetwork};
+
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct AnalyzeTxRequest {
-    pub network: String,
+    // Falls back to DEFAULT_NETWORK when omitted; handlers fill it in (see `network()`)
+    #[serde(default)]
+    pub network: Option<Network>,
+    pub tx_hash: String,
+    // Deep mode also fetches the internal call trace (requires a tracing node)
+    #[serde(default)]
+    pub deep: bool,
+    // LLM model for this call; must be in the configured allowlist
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: ExplanationFormat,
+    #[serde(default)]
+    pub verbosity: ExplanationVerbosity,
+    #[serde(default)]
+    pub persona: ExplanationPersona,
+    // Majority vote over LLM_CONSENSUS_SAMPLES LLM calls; multiplies LLM cost
+    #[serde(default)]
+    pub consensus: bool,
+    // Adds `decision_trace`, the step-by-step reasoning behind the result
+    #[serde(default)]
+    pub include_trace: bool,
+    // Adds `timing`, the time spent in each stage of the analysis
+    #[serde(default)]
+    pub include_timing: bool,
+    // Adds `prompt`, the prompt sent to the LLM, with secrets redacted
+    #[serde(default)]
+    pub include_prompt: bool,
+    // Cap on the LLM's completion tokens (default LLM_MAX_TOKENS, at most
+    // LLM_MAX_TOKENS_CEILING)
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+impl AnalyzeTxRequest {
+    /// The request's network; handlers resolve an omitted one before anything
+    /// past them sees the request.
+    pub fn network(&self) -> Network {
+        self.network.expect("network is resolved by the request handler")
+    }
+
+    /// Accepts an explorer link pasted as `tx_hash` (e.g.
+    /// `https://etherscan.io/tx/0x...`): the hash is taken from the link, and
+    /// the network from the explorer. A `network` sent along must match it.
+    pub fn expand_explorer_link(&mut self) -> Result<(), String> {
+        let Some((network, tx_hash)) = parse_explorer_link(&self.tx_hash)? else {
+            return Ok(());
+        };
+        if let Some(requested) = self.network
+            && requested != network
+        {
+            return Err(format!(
+                "`network` is {} but the explorer link is for {}",
+                requested, network
+            ));
+        }
+        self.network = Some(network);
+        self.tx_hash = tx_hash;
+        Ok(())
+    }
+}
+
+// Explorer tx-page prefixes (host and path, without "www.") and the network
+// each is for. Networks not supported here are named for the error message.
+const EXPLORER_LINKS: &[(&str, &str)] = &[
+    ("etherscan.io/tx/", "ethereum-mainnet"),
+    ("eth.blockscout.com/tx/", "ethereum-mainnet"),
+    ("ethplorer.io/tx/", "ethereum-mainnet"),
+    ("blockchair.com/ethereum/transaction/", "ethereum-mainnet"),
+    ("app.blocksec.com/explorer/tx/eth/", "ethereum-mainnet"),
+    ("dashboard.tenderly.co/tx/mainnet/", "ethereum-mainnet"),
+    ("sepolia.etherscan.io/tx/", "ethereum-sepolia"),
+    ("holesky.etherscan.io/tx/", "ethereum-holesky"),
+    ("optimistic.etherscan.io/tx/", "optimism-mainnet"),
+    ("arbiscan.io/tx/", "arbitrum-one"),
+    ("basescan.org/tx/", "base-mainnet"),
+    ("polygonscan.com/tx/", "polygon-mainnet"),
+    ("bscscan.com/tx/", "bnb-mainnet"),
+    ("snowtrace.io/tx/", "avalanche-c-chain"),
+];
+
+// (network, tx hash) of an explorer link; `None` when `input` isn't a link
+// at all, but a plain hash
+fn parse_explorer_link(input: &str) -> Result<Option<(Network, String)>, String> {
+    let input = input.trim();
+    if !input.contains('/') {
+        return Ok(None);
+    }
+    let link = input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input);
+    // Query strings and fragments (`#eventlog`) don't matter
+    let link = link.split(['?', '#']).next().unwrap_or_default();
+    let (host, path) = link.split_once('/').unwrap_or((link, ""));
+    let host = host.to_ascii_lowercase();
+    let page = format!("{}/{}", host.strip_prefix("www.").unwrap_or(&host), path);
+
+    let Some((prefix, network)) = EXPLORER_LINKS
+        .iter()
+        .find(|(prefix, _)| page.starts_with(prefix))
+    else {
+        return Err(format!(
+            "Unrecognized explorer link '{}'; expected a tx page such as \
+             https://etherscan.io/tx/0x...",
+            input
+        ));
+    };
+    let tx_hash = page[prefix.len()..].trim_end_matches('/');
+    let is_hash = tx_hash.len() == 66
+        && tx_hash.starts_with("0x")
+        && tx_hash[2..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hash {
+        return Err(format!("No tx hash in explorer link '{}'", input));
+    }
+    let network = network.parse().map_err(|e: UnknownNetwork| e.to_string())?;
+    Ok(Some((network, tx_hash.to_string())))
+}
+
+/// A tx the client already has the data for (e.g. from a log indexer),
+/// analyzed without any RPC fetch.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnalyzeLogsRequest {
+    pub network: Network,
     pub tx_hash: String,
+    pub from: String,
+    // `None` for contract creations
+    pub to: Option<String>,
+    // Wei, as a decimal or 0x-prefixed hex string
+    pub value: String,
+    pub logs: Vec<ProvidedLog>,
+    // Calldata, when known; enables method decoding and calldata rules
+    #[serde(default)]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: ExplanationFormat,
+    #[serde(default)]
+    pub verbosity: ExplanationVerbosity,
+    #[serde(default)]
+    pub persona: ExplanationPersona,
+    // As for `/analyze_tx`
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProvidedLog {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+/// `/decode` input: calldata and/or logs, optionally with the ABI or
+/// signature to decode them with.
+#[derive(Debug, Deserialize)]
+pub struct DecodeRequest {
+    #[serde(default)]
+    pub data: Option<String>,
+    #[serde(default)]
+    pub logs: Vec<ProvidedLog>,
+    // JSON ABI (array of entries), e.g. as published by the explorer
+    #[serde(default)]
+    pub abi: Option<Vec<serde_json::Value>>,
+    // Canonical function signature for `data`, e.g. "transfer(address,uint256)"
+    #[serde(default)]
+    pub signature: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
+pub struct DecodeResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub call: Option<DecodedCallInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<DecodedEventInfo>,
+}
+
+/// Where a decode's signature came from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureSource {
+    Signature,
+    Abi,
+    // The contract's verified ABI, fetched from the explorer (ABI_DECODING)
+    Explorer,
+    Bundled,
+}
+
+// `params` is set when decoding worked; otherwise `raw` holds the argument bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedCallInfo {
+    pub selector: Option<String>,
+    pub signature: Option<String>,
+    pub signature_source: Option<SignatureSource>,
+    pub params: Option<Vec<DecodedParam>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedParam {
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub value: serde_json::Value,
+}
+
+// The raw topics and data are always echoed back, for logs that didn't decode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedEventInfo {
+    pub address: String,
+    pub name: Option<String>,
+    pub signature: Option<String>,
+    pub signature_source: Option<SignatureSource>,
+    // Bundled signatures don't say which params are indexed; the first ones are assumed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub indexed_inferred: bool,
+    pub params: Option<Vec<DecodedParam>>,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+/// Where the analyzed tx data came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSource {
+    #[default]
+    Rpc,
+    // No RPC endpoint configured for the network
+    Mock,
+    // Sent in the request (`/analyze_logs`)
+    ClientProvided,
+}
+
+/// How long `natural_language_explanation` is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExplanationVerbosity {
+    // One line, for tooltips and notifications
+    Terse,
+    #[default]
+    Normal,
+    // Several paragraphs, for full reports
+    Detailed,
+}
+
+/// How `natural_language_explanation` is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExplanationFormat {
+    #[default]
+    Plain,
+    // Sections and bullet points, for reports and chat
+    Markdown,
+}
+
+/// Who `natural_language_explanation` is written for, and so what it dwells on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExplanationPersona {
+    // A general reader, with no particular focus
+    #[default]
+    Neutral,
+    // The calls, calldata, events and gas
+    Developer,
+    // Execution price, slippage, fees, and profit or loss
+    Trader,
+    // Counterparties, the flow of funds, and regulatory red flags
+    Compliance,
+    // Someone new to crypto: no jargon
+    Newbie,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AnalyzeTxQuery {
+    // Comma-separated response fields to return (default: all)
+    pub fields: Option<String>,
+    // Response schema version ("1", "2"); overrides a versioned `Accept`
+    pub schema_version: Option<String>,
+}
+
+/// Query of `GET /analyze_tx/{network}/{tx_hash}`: the POST body's options
+/// that keep the result a function of the URL.
+#[derive(Debug, Default, Deserialize)]
+pub struct AnalyzeTxGetQuery {
+    pub fields: Option<String>,
+    pub schema_version: Option<String>,
+    #[serde(default)]
+    pub deep: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: ExplanationFormat,
+    #[serde(default)]
+    pub verbosity: ExplanationVerbosity,
+    #[serde(default)]
+    pub persona: ExplanationPersona,
+    #[serde(default)]
+    pub include_trace: bool,
+    #[serde(default)]
+    pub include_timing: bool,
+    #[serde(default)]
+    pub include_prompt: bool,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyzeBatchRequest {
+    pub network: Network,
+    pub tx_hashes: Vec<String>,
+    #[serde(default)]
+    pub deep: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: ExplanationFormat,
+    #[serde(default)]
+    pub verbosity: ExplanationVerbosity,
+    #[serde(default)]
+    pub persona: ExplanationPersona,
+    // As for `/analyze_tx`
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyzeBatchResponse {
+    pub network: Network,
+    pub results: Vec<BatchItem>,
+    pub summary: BatchProgress,
+}
+
+// Exactly one of `analysis` / `error` is set
+#[derive(Debug, Serialize)]
+pub struct BatchItem {
+    pub tx_hash: String,
+    pub analysis: Option<AnalyzeTxResponse>,
+    pub error: Option<String>,
+}
+
+/// MEV bundle: txs meant to execute atomically, in order, analyzed as a unit.
+#[derive(Debug, Deserialize)]
+pub struct AnalyzeBundleRequest {
+    pub network: Network,
+    pub txs: Vec<BundleTx>,
+    #[serde(default)]
+    pub deep: bool,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub format: ExplanationFormat,
+    #[serde(default)]
+    pub verbosity: ExplanationVerbosity,
+    #[serde(default)]
+    pub persona: ExplanationPersona,
+    // As for `/analyze_tx`
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+/// A bundle entry: the hash of a mined tx, or a signed raw tx (as sent to
+/// `eth_sendRawTransaction`) that hasn't been mined.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum BundleTx {
+    Hash(String),
+    Raw {
+        raw: String,
+        // The sender isn't recovered from the signature, so give it when known
+        #[serde(default)]
+        from: Option<String>,
+    },
+}
+
+/// MEV strategy a bundle carries out (see `services::bundle`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleStrategy {
+    Arbitrage,
+    Liquidation,
+    Sandwich,
+}
+
+/// `/analyze_bundle` result: each tx's analysis plus what the bundle does as a whole.
+#[derive(Debug, Serialize)]
+pub struct BundleReport {
+    pub network: Network,
+    // `None` when no known strategy was recognized
+    pub strategy: Option<BundleStrategy>,
+    // Sender of the bundle's first tx, taken to be the searcher
+    pub searcher: Option<String>,
+    // Txs of others that a sandwich wraps
+    pub victims: Vec<String>,
+    // The searcher's (and its contract's) ERC-20 balance changes over the
+    // whole bundle, i.e. its profit per token
+    pub net_flows: Vec<TokenFlow>,
+    pub gas_used: u64,
+    // Highest risk of any tx, raised for a sandwich
+    pub risk_score: f32,
+    pub risk_reasons: Vec<String>,
+    pub txs: Vec<BatchItem>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenFlow {
+    pub token: String,
+    // Signed amount in the token's base units, as a decimal string
+    pub net: String,
+}
+
+/// NDJSON events of a streamed `/analyze_batch` (one JSON object per line).
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BatchEvent {
+    // Sent periodically while the batch runs
+    Progress(BatchProgress),
+    Result(Box<BatchItem>),
+    // The batch as a whole failed (e.g. the chain data fetch); nothing follows
+    Error { message: String },
+    // Final counts; the last event of a successful batch
+    Done(BatchProgress),
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchProgress {
+    pub processed: usize,
+    pub total: usize,
+    // Analyses whose risk score is High or worse (>= 0.75)
+    pub matches: usize,
+    pub errors: usize,
+    // Analyses with the LLM (`ai_available`), and with heuristics only
+    pub llm_analyzed: usize,
+    pub heuristic_only: usize,
+    // Some items went without the LLM because the batch's LLM budget (or the
+    // provider's quota) ran out
+    pub llm_budget_exhausted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalyzeTxResponse {
     pub tx_hash: String,
-    pub network: String,
+    pub network: Network,
+    pub tx_type: String,
+    pub protocol: Option<String>,
+    // Canonical signature of the top-level call, when it could be resolved
+    pub method: Option<String>,
+    // Set when `to` is an EIP-1967 proxy; `method` is then resolved via the implementation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation_address: Option<String>,
+    // Set for L1 <-> L2 bridge deposits/withdrawals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bridge_details: Option<BridgeDetails>,
+    // Set for Safe `execTransaction` calls (tx_type MULTISIG_EXECUTION)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigExecution>,
+    // Set for ERC-4337 bundles (tx_type ACCOUNT_ABSTRACTION), one per UserOperation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_operations: Vec<UserOperation>,
+    // Native value sent; unset on analyses stored before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Amount>,
+    // ERC-20 transfers from the logs, in log order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub token_transfers: Vec<TokenTransfer>,
+    // Uniswap V2-style swaps from the logs, with their execution price and price impact
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub swaps: Vec<SwapInfo>,
+    // Balances the tx's transfers touch, checked against the chain (see `BalanceChange`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub balance_changes: Vec<BalanceChange>,
+    // NFT sales checked for signs that buyer and seller are one party (deep mode only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wash_trades: Vec<WashTradeCheck>,
+    // The top-level call and every log decoded by name (ABI_DECODING), with the
+    // verified ABI of the contract where the explorer has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded_call: Option<DecodedCallInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub decoded_events: Vec<DecodedEventInfo>,
+    // The tx's gas price against the network's current one; pending and
+    // recent txs only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_context: Option<GasContext>,
+    pub risk_score: f32,
+    // What `risk_score` was blended from (before rule floors)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score_components: Option<ScoreComponents>,
+    pub risk_reasons: Vec<String>,
+    // What to do about the risks: a step per risk rule that fired, then the
+    // LLM's suggestions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recommendations: Vec<Recommendation>,
+    pub confidence: f32,
+    // false when the result comes from heuristics only (LLM disabled, sampled out, or failed)
+    pub ai_available: bool,
+    // "<crate version>/<model id or heuristic>/<rules hash>"
+    pub analyzer_version: String,
+    pub contracts: Vec<ContractInfo>,
+    // Tags from the labels API (LABELS_API_URL) for the sender, recipient and
+    // contract counterparties that have any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub address_labels: Vec<AddressLabel>,
+    // Gasless approvals (EIP-2612, DAI-style, Permit2) found in the calldata
+    pub permits: Vec<PermitInfo>,
+    // Honeypot checks on tokens bought in a swap (deep mode only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub token_safety: Vec<TokenSafety>,
+    pub natural_language_explanation: String,
+    // Format, length and audience of `natural_language_explanation`
+    #[serde(default)]
+    pub format: ExplanationFormat,
+    #[serde(default)]
+    pub verbosity: ExplanationVerbosity,
+    #[serde(default)]
+    pub persona: ExplanationPersona,
+    #[serde(default)]
+    pub source: DataSource,
+    // Blocks on top of (and including) the tx's block at analysis time; unset
+    // when the data didn't come from a node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmations: Option<u64>,
+    // Fewer than FINALITY_CONFIRMATIONS: a reorg could still drop or move the tx
+    #[serde(default)]
+    pub provisional: bool,
+    // Set for failed txs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<FailureReason>,
+    // The revert message decoded by the node's tracer (deep mode only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    // false when the receipt wasn't out yet: `status` is pending, and findings
+    // that come from logs (swaps, bridge events) are missing
+    #[serde(default = "receipt_available_default")]
+    pub receipt_available: bool,
+    // Set when the request asked for `consensus` and the LLM was used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus: Option<ConsensusInfo>,
+    // Set when the request asked for `include_trace`, in the order the steps ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision_trace: Option<Vec<TraceStep>>,
+    // Optional stages dropped to meet RESPONSE_DEADLINE_MS ("llm", "trace",
+    // "token_safety", "token_transfers", "swaps", "balance_changes", "wash_trades",
+    // "gas_context", "decoded_events", "contracts", "address_labels")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deadline_skipped: Vec<String>,
+    // Set when the request asked for `include_timing`; never stored in history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<Timing>,
+    // Set when the request asked for `include_prompt` and the LLM was called;
+    // never stored in history or job results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+impl AnalyzeTxResponse {
+    /// Appends a step to `decision_trace`; a no-op when no trace was asked for.
+    pub fn trace(&mut self, stage: &str, detail: impl Into<String>, score: Option<f32>) {
+        if let Some(trace) = &mut self.decision_trace {
+            trace.push(TraceStep {
+                stage: stage.to_string(),
+                detail: detail.into(),
+                score,
+            });
+        }
+    }
+}
+
+/// Schema v1 of the analysis response, frozen at its original fields.
+/// `AnalyzeTxResponse` itself is the latest schema.
+#[derive(Debug, Serialize)]
+pub struct AnalyzeTxResponseV1 {
+    pub tx_hash: String,
+    pub network: Network,
     pub tx_type: String,
     pub protocol: Option<String>,
     pub risk_score: f32,
@@ -17,3 +594,610 @@ pub struct AnalyzeTxResponse {
     pub natural_language_explanation: String,
 }
 
+impl From<&AnalyzeTxResponse> for AnalyzeTxResponseV1 {
+    fn from(analysis: &AnalyzeTxResponse) -> Self {
+        Self {
+            tx_hash: analysis.tx_hash.clone(),
+            network: analysis.network,
+            tx_type: analysis.tx_type.clone(),
+            protocol: analysis.protocol.clone(),
+            risk_score: analysis.risk_score,
+            risk_reasons: analysis.risk_reasons.clone(),
+            natural_language_explanation: analysis.natural_language_explanation.clone(),
+        }
+    }
+}
+
+// Analyses stored before the field existed all had their receipt
+fn receipt_available_default() -> bool {
+    true
+}
+
+/// `risk_score` before rule floors is `heuristic_weight * heuristic +
+/// (1 - heuristic_weight) * llm`; without an LLM score the weight is 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreComponents {
+    pub heuristic: f32,
+    pub llm: Option<f32>,
+    pub heuristic_weight: f32,
+}
+
+/// Why a failed tx failed (see `services::failure`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureReason {
+    // Used (nearly) all of its gas limit
+    OutOfGas,
+    // A contract reverted explicitly
+    Reverted,
+    // Not enough data to tell (no gas figures and no trace)
+    Unknown,
+}
+
+/// How the LLM samples of a `consensus` request voted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsensusInfo {
+    pub samples: usize,
+    // Samples that returned a usable verdict; failed ones don't vote
+    pub valid_samples: usize,
+    // tx_type -> number of samples that chose it
+    pub votes: BTreeMap<String, usize>,
+    // Share of the samples (failed ones included) that agree with the winning tx_type
+    pub consensus_confidence: f32,
+}
+
+/// An actionable next step for the sender, such as revoking an approval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recommendation {
+    #[serde(rename = "type")]
+    pub kind: RecommendationKind,
+    pub message: String,
+    // Where to act on it, e.g. revoke.cash for the sender's approvals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_url: Option<String>,
+    // The risk rule it follows from; unset for the LLM's suggestions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendationKind {
+    RevokeApproval,
+    ReportAddress,
+    AvoidToken,
+    VerifyContract,
+    ReviewTransaction,
+    Caution,
+    // Free-text advice from the LLM
+    General,
+}
+
+/// One step of the reasoning that produced an analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceStep {
+    // "classifier", "rule", "score", "llm", "consensus", "adjustment" or "final"
+    pub stage: String,
+    pub detail: String,
+    // Risk score as it stood after this step, for steps that touch it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+}
+
+/// Wall-clock time spent in an analysis, by stage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timing {
+    pub total_ms: u64,
+    // In the order the stages finished; time between them (scoring, post-processing)
+    // only counts towards the total
+    pub stages: Vec<StageTiming>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageTiming {
+    // "fetch", "method", "multisig", "user_operations", "token_safety",
+    // "token_transfers", "swaps", "balance_changes", "wash_trades", "gas_context",
+    // "decoded_events", "contracts", "deployments", "address_labels", "llm",
+    // "heuristics" or "store"
+    pub stage: String,
+    pub ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractInfo {
+    pub address: String,
+    pub verified: bool,
+    pub name: Option<String>,
+    // From an `eth_getCode` search; unset when the node can't serve historical state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment_block: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract_age_days: Option<u64>,
+    // Tags from the labels API, e.g. "Uniswap V3: Router"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressLabel {
+    pub address: String,
+    // e.g. "Binance 14", "Exchange", "Hot Wallet"
+    pub labels: Vec<String>,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeDetails {
+    // "Optimism", "Arbitrum", "Polygon PoS"
+    pub bridge: String,
+    // "deposit" (L1 -> L2) or "withdrawal" (L2 -> L1)
+    pub direction: String,
+    pub source_chain: String,
+    pub destination_chain: String,
+    // L1 token contract; null for ETH
+    pub token: Option<String>,
+    // Raw amount as a decimal string; null when only the method identified the bridge
+    pub amount: Option<String>,
+    pub recipient: Option<String>,
+}
+
+/// An exact on-chain amount, with its display form. `wei` is in base units
+/// (wei for the native token) so clients can do precise math on it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amount {
+    // Decimal string; can exceed 64 bits
+    pub wei: String,
+    // `wei` scaled by `decimals`, e.g. "1.5"; null when the decimals are unknown
+    pub formatted: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+/// A tx's gas price next to the network's current one, to tell whether it
+/// overpaid for current conditions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasContext {
+    // Effective gas price paid (offered, while pending)
+    pub tx_gas_price_gwei: f64,
+    // eth_gasPrice: the base fee plus a typical tip
+    pub current_gas_price_gwei: f64,
+    // Unset on networks without EIP-1559
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_base_fee_gwei: Option<f64>,
+    // tx / current gas price
+    pub ratio: f64,
+    pub vs_current: GasPriceVsCurrent,
+    pub network_congestion: Congestion,
+    // Gas used / gas limit of the latest block
+    pub latest_block_utilization: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasPriceVsCurrent {
+    Above,
+    // Within 10% of the current price
+    AtCurrent,
+    Below,
+}
+
+// From how full the latest block is: up to half (the EIP-1559 target), up
+// to 90%, or more
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Congestion {
+    Low,
+    Moderate,
+    High,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    pub token: String,
+    pub from: String,
+    pub to: String,
+    // Symbol and decimals are read from the token when the network has an RPC node
+    pub amount: Amount,
+}
+
+/// A swap on a Uniswap V2-style pool (V2 itself, or a fork such as SushiSwap),
+/// with its execution price next to the pool's price just before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapInfo {
+    pub pool: String,
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: Amount,
+    pub amount_out: Amount,
+    // token_out received per token_in, in whole tokens; null when either
+    // token's decimals are unknown
+    pub effective_price: Option<f64>,
+    // The pool's price from its reserves before the swap, in the same unit
+    pub spot_price: Option<f64>,
+    // How much worse than the spot price the swap executed, pool fee included;
+    // null when the pool's reserves before the swap couldn't be read
+    pub price_impact_pct: Option<f64>,
+}
+
+/// How one balance moved across the tx's block, next to what the tx's own
+/// transfers account for. Changes are signed decimal base units.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceChange {
+    pub address: String,
+    // ERC-20 contract; null for the network's native currency
+    pub token: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    // What the transfers add up to (plus value and fee for the native currency)
+    pub expected_change: String,
+    // The balance at the end of the tx's block less the one at the end of the block before
+    pub actual_change: String,
+    // false when the two differ: a transfer fee, a rebase, a hidden transfer, or
+    // another tx in the same block touching the balance
+    pub reconciled: bool,
+}
+
+/// An ERC-721 sale in the tx, checked against the recent history of its
+/// buyer and seller for signs that they're the same party.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WashTradeCheck {
+    pub collection: String,
+    // Decimal
+    pub token_id: String,
+    pub seller: String,
+    pub buyer: String,
+    // What ties the two together, e.g. a shared first funder; empty when nothing does
+    pub signals: Vec<String>,
+    // The token went back and forth between them, or two signals point the same way
+    pub likely: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultisigExecution {
+    pub safe: String,
+    // "call" or "delegatecall"
+    pub operation: String,
+    pub to: String,
+    // Raw wei amount as a decimal string
+    pub value: String,
+    pub data: String,
+    // Read from the Safe at the tx's block; null/empty when that failed
+    pub threshold: Option<u64>,
+    #[serde(default)]
+    pub owners: Vec<String>,
+    pub signature_count: usize,
+    // Owners identifiable from the signatures alone (approved-hash and contract
+    // signatures); ECDSA signers are only counted
+    #[serde(default)]
+    pub signers: Vec<String>,
+    // Heuristic analysis of the wrapped operation
+    pub inner: Box<AnalyzeTxResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserOperation {
+    // The smart account the op runs as
+    pub sender: String,
+    // Decimal string
+    pub nonce: String,
+    // Sponsor of the op's gas; null when the account pays for itself
+    pub paymaster: Option<String>,
+    // The op also deploys the account (non-empty initCode)
+    pub deploys_account: bool,
+    pub call_data: String,
+    // The account's calls, when callData is a recognized `execute`/`executeBatch`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<UserOperationCall>,
+    // Heuristic analysis of the op's action
+    pub inner: Box<AnalyzeTxResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserOperationCall {
+    pub to: String,
+    // Raw wei amount as a decimal string
+    pub value: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermitInfo {
+    // "eip2612", "dai" or "permit2"
+    pub kind: String,
+    pub token: String,
+    pub owner: String,
+    pub spender: String,
+    // Raw token amount as a decimal string (can exceed 64 bits)
+    pub value: String,
+    pub unlimited: bool,
+    // Unix time the permit (for Permit2, the granted allowance) expires; null = never
+    pub deadline: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenSafety {
+    pub token: String,
+    pub verified: bool,
+    // "passed", "reverted", "returned_false" or "inconclusive"; null without an RPC node
+    pub sell_simulation: Option<String>,
+    // Share of the bought amount withheld in transit; null when none was
+    pub transfer_tax_pct: Option<f64>,
+    pub likely_honeypot: bool,
+    pub findings: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub network: Network,
+    pub tx_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarQuery {
+    pub network: Network,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarResponse {
+    pub tx_hash: String,
+    pub network: Network,
+    pub model: String,
+    // Most similar first
+    pub neighbors: Vec<SimilarTx>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarTx {
+    pub tx_hash: String,
+    pub analysis_id: i64,
+    pub tx_type: String,
+    pub risk_score: f32,
+    // Cosine similarity of the analyses' embeddings, -1 to 1
+    pub similarity: f32,
+}
+
+// One stored analysis; `analysis` is the response exactly as it was returned
+#[derive(Debug, Serialize)]
+pub struct AnalysisRecord {
+    pub id: i64,
+    pub analyzer_version: String,
+    pub created_at: i64,
+    // When the tx was found reorged out of the block this analysis saw
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reorged_at: Option<i64>,
+    pub analysis: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackRequest {
+    pub tx_hash: String,
+    pub network: Network,
+    // Response field being corrected (e.g. "tx_type")
+    pub field: String,
+    pub correct_value: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedbackResponse {
+    pub id: i64,
+    pub analysis_id: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedbackRecord {
+    pub id: i64,
+    pub analysis_id: i64,
+    pub tx_hash: String,
+    pub network: String,
+    // Version of the analysis the feedback refers to
+    pub analyzer_version: String,
+    pub field: String,
+    pub correct_value: String,
+    pub comment: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "queued" => Some(JobStatus::Queued),
+            "running" => Some(JobStatus::Running),
+            "done" => Some(JobStatus::Done),
+            "failed" => Some(JobStatus::Failed),
+            "cancelled" => Some(JobStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    // A single `/jobs` analysis
+    Analysis,
+    // `/admin/reanalyze_history`
+    ReanalyzeHistory,
+}
+
+/// `/admin/reanalyze_history` options.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct ReanalyzeHistoryRequest {
+    // Skip the LLM, to re-score with the current rules at no LLM cost
+    #[serde(default)]
+    pub heuristic_only: bool,
+    #[serde(default)]
+    pub deep: bool,
+}
+
+/// How far a history re-analysis has got. `changed` counts txs whose
+/// `tx_type` or `risk_score` came out different.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct JobProgress {
+    pub total: u64,
+    pub processed: u64,
+    pub changed: u64,
+    pub failed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobAccepted {
+    pub job_id: i64,
+    pub status: JobStatus,
+}
+
+// `result` is set once the job is done, `error` once it has failed; `progress`
+// is tracked for history re-analyses
+#[derive(Debug, Serialize)]
+pub struct JobRecord {
+    pub job_id: i64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    // Empty (and omitted) for history re-analyses
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub network: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub tx_hash: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<JobProgress>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub high_risk: HighRiskStats,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProtocolStatsQuery {
+    #[serde(default)]
+    pub network: Option<Network>,
+    // Trailing window to aggregate over; a day when omitted
+    #[serde(default)]
+    pub window_secs: Option<u64>,
+}
+
+/// Stored analyses in the trailing window, grouped by network and detected
+/// protocol. Each tx counts once, as of its latest analysis.
+#[derive(Debug, Serialize)]
+pub struct ProtocolStatsResponse {
+    pub window_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    // Most analyzed first
+    pub protocols: Vec<ProtocolStats>,
+    // Analyses with no detected protocol
+    pub unattributed_tx_count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtocolStats {
+    pub network: Network,
+    pub protocol: String,
+    pub tx_count: u64,
+    // Native value sent across the protocol's txs
+    pub total_value: Amount,
+    pub avg_risk_score: f32,
+    pub risk: RiskDistribution,
+}
+
+// Scores below 0.5 are low, below 0.75 medium, the rest high
+#[derive(Debug, Default, Serialize)]
+pub struct RiskDistribution {
+    pub low: u64,
+    pub medium: u64,
+    pub high: u64,
+}
+
+// High-risk (>= 0.75) analyses in the trailing window; `alerting` is set while
+// the count is over the threshold
+#[derive(Debug, Serialize)]
+pub struct HighRiskStats {
+    pub window_secs: u64,
+    pub count: usize,
+    pub alert_threshold: Option<usize>,
+    pub alerting: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+
+    fn parsed(link: &str) -> Result<Option<(Network, String)>, String> {
+        parse_explorer_link(&link.replace("{hash}", HASH))
+    }
+
+    #[test]
+    fn reads_the_network_and_hash_of_explorer_links() {
+        let links = [
+            ("https://etherscan.io/tx/{hash}", Network::EthereumMainnet),
+            ("http://www.Etherscan.io/tx/{hash}/", Network::EthereumMainnet),
+            ("etherscan.io/tx/{hash}#eventlog", Network::EthereumMainnet),
+            ("https://eth.blockscout.com/tx/{hash}?tab=logs", Network::EthereumMainnet),
+            ("https://blockchair.com/ethereum/transaction/{hash}", Network::EthereumMainnet),
+            ("https://dashboard.tenderly.co/tx/mainnet/{hash}", Network::EthereumMainnet),
+            ("https://polygonscan.com/tx/{hash}", Network::PolygonMainnet),
+        ];
+        for (link, network) in links {
+            assert_eq!(parsed(link), Ok(Some((network, HASH.to_string()))), "{}", link);
+        }
+        // A plain hash isn't a link
+        assert_eq!(parsed("{hash}"), Ok(None));
+    }
+
+    #[test]
+    fn rejects_links_without_a_supported_tx_page() {
+        let unknown = parsed("https://example.com/tx/{hash}").unwrap_err();
+        assert!(unknown.starts_with("Unrecognized explorer link"), "{}", unknown);
+        // An address page of a known explorer
+        assert!(parsed("https://etherscan.io/address/0xaaaa").is_err());
+        let truncated = parsed("https://etherscan.io/tx/0x5c504ed4").unwrap_err();
+        assert!(truncated.starts_with("No tx hash"), "{}", truncated);
+        // Recognized, but for a network this service doesn't support
+        assert!(parsed("https://arbiscan.io/tx/{hash}").is_err());
+    }
+
+    #[test]
+    fn a_network_sent_along_must_match_the_link() {
+        let mut request = AnalyzeTxRequest {
+            network: Some(Network::PolygonMainnet),
+            tx_hash: format!("https://etherscan.io/tx/{}", HASH),
+            ..AnalyzeTxRequest::default()
+        };
+        assert!(request.expand_explorer_link().is_err());
+
+        request.network = None;
+        request.expand_explorer_link().unwrap();
+        assert_eq!(request.network, Some(Network::EthereumMainnet));
+        assert_eq!(request.tx_hash, HASH);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A supported chain. Parsed once at the API boundary, so everything past
+/// the request handlers works with a known network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Network {
+    EthereumMainnet,
+    PolygonMainnet,
+}
+
+impl Network {
+    pub const ALL: &[Network] = &[Network::EthereumMainnet, Network::PolygonMainnet];
+
+    /// API name, e.g. "ethereum-mainnet".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Network::EthereumMainnet => "ethereum-mainnet",
+            Network::PolygonMainnet => "polygon-mainnet",
+        }
+    }
+
+    pub fn chain_id(self) -> u64 {
+        match self {
+            Network::EthereumMainnet => 1,
+            Network::PolygonMainnet => 137,
+        }
+    }
+
+    /// Symbol of the chain's native token, in which `value` and gas are paid.
+    pub fn native_symbol(self) -> &'static str {
+        match self {
+            Network::EthereumMainnet => "ETH",
+            Network::PolygonMainnet => "MATIC",
+        }
+    }
+
+    /// Decimals of the native token: its smallest unit (wei on Ethereum) is
+    /// 10^-decimals of a token.
+    pub fn native_decimals(self) -> u8 {
+        match self {
+            Network::EthereumMainnet | Network::PolygonMainnet => 18,
+        }
+    }
+
+    /// The chain's Etherscan-family explorer API, used unless
+    /// EXPLORER_API_URL_<NETWORK> names another.
+    pub fn explorer_api_url(self) -> &'static str {
+        match self {
+            Network::EthereumMainnet => "https://api.etherscan.io/api",
+            Network::PolygonMainnet => "https://api.polygonscan.com/api",
+        }
+    }
+
+    // "ethereum-mainnet" -> "ETHEREUM_MAINNET", for per-network env vars
+    pub fn env_suffix(self) -> String {
+        self.as_str().to_uppercase().replace('-', "_")
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownNetwork(String);
+
+impl fmt::Display for UnknownNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let supported: Vec<&str> = Network::ALL.iter().map(|n| n.as_str()).collect();
+        write!(
+            f,
+            "Unsupported network '{}'; supported networks: {}",
+            self.0,
+            supported.join(", ")
+        )
+    }
+}
+
+impl FromStr for Network {
+    type Err = UnknownNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Network::ALL
+            .iter()
+            .copied()
+            .find(|n| n.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| UnknownNetwork(s.to_string()))
+    }
+}
+
+impl Serialize for Network {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Network {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use primitive_types::U256;
+
+    use super::*;
+    use crate::services::blockchain;
+
+    #[test]
+    fn each_network_carries_its_native_token() {
+        assert_eq!(Network::EthereumMainnet.native_symbol(), "ETH");
+        assert_eq!(Network::PolygonMainnet.native_symbol(), "MATIC");
+        assert_eq!(Network::PolygonMainnet.native_decimals(), 18);
+        assert_eq!(Network::PolygonMainnet.chain_id(), 137);
+    }
+
+    #[test]
+    fn formats_polygon_amounts_in_matic() {
+        let wei = U256::from(1_500_000_000_000_000_000u64);
+        let amount = blockchain::native_amount(Network::PolygonMainnet, wei);
+        assert_eq!(amount.formatted.as_deref(), Some("1.5"));
+        assert_eq!(amount.symbol.as_deref(), Some("MATIC"));
+        assert_eq!(amount.decimals, Some(18));
+    }
+
+    #[test]
+    fn parses_names_in_any_case() {
+        assert_eq!(" Polygon-Mainnet ".parse::<Network>().unwrap(), Network::PolygonMainnet);
+        assert_eq!(Network::PolygonMainnet.env_suffix(), "POLYGON_MAINNET");
+        let err = "bnb-mainnet".parse::<Network>().unwrap_err().to_string();
+        assert!(err.contains("ethereum-mainnet, polygon-mainnet"), "{}", err);
+    }
+}
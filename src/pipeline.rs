@@ -0,0 +1,1138 @@
+use std::future::Future;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use futures::future::BoxFuture;
+use primitive_types::U256;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::deadline::{within, Deadline, Stage};
+use crate::fields::{FieldSelection, LLM_FIELDS};
+use crate::history;
+use crate::metrics::Metrics;
+use crate::models::{
+    AddressLabel, AnalyzeBatchRequest, AnalyzeBundleRequest, AnalyzeLogsRequest,
+    AnalyzeTxRequest, AnalyzeTxResponse, BatchItem, BundleReport, BundleStrategy, BundleTx,
+    MultisigExecution, UserOperation, UserOperationCall,
+};
+use crate::network::Network;
+use crate::redact;
+use crate::services::ai::{self, AiError, Consensus, LlmOptions};
+use crate::services::blockchain::{self, BlockchainError};
+use crate::services::explorer::ExplorerClient;
+use crate::services::llm::{Budgeted, LlmBudget, LlmProvider};
+use crate::services::rpc::ChainClient;
+use crate::services::{
+    balances, bundle, categories, decoder, erc4337, nft, risk, safe, selectors, swaps, token_safety,
+};
+use crate::state::AppState;
+use crate::timing::{timed, Timings};
+
+#[derive(Debug, Error)]
+pub enum AnalysisError {
+    #[error("Failed to fetch tx details: {0}")]
+    Fetch(#[from] BlockchainError),
+    #[error("AI analysis failed: {0}")]
+    Ai(#[from] AiError),
+}
+
+/// Full single-tx analysis: fetch, verify counterparties, analyze, post-process.
+///
+/// Everything here is awaited inline (no spawned tasks), so dropping the
+/// returned future cancels any in-flight RPC/LLM request.
+///
+/// Work whose output the client didn't request in `fields` is skipped.
+///
+/// With a `deadline`, optional stages are dropped as it nears (see `Deadline`).
+///
+/// Each stage is timed: the breakdown goes in `timing` when the request asked
+/// for `include_timing`, and is logged when the analysis took longer than
+/// SLOW_REQUEST_MS.
+pub async fn analyze(
+    state: &AppState,
+    request: &AnalyzeTxRequest,
+    fields: &FieldSelection,
+    deadline: Option<Duration>,
+) -> Result<AnalyzeTxResponse, AnalysisError> {
+    let timings = Timings::start();
+    let deadline = deadline.map(Deadline::after);
+    let deadline = deadline.as_ref();
+    // The trace rides along with the tx, so it's dropped up front when the
+    // deadline is too short for it; receipt re-polls stop at the deadline
+    let deep = request.deep && deadline.is_none_or(|d| d.allows(Stage::Trace));
+    let mut receipt_wait = state.config.receipt_wait();
+    if let Some(deadline) = deadline {
+        let polls = deadline.remaining().as_millis() / receipt_wait.interval.as_millis().max(1);
+        receipt_wait.attempts = receipt_wait.attempts.min(polls as u32);
+    }
+
+    // 1. Fetch raw tx details from blockchain (mocked when no RPC is configured)
+    let rpc = state.rpc(request.network());
+    let fetch = blockchain::fetch_transaction(
+        rpc,
+        request.network(),
+        &request.tx_hash,
+        deep.then(|| state.config.trace_limits()),
+        receipt_wait,
+    );
+    let tx_details = timed(Some(&timings), "fetch", fetch).await?;
+
+    let run = Run {
+        deadline,
+        timings: Some(&timings),
+        ..Run::default()
+    };
+    let mut analysis = analyze_fetched(state, request, tx_details, fields, run).await?;
+
+    let timing = timings.summary();
+    if let Some(threshold) = state.config.slow_request_ms
+        && timing.total_ms > threshold
+    {
+        tracing::info!(
+            "Slow analysis of {} on {}: {}",
+            request.tx_hash,
+            request.network(),
+            timing
+        );
+    }
+    if request.include_timing {
+        analysis.timing = Some(timing);
+    }
+    Ok(analysis)
+}
+
+/// How a re-analysis of stored history (`reanalyze`) differs from a fresh one.
+#[derive(Debug, Clone, Copy)]
+pub struct Replay {
+    // Skip the LLM, to re-score at no LLM cost
+    pub heuristic_only: bool,
+}
+
+/// Re-runs the current pipeline over a tx from history. The result isn't
+/// recorded, alerted on, or sent to the webhook: the caller updates the stored
+/// analysis instead.
+pub async fn reanalyze(
+    state: &AppState,
+    network: Network,
+    tx_hash: &str,
+    deep: bool,
+    replay: Replay,
+) -> Result<AnalyzeTxResponse, AnalysisError> {
+    let request = AnalyzeTxRequest {
+        network: Some(network),
+        tx_hash: tx_hash.to_string(),
+        deep,
+        ..AnalyzeTxRequest::default()
+    };
+    let tx_details = blockchain::fetch_transaction(
+        state.rpc(network),
+        network,
+        tx_hash,
+        deep.then(|| state.config.trace_limits()),
+        state.config.receipt_wait(),
+    )
+    .await?;
+    let fields = FieldSelection::default();
+    let run = Run {
+        replay: Some(replay),
+        ..Run::default()
+    };
+    analyze_fetched(state, &request, tx_details, &fields, run).await
+}
+
+/// Analyzes a tx from client-provided data (see `blockchain::from_provided`).
+/// Nothing is fetched over RPC, which also rules out deep mode.
+pub async fn analyze_provided(
+    state: &AppState,
+    request: &AnalyzeLogsRequest,
+    tx_details: Value,
+    fields: &FieldSelection,
+) -> Result<AnalyzeTxResponse, AnalysisError> {
+    let single = AnalyzeTxRequest {
+        network: Some(request.network),
+        tx_hash: request.tx_hash.clone(),
+        model: request.model.clone(),
+        format: request.format,
+        verbosity: request.verbosity,
+        persona: request.persona,
+        max_tokens: request.max_tokens,
+        ..AnalyzeTxRequest::default()
+    };
+    analyze_fetched(state, &single, tx_details, fields, Run::default()).await
+}
+
+// How many batch items are analyzed at once (each may make an LLM call)
+pub const BATCH_CONCURRENCY: usize = 8;
+
+/// Analyzes several txs on one network. All chain data is fetched up front in a
+/// single batched RPC round-trip; per-tx failures are reported per item. The
+/// items share `budget` for their LLM use.
+pub async fn analyze_batch(
+    state: &AppState,
+    request: &AnalyzeBatchRequest,
+    budget: &LlmBudget,
+) -> Result<Vec<BatchItem>, AnalysisError> {
+    Ok(batch_items(state, request, budget).await?.collect().await)
+}
+
+/// Streaming form of `analyze_batch`: fetches the chain data, then yields each
+/// item (in input order) as soon as it's analyzed.
+pub async fn batch_items<'a>(
+    state: &'a AppState,
+    request: &'a AnalyzeBatchRequest,
+    budget: &'a LlmBudget,
+) -> Result<impl Stream<Item = BatchItem> + Send + 'a, AnalysisError> {
+    let rpc = state.rpc(request.network);
+    let fetched = blockchain::fetch_transactions(
+        rpc,
+        request.network,
+        &request.tx_hashes,
+        request.deep.then(|| state.config.trace_limits()),
+        state.config.receipt_wait(),
+    )
+    .await?;
+
+    let items = request.tx_hashes.clone().into_iter().zip(fetched);
+    let analyses = items.map(move |(tx_hash, tx_details)| async move {
+        let single = AnalyzeTxRequest {
+            network: Some(request.network),
+            tx_hash: tx_hash.clone(),
+            deep: request.deep,
+            model: request.model.clone(),
+            format: request.format,
+            verbosity: request.verbosity,
+            persona: request.persona,
+            max_tokens: request.max_tokens,
+            ..AnalyzeTxRequest::default()
+        };
+        let result = match tx_details {
+            Ok(tx_details) => {
+                let fields = FieldSelection::default();
+                let run = Run {
+                    budget: Some(budget),
+                    ..Run::default()
+                };
+                analyze_fetched(state, &single, tx_details, &fields, run).await
+            }
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(analysis) => BatchItem {
+                tx_hash,
+                analysis: Some(analysis),
+                error: None,
+            },
+            Err(e) => BatchItem {
+                tx_hash,
+                analysis: None,
+                error: Some(e.to_string()),
+            },
+        }
+    });
+
+    Ok(futures::stream::iter(analyses).buffered(BATCH_CONCURRENCY))
+}
+
+/// Analyzes a bundle's txs (mined ones fetched in one batched round-trip,
+/// raw ones decoded), then correlates them into the strategy the bundle
+/// carries out and the searcher's profit. Per-tx failures are reported per
+/// item, and correlation goes on without those txs.
+pub async fn analyze_bundle(
+    state: &AppState,
+    request: &AnalyzeBundleRequest,
+) -> Result<BundleReport, AnalysisError> {
+    let hashes: Vec<String> = request
+        .txs
+        .iter()
+        .filter_map(|tx| match tx {
+            BundleTx::Hash(hash) => Some(hash.clone()),
+            BundleTx::Raw { .. } => None,
+        })
+        .collect();
+    let fetched = if hashes.is_empty() {
+        Vec::new()
+    } else {
+        blockchain::fetch_transactions(
+            state.rpc(request.network),
+            request.network,
+            &hashes,
+            request.deep.then(|| state.config.trace_limits()),
+            state.config.receipt_wait(),
+        )
+        .await?
+    };
+
+    let mut fetched = fetched.into_iter();
+    let details: Vec<(String, Result<Value, String>)> = request
+        .txs
+        .iter()
+        .map(|tx| match tx {
+            BundleTx::Hash(hash) => {
+                let details = fetched.next().expect("one result per requested hash");
+                (hash.clone(), details.map_err(|e| e.to_string()))
+            }
+            BundleTx::Raw { raw, from } => {
+                match blockchain::from_raw(request.network, raw, from.as_deref()) {
+                    Ok(details) => {
+                        let hash = details["hash"].as_str().unwrap_or_default().to_string();
+                        (hash, Ok(details))
+                    }
+                    Err(e) => (String::new(), Err(e)),
+                }
+            }
+        })
+        .collect();
+
+    // Correlation needs the details after the analyses have consumed them
+    let analyses = details.clone().into_iter().map(move |(tx_hash, tx_details)| async move {
+        let single = AnalyzeTxRequest {
+            network: Some(request.network),
+            tx_hash: tx_hash.clone(),
+            deep: request.deep,
+            model: request.model.clone(),
+            format: request.format,
+            verbosity: request.verbosity,
+            persona: request.persona,
+            max_tokens: request.max_tokens,
+            ..AnalyzeTxRequest::default()
+        };
+        let result = match tx_details {
+            Ok(tx_details) => {
+                let fields = FieldSelection::default();
+                analyze_fetched(state, &single, tx_details, &fields, Run::default())
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(analysis) => BatchItem {
+                tx_hash,
+                analysis: Some(analysis),
+                error: None,
+            },
+            Err(e) => BatchItem {
+                tx_hash,
+                analysis: None,
+                error: Some(e),
+            },
+        }
+    });
+    let txs: Vec<BatchItem> = futures::stream::iter(analyses)
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let analyzed: Vec<&Value> = details
+        .iter()
+        .zip(&txs)
+        .filter(|(_, item)| item.analysis.is_some())
+        .filter_map(|((_, tx_details), _)| tx_details.as_ref().ok())
+        .collect();
+    let correlation = bundle::correlate(&analyzed);
+
+    let mut risk_score = txs
+        .iter()
+        .filter_map(|item| item.analysis.as_ref())
+        .map(|analysis| analysis.risk_score)
+        .fold(0.0, f32::max);
+    // A sandwich takes its profit out of the victim's trade
+    if correlation.strategy == Some(BundleStrategy::Sandwich) {
+        risk_score = risk_score.max(risk::HIGH_RISK_SCORE);
+    }
+    let mut notes = Vec::new();
+    let failed = txs.iter().filter(|item| item.error.is_some()).count();
+    if failed > 0 {
+        notes.push(format!(
+            "{} of {} txs couldn't be analyzed; the bundle was correlated without them",
+            failed,
+            txs.len()
+        ));
+    }
+    if request.txs.iter().any(|tx| matches!(tx, BundleTx::Raw { .. })) {
+        notes.push(
+            "Raw txs haven't been mined, so they have no logs; their swaps and transfers \
+             don't count towards the strategy or net flows"
+                .to_string(),
+        );
+    }
+
+    let mut report = BundleReport {
+        network: request.network,
+        strategy: correlation.strategy,
+        searcher: correlation.searcher,
+        victims: correlation.victims,
+        net_flows: correlation.net_flows,
+        gas_used: analyzed.iter().filter_map(|tx| tx["gas_used"].as_u64()).sum(),
+        risk_score,
+        risk_reasons: correlation.reasons,
+        txs,
+        notes,
+    };
+    // The analyses are redacted already; the addresses correlated from them aren't
+    if let Some(redactor) = &state.redactor {
+        redactor.redact_bundle(&mut report);
+    }
+    Ok(report)
+}
+
+// How a single analysis is run, apart from what it analyzes
+#[derive(Default, Clone, Copy)]
+struct Run<'a> {
+    replay: Option<Replay>,
+    budget: Option<&'a LlmBudget>,
+    deadline: Option<&'a Deadline>,
+    timings: Option<&'a Timings>,
+}
+
+impl Run<'_> {
+    // Runs an optional stage under the deadline, timed unless it was dropped
+    async fn stage<T>(&self, stage: Stage, work: impl Future<Output = T>) -> Option<T> {
+        if self.deadline.is_some_and(|d| !d.allows(stage)) {
+            return None;
+        }
+        timed(self.timings, stage.as_str(), within(self.deadline, stage, work)).await
+    }
+
+    async fn timed<T>(&self, stage: &'static str, work: impl Future<Output = T>) -> T {
+        timed(self.timings, stage, work).await
+    }
+}
+
+async fn analyze_fetched(
+    state: &AppState,
+    request: &AnalyzeTxRequest,
+    mut tx_details: Value,
+    fields: &FieldSelection,
+    run: Run<'_>,
+) -> Result<AnalyzeTxResponse, AnalysisError> {
+    let Run {
+        replay,
+        budget,
+        deadline,
+        ..
+    } = run;
+    let wants_llm = fields.wants_any(LLM_FIELDS);
+    let heuristic_only = replay.is_some_and(|r| r.heuristic_only);
+    // Client-provided txs are analyzed from what was sent, without touching the node
+    let rpc = state
+        .rpc(request.network())
+        .filter(|_| tx_details["source"] != "client_provided");
+    // Reads of state at the tx's block need an archive node, unless the block
+    // is recent enough that a full node still has its state
+    let recent = tx_details["confirmations"]
+        .as_u64()
+        .is_some_and(|c| c <= FULL_NODE_STATE_BLOCKS);
+    let archive = state.archive_rpc(request.network()).filter(|_| rpc.is_some());
+    let historical = archive.or(rpc.filter(|_| recent));
+    let explorer = state.explorer(request.network());
+    // Enrichments skipped for want of an archive node, for the note
+    let mut needs_archive: Vec<&str> = Vec::new();
+
+    // 2. Resolve the top-level method; it's also part of what the LLM sees
+    if (fields.wants("method") || wants_llm)
+        && let Some(method) = run.timed("method", resolve_method(explorer, &tx_details)).await
+    {
+        tx_details["method"] = Value::String(method);
+    }
+
+    // Before anything that reads the call sequence (risk rules, permits, the LLM)
+    let multisig = multisig_execution(state, historical, request, &tx_details, 0);
+    if let Some(multisig) = run.timed("multisig", multisig).await {
+        if rpc.is_some() && historical.is_none() {
+            needs_archive.push("the Safe's owners and threshold");
+        }
+        tx_details["multisig"] = serde_json::json!(multisig);
+    }
+    let ops = user_operations(state, request, &tx_details);
+    if let Some(ops) = run.timed("user_operations", ops).await {
+        tx_details["user_operations"] = serde_json::json!(ops);
+    }
+
+    // Honeypot checks cost an eth_call and an explorer lookup per bought token,
+    // so they only run in deep mode. Their findings feed the risk rules.
+    if request.deep
+        && (fields.wants("token_safety") || wants_llm)
+        && let Some(safety) = run
+            .stage(
+                Stage::TokenSafety,
+                token_safety::assess(historical, explorer, &tx_details),
+            )
+            .await
+    {
+        if rpc.is_some() && historical.is_none() && !safety.is_empty() {
+            needs_archive.push("token sell simulations");
+        }
+        if !safety.is_empty() {
+            tx_details["token_safety"] = serde_json::json!(safety);
+        }
+    }
+
+    // Token amounts, scaled by each token's decimals for the response and the LLM
+    if (fields.wants("token_transfers") || wants_llm)
+        && let Some(transfers) = run
+            .stage(
+                Stage::TokenTransfers,
+                state.tokens.transfers(request.network(), rpc, &tx_details),
+            )
+            .await
+        && !transfers.is_empty()
+    {
+        tx_details["token_transfers"] = serde_json::json!(transfers);
+    }
+
+    // Execution price and price impact of V2-style swaps; high impact feeds a risk rule
+    if let Some(rpc) = rpc
+        && (fields.wants("swaps") || wants_llm)
+        && let Some(swaps) = run
+            .stage(
+                Stage::Swaps,
+                swaps::assess(request.network(), rpc, historical, &state.tokens, &tx_details),
+            )
+            .await
+        && !swaps.is_empty()
+    {
+        if historical.is_none() && swaps.iter().any(|swap| swap.price_impact_pct.is_none()) {
+            needs_archive.push("the price impact of swaps on pools without a Sync log");
+        }
+        tx_details["swaps"] = serde_json::json!(swaps);
+    }
+
+    // Balances before and after the tx's block, against what its transfers say;
+    // ones that don't reconcile feed a risk rule
+    if rpc.is_some()
+        && (fields.wants("balance_changes") || wants_llm)
+        && tx_details["block_number"].is_u64()
+    {
+        match historical {
+            Some(historical) => {
+                let reconcile =
+                    balances::reconcile(request.network(), historical, &state.tokens, &tx_details);
+                if let Some(changes) = run.stage(Stage::BalanceChanges, reconcile).await
+                    && !changes.is_empty()
+                {
+                    tx_details["balance_changes"] = serde_json::json!(changes);
+                }
+            }
+            None => needs_archive.push("balance changes"),
+        }
+    }
+
+    // Wash-trade checks cost four explorer lookups per NFT sale, so they only
+    // run in deep mode. Likely wash trades feed a risk rule.
+    if request.deep
+        && (fields.wants("wash_trades") || wants_llm)
+        && let Some(checks) = run
+            .stage(Stage::WashTrades, nft::wash_trades(explorer, &tx_details))
+            .await
+        && !checks.is_empty()
+    {
+        tx_details["wash_trades"] = serde_json::json!(checks);
+    }
+
+    // Whether a recent or pending tx overpaid for current network conditions
+    if let Some(rpc) = rpc
+        && (fields.wants("gas_context") || wants_llm)
+        && let Some(Some(gas)) = run
+            .stage(
+                Stage::GasContext,
+                state.gas_prices.context(request.network(), rpc, &tx_details),
+            )
+            .await
+    {
+        tx_details["gas_context"] = serde_json::json!(gas);
+    }
+
+    // Method and logs by name, from the verified ABIs where there are any
+    if state.config.abi_decoding
+        && (fields.wants_any(&["decoded_call", "decoded_events"]) || wants_llm)
+        && let Some((call, events)) = run
+            .stage(Stage::DecodedEvents, decoder::decode_tx(explorer, &tx_details))
+            .await
+    {
+        if let Some(call) = call {
+            tx_details["decoded_call"] = serde_json::json!(call);
+        }
+        if !events.is_empty() {
+            tx_details["decoded_events"] = serde_json::json!(events);
+        }
+    }
+
+    // 3. Check contract source verification for counterparties (cached).
+    //    Safe mode needs it regardless of what the client asked to see.
+    let mut contracts = if fields.wants("contracts") || state.config.safe_mode {
+        run.stage(Stage::Contracts, explorer.verify_counterparties(&tx_details))
+            .await
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    // Their deployment age feeds the NEW_CONTRACT rule. Finding it searches
+    // back through old blocks, so it's archive-only whatever the tx's age.
+    if let (Some(archive), Some(block)) = (archive, tx_details["block_number"].as_u64())
+        && !contracts.is_empty()
+    {
+        let deployments = state
+            .deployments
+            .annotate(request.network(), archive, block, &mut contracts);
+        run.timed("deployments", within(deadline, Stage::Contracts, deployments)).await;
+        tx_details["contracts"] = serde_json::json!(contracts);
+    } else if rpc.is_some() && !contracts.is_empty() {
+        needs_archive.push("contract ages (NEW_CONTRACT rule)");
+        tx_details["contracts"] = serde_json::json!(contracts);
+    }
+
+    // Exchange and protocol tags make for far more readable explanations
+    if let Some(labels) = &state.labels
+        && (fields.wants("address_labels") || fields.wants("contracts") || wants_llm)
+    {
+        let mut addresses: Vec<String> = ["from", "to"]
+            .iter()
+            .filter_map(|key| tx_details[*key].as_str().map(str::to_string))
+            .chain(contracts.iter().map(|c| c.address.clone()))
+            .collect();
+        let mut seen = std::collections::HashSet::new();
+        addresses.retain(|a| seen.insert(a.to_lowercase()));
+
+        let lookup = labels.lookup(request.network(), &addresses);
+        let found = run.stage(Stage::AddressLabels, lookup).await.unwrap_or_default();
+        for contract in &mut contracts {
+            if let Some((_, tags)) = found
+                .iter()
+                .find(|(address, _)| address.eq_ignore_ascii_case(&contract.address))
+            {
+                contract.labels = tags.clone();
+            }
+        }
+        let found: Vec<AddressLabel> = found
+            .into_iter()
+            .map(|(address, labels)| AddressLabel { address, labels })
+            .collect();
+        if !found.is_empty() {
+            tx_details["address_labels"] = serde_json::json!(found);
+        }
+    }
+
+    // Ahead of the built-in classifier (and the LLM's) in ai.rs
+    if let Some(category) = categories::classify(&state.config.custom_categories, &tx_details) {
+        tx_details["custom_category"] = Value::String(category.to_string());
+    }
+
+    // Allowlisted counterparties are routine flows: heuristics only, lower base risk
+    let network = state.config.networks.get(&request.network());
+    let known_safe = tx_details["to"]
+        .as_str()
+        .filter(|to| network.is_some_and(|net| net.is_known_safe(to)))
+        .map(str::to_string);
+    if known_safe.is_some() {
+        tx_details["known_safe"] = Value::Bool(true);
+        if wants_llm && state.llm.is_some() {
+            Metrics::inc(&state.metrics.llm_skipped_known_safe);
+        }
+    }
+
+    // 4. Decide whether this tx gets the LLM: only if the client wants something
+    //    it produces, and sampling (which keeps cost down under load) lets it in
+    let llm_eligible = wants_llm && known_safe.is_none() && !heuristic_only;
+    let llm = state.llm.as_deref().filter(|_| llm_eligible).filter(|_| {
+        let sampled = ai::sampled_in(&request.tx_hash, state.config.llm_sample_rate);
+        Metrics::inc(if sampled {
+            &state.metrics.llm_sampled_in
+        } else {
+            &state.metrics.llm_sampled_out
+        });
+        sampled
+    });
+    let sampled_out = llm_eligible && state.llm.is_some() && llm.is_none();
+    let out_of_time = llm.is_some() && deadline.is_some_and(|d| !d.allows(Stage::Llm));
+    let llm = llm.filter(|_| !out_of_time);
+    // A batch's items draw on its LLM budget, which also runs out when the
+    // provider's quota does
+    let over_budget = llm.is_some() && budget.is_some_and(|b| !b.take());
+    let budgeted = llm.zip(budget).map(|(inner, budget)| Budgeted { inner, budget });
+    let llm = match &budgeted {
+        _ if over_budget => None,
+        Some(budgeted) => Some(budgeted as &dyn LlmProvider),
+        None => llm,
+    };
+    // Already checked against the allowlist by the route
+    let model = request.model.as_deref().unwrap_or(&state.config.llm_model);
+    let options = LlmOptions {
+        explanation: fields.wants("natural_language_explanation"),
+        recommendations: fields.wants("recommendations"),
+        model,
+        consensus: request.consensus.then(|| Consensus {
+            samples: state.config.llm_consensus_samples.max(1),
+            temperature: state.config.llm_consensus_temperature,
+        }),
+        trace: request.include_trace,
+        prompt: request.include_prompt,
+        max_tokens: request.max_tokens.unwrap_or(state.config.llm_max_tokens),
+        ..LlmOptions::new(&state.config, request)
+    };
+
+    // 5. Call AI analyzer with structured tx summary. An LLM call still out
+    //    when the deadline hits is dropped for the heuristic result.
+    let analyzed = match llm {
+        Some(_) => {
+            let analysis = ai::analyze_transaction(
+                llm,
+                request.network(),
+                &request.tx_hash,
+                &tx_details,
+                contracts.clone(),
+                options,
+            );
+            run.stage(Stage::Llm, analysis).await
+        }
+        None => None,
+    };
+    let cut_off = llm.is_some() && analyzed.is_none();
+    let mut analysis = match analyzed {
+        Some(analysis) => analysis?,
+        None => {
+            let analysis = ai::analyze_transaction(
+                None,
+                request.network(),
+                &request.tx_hash,
+                &tx_details,
+                contracts,
+                options,
+            );
+            run.timed("heuristics", analysis).await?
+        }
+    };
+    // The prompt carries whatever the tx data does, so a configured key that
+    // somehow ended up in it mustn't be echoed back
+    if let Some(prompt) = &mut analysis.prompt {
+        *prompt = redact::redact_secrets(prompt, &state.config.secrets());
+    }
+
+    let llm_skipped = if known_safe.is_some() {
+        Some("counterparty is on the known-safe allowlist")
+    } else if sampled_out {
+        Some("sampled out by LLM_SAMPLE_RATE")
+    } else if over_budget {
+        Some("the batch's LLM budget is exhausted")
+    } else if out_of_time {
+        Some("too little time left before the response deadline")
+    } else if cut_off {
+        Some("cut off at the response deadline")
+    } else if heuristic_only {
+        Some("heuristic-only history re-analysis")
+    } else if !wants_llm {
+        Some("no LLM-derived field was requested")
+    } else if state.llm.is_none() {
+        Some("no LLM provider is configured")
+    } else {
+        None
+    };
+    if let Some(reason) = llm_skipped {
+        analysis.trace("llm", format!("LLM skipped: {}", reason), None);
+    }
+
+    if !needs_archive.is_empty() {
+        analysis.notes.push(format!(
+            "Skipped {}: needs historical state, and no archive node is configured for {}",
+            needs_archive.join(", "),
+            request.network()
+        ));
+    }
+    if !tx_details["trace_truncated"].is_null() {
+        analysis.notes.push(
+            "The internal call trace exceeds TRACE_MAX_DEPTH or TRACE_MAX_CALLS; calls past \
+             the limits were left out"
+                .to_string(),
+        );
+    }
+    if let Some(contract) = known_safe {
+        analysis
+            .notes
+            .push(format!("Interacts with known-safe contract {}", contract));
+    }
+    if sampled_out {
+        analysis
+            .notes
+            .push("Sampled out of LLM analysis; showing heuristic result only".to_string());
+    }
+    if over_budget {
+        analysis
+            .notes
+            .push("The batch's LLM budget ran out; showing heuristic result only".to_string());
+    }
+    let skipped = deadline.map(Deadline::skipped).unwrap_or_default();
+    if !skipped.is_empty() {
+        Metrics::inc(&state.metrics.deadline_degraded);
+        analysis.notes.push(format!(
+            "Skipped {} to meet the response deadline; showing what was ready",
+            skipped.join(", ")
+        ));
+        analysis.deadline_skipped = skipped.into_iter().map(str::to_string).collect();
+    }
+
+    if state.config.safe_mode {
+        let before = analysis.confidence;
+        ai::apply_safe_mode(&mut analysis, state.config.safe_mode_max_confidence);
+        if analysis.confidence < before {
+            let confidence = analysis.confidence;
+            analysis.trace(
+                "adjustment",
+                format!("Safe mode capped confidence at {:.2} (unverified contract)", confidence),
+                None,
+            );
+        }
+    }
+
+    if !analysis.receipt_available {
+        analysis.notes.push(
+            "Receipt not available yet; analyzed from the transaction alone, so status, \
+             logs and gas used are pending"
+                .to_string(),
+        );
+    }
+
+    // A shallow block can still be reorged away; see reorg.rs for the re-check
+    if let Some(confirmations) = analysis.confirmations
+        && confirmations < state.config.finality_confirmations
+    {
+        analysis.provisional = true;
+        analysis.notes.push(format!(
+            "Only {} confirmation(s); this result is provisional until the block is final",
+            confirmations
+        ));
+    }
+
+    let decided_by = if analysis.ai_available { "LLM" } else { "heuristics only" };
+    let final_step = format!(
+        "{} ({}), confidence {:.2}",
+        analysis.tx_type, decided_by, analysis.confidence
+    );
+    analysis.trace("final", final_step, Some(analysis.risk_score));
+
+    let version_model = if analysis.ai_available { model } else { "heuristic" };
+    analysis.analyzer_version = analyzer_version(version_model, &state.rules_hash);
+
+    // Before storing, so history and job results never hold raw addresses either
+    if let Some(redactor) = &state.redactor {
+        redactor.redact_analysis(&mut analysis);
+    }
+    if replay.is_some() {
+        return Ok(analysis);
+    }
+    // Only for this response: it's neither published nor kept in history
+    let prompt = analysis.prompt.take();
+
+    if analysis.risk_score >= risk::HIGH_RISK_SCORE {
+        state.high_risk.record(&analysis);
+    }
+    if let Some(emitter) = &state.emitter {
+        emitter.publish(&analysis, &state.metrics);
+    }
+
+    // History is best effort and written in the background: a write failure
+    // shouldn't fail the request
+    let notify = state.config.webhook_url.is_some()
+        && analysis.risk_score >= state.config.webhook_min_risk;
+    let block = tx_details["block_hash"]
+        .as_str()
+        .zip(tx_details["block_number"].as_u64())
+        .filter(|_| analysis.provisional && state.config.reorg_check_interval_secs > 0);
+    let queued = history::record(state, &analysis, notify, block);
+    run.timed("store", queued).await;
+
+    analysis.prompt = prompt;
+    Ok(analysis)
+}
+
+// Full nodes keep the state of (at least) this many recent blocks
+const FULL_NODE_STATE_BLOCKS: u64 = 128;
+
+// A Safe owned by a Safe is unwrapped too, up to this many levels
+const MAX_MULTISIG_DEPTH: usize = 3;
+
+// The operation wrapped by a Safe `execTransaction`, analyzed heuristically as
+// if the Safe had sent it. The LLM sees it as part of the outer tx instead.
+fn multisig_execution<'a>(
+    state: &'a AppState,
+    rpc: Option<&'a dyn ChainClient>,
+    request: &'a AnalyzeTxRequest,
+    tx_details: &'a Value,
+    depth: usize,
+) -> BoxFuture<'a, Option<MultisigExecution>> {
+    Box::pin(async move {
+        if depth >= MAX_MULTISIG_DEPTH {
+            return None;
+        }
+        let exec = safe::decode_exec(tx_details["input"].as_str()?)?;
+        let safe_address = tx_details["to"].as_str()?.to_lowercase();
+        let block = tx_details["block_number"].as_u64();
+        let (threshold, owners) = match rpc {
+            Some(rpc) => safe::owners(rpc, &safe_address, block).await,
+            None => (None, None),
+        };
+
+        // Logs (and a trace, if any) belong to the whole tx, so the inner call shares them
+        let mut inner_details = serde_json::json!({
+            "hash": tx_details["hash"],
+            "from": safe_address,
+            "to": exec.to,
+            "value": blockchain::native_amount(request.network(), exec.value),
+            "input": exec.data,
+            "status": tx_details["status"],
+            "block_number": tx_details["block_number"],
+            "block_timestamp": tx_details["block_timestamp"],
+            "logs": tx_details["logs"],
+            "source": tx_details["source"],
+        });
+        let explorer = state.explorer(request.network());
+        if let Some(method) = resolve_method(explorer, &inner_details).await {
+            inner_details["method"] = Value::String(method);
+        }
+        if let Some(nested) = multisig_execution(state, rpc, request, &inner_details, depth + 1).await {
+            inner_details["multisig"] = serde_json::json!(nested);
+        }
+
+        let options = LlmOptions::new(&state.config, request);
+        let mut inner = ai::analyze_transaction(
+            None,
+            request.network(),
+            &request.tx_hash,
+            &inner_details,
+            Vec::new(),
+            options,
+        )
+        .await
+        .ok()?;
+        inner.analyzer_version = analyzer_version("heuristic", &state.rules_hash);
+
+        Some(MultisigExecution {
+            safe: safe_address,
+            operation: exec.operation.to_string(),
+            to: exec.to,
+            value: exec.value.to_string(),
+            data: exec.data,
+            threshold,
+            owners: owners.unwrap_or_default(),
+            signature_count: exec.signature_count,
+            signers: exec.signers,
+            inner: Box::new(inner),
+        })
+    })
+}
+
+// Each UserOperation of an ERC-4337 bundle, analyzed heuristically as the
+// account's own action. The LLM sees them as part of the bundle instead.
+async fn user_operations(
+    state: &AppState,
+    request: &AnalyzeTxRequest,
+    tx_details: &Value,
+) -> Option<Vec<UserOperation>> {
+    let ops = erc4337::decode_bundle(tx_details["input"].as_str()?)?;
+    let entry_point = tx_details["to"].as_str()?.to_lowercase();
+
+    let mut analyzed = Vec::with_capacity(ops.len());
+    for op in ops {
+        let calls = erc4337::account_calls(&op.call_data);
+        // A single call is the op's action itself; a batch is analyzed as the
+        // account's call, with the batched calls as its call sequence
+        let mut inner_details = match calls.as_slice() {
+            [call] => serde_json::json!({
+                "from": op.sender,
+                "to": call.to,
+                "value": blockchain::native_amount(request.network(), call.value),
+                "input": call.data,
+            }),
+            _ => serde_json::json!({
+                "from": entry_point,
+                "to": op.sender,
+                "value": blockchain::native_amount(request.network(), U256::zero()),
+                "input": op.call_data,
+            }),
+        };
+        // The logs (and status) are the whole bundle's, as for a Safe's inner call
+        for key in ["hash", "status", "block_number", "block_timestamp", "logs", "source"] {
+            inner_details[key] = tx_details[key].clone();
+        }
+        if calls.len() > 1 {
+            let root = serde_json::json!({
+                "type": "CALL",
+                "from": entry_point,
+                "to": op.sender,
+                "input": op.call_data,
+                "depth": 0,
+            });
+            let batched = calls.iter().map(|call| {
+                serde_json::json!({
+                    "type": "CALL",
+                    "from": op.sender,
+                    "to": call.to,
+                    "input": call.data,
+                    "value": format!("0x{:x}", call.value),
+                    "depth": 1,
+                })
+            });
+            inner_details["calls"] = std::iter::once(root).chain(batched).collect();
+        }
+        let explorer = state.explorer(request.network());
+        if let Some(method) = resolve_method(explorer, &inner_details).await {
+            inner_details["method"] = Value::String(method);
+        }
+
+        let options = LlmOptions::new(&state.config, request);
+        let Ok(mut inner) = ai::analyze_transaction(
+            None,
+            request.network(),
+            &request.tx_hash,
+            &inner_details,
+            Vec::new(),
+            options,
+        )
+        .await
+        else {
+            continue;
+        };
+        inner.analyzer_version = analyzer_version("heuristic", &state.rules_hash);
+
+        analyzed.push(UserOperation {
+            sender: op.sender,
+            nonce: op.nonce.to_string(),
+            paymaster: op.paymaster,
+            deploys_account: !op.init_code.is_empty(),
+            call_data: op.call_data,
+            calls: calls
+                .into_iter()
+                .map(|call| UserOperationCall {
+                    to: call.to,
+                    value: call.value.to_string(),
+                    data: call.data,
+                })
+                .collect(),
+            inner: Box::new(inner),
+        });
+    }
+    (!analyzed.is_empty()).then_some(analyzed)
+}
+
+// Bundled selector table first (offline, no lookups), then the verified ABI of
+// the contract holding the logic: the implementation when `to` is a proxy.
+async fn resolve_method(explorer: &ExplorerClient, tx_details: &Value) -> Option<String> {
+    let input = tx_details["input"].as_str()?;
+    let selector = input.get(..10)?;
+    if let Some(signature) = selectors::function_signature(selector) {
+        return Some(signature.to_string());
+    }
+
+    let logic = tx_details["proxy"]["implementation"]
+        .as_str()
+        .or(tx_details["to"].as_str())?;
+    explorer.function_signature(logic, selector).await
+}
+
+pub fn analyzer_version(model: &str, rules_hash: &str) -> String {
+    format!("{}/{}/{}", env!("CARGO_PKG_VERSION"), model, rules_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::testing::{self, MockChain, MockLlm, RECIPIENT, SENDER, TRANSFER_HASH};
+    use crate::network::Network;
+
+    // A contract call 200 blocks deep, past what a full node keeps state for
+    fn old_call() -> MockChain {
+        let tx = json!({
+            "hash": TRANSFER_HASH,
+            "from": SENDER,
+            "to": RECIPIENT,
+            "value": "0x0",
+            "input": "0x38ed1739",
+            "gas": "0x186a0",
+            "gasPrice": "0x3b9aca00",
+            "blockNumber": "0x1312d00",
+        });
+        MockChain::transfer()
+            .with_params("eth_getTransactionByHash", json!([TRANSFER_HASH]), tx)
+            .with("eth_blockNumber", json!("0x1312dc8"))
+    }
+
+    #[tokio::test]
+    async fn a_full_node_skips_the_historical_reads_with_a_note() {
+        let state = testing::state(old_call(), None);
+        assert!(state.archive_rpc(Network::EthereumMainnet).is_none());
+
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(
+            body["notes"],
+            json!([
+                "Skipped balance changes, contract ages (NEW_CONTRACT rule): needs historical \
+                 state, and no archive node is configured for ethereum-mainnet"
+            ])
+        );
+        assert!(body.get("balance_changes").is_none());
+        assert!(body["contracts"][0].get("deployment_block").is_none());
+    }
+
+    #[tokio::test]
+    async fn an_archival_main_node_serves_the_historical_reads() {
+        let mut config = testing::config();
+        for network in config.networks.values_mut() {
+            network.rpc_archival = true;
+        }
+        let state = testing::state_with(config, old_call(), None);
+        assert!(state.archive_rpc(Network::EthereumMainnet).is_some());
+
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(status, 200, "{}", body);
+        assert!(body.get("notes").is_none(), "{}", body);
+        assert_eq!(body["contracts"][0]["address"], RECIPIENT);
+    }
+
+    #[tokio::test]
+    async fn items_after_the_quota_runs_out_are_heuristic_only() {
+        // Any hash is the mined transfer
+        let tx = json!({
+            "from": SENDER,
+            "to": RECIPIENT,
+            "value": "0xde0b6b3a7640000",
+            "input": "0x",
+            "gas": "0x5208",
+            "gasPrice": "0x3b9aca00",
+            "blockNumber": "0x1312d00",
+        });
+        let receipt = json!({
+            "status": "0x1",
+            "gasUsed": "0x5208",
+            "blockHash": format!("0x{}", "f".repeat(64)),
+            "logs": [],
+        });
+        let chain = MockChain::transfer()
+            .with("eth_getTransactionByHash", tx)
+            .with("eth_getTransactionReceipt", receipt);
+        let llm = MockLlm::transfer().quota(2);
+        let state = testing::state(chain, Some(llm));
+
+        let tx_hashes: Vec<String> =
+            (1..=5).map(|i| format!("0x{}", i.to_string().repeat(64))).collect();
+        let request = testing::post_json(
+            "/analyze_batch",
+            json!({ "network": "ethereum-mainnet", "tx_hashes": tx_hashes }),
+        );
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(status, 200, "{}", body);
+        let summary = &body["summary"];
+        assert_eq!(summary["total"], 5, "{}", body);
+        assert_eq!(summary["errors"], 0);
+        assert_eq!(summary["llm_analyzed"], 2);
+        assert_eq!(summary["heuristic_only"], 3);
+        assert_eq!(summary["llm_budget_exhausted"], true);
+        // In input order, whichever items got the LLM
+        for (item, tx_hash) in body["results"].as_array().unwrap().iter().zip(&tx_hashes) {
+            assert_eq!(&item["tx_hash"], tx_hash);
+        }
+    }
+}
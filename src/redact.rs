@@ -0,0 +1,253 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json::Value;
+use thiserror::Error;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::config::Config;
+use crate::models::{AnalyzeTxResponse, BundleReport};
+use crate::util::keccak256;
+
+/// How addresses are rewritten in responses, history, and logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RedactionMode {
+    #[default]
+    Off,
+    // 0x1234...abcd
+    Mask,
+    // addr:<16 hex chars>, a keyed hash of the address
+    Hash,
+}
+
+impl FromStr for RedactionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(RedactionMode::Off),
+            "mask" => Ok(RedactionMode::Mask),
+            "hash" => Ok(RedactionMode::Hash),
+            other => Err(format!("unknown redaction mode '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RedactionError {
+    // An unkeyed hash of an address is trivially reversible
+    #[error("ADDRESS_REDACTION=hash requires REDACTION_KEY")]
+    MissingKey,
+}
+
+/// Rewrites every 20-byte hex address found in text. The output depends only
+/// on the address (case-insensitively) and the deployment's key, so the same
+/// address always maps to the same redacted value.
+#[derive(Clone)]
+pub struct Redactor {
+    mode: RedactionMode,
+    key: Vec<u8>,
+}
+
+impl Redactor {
+    /// `None` when redaction is off; an error when hashing is configured
+    /// without a key.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, RedactionError> {
+        let key = match config.address_redaction {
+            RedactionMode::Off => return Ok(None),
+            RedactionMode::Mask => Vec::new(),
+            RedactionMode::Hash => config
+                .redaction_key
+                .clone()
+                .ok_or(RedactionError::MissingKey)?
+                .into_bytes(),
+        };
+        Ok(Some(Self {
+            mode: config.address_redaction,
+            key,
+        }))
+    }
+
+    pub fn redact_address(&self, address: &str) -> String {
+        let address = address.to_ascii_lowercase();
+        match self.mode {
+            RedactionMode::Off => address,
+            RedactionMode::Mask => format!("{}...{}", &address[..6], &address[38..]),
+            RedactionMode::Hash => {
+                let mut input = self.key.clone();
+                input.extend_from_slice(address.as_bytes());
+                format!("addr:{}", hex::encode(&keccak256(&input)[..8]))
+            }
+        }
+    }
+
+    /// Replaces addresses (`0x` + exactly 40 hex digits) anywhere in `text`.
+    /// Longer hex strings such as tx hashes and calldata are left alone.
+    pub fn redact_text(&self, text: &str) -> String {
+        let bytes = text.as_bytes();
+        let mut out = String::with_capacity(text.len());
+        let mut copied = 0;
+        let mut i = 0;
+
+        while i + 42 <= bytes.len() {
+            let starts_word = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+            if starts_word && bytes[i] == b'0' && (bytes[i + 1] | 0x20) == b'x' {
+                let digits = bytes[i + 2..].iter().take_while(|b| b.is_ascii_hexdigit()).count();
+                let ends_word = bytes
+                    .get(i + 2 + digits)
+                    .is_none_or(|b| !b.is_ascii_alphanumeric());
+                if digits == 40 && ends_word {
+                    out.push_str(&text[copied..i]);
+                    out.push_str(&self.redact_address(&text[i..i + 42]));
+                    i += 42;
+                    copied = i;
+                    continue;
+                }
+                i += 2 + digits;
+                continue;
+            }
+            i += 1;
+        }
+        out.push_str(&text[copied..]);
+        out
+    }
+
+    /// Redacts every string in the analysis, including addresses mentioned in
+    /// risk reasons and the explanation.
+    pub fn redact_analysis(&self, analysis: &mut AnalyzeTxResponse) {
+        let Ok(mut value) = serde_json::to_value(&*analysis) else {
+            return;
+        };
+        self.redact_value(&mut value);
+        match serde_json::from_value(value) {
+            Ok(redacted) => *analysis = redacted,
+            Err(e) => tracing::warn!("Failed to redact analysis: {}", e),
+        }
+    }
+
+    /// Redacts the addresses a bundle report adds to its (already redacted)
+    /// analyses.
+    pub fn redact_bundle(&self, report: &mut BundleReport) {
+        let redact = |text: &mut String| *text = self.redact_text(text);
+        report.searcher.iter_mut().for_each(redact);
+        report.risk_reasons.iter_mut().for_each(redact);
+        report.net_flows.iter_mut().for_each(|flow| redact(&mut flow.token));
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.redact_text(s),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_value(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+}
+
+/// Replaces each of `secrets` found in `text` with `[REDACTED]`. Every
+/// non-empty secret counts, however short: a short key still mustn't leak,
+/// even if that mangles ordinary words containing it.
+pub fn redact_secrets(text: &str, secrets: &[&str]) -> String {
+    secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| text.replace(secret, "[REDACTED]"))
+}
+
+/// Log writer that redacts each formatted event before it reaches stdout.
+pub struct RedactingStdout(pub Redactor);
+
+pub struct RedactingWriter<'a> {
+    redactor: &'a Redactor,
+    inner: io::Stdout,
+}
+
+impl<'a> MakeWriter<'a> for RedactingStdout {
+    type Writer = RedactingWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            redactor: &self.0,
+            inner: io::stdout(),
+        }
+    }
+}
+
+impl Write for RedactingWriter<'_> {
+    // The fmt layer writes each event in one call, so addresses aren't split across writes
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(self.redactor.redact_text(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const ADDRESS: &str = "0xAbCdEf0123456789abcdef0123456789ABCDEF01";
+
+    fn redactor(
+        mode: RedactionMode,
+        key: Option<&str>,
+    ) -> Result<Option<Redactor>, RedactionError> {
+        let mut config = testing::config();
+        config.address_redaction = mode;
+        config.redaction_key = key.map(str::to_string);
+        Redactor::from_config(&config)
+    }
+
+    fn hashing(key: &str) -> Redactor {
+        redactor(RedactionMode::Hash, Some(key)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn masks_the_middle_of_addresses() {
+        let masking = redactor(RedactionMode::Mask, None).unwrap().unwrap();
+        assert_eq!(masking.redact_address(ADDRESS), "0xabcd...ef01");
+    }
+
+    #[test]
+    fn hashes_addresses_stably_per_key() {
+        let redacted = hashing("k1").redact_address(ADDRESS);
+        assert!(redacted.starts_with("addr:") && redacted.len() == 21, "{}", redacted);
+        // Case doesn't matter, and a new redactor with the same key agrees
+        assert_eq!(hashing("k1").redact_address(&ADDRESS.to_lowercase()), redacted);
+        assert_ne!(hashing("k2").redact_address(ADDRESS), redacted);
+    }
+
+    #[test]
+    fn redacts_addresses_in_text_only() {
+        let tx_hash = format!("0x{}", "ab".repeat(32));
+        let text = format!("Sent to {}, in {}", ADDRESS, tx_hash);
+        let masking = redactor(RedactionMode::Mask, None).unwrap().unwrap();
+        assert_eq!(masking.redact_text(&text), format!("Sent to 0xabcd...ef01, in {}", tx_hash));
+    }
+
+    #[test]
+    fn hashing_without_a_key_is_a_config_error() {
+        let result = redactor(RedactionMode::Hash, None);
+        assert!(matches!(result, Err(RedactionError::MissingKey)));
+        assert!(redactor(RedactionMode::Off, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn redacts_short_secrets() {
+        let text = "key=abc123 admin=xyz";
+        assert_eq!(
+            redact_secrets(text, &["abc123", "xyz"]),
+            "key=[REDACTED] admin=[REDACTED]"
+        );
+    }
+
+    #[test]
+    fn ignores_empty_secrets() {
+        assert_eq!(redact_secrets("nothing to hide", &[""]), "nothing to hide");
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::metrics::Metrics;
+use crate::network::Network;
+use crate::services::blockchain::parse_u64;
+use crate::state::SharedState;
+use crate::store::{ProvisionalAnalysis, StoreError};
+
+/// Spawns the re-check of provisional analyses unless REORG_CHECK_INTERVAL_SECS
+/// is 0. Each pass re-reads the receipt of every pending tx: once the tx has
+/// FINALITY_CONFIRMATIONS in the block it was analyzed in, the stored analysis
+/// is marked final; if it's gone from that block, the analysis is invalidated.
+/// A missing receipt only counts as gone once the node's head is that many
+/// blocks past the block: until then the node may just not have caught up.
+pub fn start_monitor(state: &SharedState) {
+    let interval = state.config.reorg_check_interval_secs;
+    if interval == 0 {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            match state.store.provisional_analyses().await {
+                Ok(pending) => recheck(&state, pending).await,
+                Err(e) => tracing::warn!("Failed to read provisional analyses: {}", e),
+            }
+        }
+    });
+}
+
+async fn recheck(state: &SharedState, pending: Vec<ProvisionalAnalysis>) {
+    let mut by_network: HashMap<Network, Vec<ProvisionalAnalysis>> = HashMap::new();
+    for analysis in pending {
+        if let Ok(network) = analysis.network.parse() {
+            by_network.entry(network).or_default().push(analysis);
+        }
+    }
+
+    for (network, pending) in by_network {
+        let Some(rpc) = state.rpc(network) else {
+            continue;
+        };
+        let mut calls = vec![("eth_blockNumber", json!([]))];
+        calls.extend(
+            pending
+                .iter()
+                .map(|analysis| ("eth_getTransactionReceipt", json!([analysis.tx_hash]))),
+        );
+        let mut results = rpc.batch(&calls).await.into_iter();
+        let head = match results.next() {
+            Some(Ok(head)) => parse_u64(&head),
+            _ => {
+                tracing::warn!("Reorg check skipped for {}: no chain head", network);
+                continue;
+            }
+        };
+
+        for (analysis, receipt) in pending.iter().zip(results) {
+            // An RPC error says nothing about the tx; it's retried next pass
+            let Ok(receipt) = receipt else {
+                continue;
+            };
+            let outcome = match receipt["blockHash"].as_str() {
+                Some(hash) if hash.eq_ignore_ascii_case(&analysis.block_hash) => {
+                    let block = parse_u64(&receipt["blockNumber"]);
+                    let confirmations = head.saturating_sub(block) + 1;
+                    if confirmations < state.config.finality_confirmations {
+                        continue;
+                    }
+                    state.store.finalize_analysis(analysis.analysis_id, confirmations).await
+                }
+                Some(_) => invalidate(state, network, analysis).await,
+                // No receipt: back in the mempool, or a node (say, after a
+                // failover) that hasn't seen the block yet
+                None if analysis.block_number.is_some_and(|block| {
+                    head.saturating_sub(block) + 1 >= state.config.finality_confirmations
+                }) =>
+                {
+                    invalidate(state, network, analysis).await
+                }
+                None => continue,
+            };
+            if let Err(e) = outcome {
+                tracing::warn!("Failed to update analysis {}: {}", analysis.analysis_id, e);
+            }
+        }
+    }
+}
+
+async fn invalidate(
+    state: &SharedState,
+    network: Network,
+    analysis: &ProvisionalAnalysis,
+) -> Result<(), StoreError> {
+    tracing::warn!(
+        "Tx {} on {} was reorged out of block {}; invalidating analysis {}",
+        analysis.tx_hash,
+        network,
+        analysis.block_hash,
+        analysis.analysis_id
+    );
+    Metrics::inc(&state.metrics.reorgs_detected);
+    state.store.invalidate_analysis(analysis.analysis_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::models::FeedbackRequest;
+    use crate::testing::{self, MockChain, TRANSFER_HASH};
+
+    const BLOCK_HASH: &str = "0x00000000000000000000000000000000000000000000000000000000000b10c0";
+    const BLOCK: u64 = 100;
+
+    // A provisional analysis of the transfer in BLOCK, with feedback on it,
+    // checked against a node at `head` answering `receipt`
+    async fn recheck_with(head: u64, receipt: Value) -> SharedState {
+        let chain = MockChain::default()
+            .with("eth_blockNumber", json!(format!("0x{:x}", head)))
+            .with("eth_getTransactionReceipt", receipt);
+        let mut config = testing::config();
+        config.finality_confirmations = 12;
+        let state = testing::state_with(config, chain, None);
+        let analysis = testing::analysis(TRANSFER_HASH).await;
+        let id = state.store.record_analyses(&[(&analysis, false)]).await.unwrap()[0];
+        let network = Network::EthereumMainnet;
+        let tracked = state.store.track_provisional(id, network, TRANSFER_HASH, BLOCK_HASH, BLOCK);
+        tracked.await.unwrap();
+        let feedback = FeedbackRequest {
+            tx_hash: TRANSFER_HASH.to_string(),
+            network,
+            field: "tx_type".to_string(),
+            correct_value: "SWAP".to_string(),
+            comment: None,
+        };
+        state.store.record_feedback(&feedback).await.unwrap();
+
+        recheck(&state, state.store.provisional_analyses().await.unwrap()).await;
+        state
+    }
+
+    async fn reorged_at(state: &SharedState) -> Option<i64> {
+        let records = state.store.list_analyses(Network::EthereumMainnet, TRANSFER_HASH).await;
+        records.unwrap()[0].reorged_at
+    }
+
+    fn receipt(block_hash: &str, block: u64) -> Value {
+        json!({ "blockHash": block_hash, "blockNumber": format!("0x{:x}", block) })
+    }
+
+    #[tokio::test]
+    async fn a_tx_moved_to_another_block_invalidates_its_analysis() {
+        let other = format!("0x{}", "a".repeat(64));
+        let state = recheck_with(BLOCK + 1, receipt(&other, BLOCK + 1)).await;
+
+        assert!(reorged_at(&state).await.is_some());
+        assert!(state.store.provisional_analyses().await.unwrap().is_empty());
+        assert_eq!(state.metrics.reorgs_detected.load(Ordering::Relaxed), 1);
+        // The user's correction outlives the reorg
+        assert_eq!(state.store.list_feedback().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_missing_receipt_waits_for_the_node_to_pass_the_block() {
+        // A node just behind the block has no receipt yet
+        let state = recheck_with(BLOCK + 5, Value::Null).await;
+        assert!(reorged_at(&state).await.is_none());
+        assert_eq!(state.store.provisional_analyses().await.unwrap().len(), 1);
+        assert_eq!(state.metrics.reorgs_detected.load(Ordering::Relaxed), 0);
+
+        // One past it by the finality depth would have it
+        let state = recheck_with(BLOCK + 11, Value::Null).await;
+        assert!(reorged_at(&state).await.is_some());
+        assert!(state.store.provisional_analyses().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_tx_final_in_its_block_is_finalized() {
+        let state = recheck_with(BLOCK + 11, receipt(BLOCK_HASH, BLOCK)).await;
+
+        assert!(reorged_at(&state).await.is_none());
+        assert!(state.store.provisional_analyses().await.unwrap().is_empty());
+        let records = state.store.list_analyses(Network::EthereumMainnet, TRANSFER_HASH).await;
+        let analysis = &records.unwrap()[0].analysis;
+        assert_eq!(analysis["provisional"], false);
+        assert_eq!(analysis["confirmations"], 12);
+    }
+}
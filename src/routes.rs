@@ -1,34 +1,1376 @@
-use axum::{Json, http::StatusCode};
-use crate::models::{AnalyzeTxRequest, AnalyzeTxResponse};
-use crate::services::{blockchain, ai};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use futures::StreamExt;
+use primitive_types::U256;
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, mpsc};
+
+use crate::config::Config;
+use crate::encoding::{self, Cased, Encoding, SchemaVersion};
+use crate::error::{ApiError, ApiJson, ApiPath, ApiQuery};
+use crate::fields::{FieldSelection, RESPONSE_FIELDS};
+use crate::metrics::{EndpointSeries, Metrics, NetworkSeries};
+use crate::models::{
+    AnalyzeBatchRequest, AnalyzeBatchResponse, AnalyzeBundleRequest, AnalyzeLogsRequest,
+    AnalyzeTxGetQuery, AnalyzeTxQuery, AnalyzeTxRequest, AnalyzeTxResponse, AnalyzeTxResponseV1,
+    BatchEvent, BatchItem, BatchProgress, DecodeRequest, FeedbackRequest, FeedbackResponse,
+    HistoryQuery, JobAccepted, JobStatus, ProtocolStats, ProtocolStatsQuery, ProtocolStatsResponse,
+    ReanalyzeHistoryRequest, RiskDistribution, SimilarQuery, SimilarResponse, SimilarTx,
+    StatsResponse,
+};
+use crate::network::Network;
+use crate::pipeline::{self, AnalysisError};
+use crate::services::blockchain::{self, BlockchainError};
+use crate::services::{decoder, risk, similarity};
+use crate::services::llm::LlmBudget;
+use crate::services::rpc::{ChainClient, EndpointHealth};
+use crate::shedding::ShedStatus;
+use crate::state::SharedState;
+
+/// The HTTP API over `state`. Takes the state rather than building it, so
+/// the same routes can be served over injected services (see
+/// `AppState::with_services`).
+pub fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/health/ready", get(ready))
+        .route("/metrics", get(metrics))
+        .route("/stats", get(stats))
+        .route("/stats/protocols", get(protocol_stats))
+        .route("/analyze_tx", post(analyze_tx))
+        .route("/analyze_tx/:network/:tx_hash", get(analyze_tx_get))
+        .route("/analyze_logs", post(analyze_logs))
+        .route("/decode", post(decode))
+        .route("/analyze_batch", post(analyze_batch))
+        .route("/analyze_bundle", post(analyze_bundle))
+        .route("/jobs", post(submit_job))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/cancel", post(cancel_job))
+        .route("/history", get(history))
+        .route("/similar/:tx_hash", get(similar))
+        .route("/feedback", post(submit_feedback).get(export_feedback))
+        .route("/admin/reanalyze_history", post(reanalyze_history))
+        .with_state(state)
+}
 
 pub async fn health() -> &'static str {
     "OK"
 }
 
+/// Readiness, with the load-shedding state. Stays 200 while shedding, since
+/// high-priority requests are still served.
+pub async fn ready(State(state): State<SharedState>) -> Json<ShedStatus> {
+    Json(state.shedder.status())
+}
+
+pub async fn stats(State(state): State<SharedState>) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        high_risk: state.high_risk.stats(),
+    })
+}
+
+// Window `/stats/protocols` aggregates over unless `window_secs` says otherwise
+const DEFAULT_PROTOCOL_STATS_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Counts, native value and risk of the stored analyses in the trailing
+/// window, per network and detected protocol.
+pub async fn protocol_stats(
+    State(state): State<SharedState>,
+    ApiQuery(query): ApiQuery<ProtocolStatsQuery>,
+) -> Result<Json<ProtocolStatsResponse>, ApiError> {
+    let window_secs = query.window_secs.unwrap_or(DEFAULT_PROTOCOL_STATS_WINDOW_SECS);
+    if window_secs == 0 {
+        return Err(ApiError::invalid_request("window_secs must be at least 1"));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let since = now.saturating_sub(window_secs).min(i64::MAX as u64) as i64;
+    let samples = state
+        .store
+        .protocol_samples(query.network, since)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+
+    // (tx count, value in wei, summed score, distribution) per (network, protocol)
+    let mut groups: BTreeMap<(Network, String), (u64, U256, f64, RiskDistribution)> =
+        BTreeMap::new();
+    let mut unattributed_tx_count = 0;
+    for sample in samples {
+        let (Some(protocol), Ok(network)) = (sample.protocol, sample.network.parse::<Network>())
+        else {
+            unattributed_tx_count += 1;
+            continue;
+        };
+        let value = sample
+            .value_wei
+            .and_then(|wei| U256::from_dec_str(&wei).ok())
+            .unwrap_or_default();
+        let group = groups.entry((network, protocol)).or_default();
+        group.0 += 1;
+        group.1 = group.1.saturating_add(value);
+        group.2 += sample.risk_score as f64;
+        match sample.risk_score {
+            score if score >= risk::HIGH_RISK_SCORE => group.3.high += 1,
+            score if score >= 0.5 => group.3.medium += 1,
+            _ => group.3.low += 1,
+        }
+    }
+
+    let mut protocols: Vec<ProtocolStats> = groups
+        .into_iter()
+        .map(|((network, protocol), (tx_count, value, score_sum, risk))| ProtocolStats {
+            network,
+            protocol,
+            tx_count,
+            total_value: blockchain::native_amount(network, value),
+            avg_risk_score: (score_sum / tx_count as f64) as f32,
+            risk,
+        })
+        .collect();
+    protocols.sort_by_key(|stats| std::cmp::Reverse(stats.tx_count));
+    Ok(Json(ProtocolStatsResponse {
+        window_secs,
+        network: query.network,
+        protocols,
+        unattributed_tx_count,
+    }))
+}
+
+pub async fn metrics(State(state): State<SharedState>) -> String {
+    let shedding = state.shedder.shedding() as u64;
+    state.metrics.load_shedding.store(shedding, Ordering::Relaxed);
+    let mut rpc: Vec<(&str, &dyn ChainClient)> = state
+        .rpc_clients
+        .iter()
+        .map(|(network, client)| (network.as_str(), client.as_ref()))
+        .collect();
+    rpc.sort_by_key(|(network, _)| *network);
+    let endpoints: Vec<(&str, EndpointHealth)> = rpc
+        .iter()
+        .flat_map(|(network, c)| c.health().into_iter().map(move |h| (*network, h)))
+        .collect();
+    let endpoint_values = |value: fn(&EndpointHealth) -> u64| {
+        endpoints
+            .iter()
+            .map(|(network, h)| (*network, h.index, value(h)))
+            .collect()
+    };
+
+    state.metrics.render(
+        &[
+            NetworkSeries {
+                name: "analyzer_network_in_flight",
+                help: "Analysis slots currently in use per network",
+                kind: "gauge",
+                values: state.limits.in_flight(),
+            },
+            NetworkSeries {
+                name: "analyzer_rpc_failovers_total",
+                help: "RPC requests that moved on to the next endpoint after one failed",
+                kind: "counter",
+                values: rpc
+                    .iter()
+                    .map(|(network, c)| (*network, c.failovers_total()))
+                    .collect(),
+            },
+        ],
+        &[
+            EndpointSeries {
+                name: "analyzer_rpc_endpoint_up",
+                help: "0 while the RPC endpoint is benched after repeated failures",
+                kind: "gauge",
+                values: endpoint_values(|h| !h.benched as u64),
+            },
+            EndpointSeries {
+                name: "analyzer_rpc_endpoint_failures_total",
+                help: "Transient failures (transport, 5xx, rate limits) of the RPC endpoint",
+                kind: "counter",
+                values: endpoint_values(|h| h.failures_total),
+            },
+            EndpointSeries {
+                name: "analyzer_rpc_throttle_delay_ms",
+                help: "Current spacing between RPC requests after rate limiting (0 = unthrottled)",
+                kind: "gauge",
+                values: endpoint_values(|h| h.throttle_delay.as_millis() as u64),
+            },
+            EndpointSeries {
+                name: "analyzer_rpc_rate_limited_total",
+                help: "HTTP 429 responses received from the RPC provider",
+                kind: "counter",
+                values: endpoint_values(|h| h.rate_limited_total),
+            },
+        ],
+    )
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(network = tracing::field::Empty, tx_hash = %payload.tx_hash)
+)]
 pub async fn analyze_tx(
-    Json(payload): Json<AnalyzeTxRequest>,
-) -> Result<Json<AnalyzeTxResponse>, (StatusCode, String)> {
-    // 1. Fetch raw tx details from blockchain (stub for now)
-    let tx_details = blockchain::fetch_transaction(&payload.network, &payload.tx_hash)
+    State(state): State<SharedState>,
+    ApiQuery(query): ApiQuery<AnalyzeTxQuery>,
+    headers: HeaderMap,
+    ApiJson(mut payload): ApiJson<AnalyzeTxRequest>,
+) -> Result<Response, ApiError> {
+    let fields = FieldSelection::parse(query.fields.as_deref()).map_err(ApiError::invalid_request)?;
+    let version = SchemaVersion::negotiate(&headers, query.schema_version.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    let network = resolve_network(&state, &mut payload)?;
+    tracing::Span::current().record("network", network.as_str());
+    state
+        .config
+        .check_model(payload.model.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_max_tokens(payload.max_tokens)
+        .map_err(ApiError::invalid_request)?;
+    let analysis = pipeline::analyze(&state, &payload, &fields, state.config.response_deadline());
+    run_analysis(&state, network, &headers, &fields, version, analysis).await
+}
+
+/// Cacheable `/analyze_tx`: the same analysis, addressed by URL. Final results
+/// get a long-lived `Cache-Control`, so a CDN can serve repeat lookups.
+#[tracing::instrument(skip_all, fields(network = %network, tx_hash = %tx_hash))]
+pub async fn analyze_tx_get(
+    State(state): State<SharedState>,
+    ApiPath((network, tx_hash)): ApiPath<(Network, String)>,
+    ApiQuery(query): ApiQuery<AnalyzeTxGetQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let fields = FieldSelection::parse(query.fields.as_deref()).map_err(ApiError::invalid_request)?;
+    let version = SchemaVersion::negotiate(&headers, query.schema_version.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_model(query.model.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_max_tokens(query.max_tokens)
+        .map_err(ApiError::invalid_request)?;
+    // No `consensus`: its samples differ between runs, so it's POST-only
+    let request = AnalyzeTxRequest {
+        network: Some(network),
+        tx_hash,
+        deep: query.deep,
+        model: query.model,
+        format: query.format,
+        verbosity: query.verbosity,
+        persona: query.persona,
+        include_trace: query.include_trace,
+        include_timing: query.include_timing,
+        include_prompt: query.include_prompt,
+        max_tokens: query.max_tokens,
+        ..AnalyzeTxRequest::default()
+    };
+    let analysis = pipeline::analyze(&state, &request, &fields, state.config.response_deadline());
+    run_analysis(&state, network, &headers, &fields, version, analysis).await
+}
+
+// Takes the network (and hash) from an explorer link sent as `tx_hash`, and
+// fills in DEFAULT_NETWORK for a request that omitted `network`
+fn resolve_network(
+    state: &SharedState,
+    request: &mut AnalyzeTxRequest,
+) -> Result<Network, ApiError> {
+    request.expand_explorer_link().map_err(ApiError::invalid_request)?;
+    let network = state
+        .config
+        .resolve_network(request.network)
+        .map_err(ApiError::invalid_request)?;
+    request.network = Some(network);
+    Ok(network)
+}
+
+/// `/analyze_tx` for a tx whose data the client sends (e.g. from a log indexer).
+pub async fn analyze_logs(
+    State(state): State<SharedState>,
+    ApiQuery(query): ApiQuery<AnalyzeTxQuery>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<AnalyzeLogsRequest>,
+) -> Result<Response, ApiError> {
+    let fields = FieldSelection::parse(query.fields.as_deref()).map_err(ApiError::invalid_request)?;
+    let version = SchemaVersion::negotiate(&headers, query.schema_version.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_model(payload.model.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_max_tokens(payload.max_tokens)
+        .map_err(ApiError::invalid_request)?;
+    let tx_details = blockchain::from_provided(&payload).map_err(ApiError::invalid_request)?;
+    let analysis = pipeline::analyze_provided(&state, &payload, tx_details, &fields);
+    run_analysis(&state, payload.network, &headers, &fields, version, analysis).await
+}
+
+// Runs a single analysis under the network's concurrency limit and the
+// analysis timeout, and encodes the (field-filtered) result in the
+// negotiated schema version, with a `Cache-Control` per finality.
+async fn run_analysis(
+    state: &SharedState,
+    network: Network,
+    headers: &HeaderMap,
+    fields: &FieldSelection,
+    version: SchemaVersion,
+    analysis: impl Future<Output = Result<AnalyzeTxResponse, AnalysisError>>,
+) -> Result<Response, ApiError> {
+    check_shedding(state, headers)?;
+    let _slot = acquire_slot(state, network, 1).await?;
+
+    // When the client disconnects, hyper drops this future; the guard records
+    // that the analysis was abandoned, and the RPC/LLM futures are dropped with it.
+    let mut guard = CancelGuard::new(&state.metrics);
+
+    let started = Instant::now();
+    let timeout = Duration::from_secs(state.config.analysis_timeout_secs);
+    let result = tokio::time::timeout(timeout, analysis).await;
+    guard.completed = true;
+    // An unknown tx hash is the client's mistake, not a sign of overload
+    let failed = !matches!(
+        &result,
+        Ok(Ok(_)) | Ok(Err(AnalysisError::Fetch(BlockchainError::NotFound(_))))
+    );
+    state.shedder.record(started.elapsed(), failed);
+
+    let analysis = result.map_err(|_| {
+        ApiError::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            "TIMEOUT",
+            format!("Analysis timed out after {}s", timeout.as_secs()),
+        )
+    })??;
+
+    let mut response = match version {
+        SchemaVersion::V1 => {
+            encode_analysis(state, headers, fields, &AnalyzeTxResponseV1::from(&analysis))?
+        }
+        SchemaVersion::V2 => encode_analysis(state, headers, fields, &analysis)?,
+    };
+    if response.status().is_success()
+        && Encoding::from_headers(headers) == Encoding::Json
+        && encoding::accepts_schema_media(headers)
+    {
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(version.media_type()),
+        );
+    }
+    if response.status().is_success() {
+        let cache_control = cache_control(&state.config, &analysis);
+        let response_headers = response.headers_mut();
+        response_headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_str(&cache_control).expect("valid header value"),
+        );
+        // The body depends on the negotiated encoding and schema version
+        response_headers.insert(header::VARY, HeaderValue::from_static("accept"));
+    }
+    Ok(response)
+}
+
+// How long an analysis may be cached depends on the tx's finality. A final
+// one only changes when the analyzer itself does (see `analyzer_version`). A
+// provisional one is good until the next block or so, when a reorg could undo
+// it. A pending tx, or mocked data (which isn't the chain's), isn't cached.
+fn cache_control(config: &Config, analysis: &AnalyzeTxResponse) -> String {
+    // A result degraded to meet the response deadline would come out fuller next time
+    let confirmed = analysis.confirmations.is_some()
+        && analysis.receipt_available
+        && analysis.deadline_skipped.is_empty();
+    match (confirmed, analysis.provisional) {
+        (true, false) if config.cache_max_age_final_secs > 0 => {
+            format!("public, max-age={}, immutable", config.cache_max_age_final_secs)
+        }
+        (true, true) if config.cache_max_age_recent_secs > 0 => {
+            format!("public, max-age={}", config.cache_max_age_recent_secs)
+        }
+        _ => "no-store".to_string(),
+    }
+}
+
+fn encode_analysis<T: Serialize>(
+    state: &SharedState,
+    headers: &HeaderMap,
+    fields: &FieldSelection,
+    analysis: &T,
+) -> Result<Response, ApiError> {
+    let encoding = Encoding::from_headers(headers);
+    let case = state.config.response_field_case;
+    if fields.is_all() {
+        return Ok(encoding.encode(&Cased(analysis, case)));
+    }
+    let body = fields
+        .apply(analysis)
+        .map_err(|e| ApiError::internal(format!("Failed to serialize response: {}", e)))?;
+    Ok(encoding.encode(&Cased(&body, case)))
+}
+
+/// Decodes calldata and/or logs only: no fetch, no analysis, no LLM.
+pub async fn decode(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<DecodeRequest>,
+) -> Result<Response, ApiError> {
+    let decoded = decoder::decode(&payload).map_err(ApiError::invalid_request)?;
+    let decoded = Cased(&decoded, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode(&decoded))
+}
+
+pub async fn analyze_batch(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<AnalyzeBatchRequest>,
+) -> Result<Response, ApiError> {
+    if payload.tx_hashes.is_empty() || payload.tx_hashes.len() > state.config.max_batch_size {
+        return Err(ApiError::invalid_request(format!(
+            "tx_hashes must contain between 1 and {} entries",
+            state.config.max_batch_size
+        )));
+    }
+
+    state
+        .config
+        .check_model(payload.model.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_max_tokens(payload.max_tokens)
+        .map_err(ApiError::invalid_request)?;
+
+    check_shedding(&state, &headers)?;
+    let slots = payload.tx_hashes.len().min(pipeline::BATCH_CONCURRENCY);
+    let slots = acquire_slot(&state, payload.network, slots).await?;
+
+    if encoding::accepts(&headers, encoding::NDJSON) {
+        return Ok(stream_batch(state, payload, slots));
+    }
+
+    let mut guard = CancelGuard::new(&state.metrics);
+    let budget = LlmBudget::new(state.config.batch_llm_budget);
+    let results = pipeline::analyze_batch(&state, &payload, &budget).await;
+    guard.completed = true;
+
+    let results = results?;
+    let mut summary = BatchProgress {
+        total: results.len(),
+        ..Default::default()
+    };
+    for item in &results {
+        tally(&mut summary, item);
+    }
+    summary.llm_budget_exhausted = budget.exhausted();
+    let response = AnalyzeBatchResponse {
+        network: payload.network,
+        results,
+        summary,
+    };
+    let response = Cased(&response, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode(&response))
+}
+
+/// Analyzes a MEV bundle's txs as a unit: each tx, then the strategy they
+/// carry out together and the searcher's profit.
+pub async fn analyze_bundle(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<AnalyzeBundleRequest>,
+) -> Result<Response, ApiError> {
+    if payload.txs.is_empty() || payload.txs.len() > state.config.max_batch_size {
+        return Err(ApiError::invalid_request(format!(
+            "txs must contain between 1 and {} entries",
+            state.config.max_batch_size
+        )));
+    }
+
+    state
+        .config
+        .check_model(payload.model.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_max_tokens(payload.max_tokens)
+        .map_err(ApiError::invalid_request)?;
+
+    check_shedding(&state, &headers)?;
+    let slots = payload.txs.len().min(pipeline::BATCH_CONCURRENCY);
+    let _slots = acquire_slot(&state, payload.network, slots).await?;
+
+    let mut guard = CancelGuard::new(&state.metrics);
+    let report = pipeline::analyze_bundle(&state, &payload).await;
+    guard.completed = true;
+
+    let report = Cased(&report?, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode(&report))
+}
+
+// NDJSON response for `/analyze_batch`: progress events every
+// PROGRESS_INTERVAL_MS, interleaved with each result as it completes. The batch
+// runs in its own task holding the slots; a client disconnect closes the
+// channel, which stops the task and cancels the remaining analyses.
+fn stream_batch(
+    state: SharedState,
+    request: AnalyzeBatchRequest,
+    slots: Option<OwnedSemaphorePermit>,
+) -> Response {
+    let (events, rx) = mpsc::channel::<BatchEvent>(16);
+    let case = state.config.response_field_case;
+
+    tokio::spawn(async move {
+        let _slots = slots;
+        let mut guard = CancelGuard::new(&state.metrics);
+        let mut progress = BatchProgress {
+            total: request.tx_hashes.len(),
+            ..Default::default()
+        };
+        let budget = LlmBudget::new(state.config.batch_llm_budget);
+
+        let items = match pipeline::batch_items(&state, &request, &budget).await {
+            Ok(items) => items,
+            Err(e) => {
+                guard.completed = true;
+                let _ = events.send(BatchEvent::Error { message: e.to_string() }).await;
+                return;
+            }
+        };
+        let mut items = std::pin::pin!(items);
+        let mut ticker = tokio::time::interval(Duration::from_millis(
+            state.config.progress_interval_ms.max(1),
+        ));
+
+        loop {
+            let event = tokio::select! {
+                item = items.next() => match item {
+                    Some(item) => {
+                        tally(&mut progress, &item);
+                        progress.llm_budget_exhausted = budget.exhausted();
+                        BatchEvent::Result(Box::new(item))
+                    }
+                    None => break,
+                },
+                _ = ticker.tick() => BatchEvent::Progress(progress.clone()),
+            };
+            if events.send(event).await.is_err() {
+                return;
+            }
+        }
+        guard.completed = true;
+        let _ = events.send(BatchEvent::Done(progress)).await;
+    });
+
+    let lines = futures::stream::unfold(rx, move |mut rx| async move {
+        let event = rx.recv().await?;
+        let mut line = serde_json::to_vec(&Cased(&event, case)).unwrap_or_default();
+        line.push(b'\n');
+        Some((Ok::<_, Infallible>(line), rx))
+    });
+    (
+        [(header::CONTENT_TYPE, encoding::NDJSON)],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+// Counts a finished batch item into the progress/summary
+fn tally(progress: &mut BatchProgress, item: &BatchItem) {
+    progress.processed += 1;
+    match &item.analysis {
+        Some(a) => {
+            if a.risk_score >= risk::HIGH_RISK_SCORE {
+                progress.matches += 1;
+            }
+            if a.ai_available {
+                progress.llm_analyzed += 1;
+            } else {
+                progress.heuristic_only += 1;
+            }
+        }
+        None => progress.errors += 1,
+    }
+}
+
+/// Queues an analysis for the worker pool and returns its job id right away.
+pub async fn submit_job(
+    State(state): State<SharedState>,
+    ApiJson(mut payload): ApiJson<AnalyzeTxRequest>,
+) -> Result<(StatusCode, Json<JobAccepted>), ApiError> {
+    resolve_network(&state, &mut payload)?;
+    state
+        .config
+        .check_model(payload.model.as_deref())
+        .map_err(ApiError::invalid_request)?;
+    state
+        .config
+        .check_max_tokens(payload.max_tokens)
+        .map_err(ApiError::invalid_request)?;
+
+    let job_id = state.jobs.submit(&state, payload).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobAccepted {
+            job_id,
+            status: JobStatus::Queued,
+        }),
+    ))
+}
+
+/// Job status, plus the analysis (or error) once it has finished.
+pub async fn get_job(
+    State(state): State<SharedState>,
+    ApiPath(id): ApiPath<i64>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let job = state
+        .store
+        .get_job(id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                format!("Failed to fetch tx details: {}", e),
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "JOB_NOT_FOUND",
+                format!("No job with id {}", id),
             )
         })?;
+    let job = Cased(&job, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode_cacheable(&headers, &job))
+}
 
-    // 2. Call AI analyzer with structured tx summary
-    let analysis = ai::analyze_transaction(&payload.network, &payload.tx_hash, &tx_details)
+/// Cancels a running history re-analysis; it stops after the tx in flight.
+/// Admin only.
+pub async fn cancel_job(
+    State(state): State<SharedState>,
+    ApiPath(id): ApiPath<i64>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<JobAccepted>), ApiError> {
+    authorize_admin(&state, &headers)?;
+    if !state.jobs.cancel(id).await {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "JOB_NOT_CANCELLABLE",
+            format!("Job {} isn't a running history re-analysis", id),
+        ));
+    }
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobAccepted {
+            job_id: id,
+            status: JobStatus::Running,
+        }),
+    ))
+}
+
+/// Re-runs the current pipeline over the latest stored analysis of every tx
+/// in history, as a background job, and updates the stored analyses. Admin only.
+pub async fn reanalyze_history(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<ReanalyzeHistoryRequest>,
+) -> Result<(StatusCode, Json<JobAccepted>), ApiError> {
+    authorize_admin(&state, &headers)?;
+    let job_id = state.jobs.start_reanalysis(&state, payload).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobAccepted {
+            job_id,
+            status: JobStatus::Running,
+        }),
+    ))
+}
+
+/// Past analyses of a tx (one per analysis run), with `ETag` support for polling.
+pub async fn history(
+    State(state): State<SharedState>,
+    ApiQuery(query): ApiQuery<HistoryQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let records = state
+        .store
+        .list_analyses(query.network, &query.tx_hash)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("AI analysis failed: {}", e),
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let records = Cased(&records, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode_cacheable(&headers, &records))
+}
+
+// Neighbors returned by `/similar` unless `limit` says otherwise
+const DEFAULT_SIMILAR_LIMIT: usize = 10;
+const MAX_SIMILAR_LIMIT: usize = 100;
+
+/// Past analyses closest to the tx's latest analysis by embedding similarity.
+pub async fn similar(
+    State(state): State<SharedState>,
+    ApiPath(tx_hash): ApiPath<String>,
+    ApiQuery(query): ApiQuery<SimilarQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if !state.config.embeddings_enabled || state.llm.is_none() {
+        return Err(ApiError::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "EMBEDDINGS_DISABLED",
+            "Similarity search needs EMBEDDINGS_ENABLED=true and an LLM provider",
+        ));
+    }
+
+    let model = state.config.embedding_model.clone();
+    let embeddings = state
+        .store
+        .latest_embeddings(query.network, &model)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let target = embeddings
+        .iter()
+        .find(|e| e.tx_hash == tx_hash)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "EMBEDDING_NOT_FOUND",
+                format!("No embedded analysis of {} on {}; analyze it first", tx_hash, query.network),
+            )
+        })?;
+
+    let mut neighbors: Vec<SimilarTx> = embeddings
+        .iter()
+        .filter(|e| e.tx_hash != tx_hash)
+        .map(|e| SimilarTx {
+            tx_hash: e.tx_hash.clone(),
+            analysis_id: e.analysis_id,
+            tx_type: e.tx_type.clone(),
+            risk_score: e.risk_score,
+            similarity: similarity::cosine(&target.vector, &e.vector),
+        })
+        .collect();
+    neighbors.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    neighbors.truncate(query.limit.unwrap_or(DEFAULT_SIMILAR_LIMIT).min(MAX_SIMILAR_LIMIT));
+
+    let response = SimilarResponse {
+        tx_hash,
+        network: query.network,
+        model,
+        neighbors,
+    };
+    let response = Cased(&response, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode(&response))
+}
+
+pub async fn submit_feedback(
+    State(state): State<SharedState>,
+    ApiJson(payload): ApiJson<FeedbackRequest>,
+) -> Result<(StatusCode, Json<FeedbackResponse>), ApiError> {
+    if !RESPONSE_FIELDS.contains(&payload.field.as_str()) {
+        return Err(ApiError::invalid_request(format!(
+            "Unknown field '{}'; valid fields: {}",
+            payload.field,
+            RESPONSE_FIELDS.join(", ")
+        )));
+    }
+
+    let (id, analysis_id) = state
+        .store
+        .record_feedback(&payload)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "ANALYSIS_NOT_FOUND",
+                format!("No analysis on record for {} on {}", payload.tx_hash, payload.network),
             )
         })?;
 
-    Ok(Json(analysis))
+    Ok((StatusCode::CREATED, Json(FeedbackResponse { id, analysis_id })))
+}
+
+pub async fn export_feedback(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    authorize_admin(&state, &headers)?;
+
+    let records = state
+        .store
+        .list_feedback()
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let records = Cased(&records, state.config.response_field_case);
+    Ok(Encoding::from_headers(&headers).encode_cacheable(&headers, &records))
+}
+
+// Admin endpoints require `Authorization: Bearer <ADMIN_API_KEY>` and are
+// unavailable when no key is configured.
+fn authorize_admin(state: &SharedState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = state.config.admin_api_key.as_deref() else {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "FORBIDDEN",
+            "Admin endpoints are disabled",
+        ));
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if provided != Some(expected) {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+            "Invalid or missing admin token",
+        ));
+    }
+    Ok(())
+}
+
+// Requests marked `X-Priority: high` are served even while shedding
+const PRIORITY_HEADER: &str = "x-priority";
+
+fn check_shedding(state: &SharedState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let high_priority = headers
+        .get(PRIORITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("high"));
+    if high_priority || !state.shedder.shedding() {
+        return Ok(());
+    }
+    Metrics::inc(&state.metrics.requests_shed);
+    Err(ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "OVERLOADED",
+        "Shedding load while latency or errors are elevated; retry shortly",
+    ))
+}
+
+async fn acquire_slot(
+    state: &SharedState,
+    network: Network,
+    slots: usize,
+) -> Result<Option<OwnedSemaphorePermit>, ApiError> {
+    state.limits.acquire(network, slots).await.map_err(|_| {
+        Metrics::inc(&state.metrics.requests_saturated);
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "OVERLOADED",
+            format!("Too many analyses in flight for {}; retry shortly", network),
+        )
+    })
+}
+
+struct CancelGuard<'a> {
+    metrics: &'a Metrics,
+    completed: bool,
+}
+
+impl<'a> CancelGuard<'a> {
+    fn new(metrics: &'a Metrics) -> Self {
+        Self {
+            metrics,
+            completed: false,
+        }
+    }
 }
 
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            tracing::info!("Client disconnected; in-flight analysis cancelled");
+            Metrics::inc(&self.metrics.requests_cancelled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use axum::http::{StatusCode, header};
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use crate::encoding::FieldCase;
+    use crate::network::Network;
+    use crate::services::rpc::RpcError;
+    use crate::testing::{self, MockChain, MockLlm, RECIPIENT, SENDER, TRANSFER_HASH};
+
+    #[tokio::test]
+    async fn health_is_ok() {
+        let state = testing::state(MockChain::default(), None);
+        let (status, body) = testing::send(&state, testing::get("/health")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "OK");
+    }
+
+    #[tokio::test]
+    async fn analyzes_a_transfer_with_the_llm() {
+        let llm = MockLlm::transfer();
+        let prompts = llm.prompts.clone();
+        let state = testing::state(MockChain::transfer(), Some(llm));
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["tx_hash"], TRANSFER_HASH);
+        assert_eq!(body["network"], "ethereum-mainnet");
+        assert_eq!(body["tx_type"], "TRANSFER");
+        assert_eq!(body["natural_language_explanation"], "A plain ETH transfer.");
+        assert_eq!(prompts.lock().unwrap().len(), 1);
+        assert!(prompts.lock().unwrap()[0].contains(RECIPIENT));
+    }
+
+    #[tokio::test]
+    async fn analyzes_with_heuristics_without_an_llm() {
+        let state = testing::state(MockChain::transfer(), None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["tx_type"], "TRANSFER");
+    }
+
+    #[tokio::test]
+    async fn flags_call_trees_past_the_trace_limits() {
+        let mut trace = json!({ "type": "CALL", "to": RECIPIENT });
+        for _ in 0..10 {
+            trace = json!({ "type": "CALL", "to": RECIPIENT, "calls": [trace] });
+        }
+        let mut config = testing::config();
+        config.trace_max_depth = 4;
+        let chain = MockChain::transfer().with("debug_traceTransaction", trace);
+        let state = testing::state_with(config, chain, None);
+        let request =
+            testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH, "deep": true }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let reasons = body["risk_reasons"].as_array().unwrap();
+        assert!(
+            reasons.iter().any(|r| r.as_str().unwrap().contains("exceeds 4 levels deep")),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_polygon_amounts_in_matic() {
+        let mut config = testing::config();
+        config.default_network = Some(Network::PolygonMainnet);
+        let state = testing::state_with(config, MockChain::transfer(), None);
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "network": "polygon-mainnet" });
+        let (status, body) = testing::send(&state, testing::post_json("/analyze_tx", payload)).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["network"], "polygon-mainnet");
+        let value = json!({
+            "wei": "1000000000000000000",
+            "formatted": "1",
+            "symbol": "MATIC",
+            "decimals": 18
+        });
+        assert_eq!(body["value"], value);
+    }
+
+    #[tokio::test]
+    async fn takes_the_network_from_a_polygonscan_link() {
+        let mut config = testing::config();
+        config.default_network = Some(Network::PolygonMainnet);
+        let state = testing::state_with(config, MockChain::transfer(), None);
+        let link = format!("https://polygonscan.com/tx/{}", TRANSFER_HASH);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": link }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["network"], "polygon-mainnet");
+        assert_eq!(body["tx_hash"], TRANSFER_HASH);
+        assert_eq!(body["value"]["symbol"], "MATIC");
+    }
+
+    #[tokio::test]
+    async fn consensus_requests_sample_the_llm_several_times() {
+        let llm = MockLlm::transfer();
+        let prompts = llm.prompts.clone();
+        let mut config = testing::config();
+        config.llm_consensus_samples = 3;
+        let state = testing::state_with(config, MockChain::transfer(), Some(llm));
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "consensus": true });
+        let (status, body) = testing::send(&state, testing::post_json("/analyze_tx", payload)).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(prompts.lock().unwrap().len(), 3);
+        assert_eq!(body["consensus"]["valid_samples"], 3);
+        assert_eq!(body["consensus"]["votes"], json!({ "TRANSFER": 3 }));
+        assert_eq!(body["consensus"]["consensus_confidence"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn consensus_confidence_is_the_majority_share_of_the_samples_asked_for() {
+        let verdict = |tx_type: &str| {
+            json!({
+                "tx_type": tx_type,
+                "protocol": null,
+                "risk_score": 0.1,
+                "risk_reasons": [],
+                "explanation": "A plain ETH transfer."
+            })
+        };
+        // One sample of the four comes back unusable
+        let llm = MockLlm::answering_in_turn(vec![
+            verdict("TRANSFER"),
+            verdict("DEX_SWAP"),
+            json!("not a verdict"),
+            verdict("TRANSFER"),
+        ]);
+        let mut config = testing::config();
+        config.llm_consensus_samples = 4;
+        let state = testing::state_with(config, MockChain::transfer(), Some(llm));
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "consensus": true });
+        let (status, body) = testing::send(&state, testing::post_json("/analyze_tx", payload)).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let consensus = &body["consensus"];
+        assert_eq!(consensus["samples"], 4);
+        assert_eq!(consensus["valid_samples"], 3);
+        assert_eq!(consensus["votes"], json!({ "DEX_SWAP": 1, "TRANSFER": 2 }));
+        assert_eq!(consensus["consensus_confidence"], 0.5);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_heuristics_when_the_llm_fails() {
+        let state = testing::state(MockChain::transfer(), Some(MockLlm::failing("boom")));
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["tx_type"], "TRANSFER");
+        let notes = body["notes"].as_array().unwrap();
+        assert!(notes.iter().any(|note| note.as_str().unwrap().contains("LLM analysis failed")));
+    }
+
+    #[tokio::test]
+    async fn returns_the_prompt_only_when_requested() {
+        let state = testing::state(MockChain::transfer(), Some(MockLlm::transfer()));
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (_, body) = testing::send(&state, request).await;
+        assert!(body.get("prompt").is_none(), "{}", body);
+
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "include_prompt": true });
+        let (status, body) = testing::send(&state, testing::post_json("/analyze_tx", payload)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["prompt"].as_str().unwrap().contains(TRANSFER_HASH));
+    }
+
+    #[tokio::test]
+    async fn unknown_tx_is_not_found() {
+        let state = testing::state(MockChain::default(), None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error_code"], "TX_NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn node_failure_is_a_fetch_error() {
+        let chain = MockChain::failing(RpcError::Transport("endpoint 0: connection refused".into()));
+        let state = testing::state(chain, None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error_code"], "FETCH_FAILED");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        let state = testing::state(MockChain::default(), None);
+        let cases = [
+            json!({}),
+            json!({ "tx_hash": TRANSFER_HASH, "network": "no-such-chain" }),
+            json!({ "tx_hash": TRANSFER_HASH, "max_tokens": 1_000_000 }),
+        ];
+        for payload in cases {
+            let request = testing::post_json("/analyze_tx", payload.clone());
+            let (status, body) = testing::send(&state, request).await;
+            assert!(status.is_client_error(), "{} -> {}", payload, status);
+            assert_eq!(body["error_code"], "INVALID_REQUEST", "{}", payload);
+        }
+    }
+
+    #[tokio::test]
+    async fn omitted_network_uses_the_default() {
+        let mut config = testing::config();
+        config.default_network = Some(Network::PolygonMainnet);
+        let state = testing::state_with(config, MockChain::transfer(), None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["network"], "polygon-mainnet");
+    }
+
+    #[tokio::test]
+    async fn omitted_network_without_a_default_is_rejected() {
+        let mut config = testing::config();
+        config.default_network = None;
+        let state = testing::state_with(config, MockChain::transfer(), None);
+        for uri in ["/analyze_tx", "/jobs"] {
+            let request = testing::post_json(uri, json!({ "tx_hash": TRANSFER_HASH }));
+            let (status, body) = testing::send(&state, request).await;
+
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(body["error_code"], "INVALID_REQUEST", "{}", uri);
+            assert!(body["message"].as_str().unwrap().contains("DEFAULT_NETWORK"), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn explorer_links_are_analyzed_as_their_tx() {
+        let state = testing::state(MockChain::transfer(), None);
+        let link = format!("https://etherscan.io/tx/{}#eventlog", TRANSFER_HASH);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": link }));
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["tx_hash"], TRANSFER_HASH);
+        assert_eq!(body["network"], "ethereum-mainnet");
+
+        let link = format!("https://example.com/tx/{}", TRANSFER_HASH);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": link }));
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error_code"], "INVALID_REQUEST");
+        assert!(body["message"].as_str().unwrap().contains("example.com"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn protocol_stats_aggregate_the_stored_analyses() {
+        let state = testing::state(MockChain::default(), None);
+        let mut analyses = Vec::new();
+        for (tx_hash, network, protocol, risk_score) in [
+            ("0x01", Network::EthereumMainnet, Some("Uniswap V2"), 0.2),
+            ("0x02", Network::EthereumMainnet, Some("Uniswap V2"), 0.8),
+            ("0x03", Network::EthereumMainnet, None, 0.1),
+            ("0x04", Network::PolygonMainnet, Some("Aave"), 0.6),
+        ] {
+            let mut analysis = testing::analysis(tx_hash).await;
+            analysis.network = network;
+            analysis.protocol = protocol.map(str::to_string);
+            analysis.risk_score = risk_score;
+            analyses.push(analysis);
+        }
+        let records: Vec<_> = analyses.iter().map(|a| (a, false)).collect();
+        state.store.record_analyses(&records).await.unwrap();
+
+        let uri = "/stats/protocols?network=ethereum-mainnet&window_secs=3600";
+        let (status, body) = testing::send(&state, testing::get(uri)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["window_secs"], 3600);
+        assert_eq!(body["unattributed_tx_count"], 1);
+        let protocols = body["protocols"].as_array().unwrap();
+        assert_eq!(protocols.len(), 1, "{}", body);
+        assert_eq!(protocols[0]["protocol"], "Uniswap V2");
+        assert_eq!(protocols[0]["tx_count"], 2);
+        assert_eq!(protocols[0]["total_value"]["wei"], "2000000000000000000");
+        assert!((protocols[0]["avg_risk_score"].as_f64().unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(protocols[0]["risk"], json!({ "low": 1, "medium": 0, "high": 1 }));
+
+        // Every network by default
+        let (_, body) = testing::send(&state, testing::get("/stats/protocols")).await;
+        assert_eq!(body["window_secs"], 24 * 60 * 60);
+        assert_eq!(body["protocols"].as_array().unwrap().len(), 2, "{}", body);
+
+        let request = testing::get("/stats/protocols?window_secs=0");
+        let (status, _) = testing::send(&state, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn values_near_the_uint256_max_come_out_exact() {
+        let tx = json!({
+            "hash": TRANSFER_HASH,
+            "from": SENDER,
+            "to": RECIPIENT,
+            "value": format!("0x{}e", "f".repeat(63)),
+            "input": "0x",
+            "gas": format!("0x1{}", "0".repeat(20)),
+            "gasPrice": "0x3b9aca00",
+            "blockNumber": "0x1312d00"
+        });
+        let chain = MockChain::transfer().with_params(
+            "eth_getTransactionByHash",
+            json!([TRANSFER_HASH]),
+            tx,
+        );
+        let state = testing::state(chain, None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        // 2^256 - 2, as a decimal string rather than a lossy JSON number
+        assert_eq!(
+            body["value"]["wei"],
+            "115792089237316195423570985008687907853269984665640564039457584007913129639934"
+        );
+        assert_eq!(
+            body["value"]["formatted"],
+            "115792089237316195423570985008687907853269984665640564039457.584007913129639934"
+        );
+    }
+
+    #[tokio::test]
+    async fn feedback_exports_follow_the_response_field_case() {
+        let mut config = testing::config();
+        config.admin_api_key = Some("admin-key".to_string());
+        config.response_field_case = FieldCase::Camel;
+        let state = testing::state_with(config, MockChain::default(), None);
+        let analysis = testing::analysis(TRANSFER_HASH).await;
+        state.store.record_analyses(&[(&analysis, false)]).await.unwrap();
+        let feedback = json!({
+            "tx_hash": TRANSFER_HASH,
+            "network": "ethereum-mainnet",
+            "field": "tx_type",
+            "correct_value": "DEX_SWAP"
+        });
+        let (status, body) = testing::send(&state, testing::post_json("/feedback", feedback)).await;
+        assert!(status.is_success(), "{}", body);
+
+        let mut request = testing::get("/feedback");
+        let bearer = header::HeaderValue::from_static("Bearer admin-key");
+        request.headers_mut().insert(header::AUTHORIZATION, bearer);
+        let (status, body) = testing::send(&state, request).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body[0]["txHash"], TRANSFER_HASH);
+        assert_eq!(body[0]["correctValue"], "DEX_SWAP");
+        assert!(body[0].get("correct_value").is_none(), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn jobs_respect_the_max_tokens_ceiling() {
+        let state = testing::state(MockChain::default(), None);
+        let payload = json!({ "tx_hash": TRANSFER_HASH, "max_tokens": 1_000_000 });
+        let (status, body) = testing::send(&state, testing::post_json("/jobs", payload)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error_code"], "INVALID_REQUEST");
+    }
+
+    #[tokio::test]
+    async fn every_analysis_route_respects_the_max_tokens_ceiling() {
+        let state = testing::state(MockChain::transfer(), None);
+        let requests = [
+            (
+                "/analyze_logs",
+                json!({
+                    "network": "ethereum-mainnet",
+                    "tx_hash": TRANSFER_HASH,
+                    "from": SENDER,
+                    "to": RECIPIENT,
+                    "value": "0",
+                    "logs": [],
+                }),
+            ),
+            (
+                "/analyze_batch",
+                json!({ "network": "ethereum-mainnet", "tx_hashes": [TRANSFER_HASH] }),
+            ),
+            (
+                "/analyze_bundle",
+                json!({ "network": "ethereum-mainnet", "txs": [TRANSFER_HASH] }),
+            ),
+        ];
+        for (uri, mut payload) in requests {
+            payload["max_tokens"] = json!(1_000_000);
+            let (status, body) = testing::send(&state, testing::post_json(uri, payload)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert!(body["message"].as_str().unwrap().contains("max_tokens"), "{}", body);
+        }
+    }
+
+    // The `Cache-Control` of a GET analysis of TRANSFER_HASH over `chain`
+    async fn cache_header(chain: MockChain) -> String {
+        let mut config = testing::config();
+        config.receipt_poll_attempts = 0;
+        let state = testing::state_with(config, chain, None);
+        let uri = format!("/analyze_tx/ethereum-mainnet/{}", TRANSFER_HASH);
+        let response = super::router(state).oneshot(testing::get(&uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.headers()[header::CACHE_CONTROL].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn final_analyses_are_cached_for_good() {
+        // 64 blocks deep, past the default 12 confirmations
+        let cache = cache_header(MockChain::transfer()).await;
+        assert_eq!(cache, "public, max-age=86400, immutable");
+    }
+
+    #[tokio::test]
+    async fn provisional_analyses_are_cached_briefly() {
+        let chain = MockChain::transfer().with("eth_blockNumber", json!("0x1312d02"));
+        assert_eq!(cache_header(chain).await, "public, max-age=12");
+    }
+
+    #[tokio::test]
+    async fn pending_analyses_are_not_cached() {
+        let pending = json!({
+            "hash": TRANSFER_HASH,
+            "from": SENDER,
+            "to": RECIPIENT,
+            "value": "0x0",
+            "input": "0x",
+            "blockNumber": null,
+        });
+        let chain = MockChain::transfer()
+            .with_params("eth_getTransactionByHash", json!([TRANSFER_HASH]), pending)
+            .with_params("eth_getTransactionReceipt", json!([TRANSFER_HASH]), Value::Null);
+        assert_eq!(cache_header(chain).await, "no-store");
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_not_found() {
+        let state = testing::state(MockChain::default(), None);
+        let (status, _) = testing::send(&state, testing::get("/nope")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn slow_analyses_time_out() {
+        let mut config = testing::config();
+        config.analysis_timeout_secs = 1;
+        let chain = MockChain::transfer().slow(Duration::from_secs(5));
+        let state = testing::state_with(config, chain, None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["error_code"], "TIMEOUT");
+        assert_eq!(state.metrics.requests_cancelled.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn counts_analyses_abandoned_by_the_client() {
+        let chain = MockChain::transfer().slow(Duration::from_secs(5));
+        let state = testing::state(chain, None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let client = tokio::spawn({
+            let state = state.clone();
+            async move { testing::send(&state, request).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // Hyper drops the handler's future the same way when the client goes away
+        client.abort();
+        assert!(client.await.unwrap_err().is_cancelled());
+
+        assert_eq!(state.metrics.requests_cancelled.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use futures::future::BoxFuture;
+use serde_json::Value;
+use thiserror::Error;
+
+// Env var values starting with this are resolved through a provider:
+// `secret://<provider>/<path>[#<json field>]`
+const REFERENCE_PREFIX: &str = "secret://";
+
+// Resolved values by env var name, set once at startup
+static RESOLVED: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Errors name the secret, never its value.
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("not a valid reference; expected secret://<provider>/<path>")]
+    InvalidReference,
+    #[error("unknown secrets provider '{0}'")]
+    UnknownProvider(String),
+    #[error("{0} isn't set")]
+    NotFound(String),
+    #[error("can't read {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("{0} is readable by group or others (mode {1:o}); chmod 600 it")]
+    #[cfg_attr(not(unix), allow(dead_code))]
+    InsecurePermissions(String, u32),
+    #[error("the secret has no JSON field '{0}'")]
+    MissingField(String),
+    #[error("{0} support isn't compiled in; build with `--features {0}`")]
+    #[cfg_attr(all(feature = "aws-secrets", feature = "gcp-secrets"), allow(dead_code))]
+    NotCompiled(&'static str),
+    #[error("secrets manager request failed: {0}")]
+    #[cfg_attr(not(any(feature = "aws-secrets", feature = "gcp-secrets")), allow(dead_code))]
+    Request(String),
+}
+
+/// A source of secrets, addressed by a path whose form is the provider's own.
+pub trait SecretsProvider: Send + Sync {
+    fn get<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<String, SecretError>>;
+}
+
+/// `secret://env/<VAR>`: another env var, for deployments that inject
+/// secrets under names of their own.
+pub struct EnvSecrets;
+
+impl SecretsProvider for EnvSecrets {
+    fn get<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<String, SecretError>> {
+        Box::pin(async move {
+            // A reference to a reference isn't followed
+            std::env::var(path)
+                .ok()
+                .filter(|value| !value.starts_with(REFERENCE_PREFIX))
+                .ok_or_else(|| SecretError::NotFound(path.to_string()))
+        })
+    }
+}
+
+/// `secret://file/<path>`: the contents of `/<path>` (e.g. a mounted
+/// Docker or Kubernetes secret), without the trailing newline. On unix the
+/// file must not be readable by group or others.
+pub struct FileSecrets;
+
+impl SecretsProvider for FileSecrets {
+    fn get<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<String, SecretError>> {
+        Box::pin(async move {
+            let path = format!("/{}", path.trim_start_matches('/'));
+            let metadata =
+                tokio::fs::metadata(&path).await.map_err(|e| SecretError::Read(path.clone(), e))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = metadata.permissions().mode() & 0o777;
+                if mode & 0o077 != 0 {
+                    return Err(SecretError::InsecurePermissions(path, mode));
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = metadata;
+            let contents =
+                tokio::fs::read_to_string(&path).await.map_err(|e| SecretError::Read(path, e))?;
+            Ok(contents.trim_end_matches(['\n', '\r']).to_string())
+        })
+    }
+}
+
+#[cfg_attr(not(any(feature = "aws-secrets", feature = "gcp-secrets")), allow(unused_variables))]
+fn provider(name: &str, http: &reqwest::Client) -> Result<Box<dyn SecretsProvider>, SecretError> {
+    match name {
+        "env" => Ok(Box::new(EnvSecrets)),
+        "file" => Ok(Box::new(FileSecrets)),
+        #[cfg(feature = "aws-secrets")]
+        "aws" => Ok(Box::new(aws::AwsSecrets::from_env(http.clone())?)),
+        #[cfg(not(feature = "aws-secrets"))]
+        "aws" => Err(SecretError::NotCompiled("aws-secrets")),
+        #[cfg(feature = "gcp-secrets")]
+        "gcp" => Ok(Box::new(gcp::GcpSecrets::new(http.clone()))),
+        #[cfg(not(feature = "gcp-secrets"))]
+        "gcp" => Err(SecretError::NotCompiled("gcp-secrets")),
+        other => Err(SecretError::UnknownProvider(other.to_string())),
+    }
+}
+
+/// Resolves every env var set to a `secret://` reference, before the config
+/// is read. Any failure is fatal: the error names the variable and its
+/// reference, never a value. Returns how many were resolved.
+pub async fn resolve_env() -> Result<usize, String> {
+    let references: Vec<(String, String)> = std::env::vars()
+        .filter(|(_, value)| value.starts_with(REFERENCE_PREFIX))
+        .collect();
+    let http = reqwest::Client::new();
+    let mut providers: HashMap<String, Box<dyn SecretsProvider>> = HashMap::new();
+    let mut resolved = HashMap::with_capacity(references.len());
+    for (key, reference) in references {
+        let value = resolve(&reference, &http, &mut providers)
+            .await
+            .map_err(|e| format!("Can't resolve {} ({}): {}", key, reference, e))?;
+        resolved.insert(key, value);
+    }
+    let count = resolved.len();
+    let _ = RESOLVED.set(resolved);
+    Ok(count)
+}
+
+async fn resolve(
+    reference: &str,
+    http: &reqwest::Client,
+    providers: &mut HashMap<String, Box<dyn SecretsProvider>>,
+) -> Result<String, SecretError> {
+    let rest = reference.strip_prefix(REFERENCE_PREFIX).ok_or(SecretError::InvalidReference)?;
+    let (rest, field) = match rest.split_once('#') {
+        Some((rest, field)) => (rest, Some(field)),
+        None => (rest, None),
+    };
+    let (name, path) = rest
+        .split_once('/')
+        .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+        .ok_or(SecretError::InvalidReference)?;
+    if !providers.contains_key(name) {
+        providers.insert(name.to_string(), provider(name, http)?);
+    }
+    let value = providers[name].get(path).await?;
+
+    // A field of a secret stored as a JSON object
+    let Some(field) = field else {
+        return Ok(value);
+    };
+    let object: Value = serde_json::from_str(&value).unwrap_or(Value::Null);
+    match &object[field] {
+        Value::String(s) => Ok(s.clone()),
+        Value::Null => Err(SecretError::MissingField(field.to_string())),
+        other => Ok(other.to_string()),
+    }
+}
+
+/// An env var's value for the config: the resolved secret for a reference,
+/// otherwise the raw value. A reference that wasn't resolved reads as unset.
+pub fn var(key: &str) -> Option<String> {
+    if let Some(value) = RESOLVED.get().and_then(|resolved| resolved.get(key)) {
+        return Some(value.clone());
+    }
+    std::env::var(key).ok().filter(|value| !value.starts_with(REFERENCE_PREFIX))
+}
+
+/// Whether `key` was resolved from a secrets provider, so its value must
+/// not be logged.
+pub fn is_secret(key: &str) -> bool {
+    RESOLVED.get().is_some_and(|resolved| resolved.contains_key(key))
+}
+
+#[cfg(feature = "aws-secrets")]
+mod aws {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use futures::future::BoxFuture;
+    use ring::{digest, hmac};
+    use serde_json::{Value, json};
+
+    use super::{SecretError, SecretsProvider};
+
+    const SERVICE: &str = "secretsmanager";
+
+    /// `secret://aws/<secret id or ARN>`: the SecretString of the secret's
+    /// current version from AWS Secrets Manager. Credentials and region come
+    /// from the standard AWS_* variables.
+    pub struct AwsSecrets {
+        http: reqwest::Client,
+        endpoint: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+    }
+
+    impl AwsSecrets {
+        pub fn from_env(http: reqwest::Client) -> Result<Self, SecretError> {
+            let var = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+            let required =
+                |key: &'static str| var(key).ok_or_else(|| SecretError::NotFound(key.to_string()));
+            let region = var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .ok_or_else(|| SecretError::NotFound("AWS_REGION".to_string()))?;
+            // Set for a local stand-in (e.g. LocalStack)
+            let endpoint = var("AWS_ENDPOINT_URL_SECRETS_MANAGER")
+                .unwrap_or_else(|| format!("https://{}.{}.amazonaws.com", SERVICE, region));
+            Ok(Self {
+                http,
+                endpoint: endpoint.trim_end_matches('/').to_string(),
+                region,
+                access_key_id: required("AWS_ACCESS_KEY_ID")?,
+                secret_access_key: required("AWS_SECRET_ACCESS_KEY")?,
+                session_token: var("AWS_SESSION_TOKEN"),
+            })
+        }
+
+        // SigV4 Authorization header for a POST to `/`
+        fn authorization(&self, host: &str, amz_date: &str, body: &str) -> String {
+            let date = &amz_date[..8];
+            let mut headers = vec![
+                ("content-type", "application/x-amz-json-1.1"),
+                ("host", host),
+                ("x-amz-date", amz_date),
+                ("x-amz-target", "secretsmanager.GetSecretValue"),
+            ];
+            if let Some(token) = &self.session_token {
+                headers.push(("x-amz-security-token", token));
+            }
+            headers.sort();
+            let canonical_headers: String =
+                headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+            let signed_headers =
+                headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+            let canonical_request = format!(
+                "POST\n/\n\n{}\n{}\n{}",
+                canonical_headers,
+                signed_headers,
+                sha256_hex(body)
+            );
+            let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                amz_date,
+                scope,
+                sha256_hex(&canonical_request)
+            );
+            let key = [date, &self.region, SERVICE, "aws4_request"].iter().fold(
+                format!("AWS4{}", self.secret_access_key).into_bytes(),
+                |key, part| sign(&key, part),
+            );
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id,
+                scope,
+                signed_headers,
+                hex::encode(sign(&key, &string_to_sign))
+            )
+        }
+    }
+
+    impl SecretsProvider for AwsSecrets {
+        fn get<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<String, SecretError>> {
+            Box::pin(async move {
+                let url = reqwest::Url::parse(&format!("{}/", self.endpoint))
+                    .map_err(|e| SecretError::Request(e.to_string()))?;
+                let host = match (url.host_str(), url.port()) {
+                    (Some(host), Some(port)) => format!("{}:{}", host, port),
+                    (Some(host), None) => host.to_string(),
+                    (None, _) => return Err(SecretError::Request("endpoint has no host".into())),
+                };
+                let body = json!({ "SecretId": path }).to_string();
+                let amz_date = amz_date();
+                let mut request = self
+                    .http
+                    .post(url)
+                    .header("content-type", "application/x-amz-json-1.1")
+                    .header("x-amz-date", &amz_date)
+                    .header("x-amz-target", "secretsmanager.GetSecretValue")
+                    .header("authorization", self.authorization(&host, &amz_date, &body));
+                if let Some(token) = &self.session_token {
+                    request = request.header("x-amz-security-token", token);
+                }
+                let response = request
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(|e| SecretError::Request(e.to_string()))?;
+                let status = response.status();
+                let json: Value =
+                    response.json().await.map_err(|e| SecretError::Request(e.to_string()))?;
+                if !status.is_success() {
+                    // The error type and message; the body holds no secret
+                    let kind = json["__type"].as_str().unwrap_or("unknown error");
+                    return Err(SecretError::Request(format!("{} ({})", kind, status)));
+                }
+                json["SecretString"].as_str().map(str::to_string).ok_or_else(|| {
+                    SecretError::Request("the secret has no SecretString".to_string())
+                })
+            })
+        }
+    }
+
+    fn sha256_hex(data: &str) -> String {
+        hex::encode(digest::digest(&digest::SHA256, data.as_bytes()))
+    }
+
+    fn sign(key: &[u8], data: &str) -> Vec<u8> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        hmac::sign(&key, data.as_bytes()).as_ref().to_vec()
+    }
+
+    // Now as YYYYMMDD'T'HHMMSS'Z'
+    fn amz_date() -> String {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let (days, time) = (secs / 86400, secs % 86400);
+        // Civil date from days since the epoch (Howard Hinnant's algorithm)
+        let z = days as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    }
+}
+
+#[cfg(feature = "gcp-secrets")]
+mod gcp {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use futures::future::BoxFuture;
+    use serde_json::Value;
+
+    use super::{SecretError, SecretsProvider};
+
+    const METADATA_TOKEN_URL: &str =
+        "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+    /// `secret://gcp/<project>/<secret>[/<version>]`: a secret version from
+    /// Google Secret Manager, `latest` by default. Authenticates with
+    /// GOOGLE_OAUTH_ACCESS_TOKEN when set, otherwise the instance's service
+    /// account from the metadata server.
+    pub struct GcpSecrets {
+        http: reqwest::Client,
+    }
+
+    impl GcpSecrets {
+        pub fn new(http: reqwest::Client) -> Self {
+            Self { http }
+        }
+
+        async fn access_token(&self) -> Result<String, SecretError> {
+            if let Some(token) =
+                std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN").ok().filter(|t| !t.trim().is_empty())
+            {
+                return Ok(token);
+            }
+            let json: Value = self
+                .http
+                .get(METADATA_TOKEN_URL)
+                .header("Metadata-Flavor", "Google")
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| SecretError::Request(format!("metadata server: {}", e)))?
+                .json()
+                .await
+                .map_err(|e| SecretError::Request(e.to_string()))?;
+            json["access_token"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| SecretError::Request("metadata server gave no token".to_string()))
+        }
+    }
+
+    impl SecretsProvider for GcpSecrets {
+        fn get<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<String, SecretError>> {
+            Box::pin(async move {
+                let parts: Vec<&str> = path.split('/').collect();
+                let (project, secret, version) = match parts.as_slice() {
+                    [project, secret] => (*project, *secret, "latest"),
+                    [project, secret, version] => (*project, *secret, *version),
+                    _ => return Err(SecretError::InvalidReference),
+                };
+                let url = format!(
+                    "https://secretmanager.googleapis.com/v1/projects/{}/secrets/{}/versions/{}:access",
+                    project, secret, version
+                );
+                let response = self
+                    .http
+                    .get(url)
+                    .bearer_auth(self.access_token().await?)
+                    .send()
+                    .await
+                    .map_err(|e| SecretError::Request(e.to_string()))?;
+                let status = response.status();
+                let json: Value =
+                    response.json().await.map_err(|e| SecretError::Request(e.to_string()))?;
+                if !status.is_success() {
+                    let message = json["error"]["status"].as_str().unwrap_or("unknown error");
+                    return Err(SecretError::Request(format!("{} ({})", message, status)));
+                }
+                let data = json["payload"]["data"].as_str().unwrap_or("");
+                let bytes = STANDARD
+                    .decode(data)
+                    .map_err(|_| SecretError::Request("payload isn't base64".to_string()))?;
+                String::from_utf8(bytes)
+                    .map_err(|_| SecretError::Request("payload isn't UTF-8".to_string()))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers `secret://mock/<path>` with the path's entry
+    struct MockSecrets(HashMap<&'static str, &'static str>);
+
+    impl SecretsProvider for MockSecrets {
+        fn get<'a>(&'a self, path: &'a str) -> BoxFuture<'a, Result<String, SecretError>> {
+            Box::pin(async move {
+                self.0
+                    .get(path)
+                    .map(|value| value.to_string())
+                    .ok_or_else(|| SecretError::NotFound(path.to_string()))
+            })
+        }
+    }
+
+    async fn resolve_mock(reference: &str) -> Result<String, SecretError> {
+        let secrets = HashMap::from([
+            ("llm/key", "sk-plain"),
+            ("prod/app", r#"{"db": "postgres://app", "port": 5432}"#),
+        ]);
+        let mut providers: HashMap<String, Box<dyn SecretsProvider>> =
+            HashMap::from([("mock".to_string(), Box::new(MockSecrets(secrets)) as _)]);
+        resolve(reference, &reqwest::Client::new(), &mut providers).await
+    }
+
+    #[tokio::test]
+    async fn resolves_values_and_json_fields() {
+        assert_eq!(resolve_mock("secret://mock/llm/key").await.unwrap(), "sk-plain");
+        assert_eq!(resolve_mock("secret://mock/prod/app#db").await.unwrap(), "postgres://app");
+        assert_eq!(resolve_mock("secret://mock/prod/app#port").await.unwrap(), "5432");
+        assert!(matches!(
+            resolve_mock("secret://mock/prod/app#user").await,
+            Err(SecretError::MissingField(field)) if field == "user"
+        ));
+        assert!(matches!(
+            resolve_mock("secret://mock/llm/key#field").await,
+            Err(SecretError::MissingField(_))
+        ));
+        assert!(matches!(
+            resolve_mock("secret://mock/missing").await,
+            Err(SecretError::NotFound(path)) if path == "missing"
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_references_and_unknown_providers() {
+        let malformed = ["secret://", "secret://mock", "secret://mock/", "secret:///path", "env/X"];
+        for reference in malformed {
+            assert!(
+                matches!(resolve_mock(reference).await, Err(SecretError::InvalidReference)),
+                "{}",
+                reference
+            );
+        }
+        assert!(matches!(
+            resolve_mock("secret://vault/key").await,
+            Err(SecretError::UnknownProvider(name)) if name == "vault"
+        ));
+        #[cfg(not(feature = "aws-secrets"))]
+        assert!(matches!(
+            resolve_mock("secret://aws/key").await,
+            Err(SecretError::NotCompiled("aws-secrets"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn env_secrets_read_other_variables() {
+        assert!(EnvSecrets.get("PATH").await.is_ok());
+        assert!(matches!(
+            EnvSecrets.get("SECRETS_TEST_UNSET_VARIABLE").await,
+            Err(SecretError::NotFound(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn file_secrets_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("secret-test-{}", std::process::id()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+        let reference = path.to_str().unwrap();
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(FileSecrets.get(reference).await.unwrap(), "hunter2");
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let error = FileSecrets.get(reference).await.unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(error, SecretError::InsecurePermissions(_, 0o644)));
+        // The error names the file, never its contents
+        assert!(!error.to_string().contains("hunter2"));
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::Instant;
+
+use serde_json::json;
+
+use crate::fields::FieldSelection;
+use crate::models::AnalyzeTxRequest;
+use crate::network::Network;
+use crate::pipeline;
+use crate::services::decode;
+use crate::state::AppState;
+
+// Known transactions every instance should be able to analyze
+const FIXTURES: &[(Network, &str)] = &[
+    // First ETH transfer ever (block 46147)
+    (
+        Network::EthereumMainnet,
+        "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060",
+    ),
+];
+
+// approve(0x…bb, MAX_UINT): must decode from the bundled selector table
+const APPROVE_CALLDATA: &str = "0x095ea7b3000000000000000000000000bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+struct Report {
+    failures: usize,
+    total: usize,
+}
+
+impl Report {
+    fn record(&mut self, name: &str, started: Instant, result: Result<String, String>) {
+        self.total += 1;
+        let ms = started.elapsed().as_millis();
+        match result {
+            Ok(detail) => println!("PASS  {:<28} {} ({} ms)", name, detail, ms),
+            Err(e) => {
+                self.failures += 1;
+                println!("FAIL  {:<28} {} ({} ms)", name, e, ms);
+            }
+        }
+    }
+
+    fn skip(&self, name: &str, reason: &str) {
+        println!("SKIP  {:<28} {}", name, reason);
+    }
+}
+
+/// One-shot `--self-test`: checks RPC and LLM connectivity, then runs the
+/// fixture transactions through the normal analysis pipeline. Prints a
+/// report and returns whether every check passed.
+pub async fn run(state: &AppState) -> bool {
+    let mut report = Report { failures: 0, total: 0 };
+
+    for network in Network::ALL {
+        let name = format!("rpc {}", network);
+        match state.rpc(*network) {
+            Some(rpc) => {
+                let started = Instant::now();
+                let result = rpc
+                    .call("eth_blockNumber", json!([]))
+                    .await
+                    .map(|head| format!("head block {}", head.as_str().unwrap_or("?")))
+                    .map_err(|e| e.to_string());
+                report.record(&name, started, result);
+            }
+            None => report.skip(&name, "no RPC_URL configured; mocked data"),
+        }
+    }
+
+    match &state.llm {
+        Some(llm) => {
+            let started = Instant::now();
+            let result = llm
+                .complete(
+                    llm.model(),
+                    "Reply with the JSON object {\"ok\": true}.",
+                    None,
+                    state.config.llm_max_tokens,
+                )
+                .await
+                .map(|_| format!("model {}", llm.model()))
+                .map_err(|e| e.to_string());
+            report.record("llm", started, result);
+        }
+        None => report.skip("llm", "LLM_API_KEY not set; heuristic-only"),
+    }
+
+    let started = Instant::now();
+    let result = match decode::decode_calldata(APPROVE_CALLDATA) {
+        Some(call) if call.name == "approve" => Ok("approve(address,uint256)".to_string()),
+        other => Err(format!("unexpected decode result: {:?}", other.map(|c| c.name))),
+    };
+    report.record("decoder", started, result);
+
+    for (network, tx_hash) in FIXTURES {
+        let request = AnalyzeTxRequest {
+            network: Some(*network),
+            tx_hash: tx_hash.to_string(),
+            ..AnalyzeTxRequest::default()
+        };
+        let started = Instant::now();
+        let result = pipeline::analyze(state, &request, &FieldSelection::default(), None)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|analysis| {
+                if (0.0..=1.0).contains(&analysis.risk_score) {
+                    Ok(format!("{} risk {:.2}", analysis.tx_type, analysis.risk_score))
+                } else {
+                    Err(format!("risk_score {} out of range", analysis.risk_score))
+                }
+            });
+        report.record(&format!("analyze {}", &tx_hash[..10]), started, result);
+    }
+
+    if report.failures == 0 {
+        println!("Self-test passed ({} checks)", report.total);
+    } else {
+        println!(
+            "Self-test FAILED: {} of {} checks failed",
+            report.failures, report.total
+        );
+    }
+    report.failures == 0
+}
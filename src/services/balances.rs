@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+
+use primitive_types::U256;
+use serde_json::{Value, json};
+
+use crate::models::BalanceChange;
+use crate::network::Network;
+use crate::services::blockchain::parse_quantity;
+use crate::services::rpc::ChainClient;
+use crate::services::tokens::{self, TokenMetadataCache};
+
+// Balances checked per tx; each costs two reads in one batch request
+const MAX_BALANCES: usize = 20;
+
+// balanceOf(address)
+const BALANCE_OF_SELECTOR: &str = "0x70a08231";
+
+// Share-based tokens (stETH and the like) round each transfer by a wei or two
+const TOKEN_TOLERANCE: u64 = 2;
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+// What the tx's own transfers move in and out of one balance
+#[derive(Default)]
+struct Expected {
+    gain: U256,
+    loss: U256,
+}
+
+/// Every balance the tx's transfers touch, read at the end of the block
+/// before and of the tx's block and compared with what the transfers add up
+/// to. ERC-20 balances follow the `Transfer` logs. Native balances follow
+/// the value, fee and value-carrying calls, so they're only checked when
+/// those are all known: with a trace (deep mode), for a plain transfer, or
+/// for a failed tx, which only pays its fee. Reads go to `historical`.
+pub async fn reconcile(
+    network: Network,
+    historical: &dyn ChainClient,
+    tokens: &TokenMetadataCache,
+    tx_details: &Value,
+) -> Vec<BalanceChange> {
+    let Some(block) = tx_details["block_number"].as_u64().filter(|&block| block > 0) else {
+        return Vec::new();
+    };
+    let expected: Vec<((Option<String>, String), Expected)> =
+        expected_changes(tx_details).into_iter().take(MAX_BALANCES).collect();
+    if expected.is_empty() {
+        return Vec::new();
+    }
+    tokens
+        .load(network, historical, expected.iter().filter_map(|((token, _), _)| token.as_deref()))
+        .await;
+
+    let calls: Vec<(&str, Value)> = expected
+        .iter()
+        .flat_map(|((token, address), _)| {
+            [block - 1, block].map(|block| balance_call(token.as_deref(), address, block))
+        })
+        .collect();
+    let mut results = historical.batch(&calls).await.into_iter();
+
+    // A contract that isn't a token answers balanceOf with empty output
+    let mut balance = || {
+        let output = results.next()?.ok()?;
+        output.as_str().filter(|hex| hex.len() > 2)?;
+        Some(parse_quantity(&output))
+    };
+    let mut changes = Vec::new();
+    for ((token, address), expected) in expected {
+        let (Some(before), Some(after)) = (balance(), balance()) else {
+            continue;
+        };
+        let tolerance = if token.is_some() { TOKEN_TOLERANCE } else { 0 };
+        let (lhs, rhs) = (
+            before.saturating_add(expected.gain),
+            after.saturating_add(expected.loss),
+        );
+        let (symbol, decimals) = match &token {
+            Some(token) => tokens.metadata(network, token),
+            None => (
+                Some(network.native_symbol().to_string()),
+                Some(network.native_decimals()),
+            ),
+        };
+        changes.push(BalanceChange {
+            address,
+            token,
+            symbol,
+            decimals,
+            expected_change: signed(expected.gain, expected.loss),
+            actual_change: signed(after, before),
+            reconciled: lhs.max(rhs) - lhs.min(rhs) <= U256::from(tolerance),
+        });
+    }
+    changes
+}
+
+// (token, address) -> expected movement; `None` is the native currency
+fn expected_changes(tx_details: &Value) -> BTreeMap<(Option<String>, String), Expected> {
+    let mut expected: BTreeMap<(Option<String>, String), Expected> = BTreeMap::new();
+    let mut book = |token: Option<&str>, from: &str, to: &str, amount: U256| {
+        if amount.is_zero() {
+            return;
+        }
+        let token = token.map(str::to_string);
+        if from != ZERO_ADDRESS {
+            let entry = expected.entry((token.clone(), from.to_string())).or_default();
+            entry.loss = entry.loss.saturating_add(amount);
+        }
+        if to != ZERO_ADDRESS {
+            let entry = expected.entry((token, to.to_string())).or_default();
+            entry.gain = entry.gain.saturating_add(amount);
+        }
+    };
+
+    let address = |key: &str| tx_details[key].as_str().unwrap_or(ZERO_ADDRESS).to_lowercase();
+    let (sender, recipient) = (address("from"), address("to"));
+    let fee = U256::from(tx_details["gas_used"].as_u64().unwrap_or(0))
+        * U256::from(tx_details["gas_price"].as_u64().unwrap_or(0));
+    // The fee goes to the block producer, which isn't tracked
+    book(None, &sender, ZERO_ADDRESS, fee);
+    if tx_details["status"] == "failed" {
+        return expected;
+    }
+
+    for (token, from, to, amount) in tokens::transfer_logs(tx_details) {
+        book(Some(&token), &from, &to, amount);
+    }
+
+    let plain_transfer = tx_details["input"].as_str().is_none_or(|input| input.len() <= 2);
+    match tx_details["calls"].as_array() {
+        // The trace's first call is the tx itself, with its value
+        Some(calls) => {
+            for call in calls {
+                // Delegate and static calls carry no value of their own
+                let kind = call["type"].as_str().unwrap_or("").to_ascii_uppercase();
+                if matches!(kind.as_str(), "DELEGATECALL" | "STATICCALL") {
+                    continue;
+                }
+                let from = call["from"].as_str().unwrap_or(ZERO_ADDRESS).to_lowercase();
+                let to = call["to"].as_str().unwrap_or(ZERO_ADDRESS).to_lowercase();
+                book(None, &from, &to, parse_quantity(&call["value"]));
+            }
+        }
+        None if plain_transfer => {
+            let value = tx_details["value"]["wei"]
+                .as_str()
+                .and_then(|wei| U256::from_dec_str(wei).ok())
+                .unwrap_or_default();
+            book(None, &sender, &recipient, value);
+        }
+        // Contract calls can move native value internally, which only a trace shows
+        None => expected.retain(|(token, _), _| token.is_some()),
+    }
+    expected
+}
+
+fn balance_call(token: Option<&str>, address: &str, block: u64) -> (&'static str, Value) {
+    let block = format!("0x{:x}", block);
+    match token {
+        Some(token) => {
+            let data = format!("{}{:0>64}", BALANCE_OF_SELECTOR, address.trim_start_matches("0x"));
+            ("eth_call", json!([{ "to": token, "data": data }, block]))
+        }
+        None => ("eth_getBalance", json!([address, block])),
+    }
+}
+
+// `plus - minus` as a signed decimal string
+fn signed(plus: U256, minus: U256) -> String {
+    if plus >= minus {
+        (plus - minus).to_string()
+    } else {
+        format!("-{}", minus - plus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockChain, RECIPIENT, SENDER};
+
+    const TOKEN: &str = "0x70c0e070c0e070c0e070c0e070c0e070c0e070c0";
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+    fn word(value: u64) -> String {
+        format!("0x{:064x}", value)
+    }
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", address.trim_start_matches("0x"))
+    }
+
+    fn token_transfer(amount: u64) -> Value {
+        json!({
+            "address": TOKEN,
+            "topics": [TRANSFER, topic(SENDER), topic(RECIPIENT)],
+            "data": word(amount)
+        })
+    }
+
+    // A tx in block 100 paying a 21000 wei fee
+    fn tx(input: &str, wei: u64, logs: Vec<Value>) -> Value {
+        json!({
+            "block_number": 100,
+            "from": SENDER,
+            "to": RECIPIENT,
+            "input": input,
+            "gas_used": 21000,
+            "gas_price": 1,
+            "status": "success",
+            "value": { "wei": wei.to_string() },
+            "logs": logs
+        })
+    }
+
+    fn with_balance(chain: MockChain, address: &str, before: u64, after: u64) -> MockChain {
+        let balance = |wei: u64| json!(format!("0x{:x}", wei));
+        chain
+            .with_params("eth_getBalance", json!([address, "0x63"]), balance(before))
+            .with_params("eth_getBalance", json!([address, "0x64"]), balance(after))
+    }
+
+    fn with_token_balance(chain: MockChain, address: &str, before: u64, after: u64) -> MockChain {
+        let data = format!("{}{}", BALANCE_OF_SELECTOR, &topic(address)[2..]);
+        let call = |block: &str| json!([{ "to": TOKEN, "data": data }, block]);
+        chain
+            .with_params("eth_call", call("0x63"), json!(word(before)))
+            .with_params("eth_call", call("0x64"), json!(word(after)))
+    }
+
+    fn find<'a>(
+        changes: &'a [BalanceChange],
+        token: Option<&str>,
+        address: &str,
+    ) -> &'a BalanceChange {
+        changes
+            .iter()
+            .find(|change| change.token.as_deref() == token && change.address == address)
+            .unwrap_or_else(|| panic!("no change for {:?} {}", token, address))
+    }
+
+    #[tokio::test]
+    async fn reconciles_a_plain_transfer_with_its_fee() {
+        let chain = with_balance(MockChain::default(), SENDER, 100_000, 78_000);
+        let chain = with_balance(chain, RECIPIENT, 5, 1005);
+        let tokens = TokenMetadataCache::default();
+        let changes =
+            reconcile(Network::EthereumMainnet, &chain, &tokens, &tx("0x", 1000, vec![])).await;
+
+        assert_eq!(changes.len(), 2);
+        let sender = find(&changes, None, SENDER);
+        assert_eq!(sender.expected_change, "-22000");
+        assert_eq!(sender.actual_change, "-22000");
+        assert!(sender.reconciled);
+        assert_eq!(sender.symbol.as_deref(), Some("ETH"));
+        let recipient = find(&changes, None, RECIPIENT);
+        assert_eq!(recipient.actual_change, "1000");
+        assert!(recipient.reconciled);
+    }
+
+    #[tokio::test]
+    async fn flags_a_balance_the_transfers_do_not_explain() {
+        // The recipient got 10 less than the log says, as from a transfer fee;
+        // the sender's 1 wei gap is within share rounding
+        let chain = with_token_balance(MockChain::default(), SENDER, 1000, 501);
+        let chain = with_token_balance(chain, RECIPIENT, 0, 490);
+        let tokens = TokenMetadataCache::default();
+        let tx = tx("0xa9059cbb", 0, vec![token_transfer(500)]);
+        let changes = reconcile(Network::EthereumMainnet, &chain, &tokens, &tx).await;
+
+        // Without a trace, native balances of a contract call aren't checked
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|change| change.token.as_deref() == Some(TOKEN)));
+        assert!(find(&changes, Some(TOKEN), SENDER).reconciled);
+        let recipient = find(&changes, Some(TOKEN), RECIPIENT);
+        assert_eq!(recipient.expected_change, "500");
+        assert_eq!(recipient.actual_change, "490");
+        assert!(!recipient.reconciled);
+    }
+
+    #[tokio::test]
+    async fn skips_balances_that_cannot_be_read() {
+        // Only the sender's balances are known; the node answers null for the rest
+        let chain = with_balance(MockChain::default(), SENDER, 100_000, 78_000);
+        let tokens = TokenMetadataCache::default();
+        let changes =
+            reconcile(Network::EthereumMainnet, &chain, &tokens, &tx("0x", 1000, vec![])).await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].address, SENDER);
+
+        let mut pending = tx("0x", 1000, vec![]);
+        pending["block_number"] = Value::Null;
+        assert!(reconcile(Network::EthereumMainnet, &chain, &tokens, &pending).await.is_empty());
+    }
+
+    #[test]
+    fn books_traced_calls_and_only_the_fee_of_failed_txs() {
+        let mut traced = tx("0x12345678", 1000, vec![]);
+        traced["calls"] = json!([
+            { "type": "CALL", "from": SENDER, "to": RECIPIENT, "value": "0x3e8" },
+            { "type": "CALL", "from": RECIPIENT, "to": TOKEN, "value": "0x64" },
+            { "type": "DELEGATECALL", "from": TOKEN, "to": SENDER, "value": "0x64" }
+        ]);
+        let expected = expected_changes(&traced);
+        let native = |address: &str| {
+            let entry = &expected[&(None, address.to_string())];
+            signed(entry.gain, entry.loss)
+        };
+        assert_eq!(native(SENDER), "-22000");
+        assert_eq!(native(RECIPIENT), "900");
+        assert_eq!(native(TOKEN), "100");
+
+        let mut failed = tx("0x", 1000, vec![token_transfer(500)]);
+        failed["status"] = json!("failed");
+        let expected = expected_changes(&failed);
+        assert_eq!(expected.len(), 1);
+        assert_eq!(expected[&(None, SENDER.to_string())].loss, U256::from(21000));
+    }
+}
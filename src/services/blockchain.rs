@@ -1,32 +1,614 @@
+use std::time::{Duration, Instant};
+
+use primitive_types::U256;
 use thiserror::Error;
-use serde_json::Value;
+use serde_json::{json, Value};
+
+use crate::models::{AnalyzeLogsRequest, Amount};
+use crate::network::Network;
+use crate::services::proxy;
+use crate::services::rpc::{ChainClient, RpcError};
+use crate::util::keccak256;
 
 #[derive(Debug, Error)]
 pub enum BlockchainError {
-    #[error("Unsupported network: {0}")]
-    UnsupportedNetwork(String),
+    #[error("Transaction not found: {0}")]
+    NotFound(String),
     #[error("RPC error: {0}")]
-    #[allow(dead_code)]
     RpcError(String),
 }
 
+/// How long to keep asking for a receipt that lags its tx right after inclusion.
+#[derive(Debug, Clone, Copy)]
+pub struct ReceiptWait {
+    pub attempts: u32,
+    pub interval: Duration,
+}
+
+/// Bounds on the deep-mode call trace, which a pathological contract can make
+/// arbitrarily deep or large. Frames past them are left out of `calls`, and
+/// the tx gets a `trace_truncated` marker (see the COMPLEX_CALL_TREE rule).
+#[derive(Debug, Clone, Copy)]
+pub struct TraceLimits {
+    // Deepest frame kept; the top-level call is depth 0
+    pub max_depth: usize,
+    pub max_calls: usize,
+}
+
+/// Fetches a tx (plus receipt, and the internal call trace in deep mode, i.e.
+/// when `trace` limits are given).
+/// Falls back to mocked data when no RPC endpoint is configured for the network.
 pub async fn fetch_transaction(
-    network: &str,
+    rpc: Option<&dyn ChainClient>,
+    network: Network,
+    tx_hash: &str,
+    trace: Option<TraceLimits>,
+    receipt_wait: ReceiptWait,
+) -> Result<Value, BlockchainError> {
+    fetch_transactions(rpc, network, &[tx_hash.to_string()], trace, receipt_wait)
+        .await?
+        .pop()
+        .expect("one result per requested hash")
+}
+
+/// Batch variant of `fetch_transaction`: everything needed for all hashes
+/// (tx, receipt, optional trace, plus the chain head) goes out as a single
+/// JSON-RPC batch. Results are returned per hash, in input order. A tx whose
+/// receipt isn't out yet is re-polled per `receipt_wait`, then analyzed from
+/// the tx alone (`receipt_available: false`).
+#[tracing::instrument(
+    name = "rpc.fetch_transactions",
+    skip_all,
+    fields(
+        network = %network,
+        count = tx_hashes.len(),
+        provider = tracing::field::Empty,
+        latency_ms = tracing::field::Empty
+    )
+)]
+pub async fn fetch_transactions(
+    rpc: Option<&dyn ChainClient>,
+    network: Network,
+    tx_hashes: &[String],
+    trace: Option<TraceLimits>,
+    receipt_wait: ReceiptWait,
+) -> Result<Vec<Result<Value, BlockchainError>>, BlockchainError> {
+    let started = Instant::now();
+
+    let span = tracing::Span::current();
+    let results = match rpc {
+        Some(rpc) => {
+            span.record("provider", "rpc");
+            fetch_from_rpc(rpc, network, tx_hashes, trace, receipt_wait).await
+        }
+        None => {
+            span.record("provider", "mock");
+            tx_hashes.iter().map(|h| Ok(mock_transaction(network, h))).collect()
+        }
+    };
+
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    Ok(results)
+}
+
+async fn fetch_from_rpc(
+    rpc: &dyn ChainClient,
+    network: Network,
+    tx_hashes: &[String],
+    limits: Option<TraceLimits>,
+    receipt_wait: ReceiptWait,
+) -> Vec<Result<Value, BlockchainError>> {
+    // Layout: [eth_blockNumber, (tx, receipt[, trace]) per hash]
+    let mut calls: Vec<(&str, Value)> = vec![("eth_blockNumber", json!([]))];
+    for hash in tx_hashes {
+        calls.push(("eth_getTransactionByHash", json!([hash])));
+        calls.push(("eth_getTransactionReceipt", json!([hash])));
+        if limits.is_some() {
+            calls.push(("debug_traceTransaction", json!([hash, { "tracer": "callTracer" }])));
+        }
+    }
+
+    let mut results = rpc.batch(&calls).await.into_iter();
+    let head = results.next().and_then(Result::ok);
+
+    let mut rows: Vec<_> = tx_hashes
+        .iter()
+        .map(|hash| {
+            let tx = results.next().expect("tx result");
+            let receipt = results.next().expect("receipt result");
+            let trace = limits.and_then(|limits| Some((results.next()?, limits)));
+            (hash, tx, receipt, trace)
+        })
+        .collect();
+
+    // Nodes can serve a just-included tx a moment before its receipt
+    futures::future::join_all(
+        rows.iter_mut()
+            .filter(|(_, tx, receipt, _)| {
+                tx.as_ref().is_ok_and(|tx| !tx.is_null())
+                    && receipt.as_ref().is_ok_and(Value::is_null)
+            })
+            .map(|(hash, _, receipt, _)| async move {
+                *receipt = Ok(wait_for_receipt(rpc, hash, receipt_wait).await);
+            }),
+    )
+    .await;
+
+    let mut fetched: Vec<Result<Value, BlockchainError>> = rows
+        .into_iter()
+        .map(|(hash, tx, receipt, trace)| {
+            assemble(network, hash, tx, receipt, trace, head.as_ref())
+        })
+        .collect();
+
+    // The block's time of a mined contract call, which permit deadlines are
+    // measured against (see the RISKY_PERMIT rule)
+    let blocks: Vec<Option<u64>> = fetched
+        .iter()
+        .map(|details| {
+            let details = details.as_ref().ok()?;
+            details["input"].as_str().filter(|input| input.len() > 2)?;
+            details["block_number"].as_u64()
+        })
+        .collect();
+    // Both need each tx's details, so they can't ride along in the batch above
+    let (_, timestamps) = futures::join!(
+        futures::future::join_all(
+            fetched
+                .iter_mut()
+                .filter_map(|r| r.as_mut().ok())
+                .map(|details| attach_proxy(rpc, details)),
+        ),
+        futures::future::join_all(blocks.into_iter().map(|block| async move {
+            let block = block?;
+            block_timestamp(rpc, block)
+                .await
+                .inspect_err(|e| tracing::warn!("Failed to read block {}'s time: {}", block, e))
+                .ok()
+        })),
+    );
+    for (details, timestamp) in fetched.iter_mut().zip(timestamps) {
+        if let (Ok(details), Some(timestamp)) = (details, timestamp) {
+            details["block_timestamp"] = json!(timestamp);
+        }
+    }
+    fetched
+}
+
+/// The unix time of `block`, from its header.
+pub async fn block_timestamp(rpc: &dyn ChainClient, block: u64) -> Result<u64, RpcError> {
+    let header = rpc
+        .call("eth_getBlockByNumber", json!([format!("0x{:x}", block), false]))
+        .await?;
+    header["timestamp"]
+        .as_str()
+        .and_then(|t| u64::from_str_radix(t.trim_start_matches("0x"), 16).ok())
+        .ok_or_else(|| RpcError::BadResponse(format!("block {} has no timestamp", block)))
+}
+
+// Null when the receipt is still missing after the last attempt
+async fn wait_for_receipt(rpc: &dyn ChainClient, tx_hash: &str, wait: ReceiptWait) -> Value {
+    for _ in 0..wait.attempts {
+        tokio::time::sleep(wait.interval).await;
+        match rpc.call("eth_getTransactionReceipt", json!([tx_hash])).await {
+            Ok(receipt) if !receipt.is_null() => return receipt,
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Receipt poll for {} failed: {}", tx_hash, e),
+        }
+    }
+    Value::Null
+}
+
+// Best effort: a failed slot read just leaves the tx without proxy info
+async fn attach_proxy(rpc: &dyn ChainClient, details: &mut Value) {
+    let has_calldata = details["input"].as_str().is_some_and(|i| i.len() > 2);
+    let Some(to) = details["to"].as_str().filter(|_| has_calldata).map(str::to_string) else {
+        return;
+    };
+
+    match proxy::resolve_implementation(rpc, &to).await {
+        Ok(Some(implementation)) => {
+            details["proxy"] = json!({ "address": to, "implementation": implementation });
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Proxy slot lookup failed for {}: {}", to, e),
+    }
+}
+
+fn assemble(
+    network: Network,
     tx_hash: &str,
+    tx: Result<Value, RpcError>,
+    receipt: Result<Value, RpcError>,
+    trace: Option<(Result<Value, RpcError>, TraceLimits)>,
+    head: Option<&Value>,
 ) -> Result<Value, BlockchainError> {
-    // TODO: Replace with real RPC call using ethers-rs or web3
-    // For now, return a mocked tx JSON
-    if network != "ethereum-mainnet" {
-        return Err(BlockchainError::UnsupportedNetwork(network.to_string()));
+    let rpc_err = |e: RpcError| BlockchainError::RpcError(e.to_string());
+
+    let tx = tx.map_err(rpc_err)?;
+    if tx.is_null() {
+        return Err(BlockchainError::NotFound(tx_hash.to_string()));
+    }
+
+    let receipt = receipt.map_err(rpc_err)?;
+
+    // Internal calls need a tracing-enabled node, so they're deep-mode only and
+    // best effort: analysis continues on top-level data if tracing fails.
+    let trace = trace.and_then(|(t, limits)| {
+        t.inspect_err(|e| tracing::warn!("debug_traceTransaction failed for {}: {}", tx_hash, e))
+            .ok()
+            .map(|t| (t, limits))
+    });
+
+    let trace = trace.as_ref().map(|(t, limits)| (t, *limits));
+    let mut details = normalize(network, &tx, &receipt, trace);
+    // Still in the mempool: no block, so no confirmations either
+    if tx["blockNumber"].is_null() {
+        if let Some(object) = details.as_object_mut() {
+            object.remove("block_number");
+        }
+    } else if let Some(head) = head {
+        let confirmations = parse_u64(head).saturating_sub(parse_u64(&tx["blockNumber"])) + 1;
+        details["confirmations"] = json!(confirmations);
+    }
+    Ok(details)
+}
+
+/// Tx details from client-provided data, in the same shape as fetched ones:
+/// the fields are validated, laid out like RPC tx/receipt objects, and
+/// normalized the same way. Fields the client can't know (gas used and limit,
+/// the block hash, and the block when not given) are left out rather than zeroed.
+pub fn from_provided(request: &AnalyzeLogsRequest) -> Result<Value, String> {
+    check_hex("tx_hash", &request.tx_hash, Some(32))?;
+    check_hex("from", &request.from, Some(20))?;
+    if let Some(to) = &request.to {
+        check_hex("to", to, Some(20))?;
+    }
+    if let Some(input) = &request.input {
+        check_hex("input", input, None)?;
     }
+    let value = match request.value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(&request.value).ok(),
+    }
+    .ok_or_else(|| format!("value '{}' is not a wei amount", request.value))?;
+
+    let mut logs = Vec::with_capacity(request.logs.len());
+    for (i, log) in request.logs.iter().enumerate() {
+        check_hex(&format!("logs[{}].address", i), &log.address, Some(20))?;
+        if log.topics.len() > 4 {
+            return Err(format!("logs[{}] has {} topics; at most 4", i, log.topics.len()));
+        }
+        for (j, topic) in log.topics.iter().enumerate() {
+            check_hex(&format!("logs[{}].topics[{}]", i, j), topic, Some(32))?;
+        }
+        check_hex(&format!("logs[{}].data", i), &log.data, None)?;
+        logs.push(json!({ "address": log.address, "topics": log.topics, "data": log.data }));
+    }
+
+    let tx = json!({
+        "hash": request.tx_hash,
+        "from": request.from,
+        "to": request.to,
+        "value": format!("0x{:x}", value),
+        "input": request.input.as_deref().unwrap_or("0x"),
+        "blockNumber": request.block_number.map(|n| format!("0x{:x}", n)),
+    });
+    // Only successful txs emit logs
+    let receipt = json!({ "status": "0x1", "logs": logs });
+
+    let mut details = normalize(request.network, &tx, &receipt, None);
+    let object = details.as_object_mut().expect("normalized details are an object");
+    object.remove("gas_used");
+    object.remove("gas_limit");
+    object.remove("block_hash");
+    if request.block_number.is_none() {
+        object.remove("block_number");
+    }
+    details["source"] = json!("client_provided");
+    Ok(details)
+}
+
+/// Tx details for a signed raw tx (as sent to `eth_sendRawTransaction`) that
+/// hasn't been mined: no receipt, so it's pending, with no logs. Legacy,
+/// EIP-2930 and EIP-1559 txs are decoded, and one signed for another chain is
+/// rejected. The sender isn't recovered from the signature; it's `from` if given.
+pub fn from_raw(network: Network, raw: &str, from: Option<&str>) -> Result<Value, String> {
+    check_hex("raw", raw, None)?;
+    if let Some(from) = from {
+        check_hex("from", from, Some(20))?;
+    }
+    let bytes = hex::decode(&raw[2..]).map_err(|e| e.to_string())?;
+    let malformed = || "raw is not an RLP-encoded signed transaction".to_string();
+
+    // Field positions of gas, to, value and data, and where the chain id is
+    let (payload, fields) = match bytes.first() {
+        Some(0x01) => (&bytes[1..], [3, 4, 5, 6]),
+        Some(0x02) => (&bytes[1..], [4, 5, 6, 7]),
+        Some(b) if *b >= 0xc0 => (&bytes[..], [2, 3, 4, 5]),
+        Some(b) => return Err(format!("unsupported transaction type 0x{:02x}", b)),
+        None => return Err(malformed()),
+    };
+    let Some((Rlp::List(items), [])) = rlp_item(payload) else {
+        return Err(malformed());
+    };
+    let field = |i: usize| match items.get(i) {
+        Some(Rlp::Bytes(b)) => Ok(*b),
+        _ => Err(malformed()),
+    };
+    let quantity = |b: &[u8]| format!("0x{:x}", U256::from_big_endian(b));
+
+    // Typed txs lead with the chain id; legacy ones fold it into v (EIP-155)
+    let chain_id = if bytes[0] < 0xc0 {
+        Some(U256::from_big_endian(field(0)?))
+    } else {
+        let v = U256::from_big_endian(field(6)?);
+        (v >= U256::from(35)).then(|| (v - 35) / 2)
+    };
+    if let Some(chain_id) = chain_id
+        && chain_id != U256::from(network.chain_id())
+    {
+        return Err(format!("raw is signed for chain {}, not {}", chain_id, network));
+    }
+
+    let [gas, to, value, data] = fields.map(field);
+    let to = to?;
+    let tx = json!({
+        "hash": format!("0x{}", hex::encode(keccak256(&bytes))),
+        "from": from,
+        "to": (!to.is_empty()).then(|| format!("0x{}", hex::encode(to))),
+        "value": quantity(value?),
+        "input": format!("0x{}", hex::encode(data?)),
+        "gas": quantity(gas?),
+        "blockNumber": null,
+    });
+
+    let mut details = normalize(network, &tx, &Value::Null, None);
+    let object = details.as_object_mut().expect("normalized details are an object");
+    object.remove("block_number");
+    object.remove("block_hash");
+    details["source"] = json!("client_provided");
+    Ok(details)
+}
+
+enum Rlp<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<Rlp<'a>>),
+}
+
+// One RLP item off the front of `data`, and the bytes after it
+fn rlp_item(data: &[u8]) -> Option<(Rlp<'_>, &[u8])> {
+    let (&prefix, rest) = data.split_first()?;
+    let (is_list, len, rest) = match prefix {
+        0x00..=0x7f => return Some((Rlp::Bytes(&data[..1]), rest)),
+        0x80..=0xb7 => (false, (prefix - 0x80) as usize, rest),
+        0xc0..=0xf7 => (true, (prefix - 0xc0) as usize, rest),
+        _ => {
+            let len_len = (prefix - if prefix < 0xc0 { 0xb7 } else { 0xf7 }) as usize;
+            if rest.len() < len_len || len_len > 8 {
+                return None;
+            }
+            let len = rest[..len_len].iter().fold(0usize, |n, b| (n << 8) | *b as usize);
+            (prefix >= 0xc0, len, &rest[len_len..])
+        }
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (body, rest) = rest.split_at(len);
+    if !is_list {
+        return Some((Rlp::Bytes(body), rest));
+    }
+    let mut items = Vec::new();
+    let mut body = body;
+    while !body.is_empty() {
+        let (item, after) = rlp_item(body)?;
+        items.push(item);
+        body = after;
+    }
+    Some((Rlp::List(items), rest))
+}
+
+// 0x-prefixed hex, of exactly `bytes` bytes when given
+fn check_hex(field: &str, value: &str, bytes: Option<usize>) -> Result<(), String> {
+    let ok = value.strip_prefix("0x").is_some_and(|hex| {
+        hex.len() % 2 == 0
+            && hex.chars().all(|c| c.is_ascii_hexdigit())
+            && bytes.is_none_or(|n| hex.len() == n * 2)
+    });
+    if ok {
+        return Ok(());
+    }
+    Err(match bytes {
+        Some(n) => format!("{} must be 0x-prefixed hex of {} bytes", field, n),
+        None => format!("{} must be 0x-prefixed hex", field),
+    })
+}
+
+// Reshape raw RPC objects into the flat structure the analyzer works on
+// (same shape as the mock).
+// A null receipt (not available yet) leaves the receipt fields pending:
+// status "pending", no logs and no gas used
+fn normalize(
+    network: Network,
+    tx: &Value,
+    receipt: &Value,
+    trace: Option<(&Value, TraceLimits)>,
+) -> Value {
+    let status = match receipt["status"].as_str() {
+        Some("0x1") => "success",
+        _ if receipt.is_null() => "pending",
+        _ => "failed",
+    };
+
+    let logs: Vec<Value> = receipt["logs"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .map(|log| {
+            json!({
+                "address": log["address"],
+                "topics": log["topics"],
+                "data": log["data"],
+            })
+        })
+        .collect();
+
+    let mut details = json!({
+        "hash": tx["hash"],
+        "from": tx["from"],
+        "to": tx["to"],
+        "value": native_amount(network, parse_quantity(&tx["value"])),
+        "input": tx["input"],
+        "gas_used": parse_u64(&receipt["gasUsed"]),
+        "gas_limit": parse_u64(&tx["gas"]),
+        "status": status,
+        "block_number": parse_u64(&tx["blockNumber"]),
+        "block_hash": receipt["blockHash"],
+        "logs": logs,
+        "source": "rpc",
+    });
+
+    if let Some((trace, limits)) = trace {
+        let mut calls = Vec::new();
+        let mut truncated = Truncation::default();
+        flatten_calls(trace, 0, limits, &mut calls, &mut truncated);
+        details["calls"] = Value::Array(calls);
+        if truncated.depth || truncated.calls {
+            details["trace_truncated"] = json!({
+                "max_depth": truncated.depth.then_some(limits.max_depth),
+                "max_calls": truncated.calls.then_some(limits.max_calls),
+            });
+        }
+        // The top frame's error is the tx's ("out of gas", "execution reverted")
+        if let Some(error) = trace["error"].as_str() {
+            details["error"] = json!(error);
+        }
+        if let Some(revert_reason) = trace["revertReason"].as_str() {
+            details["revert_reason"] = json!(revert_reason);
+        }
+    }
+    // What the tx paid per gas; while pending, what it offers
+    let gas_price = parse_quantity(if receipt["effectiveGasPrice"].is_null() {
+        &tx["gasPrice"]
+    } else {
+        &receipt["effectiveGasPrice"]
+    });
+    if !gas_price.is_zero() {
+        details["gas_price"] = json!(saturating_u64(gas_price));
+    }
+    if receipt.is_null()
+        && let Some(object) = details.as_object_mut()
+    {
+        object.remove("gas_used");
+        object.insert("receipt_available".to_string(), Value::Bool(false));
+    }
+
+    details
+}
+
+// Which of the trace limits cut frames off
+#[derive(Default)]
+struct Truncation {
+    depth: bool,
+    calls: bool,
+}
 
-    let mock = serde_json::json!({
+// callTracer returns a nested frame tree; flatten it depth-first, which is
+// execution order. Stops at the limits, so a pathological tree costs no more
+// than a large ordinary one.
+fn flatten_calls(
+    frame: &Value,
+    depth: usize,
+    limits: TraceLimits,
+    out: &mut Vec<Value>,
+    truncated: &mut Truncation,
+) {
+    if out.len() >= limits.max_calls {
+        truncated.calls = true;
+        return;
+    }
+    out.push(json!({
+        "type": frame["type"],
+        "from": frame["from"],
+        "to": frame["to"],
+        "input": frame["input"],
+        "value": frame["value"],
+        "depth": depth,
+    }));
+    let children = frame["calls"].as_array().map(Vec::as_slice).unwrap_or_default();
+    // Frames too deep for the RPC client to parse come back as nulls (see
+    // `rpc::prune_nesting`)
+    if frame.get("calls").is_some_and(Value::is_null) || children.iter().any(Value::is_null) {
+        truncated.depth = true;
+    }
+    if !children.is_empty() && depth >= limits.max_depth {
+        truncated.depth = true;
+        return;
+    }
+    for child in children.iter().filter(|child| !child.is_null()) {
+        flatten_calls(child, depth + 1, limits, out, truncated);
+    }
+}
+
+pub fn parse_quantity(v: &Value) -> U256 {
+    v.as_str()
+        .and_then(|s| U256::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+        .unwrap_or_default()
+}
+
+/// A quantity expected to fit in 64 bits (gas, gas prices, block numbers).
+/// Larger ones saturate rather than wrap, so a bogus huge value can't pass
+/// for a small one.
+pub fn parse_u64(v: &Value) -> u64 {
+    saturating_u64(parse_quantity(v))
+}
+
+pub fn saturating_u64(value: U256) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+/// The native amount `wei` (the smallest unit, whatever the chain calls it)
+/// in `network`'s native token, as it appears in the tx details and the response.
+pub fn native_amount(network: Network, wei: U256) -> Amount {
+    let decimals = network.native_decimals();
+    Amount {
+        wei: wei.to_string(),
+        formatted: Some(format_units(wei, decimals)),
+        symbol: Some(network.native_symbol().to_string()),
+        decimals: Some(decimals),
+    }
+}
+
+/// `amount` base units scaled down by `decimals`, exactly (digit shifting, no
+/// floats), with trailing fractional zeros trimmed: 1500000000000000000 at
+/// 18 decimals is "1.5".
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, frac) = padded.split_at(padded.len() - decimals);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, frac)
+    }
+}
+
+fn mock_transaction(network: Network, tx_hash: &str) -> Value {
+    serde_json::json!({
         "hash": tx_hash,
         "from": "0x1234...abcd",
         "to": "0xabcd...1234",
-        "value": "1.5 ETH",
+        "value": native_amount(network, U256::from(1_500_000_000_000_000_000u64)),
         "gas_used": 21000,
+        "gas_limit": 21000,
         "status": "success",
+        "source": "mock",
         "logs": [
             {
                 "address": "0xUniswapV3Pool...",
@@ -34,8 +616,291 @@ pub async fn fetch_transaction(
                 "data": "..."
             }
         ]
-    });
-
-    Ok(mock)
+    })
 }
 
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::future::BoxFuture;
+
+    use super::*;
+    use crate::testing::{self, MockChain, TRANSFER_HASH};
+
+    const TO: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    // RLP of a byte string, or of a list of already-encoded items
+    fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+        match bytes {
+            [b] if *b < 0x80 => vec![*b],
+            _ => [length_prefix(0x80, bytes.len()), bytes.to_vec()].concat(),
+        }
+    }
+
+    fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+        let body = items.concat();
+        [length_prefix(0xc0, body.len()), body].concat()
+    }
+
+    fn length_prefix(offset: u8, len: usize) -> Vec<u8> {
+        if len <= 55 {
+            return vec![offset + len as u8];
+        }
+        let len_bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        [vec![offset + 55 + len_bytes.len() as u8], len_bytes].concat()
+    }
+
+    fn quantity(n: u64) -> Vec<u8> {
+        rlp_bytes(&n.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect::<Vec<_>>())
+    }
+
+    fn eip1559(chain_id: u64, to: &str, data: &[u8]) -> String {
+        let fields = [
+            quantity(chain_id),
+            quantity(7),
+            quantity(1_000_000_000),
+            quantity(30_000_000_000),
+            quantity(21_000),
+            rlp_bytes(&hex::decode(to).unwrap()),
+            quantity(1_500_000_000_000_000_000),
+            rlp_bytes(data),
+            rlp_list(&[]),
+            quantity(1),
+            rlp_bytes(&[0x11; 32]),
+            rlp_bytes(&[0x22; 32]),
+        ];
+        format!("0x02{}", hex::encode(rlp_list(&fields)))
+    }
+
+    fn legacy(v: u64) -> String {
+        let fields = [
+            quantity(7),
+            quantity(30_000_000_000),
+            quantity(21_000),
+            rlp_bytes(&hex::decode(TO).unwrap()),
+            quantity(1),
+            rlp_bytes(&[]),
+            quantity(v),
+            rlp_bytes(&[0x11; 32]),
+            rlp_bytes(&[0x22; 32]),
+        ];
+        format!("0x{}", hex::encode(rlp_list(&fields)))
+    }
+
+    #[test]
+    fn decodes_a_raw_eip1559_tx() {
+        // Long enough for the long-form length prefix
+        let data = [0xa9u8; 100];
+        let raw = eip1559(1, TO, &data);
+        let sender = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let details = from_raw(Network::EthereumMainnet, &raw, Some(sender)).unwrap();
+
+        let hash = keccak256(&hex::decode(&raw[2..]).unwrap());
+        assert_eq!(details["hash"], format!("0x{}", hex::encode(hash)));
+        assert_eq!(details["from"], sender);
+        assert_eq!(details["to"], format!("0x{}", TO));
+        assert_eq!(details["value"]["formatted"], "1.5");
+        assert_eq!(details["input"], format!("0x{}", hex::encode(data)));
+        assert_eq!(details["gas_limit"], 21_000);
+        assert_eq!(details["status"], "pending");
+        assert_eq!(details["source"], "client_provided");
+        assert!(details.get("block_number").is_none());
+    }
+
+    #[test]
+    fn reads_contract_creations_as_having_no_to() {
+        let raw = eip1559(1, "", &[0x60, 0x80]);
+        let details = from_raw(Network::EthereumMainnet, &raw, None).unwrap();
+        assert!(details["to"].is_null());
+    }
+
+    #[test]
+    fn checks_the_chain_id_of_typed_and_legacy_txs() {
+        assert!(from_raw(Network::PolygonMainnet, &eip1559(137, TO, &[]), None).is_ok());
+        let err = from_raw(Network::EthereumMainnet, &eip1559(137, TO, &[]), None).unwrap_err();
+        assert!(err.contains("chain 137"), "{}", err);
+
+        // EIP-155: v = chain_id * 2 + 35 (or 36)
+        assert!(from_raw(Network::EthereumMainnet, &legacy(37), None).is_ok());
+        assert!(from_raw(Network::EthereumMainnet, &legacy(310), None).is_err());
+        // Pre-EIP-155 signatures aren't bound to a chain
+        assert!(from_raw(Network::PolygonMainnet, &legacy(27), None).is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_raw_txs() {
+        let truncated = eip1559(1, TO, &[]);
+        let truncated = &truncated[..truncated.len() - 2];
+        for raw in ["0x", "0x03c0", "0x02c0", truncated, "02f8", "0xzz"] {
+            assert!(from_raw(Network::EthereumMainnet, raw, None).is_err(), "{}", raw);
+        }
+        let short_from = Some("0x1234");
+        assert!(from_raw(Network::EthereumMainnet, &legacy(37), short_from).is_err());
+    }
+
+    #[test]
+    fn quantities_past_64_bits_saturate() {
+        assert_eq!(parse_u64(&json!("0x5208")), 21_000);
+        assert_eq!(parse_u64(&json!("0x10000000000000001")), u64::MAX);
+        assert_eq!(parse_u64(&Value::Null), 0);
+        assert_eq!(saturating_u64(U256::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn amounts_stay_exact_across_the_uint256_range() {
+        let max = parse_quantity(&json!(format!("0x{}", "f".repeat(64))));
+        assert_eq!(max, U256::MAX);
+        let amount = native_amount(Network::EthereumMainnet, max);
+        assert_eq!(amount.wei, U256::MAX.to_string());
+        assert_eq!(
+            amount.formatted.as_deref(),
+            Some("115792089237316195423570985008687907853269984665640564039457.584007913129639935")
+        );
+        assert_eq!(format_units(U256::from(1), 18), "0.000000000000000001");
+        assert_eq!(format_units(U256::from(42), 0), "42");
+    }
+
+    #[test]
+    fn normalized_gas_fields_saturate() {
+        let huge = format!("0x1{}", "0".repeat(20));
+        let tx = json!({
+            "hash": "0x01",
+            "value": format!("0x{}", "f".repeat(64)),
+            "gas": huge,
+            "gasPrice": huge,
+            "blockNumber": "0x10"
+        });
+        let receipt = json!({ "status": "0x1", "gasUsed": "0x5208", "logs": [] });
+        let details = normalize(Network::EthereumMainnet, &tx, &receipt, None);
+
+        assert_eq!(details["gas_limit"], u64::MAX);
+        assert_eq!(details["gas_price"], u64::MAX);
+        assert_eq!(details["gas_used"], 21_000);
+        assert_eq!(details["block_number"], 16);
+        assert_eq!(details["value"]["wei"], U256::MAX.to_string());
+    }
+
+    // A frame with `depth` levels of `width` nested calls below it
+    fn call_tree(depth: usize, width: usize) -> Value {
+        let mut frame = json!({ "type": "CALL", "to": format!("0x{}", TO) });
+        for _ in 0..depth {
+            let calls = vec![frame; width];
+            frame = json!({ "type": "CALL", "to": format!("0x{}", TO), "calls": calls });
+        }
+        frame
+    }
+
+    fn traced(trace: &Value, max_depth: usize, max_calls: usize) -> Value {
+        let tx = json!({ "hash": "0x01", "value": "0x0", "blockNumber": "0x10" });
+        let receipt = json!({ "status": "0x1", "gasUsed": "0x5208", "logs": [] });
+        let limits = TraceLimits { max_depth, max_calls };
+        normalize(Network::EthereumMainnet, &tx, &receipt, Some((trace, limits)))
+    }
+
+    #[test]
+    fn deep_call_trees_are_cut_at_the_limits() {
+        let details = traced(&call_tree(100, 1), 32, 2000);
+        assert_eq!(details["calls"].as_array().unwrap().len(), 33);
+        assert_eq!(details["calls"][32]["depth"], 32);
+        assert_eq!(details["trace_truncated"], json!({ "max_depth": 32, "max_calls": null }));
+
+        let details = traced(&call_tree(3, 4), 32, 50);
+        assert_eq!(details["calls"].as_array().unwrap().len(), 50);
+        assert_eq!(details["trace_truncated"], json!({ "max_depth": null, "max_calls": 50 }));
+
+        let details = traced(&call_tree(3, 2), 32, 2000);
+        assert_eq!(details["calls"].as_array().unwrap().len(), 15);
+        assert!(details["trace_truncated"].is_null());
+    }
+
+    #[test]
+    fn frames_pruned_by_the_rpc_client_count_as_too_deep() {
+        let mut trace = call_tree(2, 1);
+        trace["calls"][0]["calls"] = Value::Null;
+        let details = traced(&trace, 32, 2000);
+        assert_eq!(details["calls"].as_array().unwrap().len(), 2);
+        assert_eq!(details["trace_truncated"]["max_depth"], 32);
+    }
+
+    // MockChain::transfer, whose receipt only shows up after `lag` receipt requests
+    struct LaggingNode {
+        chain: MockChain,
+        lag: usize,
+        receipt_requests: AtomicUsize,
+    }
+
+    impl LaggingNode {
+        fn new(lag: usize) -> Self {
+            Self {
+                chain: MockChain::transfer(),
+                lag,
+                receipt_requests: AtomicUsize::new(0),
+            }
+        }
+
+        async fn answer(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+            if method == "eth_getTransactionReceipt"
+                && self.receipt_requests.fetch_add(1, Ordering::Relaxed) < self.lag
+            {
+                return Ok(Value::Null);
+            }
+            self.chain.call(method, params).await
+        }
+    }
+
+    impl ChainClient for LaggingNode {
+        fn call<'a>(
+            &'a self,
+            method: &'a str,
+            params: Value,
+        ) -> BoxFuture<'a, Result<Value, RpcError>> {
+            Box::pin(self.answer(method, params))
+        }
+
+        fn batch<'a>(
+            &'a self,
+            calls: &'a [(&'a str, Value)],
+        ) -> BoxFuture<'a, Vec<Result<Value, RpcError>>> {
+            Box::pin(futures::future::join_all(
+                calls.iter().map(|(method, params)| self.answer(method, params.clone())),
+            ))
+        }
+    }
+
+    async fn fetch(node: &LaggingNode) -> Value {
+        let wait = ReceiptWait {
+            attempts: 3,
+            interval: Duration::from_millis(5),
+        };
+        let network = Network::EthereumMainnet;
+        let hashes = [TRANSFER_HASH.to_string()];
+        let mut fetched = fetch_transactions(Some(node), network, &hashes, None, wait)
+            .await
+            .unwrap();
+        fetched.remove(0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_tx_without_a_receipt_is_analyzed_from_the_tx_alone() {
+        let node = LaggingNode::new(usize::MAX);
+        let details = fetch(&node).await;
+
+        // The batch's request, then every poll
+        assert_eq!(node.receipt_requests.load(Ordering::Relaxed), 1 + 3);
+        assert_eq!(details["receipt_available"], false);
+        assert!(details.get("gas_used").is_none());
+        assert_eq!(details["hash"], TRANSFER_HASH);
+        assert_eq!(details["from"], testing::SENDER);
+    }
+
+    #[tokio::test]
+    async fn a_lagging_receipt_is_picked_up_by_a_poll() {
+        let node = LaggingNode::new(2);
+        let details = fetch(&node).await;
+
+        assert_eq!(node.receipt_requests.load(Ordering::Relaxed), 3);
+        assert!(details.get("receipt_available").is_none());
+        assert_eq!(details["gas_used"], 21_000);
+    }
+}
@@ -0,0 +1,335 @@
+use primitive_types::U256;
+use serde_json::Value;
+
+use crate::models::BridgeDetails;
+use crate::services::{decode, selectors};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Deposit,
+    Withdrawal,
+}
+
+// Where a value sits in a log: an indexed topic (1-based, topic0 is the event)
+// or a 32-byte word of the data
+#[derive(Clone, Copy)]
+enum Slot {
+    Topic(usize),
+    Word(usize),
+}
+
+struct BridgeEvent {
+    signature: &'static str,
+    direction: Direction,
+    amount: Slot,
+    // None for native ETH
+    token: Option<Slot>,
+    recipient: Slot,
+}
+
+struct Bridge {
+    name: &'static str,
+    l2: &'static str,
+    // L1 (Ethereum mainnet) contracts, lowercase
+    contracts: &'static [&'static str],
+    events: &'static [BridgeEvent],
+}
+
+const BRIDGES: &[Bridge] = &[
+    Bridge {
+        name: "Optimism",
+        l2: "optimism",
+        contracts: &[
+            "0x99c9fc46f92e8a1c0dec1b1747d010903e884be1", // L1StandardBridge
+            "0xbeb5fc579115071764c7423a4f12edde41f106ed", // OptimismPortal
+            "0x25ace71c97b33cc4729cf772ae268934f7ab5fa1", // L1CrossDomainMessenger
+        ],
+        events: &[
+            BridgeEvent {
+                signature: "ETHDepositInitiated(address,address,uint256,bytes)",
+                direction: Direction::Deposit,
+                amount: Slot::Word(0),
+                token: None,
+                recipient: Slot::Topic(2),
+            },
+            BridgeEvent {
+                signature: "ERC20DepositInitiated(address,address,address,address,uint256,bytes)",
+                direction: Direction::Deposit,
+                amount: Slot::Word(1),
+                token: Some(Slot::Topic(1)),
+                recipient: Slot::Word(0),
+            },
+            BridgeEvent {
+                signature: "ETHWithdrawalFinalized(address,address,uint256,bytes)",
+                direction: Direction::Withdrawal,
+                amount: Slot::Word(0),
+                token: None,
+                recipient: Slot::Topic(2),
+            },
+            BridgeEvent {
+                signature: "ERC20WithdrawalFinalized(address,address,address,address,uint256,bytes)",
+                direction: Direction::Withdrawal,
+                amount: Slot::Word(1),
+                token: Some(Slot::Topic(1)),
+                recipient: Slot::Word(0),
+            },
+        ],
+    },
+    Bridge {
+        name: "Arbitrum",
+        l2: "arbitrum-one",
+        contracts: &[
+            "0x4dbd4fc535ac27206064b68ffcf827b0a60bab3f", // Delayed Inbox
+            "0x72ce9c846789fdb6fc1f34ac4ad25dd9ef7031ef", // L1 Gateway Router
+            "0xa3a7b6f88361f48403514059f1f16c8e78d60eec", // L1 ERC20 Gateway
+            "0x0b9857ae2d4a3dbe74ffe1d7df045bb7f96e4840", // Outbox
+            "0x8315177ab297ba92a06054ce80a67ed4dbd7ed3a", // Bridge
+        ],
+        events: &[
+            BridgeEvent {
+                signature: "DepositInitiated(address,address,address,uint256,uint256)",
+                direction: Direction::Deposit,
+                amount: Slot::Word(1),
+                token: Some(Slot::Word(0)),
+                recipient: Slot::Topic(2),
+            },
+            BridgeEvent {
+                signature: "WithdrawalFinalized(address,address,address,uint256,uint256)",
+                direction: Direction::Withdrawal,
+                amount: Slot::Word(1),
+                token: Some(Slot::Word(0)),
+                recipient: Slot::Topic(2),
+            },
+        ],
+    },
+    Bridge {
+        name: "Polygon PoS",
+        l2: "polygon-pos",
+        contracts: &[
+            "0xa0c68c638235ee32657e8f720a23cec1bfc77c77", // RootChainManager
+            "0x8484ef722627bf18ca5ae6bcf031c23e6e922b30", // EtherPredicate
+            "0x40ec5b33f54e0e8a33a975908c5ba1c14e5bbbdf", // ERC20Predicate
+            "0x401f6c983ea34274ec46f84d70b31c151321188b", // Plasma DepositManager
+        ],
+        events: &[
+            BridgeEvent {
+                signature: "LockedEther(address,address,uint256)",
+                direction: Direction::Deposit,
+                amount: Slot::Word(0),
+                token: None,
+                recipient: Slot::Topic(2),
+            },
+            BridgeEvent {
+                signature: "LockedERC20(address,address,address,uint256)",
+                direction: Direction::Deposit,
+                amount: Slot::Word(0),
+                token: Some(Slot::Topic(3)),
+                recipient: Slot::Topic(2),
+            },
+            BridgeEvent {
+                signature: "ExitedEther(address,uint256)",
+                direction: Direction::Withdrawal,
+                amount: Slot::Word(0),
+                token: None,
+                recipient: Slot::Topic(1),
+            },
+            BridgeEvent {
+                signature: "ExitedERC20(address,address,uint256)",
+                direction: Direction::Withdrawal,
+                amount: Slot::Word(0),
+                token: Some(Slot::Topic(2)),
+                recipient: Slot::Topic(1),
+            },
+        ],
+    },
+];
+
+/// Detects an L1 <-> L2 bridge transfer. A bridge event emitted by one of the
+/// bridge's own contracts gives the full details; otherwise a call into a
+/// bridge contract is classified by its method name alone.
+pub fn detect(tx_details: &Value) -> Option<BridgeDetails> {
+    let logs = tx_details["logs"].as_array().map(Vec::as_slice).unwrap_or_default();
+    for log in logs {
+        let emitter = log["address"].as_str().unwrap_or("").to_lowercase();
+        let Some(bridge) = BRIDGES.iter().find(|b| b.contracts.contains(&emitter.as_str())) else {
+            continue;
+        };
+        let Some(signature) = log["topics"][0].as_str().and_then(selectors::event_signature) else {
+            continue;
+        };
+        if let Some(event) = bridge.events.iter().find(|e| e.signature == signature)
+            && let Some(details) = from_event(bridge, event, log)
+        {
+            return Some(details);
+        }
+    }
+
+    let to = tx_details["to"].as_str().unwrap_or("").to_lowercase();
+    let bridge = BRIDGES.iter().find(|b| b.contracts.contains(&to.as_str()))?;
+    let method = decode::decode_calldata(tx_details["input"].as_str().unwrap_or(""))?.name;
+    let direction = direction_from_method(&method)?;
+    Some(details(bridge, direction, None, None, None))
+}
+
+fn from_event(bridge: &Bridge, event: &BridgeEvent, log: &Value) -> Option<BridgeDetails> {
+    let topics: Vec<&str> = log["topics"]
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+    let data = decode::decode_hex(log["data"].as_str().unwrap_or("0x")).unwrap_or_default();
+
+    let word = |slot: Slot| -> Option<[u8; 32]> {
+        let bytes = match slot {
+            Slot::Topic(i) => decode::decode_hex(topics.get(i)?)?,
+            Slot::Word(i) => data.get(i * 32..i * 32 + 32)?.to_vec(),
+        };
+        bytes.try_into().ok()
+    };
+    let address = |slot: Slot| word(slot).map(|w| format!("0x{}", hex::encode(&w[12..])));
+
+    let amount = U256::from_big_endian(&word(event.amount)?);
+    let token = match event.token {
+        Some(slot) => Some(address(slot)?),
+        None => None,
+    };
+    Some(details(
+        bridge,
+        event.direction,
+        token,
+        Some(amount.to_string()),
+        address(event.recipient),
+    ))
+}
+
+fn direction_from_method(method: &str) -> Option<Direction> {
+    const DEPOSITS: &[&str] = &["deposit", "bridge", "outboundTransfer", "createRetryableTicket", "sendToL2"];
+    const WITHDRAWALS: &[&str] = &["finalize", "prove", "exit", "executeTransaction", "withdraw"];
+
+    if DEPOSITS.iter().any(|p| method.starts_with(p)) {
+        Some(Direction::Deposit)
+    } else if WITHDRAWALS.iter().any(|p| method.starts_with(p)) {
+        Some(Direction::Withdrawal)
+    } else {
+        None
+    }
+}
+
+fn details(
+    bridge: &Bridge,
+    direction: Direction,
+    token: Option<String>,
+    amount: Option<String>,
+    recipient: Option<String>,
+) -> BridgeDetails {
+    let (source_chain, destination_chain) = match direction {
+        Direction::Deposit => ("ethereum-mainnet", bridge.l2),
+        Direction::Withdrawal => (bridge.l2, "ethereum-mainnet"),
+    };
+    BridgeDetails {
+        bridge: bridge.name.to_string(),
+        direction: match direction {
+            Direction::Deposit => "deposit",
+            Direction::Withdrawal => "withdrawal",
+        }
+        .to_string(),
+        source_chain: source_chain.to_string(),
+        destination_chain: destination_chain.to_string(),
+        token,
+        amount,
+        recipient,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::util::keccak256;
+
+    const SENDER: &str = "0x1111111111111111111111111111111111111111";
+    const RECIPIENT: &str = "0x2222222222222222222222222222222222222222";
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+
+    fn topic(value: &str) -> String {
+        if value.starts_with("0x") {
+            format!("0x{:0>64}", value.trim_start_matches("0x"))
+        } else {
+            format!("0x{}", hex::encode(keccak256(value.as_bytes())))
+        }
+    }
+
+    fn word(hex: &str) -> String {
+        format!("{:0>64}", hex.trim_start_matches("0x"))
+    }
+
+    #[test]
+    fn reads_an_optimism_eth_deposit_from_its_event() {
+        // ETHDepositInitiated(from, to, amount, extraData) from L1StandardBridge,
+        // with 1.5 ETH and empty extraData
+        let tx = json!({
+            "to": "0x99C9fc46f92E8a1c0deC1b1747d010903E884bE1",
+            "logs": [{
+                "address": "0x99C9fc46f92E8a1c0deC1b1747d010903E884bE1",
+                "topics": [
+                    topic("ETHDepositInitiated(address,address,uint256,bytes)"),
+                    topic(SENDER),
+                    topic(RECIPIENT),
+                ],
+                "data": format!("0x{}{}{}", word("14d1120d7b160000"), word("40"), word("0")),
+            }],
+        });
+
+        let details = detect(&tx).unwrap();
+        assert_eq!(details.bridge, "Optimism");
+        assert_eq!(details.direction, "deposit");
+        assert_eq!(details.source_chain, "ethereum-mainnet");
+        assert_eq!(details.destination_chain, "optimism");
+        assert_eq!(details.token, None);
+        assert_eq!(details.amount.as_deref(), Some("1500000000000000000"));
+        assert_eq!(details.recipient.as_deref(), Some(RECIPIENT));
+    }
+
+    #[test]
+    fn reads_an_arbitrum_token_deposit_from_its_event() {
+        // DepositInitiated(l1Token, from, to, sequenceNumber, amount) from the
+        // ERC20 gateway: 250 USDC
+        let tx = json!({
+            "logs": [{
+                "address": "0xa3A7B6F88361F48403514059F1F16C8E78d60EeC",
+                "topics": [
+                    topic("DepositInitiated(address,address,address,uint256,uint256)"),
+                    topic(SENDER),
+                    topic(RECIPIENT),
+                    topic("0x1f4a"),
+                ],
+                "data": format!("0x{}{}", word(USDC), word("ee6b280")),
+            }],
+        });
+
+        let details = detect(&tx).unwrap();
+        assert_eq!(details.bridge, "Arbitrum");
+        assert_eq!(details.destination_chain, "arbitrum-one");
+        assert_eq!(details.token.as_deref(), Some(USDC));
+        assert_eq!(details.amount.as_deref(), Some("250000000"));
+        assert_eq!(details.recipient.as_deref(), Some(RECIPIENT));
+    }
+
+    #[test]
+    fn ignores_bridge_events_from_other_contracts() {
+        let tx = json!({
+            "to": SENDER,
+            "logs": [{
+                "address": SENDER,
+                "topics": [
+                    topic("ETHDepositInitiated(address,address,uint256,bytes)"),
+                    topic(SENDER),
+                    topic(RECIPIENT),
+                ],
+                "data": format!("0x{}{}{}", word("1"), word("40"), word("0")),
+            }],
+        });
+        assert!(detect(&tx).is_none());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashSet};
+
+use primitive_types::U256;
+use serde_json::Value;
+
+use crate::models::{BundleStrategy, TokenFlow};
+use crate::services::decode;
+
+/// What a bundle's txs do together, read from their logs.
+#[derive(Debug, Default)]
+pub struct Correlation {
+    pub strategy: Option<BundleStrategy>,
+    pub searcher: Option<String>,
+    pub victims: Vec<String>,
+    pub net_flows: Vec<TokenFlow>,
+    pub reasons: Vec<String>,
+}
+
+/// Correlates a bundle's txs, in bundle order. The searcher is the first tx's
+/// sender; its balance changes are those of the sender and of the contract
+/// that tx calls (MEV bots hold funds in their own contract). The strategy is,
+/// in order of precedence:
+///
+/// - a liquidation, when any tx liquidates a lending position;
+/// - a sandwich, when the searcher swaps on a pool both before and after
+///   someone else's swap on it;
+/// - an arbitrage, when the searcher swaps across two or more pools and ends
+///   with no token balance lower and at least one higher.
+pub fn correlate(txs: &[&Value]) -> Correlation {
+    let Some(first) = txs.first() else {
+        return Correlation::default();
+    };
+    let searcher = address(&first["from"]);
+    let searcher_accounts: HashSet<String> = [searcher.clone(), address(&first["to"])]
+        .into_iter()
+        .flatten()
+        .collect();
+    let net_flows = net_flows(txs, &searcher_accounts);
+    let mut correlation = Correlation {
+        searcher: searcher.clone(),
+        net_flows: net_flows
+            .iter()
+            .map(|(token, net)| TokenFlow {
+                token: token.clone(),
+                net: net.to_string(),
+            })
+            .collect(),
+        ..Correlation::default()
+    };
+
+    if let Some(i) = txs.iter().position(|tx| has_event(tx, "LiquidationCall")) {
+        correlation.strategy = Some(BundleStrategy::Liquidation);
+        correlation
+            .reasons
+            .push(format!("Tx {} liquidates a lending position", i + 1));
+        return correlation;
+    }
+
+    let senders: Vec<Option<String>> = txs.iter().map(|tx| address(&tx["from"])).collect();
+    let pools: Vec<HashSet<String>> = txs.iter().map(|tx| swap_pools(tx)).collect();
+    let by_searcher = |i: usize| searcher.is_some() && senders[i] == searcher;
+
+    // The searcher's last tx that swaps back on a pool its first one used
+    for back in (2..txs.len()).rev().filter(|&i| by_searcher(i)) {
+        let shared: HashSet<&String> = pools[0].intersection(&pools[back]).collect();
+        let victims: Vec<usize> = (1..back)
+            .filter(|&i| !by_searcher(i) && pools[i].iter().any(|p| shared.contains(p)))
+            .collect();
+        if victims.is_empty() {
+            continue;
+        }
+        correlation.strategy = Some(BundleStrategy::Sandwich);
+        correlation.victims = victims
+            .iter()
+            .filter_map(|&i| txs[i]["hash"].as_str().map(str::to_string))
+            .collect();
+        correlation.reasons.push(format!(
+            "Sandwich: txs 1 and {} trade around {} victim swap(s) on the same pool",
+            back + 1,
+            victims.len()
+        ));
+        return correlation;
+    }
+
+    let searcher_pools: HashSet<&String> = pools
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| by_searcher(*i))
+        .flat_map(|(_, pools)| pools)
+        .collect();
+    let gained = net_flows.values().any(|net| net.positive());
+    let lost = net_flows.values().any(|net| !net.positive());
+    if searcher_pools.len() >= 2 && gained && !lost {
+        correlation.strategy = Some(BundleStrategy::Arbitrage);
+        correlation.reasons.push(format!(
+            "Arbitrage: swaps across {} pools end with a net gain and no loss",
+            searcher_pools.len()
+        ));
+    }
+    correlation
+}
+
+// A signed token amount
+#[derive(Debug, Clone, Copy, Default)]
+struct Net {
+    gained: U256,
+    lost: U256,
+}
+
+impl Net {
+    fn positive(&self) -> bool {
+        self.gained > self.lost
+    }
+
+    fn is_zero(&self) -> bool {
+        self.gained == self.lost
+    }
+}
+
+impl std::fmt::Display for Net {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.positive() {
+            write!(f, "{}", self.gained - self.lost)
+        } else {
+            write!(f, "-{}", self.lost - self.gained)
+        }
+    }
+}
+
+// Net ERC-20 transfers into `accounts` over the bundle, per token (tokens
+// that net to zero are left out)
+fn net_flows(txs: &[&Value], accounts: &HashSet<String>) -> BTreeMap<String, Net> {
+    let mut flows: BTreeMap<String, Net> = BTreeMap::new();
+    for log in txs.iter().flat_map(|tx| logs(tx)) {
+        let topics = log["topics"].as_array().map(Vec::as_slice).unwrap_or_default();
+        // ERC-721 Transfer has the same topic0 but a fourth (tokenId) topic
+        if topics.len() != 3 || topics[0].as_str().and_then(decode::event_name) != Some("Transfer") {
+            continue;
+        }
+        let (Some(token), Some(from), Some(to)) = (
+            address(&log["address"]),
+            topics[1].as_str().and_then(topic_address),
+            topics[2].as_str().and_then(topic_address),
+        ) else {
+            continue;
+        };
+        let amount = log["data"]
+            .as_str()
+            .and_then(decode::decode_hex)
+            .filter(|d| d.len() >= 32)
+            .map(|d| U256::from_big_endian(&d[..32]))
+            .unwrap_or_default();
+        let net = flows.entry(token).or_default();
+        if accounts.contains(&to) {
+            net.gained = net.gained.saturating_add(amount);
+        }
+        if accounts.contains(&from) {
+            net.lost = net.lost.saturating_add(amount);
+        }
+    }
+    flows.retain(|_, net| !net.is_zero());
+    flows
+}
+
+fn logs(tx: &Value) -> &[Value] {
+    tx["logs"].as_array().map(Vec::as_slice).unwrap_or_default()
+}
+
+fn has_event(tx: &Value, name: &str) -> bool {
+    logs(tx)
+        .iter()
+        .any(|log| log["topics"][0].as_str().and_then(decode::event_name) == Some(name))
+}
+
+// Pools (log addresses) the tx swapped on
+fn swap_pools(tx: &Value) -> HashSet<String> {
+    logs(tx)
+        .iter()
+        .filter(|log| log["topics"][0].as_str().and_then(decode::event_name) == Some("Swap"))
+        .filter_map(|log| address(&log["address"]))
+        .collect()
+}
+
+fn address(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_lowercase)
+}
+
+fn topic_address(topic: &str) -> Option<String> {
+    let hex = topic.strip_prefix("0x")?;
+    (hex.len() == 64).then(|| format!("0x{}", &hex[24..]).to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const SEARCHER: &str = "0x5ea2c4e25ea2c4e25ea2c4e25ea2c4e25ea2c4e2";
+    const BOT: &str = "0xb0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0";
+    const VICTIM: &str = "0x1111111111111111111111111111111111111111";
+    const POOL_A: &str = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const POOL_B: &str = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const SWAP: &str = "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    const LIQUIDATION: &str = "0xe413a321e8681d831f4dbccbca790d2952b56f977908e45be37335533e005286";
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", address.trim_start_matches("0x"))
+    }
+
+    fn swap(pool: &str) -> Value {
+        json!({ "address": pool, "topics": [SWAP], "data": "0x" })
+    }
+
+    fn transfer(token: &str, from: &str, to: &str, amount: u64) -> Value {
+        json!({
+            "address": token,
+            "topics": [TRANSFER, topic(from), topic(to)],
+            "data": format!("0x{:064x}", amount),
+        })
+    }
+
+    fn tx(hash: &str, from: &str, to: &str, logs: Vec<Value>) -> Value {
+        json!({ "hash": hash, "from": from, "to": to, "logs": logs })
+    }
+
+    #[test]
+    fn spots_a_sandwich_around_a_victim_swap() {
+        let front = tx("0x01", SEARCHER, BOT, vec![swap(POOL_A)]);
+        let victim = tx("0x02", VICTIM, POOL_A, vec![swap(POOL_A)]);
+        let back = tx("0x03", SEARCHER, BOT, vec![swap(POOL_A)]);
+        let correlation = correlate(&[&front, &victim, &back]);
+
+        assert_eq!(correlation.strategy, Some(BundleStrategy::Sandwich));
+        assert_eq!(correlation.searcher.as_deref(), Some(SEARCHER));
+        assert_eq!(correlation.victims, vec!["0x02".to_string()]);
+    }
+
+    #[test]
+    fn spots_a_profitable_arbitrage() {
+        let logs = vec![
+            swap(POOL_A),
+            swap(POOL_B),
+            transfer(WETH, BOT, POOL_A, 100),
+            transfer(WETH, POOL_B, BOT, 130),
+        ];
+        let arb = tx("0x01", SEARCHER, BOT, logs);
+        let correlation = correlate(&[&arb]);
+
+        assert_eq!(correlation.strategy, Some(BundleStrategy::Arbitrage));
+        assert_eq!(correlation.net_flows.len(), 1);
+        assert_eq!(correlation.net_flows[0].token, WETH);
+        assert_eq!(correlation.net_flows[0].net, "30");
+    }
+
+    #[test]
+    fn a_losing_round_trip_is_not_an_arbitrage() {
+        let logs = vec![
+            swap(POOL_A),
+            swap(POOL_B),
+            transfer(WETH, BOT, POOL_A, 100),
+            transfer(WETH, POOL_B, BOT, 90),
+        ];
+        let correlation = correlate(&[&tx("0x01", SEARCHER, BOT, logs)]);
+        assert_eq!(correlation.strategy, None);
+        assert_eq!(correlation.net_flows[0].net, "-10");
+    }
+
+    #[test]
+    fn liquidations_take_precedence() {
+        let liquidation = json!({ "address": POOL_B, "topics": [LIQUIDATION], "data": "0x" });
+        let front = tx("0x01", SEARCHER, BOT, vec![swap(POOL_A)]);
+        let victim = tx("0x02", VICTIM, POOL_A, vec![swap(POOL_A), liquidation]);
+        let back = tx("0x03", SEARCHER, BOT, vec![swap(POOL_A)]);
+        let correlation = correlate(&[&front, &victim, &back]);
+
+        assert_eq!(correlation.strategy, Some(BundleStrategy::Liquidation));
+        assert!(correlation.reasons[0].starts_with("Tx 2"));
+    }
+
+    #[test]
+    fn empty_bundles_have_no_searcher() {
+        let correlation = correlate(&[]);
+        assert!(correlation.searcher.is_none() && correlation.strategy.is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CategoryError {
+    #[error("failed to read {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("invalid custom categories: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("custom category {0} has no matching rules")]
+    NoRules(String),
+}
+
+/// A user-defined `tx_type`, assigned when every rule that is set matches.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomCategory {
+    pub name: String,
+    // Matches when the tx's sender or recipient is in the set
+    #[serde(default)]
+    pub counterparties: HashSet<String>,
+    #[serde(default)]
+    pub min_value_eth: Option<f64>,
+    #[serde(default)]
+    pub max_value_eth: Option<f64>,
+    // 4-byte selectors of the top-level call, e.g. "0xa9059cbb"
+    #[serde(default)]
+    pub selectors: HashSet<String>,
+}
+
+/// Reads the JSON array of categories from CUSTOM_CATEGORIES_PATH.
+pub fn load(path: &str) -> Result<Vec<CustomCategory>, CategoryError> {
+    let raw =
+        std::fs::read_to_string(path).map_err(|e| CategoryError::Read(path.to_string(), e))?;
+    let mut categories: Vec<CustomCategory> = serde_json::from_str(&raw)?;
+    for category in &mut categories {
+        // A category without rules would swallow every tx
+        if category.counterparties.is_empty()
+            && category.selectors.is_empty()
+            && category.min_value_eth.is_none()
+            && category.max_value_eth.is_none()
+        {
+            return Err(CategoryError::NoRules(category.name.clone()));
+        }
+        category.counterparties = lowercase(&category.counterparties);
+        category.selectors = lowercase(&category.selectors);
+    }
+    Ok(categories)
+}
+
+/// The first category (in config order) matching the tx, if any.
+pub fn classify<'a>(categories: &'a [CustomCategory], tx_details: &Value) -> Option<&'a str> {
+    let from = tx_details["from"].as_str().map(str::to_lowercase);
+    let to = tx_details["to"].as_str().map(str::to_lowercase);
+    let selector = tx_details["input"]
+        .as_str()
+        .filter(|input| input.len() >= 10)
+        .map(|input| input[..10].to_lowercase());
+    // Bounds are in the native token (ETH on Ethereum), so compared against the
+    // formatted amount
+    let value = tx_details["value"]["formatted"]
+        .as_str()
+        .and_then(|v| v.parse::<f64>().ok());
+
+    categories
+        .iter()
+        .find(|category| {
+            let counterparty = category.counterparties.is_empty()
+                || [&from, &to]
+                    .into_iter()
+                    .flatten()
+                    .any(|address| category.counterparties.contains(address));
+            let selector = category.selectors.is_empty()
+                || selector.as_ref().is_some_and(|s| category.selectors.contains(s));
+            let min = category
+                .min_value_eth
+                .is_none_or(|min| value.is_some_and(|v| v >= min));
+            let max = category
+                .max_value_eth
+                .is_none_or(|max| value.is_some_and(|v| v <= max));
+            counterparty && selector && min && max
+        })
+        .map(|category| category.name.as_str())
+}
+
+fn lowercase(set: &HashSet<String>) -> HashSet<String> {
+    set.iter().map(|s| s.trim().to_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, MockChain, RECIPIENT, TRANSFER_HASH};
+
+    fn write(name: &str, contents: &str) -> String {
+        let file = format!("categories-{}-{}.json", name, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    async fn analyze(categories: Vec<CustomCategory>) -> (u16, Value) {
+        let mut config = testing::config();
+        config.custom_categories = categories;
+        let state = testing::state_with(config, MockChain::transfer(), None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (status, body) = testing::send(&state, request).await;
+        (status.as_u16(), body)
+    }
+
+    #[tokio::test]
+    async fn a_custom_category_overrides_the_default_label() {
+        let (status, body) = analyze(Vec::new()).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["tx_type"], "TRANSFER");
+
+        // Counterparties match case-insensitively; the transfer sends 1 ETH
+        let rules = format!(
+            r#"[
+                {{ "name": "TREASURY_PAYOUT", "counterparties": ["{}"], "min_value_eth": 0.5 }},
+                {{ "name": "ANY_LARGE", "min_value_eth": 0.1 }}
+            ]"#,
+            RECIPIENT.to_uppercase().replacen("0X", "0x", 1)
+        );
+        let path = write("override", &rules);
+        let categories = load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let (_, body) = analyze(categories).await;
+        // The first matching category wins
+        assert_eq!(body["tx_type"], "TREASURY_PAYOUT");
+    }
+
+    #[test]
+    fn value_bounds_need_a_known_value() {
+        let categories = vec![CustomCategory {
+            name: "SMALL".to_string(),
+            counterparties: HashSet::new(),
+            min_value_eth: None,
+            max_value_eth: Some(0.01),
+            selectors: HashSet::new(),
+        }];
+        let small = json!({ "value": { "formatted": "0.001" } });
+        assert_eq!(classify(&categories, &small), Some("SMALL"));
+        assert_eq!(classify(&categories, &json!({ "value": { "formatted": "2" } })), None);
+        assert_eq!(classify(&categories, &json!({})), None);
+    }
+
+    #[test]
+    fn rejects_categories_without_rules() {
+        let path = write("no-rules", r#"[{ "name": "EVERYTHING" }]"#);
+        let result = load(&path);
+        std::fs::remove_file(path).unwrap();
+        assert!(matches!(result, Err(CategoryError::NoRules(name)) if name == "EVERYTHING"));
+        assert!(matches!(load("/nonexistent/categories.json"), Err(CategoryError::Read(..))));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::json;
+
+use crate::models::ContractInfo;
+use crate::network::Network;
+use crate::services::blockchain;
+use crate::services::rpc::{ChainClient, RpcError};
+use crate::store::unix_now;
+
+const SECS_PER_DAY: u64 = 24 * 3600;
+
+#[derive(Debug, Clone, Copy)]
+struct Deployment {
+    block: u64,
+    timestamp: u64,
+}
+
+/// Deployment blocks of contracts, found by binary search over `eth_getCode`
+/// (which needs an archive node). Cached per network and address: once
+/// found, a deployment never moves, and the age is computed from it on read.
+#[derive(Default)]
+pub struct DeploymentCache {
+    cache: Mutex<HashMap<(Network, String), Deployment>>,
+}
+
+impl DeploymentCache {
+    /// Fills `deployment_block` and `contract_age_days` for each contract that
+    /// had code at `block` (the tx's block). Failed lookups leave them unset.
+    pub async fn annotate(
+        &self,
+        network: Network,
+        rpc: &dyn ChainClient,
+        block: u64,
+        contracts: &mut [ContractInfo],
+    ) {
+        for contract in contracts {
+            let Some(deployment) = self.deployment(network, rpc, &contract.address, block).await
+            else {
+                continue;
+            };
+            contract.deployment_block = Some(deployment.block);
+            contract.contract_age_days =
+                Some((unix_now() as u64).saturating_sub(deployment.timestamp) / SECS_PER_DAY);
+        }
+    }
+
+    async fn deployment(
+        &self,
+        network: Network,
+        rpc: &dyn ChainClient,
+        address: &str,
+        block: u64,
+    ) -> Option<Deployment> {
+        let key = (network, address.to_lowercase());
+        if let Some(hit) = self.cache.lock().unwrap().get(&key) {
+            return Some(*hit);
+        }
+
+        let found = search(rpc, address, block)
+            .await
+            .inspect_err(|e| tracing::warn!("Deployment lookup failed for {}: {}", address, e))
+            .ok()??;
+        self.cache.lock().unwrap().insert(key, found);
+        Some(found)
+    }
+}
+
+// Lowest block at which `address` has code; `None` if it has none at `block`
+async fn search(rpc: &dyn ChainClient, address: &str, block: u64) -> Result<Option<Deployment>, RpcError> {
+    if !has_code(rpc, address, block).await? {
+        return Ok(None);
+    }
+    let (mut lo, mut hi) = (0, block);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if has_code(rpc, address, mid).await? {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+
+    let timestamp = blockchain::block_timestamp(rpc, lo).await?;
+    Ok(Some(Deployment { block: lo, timestamp }))
+}
+
+async fn has_code(rpc: &dyn ChainClient, address: &str, block: u64) -> Result<bool, RpcError> {
+    let code = rpc
+        .call("eth_getCode", json!([address, format!("0x{:x}", block)]))
+        .await?;
+    Ok(code.as_str().is_some_and(|c| c.len() > 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::future::BoxFuture;
+    use serde_json::Value;
+
+    use super::*;
+
+    const CONTRACT: &str = "0x3333333333333333333333333333333333333333";
+    const WALLET: &str = "0x4444444444444444444444444444444444444444";
+    const DEPLOYED_AT: u64 = 12_345;
+
+    // An archive node where CONTRACT has code from DEPLOYED_AT on, with a block
+    // every 12s from the unix epoch
+    #[derive(Default)]
+    struct ArchiveNode {
+        calls: AtomicUsize,
+    }
+
+    impl ArchiveNode {
+        fn answer(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            let block = |i: usize| {
+                let hex = params[i].as_str().unwrap().trim_start_matches("0x");
+                u64::from_str_radix(hex, 16).unwrap()
+            };
+            match method {
+                "eth_getCode" if params[0] == CONTRACT && block(1) >= DEPLOYED_AT => {
+                    Ok(json!("0x6080604052"))
+                }
+                "eth_getCode" => Ok(json!("0x")),
+                "eth_getBlockByNumber" => {
+                    Ok(json!({ "timestamp": format!("0x{:x}", block(0) * 12) }))
+                }
+                _ => Err(RpcError::BadResponse(format!("unexpected {}", method))),
+            }
+        }
+    }
+
+    impl ChainClient for ArchiveNode {
+        fn call<'a>(
+            &'a self,
+            method: &'a str,
+            params: Value,
+        ) -> BoxFuture<'a, Result<Value, RpcError>> {
+            Box::pin(async move { self.answer(method, &params) })
+        }
+
+        fn batch<'a>(
+            &'a self,
+            calls: &'a [(&'a str, Value)],
+        ) -> BoxFuture<'a, Vec<Result<Value, RpcError>>> {
+            Box::pin(async move {
+                calls.iter().map(|(method, params)| self.answer(method, params)).collect()
+            })
+        }
+    }
+
+    fn contract(address: &str) -> ContractInfo {
+        ContractInfo {
+            address: address.to_string(),
+            verified: true,
+            name: None,
+            deployment_block: None,
+            contract_age_days: None,
+            labels: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_the_deployment_block_by_binary_search() {
+        let (cache, node) = (DeploymentCache::default(), ArchiveNode::default());
+        let mut contracts = [contract(CONTRACT), contract(WALLET)];
+        cache.annotate(Network::EthereumMainnet, &node, 20_000, &mut contracts).await;
+
+        assert_eq!(contracts[0].deployment_block, Some(DEPLOYED_AT));
+        let age = (unix_now() as u64 - DEPLOYED_AT * 12) / SECS_PER_DAY;
+        assert_eq!(contracts[0].contract_age_days, Some(age));
+        // No code at the tx's block: not a contract (yet), so left unset
+        assert_eq!(contracts[1].deployment_block, None);
+        assert_eq!(contracts[1].contract_age_days, None);
+
+        // A check at the tx's block each, ~log2(20_000) probes, and one header
+        let calls = node.calls.load(Ordering::Relaxed);
+        assert!(calls <= 2 + 15 + 1, "{} calls", calls);
+
+        // Cached: the same contract costs no more calls
+        let mut again = [contract(CONTRACT)];
+        cache.annotate(Network::EthereumMainnet, &node, 30_000, &mut again).await;
+        assert_eq!(again[0].deployment_block, Some(DEPLOYED_AT));
+        assert_eq!(node.calls.load(Ordering::Relaxed), calls);
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_leave_the_age_unset() {
+        let cache = DeploymentCache::default();
+        let mut contracts = [contract(CONTRACT)];
+        let node = crate::testing::MockChain::failing(RpcError::Http(503));
+        cache.annotate(Network::EthereumMainnet, &node, 20_000, &mut contracts).await;
+        assert_eq!(contracts[0].deployment_block, None);
+        assert_eq!(contracts[0].contract_age_days, None);
+    }
+}
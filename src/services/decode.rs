@@ -0,0 +1,430 @@
+use primitive_types::U256;
+
+use serde_json::Value;
+
+use crate::services::selectors;
+use crate::util::keccak256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    Address,
+    Uint,
+    Int,
+    Bool,
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<ParamType>),
+    FixedArray(Box<ParamType>, usize),
+    Tuple(Vec<ParamType>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiValue {
+    Address(String),
+    Uint(U256),
+    // Raw two's-complement word
+    Int(U256),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(Vec<AbiValue>),
+    Tuple(Vec<AbiValue>),
+}
+
+impl AbiValue {
+    pub fn as_address(&self) -> Option<&str> {
+        match self {
+            AbiValue::Address(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_uint(&self) -> Option<U256> {
+        match self {
+            AbiValue::Uint(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedCall {
+    pub selector: String,
+    pub name: String,
+    pub params: Vec<AbiValue>,
+}
+
+/// Decodes calldata against the bundled selector table. Returns `None` for
+/// empty calldata, unknown selectors, or params that don't fit the signature.
+pub fn decode_calldata(input: &str) -> Option<DecodedCall> {
+    let bytes = decode_hex(input)?;
+    if bytes.len() < 4 {
+        return None;
+    }
+
+    let selector = format!("0x{}", hex::encode(&bytes[..4]));
+    let signature = selectors::function_signature(&selector)?;
+
+    let (name, types) = parse_signature(signature)?;
+    let params = decode_params(&types, &bytes[4..])?;
+
+    Some(DecodedCall {
+        selector,
+        name: name.to_string(),
+        params,
+    })
+}
+
+/// Decodes calldata against a caller-supplied canonical signature. `None`
+/// when the selector doesn't match it or the params don't fit.
+pub fn decode_calldata_as(input: &str, signature: &str) -> Option<DecodedCall> {
+    let bytes = decode_hex(input)?;
+    let selector = format!("0x{}", hex::encode(bytes.get(..4)?));
+    if selector != function_selector(signature) {
+        return None;
+    }
+    let (name, types) = parse_signature(signature)?;
+    Some(DecodedCall {
+        selector,
+        name: name.to_string(),
+        params: decode_params(&types, &bytes[4..])?,
+    })
+}
+
+/// Decodes a log against its event signature. `indexed[i]` says whether
+/// param `i` is in the topics (after topic0) rather than the data. Indexed
+/// dynamic values are only stored as their hash, which comes back as bytes.
+pub fn decode_log(
+    signature: &str,
+    indexed: &[bool],
+    topics: &[String],
+    data: &str,
+) -> Option<Vec<AbiValue>> {
+    let (_, types) = parse_signature(signature)?;
+    if indexed.len() != types.len()
+        || indexed.iter().filter(|&&i| i).count() != topics.len().saturating_sub(1)
+    {
+        return None;
+    }
+
+    let data_types: Vec<ParamType> = types
+        .iter()
+        .zip(indexed)
+        .filter(|(_, indexed)| !**indexed)
+        .map(|(ty, _)| ty.clone())
+        .collect();
+    let data = decode_hex(data)?;
+    // Trailing bytes mean the layout guess was wrong, not extra payload
+    if data_types.iter().all(|ty| !is_dynamic(ty))
+        && data.len() != data_types.iter().map(head_size).sum::<usize>()
+    {
+        return None;
+    }
+    let mut data_values = decode_params(&data_types, &data)?.into_iter();
+    let mut topic_words = topics.iter().skip(1);
+
+    types
+        .iter()
+        .zip(indexed)
+        .map(|(ty, indexed)| {
+            if !indexed {
+                return data_values.next();
+            }
+            let word = decode_hex(topic_words.next()?)?;
+            if is_dynamic(ty) || matches!(ty, ParamType::Tuple(_) | ParamType::FixedArray(..)) {
+                Some(AbiValue::Bytes(word))
+            } else {
+                decode_static(ty, &word)
+            }
+        })
+        .collect()
+}
+
+/// Param types of a canonical signature, as written ("uint256", "(address,bool)[]").
+pub fn signature_types(signature: &str) -> Vec<String> {
+    signature
+        .split_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .and_then(split_type_list)
+        .map(|types| types.into_iter().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// JSON form of a decoded value: numbers as decimal strings (they can exceed
+/// 64 bits), signed ints with their sign, bytes as 0x hex.
+pub fn to_json(value: &AbiValue) -> Value {
+    match value {
+        AbiValue::Address(a) => Value::String(a.clone()),
+        AbiValue::Uint(v) => Value::String(v.to_string()),
+        AbiValue::Int(v) if v.bit(255) => {
+            Value::String(format!("-{}", (!*v).overflowing_add(U256::one()).0))
+        }
+        AbiValue::Int(v) => Value::String(v.to_string()),
+        AbiValue::Bool(b) => Value::Bool(*b),
+        AbiValue::Bytes(b) => Value::String(format!("0x{}", hex::encode(b))),
+        AbiValue::String(s) => Value::String(s.clone()),
+        AbiValue::Array(items) | AbiValue::Tuple(items) => {
+            Value::Array(items.iter().map(to_json).collect())
+        }
+    }
+}
+
+/// Decodes ABI-encoded return data (e.g. from `eth_call`) as the comma-separated
+/// `types`, such as `"address[]"`.
+pub fn decode_output(types: &str, data: &str) -> Option<Vec<AbiValue>> {
+    decode_params(&parse_type_list(types)?, &decode_hex(data)?)
+}
+
+/// Event name ("Swap") for a log's topic0, if it's in the bundled table.
+pub fn event_name(topic0: &str) -> Option<&'static str> {
+    selectors::event_signature(topic0)
+        .and_then(|sig| sig.split_once('('))
+        .map(|(name, _)| name)
+}
+
+/// 4-byte selector ("0x095ea7b3") of a canonical function signature.
+pub fn function_selector(signature: &str) -> String {
+    format!("0x{}", hex::encode(&keccak256(signature.as_bytes())[..4]))
+}
+
+/// Canonical signature ("approve(address,uint256)") of a JSON ABI entry.
+pub fn abi_signature(entry: &Value) -> Option<String> {
+    let name = entry["name"].as_str()?;
+    let inputs = entry["inputs"].as_array()?;
+    let types: Option<Vec<String>> = inputs.iter().map(abi_type).collect();
+    Some(format!("{}({})", name, types?.join(",")))
+}
+
+// Tuples are spelled out as "(member,...)" plus any array suffix ("tuple[]" -> "(...)[]")
+fn abi_type(param: &Value) -> Option<String> {
+    let ty = param["type"].as_str()?;
+    match ty.strip_prefix("tuple") {
+        Some(suffix) => {
+            let members: Option<Vec<String>> =
+                param["components"].as_array()?.iter().map(abi_type).collect();
+            Some(format!("({}){}", members?.join(","), suffix))
+        }
+        None => Some(ty.to_string()),
+    }
+}
+
+pub fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    hex::decode(s.strip_prefix("0x").unwrap_or(s)).ok()
+}
+
+// "approve(address,uint256)" -> ("approve", [Address, Uint])
+fn parse_signature(signature: &str) -> Option<(&str, Vec<ParamType>)> {
+    let (name, rest) = signature.split_once('(')?;
+    let args = rest.strip_suffix(')')?;
+    Some((name, parse_type_list(args)?))
+}
+
+fn parse_type_list(list: &str) -> Option<Vec<ParamType>> {
+    split_type_list(list)?.into_iter().map(parse_type).collect()
+}
+
+// Splits on top-level commas only, so tuple members stay together
+fn split_type_list(list: &str) -> Option<Vec<&str>> {
+    if list.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mut types = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                types.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    types.push(&list[start..]);
+    Some(types)
+}
+
+fn parse_type(ty: &str) -> Option<ParamType> {
+    // Array suffixes bind last: "uint256[2][]" is a dynamic array of uint256[2]
+    if let Some(inner) = ty.strip_suffix("[]") {
+        return Some(ParamType::Array(Box::new(parse_type(inner)?)));
+    }
+    if ty.ends_with(']') {
+        let open = ty.rfind('[')?;
+        let len = ty[open + 1..ty.len() - 1].parse().ok()?;
+        return Some(ParamType::FixedArray(Box::new(parse_type(&ty[..open])?), len));
+    }
+    if let Some(members) = ty.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        return Some(ParamType::Tuple(parse_type_list(members)?));
+    }
+
+    match ty {
+        "address" => Some(ParamType::Address),
+        "bool" => Some(ParamType::Bool),
+        "bytes" => Some(ParamType::Bytes),
+        "string" => Some(ParamType::String),
+        t if t.starts_with("uint") => Some(ParamType::Uint),
+        t if t.starts_with("int") => Some(ParamType::Int),
+        t if t.starts_with("bytes") => {
+            let size = t[5..].parse().ok().filter(|s| (1..=32).contains(s))?;
+            Some(ParamType::FixedBytes(size))
+        }
+        _ => None,
+    }
+}
+
+fn is_dynamic(ty: &ParamType) -> bool {
+    match ty {
+        ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+        ParamType::FixedArray(inner, _) => is_dynamic(inner),
+        ParamType::Tuple(members) => members.iter().any(is_dynamic),
+        _ => false,
+    }
+}
+
+// Bytes a type takes up in the head of its enclosing tuple
+fn head_size(ty: &ParamType) -> usize {
+    if is_dynamic(ty) {
+        return 32;
+    }
+    match ty {
+        ParamType::FixedArray(inner, len) => head_size(inner) * len,
+        ParamType::Tuple(members) => members.iter().map(head_size).sum(),
+        _ => 32,
+    }
+}
+
+// `data` is the encoded tuple body; dynamic members are offsets relative to it
+fn decode_params(types: &[ParamType], data: &[u8]) -> Option<Vec<AbiValue>> {
+    let mut offset = 0;
+    types
+        .iter()
+        .map(|ty| {
+            let value = if is_dynamic(ty) {
+                let tail: usize = U256::from_big_endian(word(data, offset)?).try_into().ok()?;
+                decode_tail(ty, data.get(tail..)?)
+            } else {
+                decode_static(ty, data.get(offset..)?)
+            };
+            offset += head_size(ty);
+            value
+        })
+        .collect()
+}
+
+fn decode_static(ty: &ParamType, data: &[u8]) -> Option<AbiValue> {
+    match ty {
+        ParamType::Address => Some(AbiValue::Address(format!("0x{}", hex::encode(&word(data, 0)?[12..])))),
+        ParamType::Uint => Some(AbiValue::Uint(U256::from_big_endian(word(data, 0)?))),
+        ParamType::Int => Some(AbiValue::Int(U256::from_big_endian(word(data, 0)?))),
+        ParamType::Bool => Some(AbiValue::Bool(word(data, 0)?[31] != 0)),
+        ParamType::FixedBytes(size) => Some(AbiValue::Bytes(word(data, 0)?[..*size].to_vec())),
+        ParamType::FixedArray(inner, len) => {
+            Some(AbiValue::Array(decode_params(&vec![(**inner).clone(); *len], data)?))
+        }
+        ParamType::Tuple(members) => Some(AbiValue::Tuple(decode_params(members, data)?)),
+        ParamType::Bytes | ParamType::String | ParamType::Array(_) => None,
+    }
+}
+
+// Dynamic values are laid out at their tail offset
+fn decode_tail(ty: &ParamType, data: &[u8]) -> Option<AbiValue> {
+    match ty {
+        ParamType::Bytes | ParamType::String => {
+            let len: usize = U256::from_big_endian(word(data, 0)?).try_into().ok()?;
+            let raw = data.get(32..32usize.checked_add(len)?)?.to_vec();
+            if *ty == ParamType::String {
+                Some(AbiValue::String(String::from_utf8_lossy(&raw).into_owned()))
+            } else {
+                Some(AbiValue::Bytes(raw))
+            }
+        }
+        ParamType::Array(inner) => {
+            let len: usize = U256::from_big_endian(word(data, 0)?).try_into().ok()?;
+            // Every element needs at least one head word; reject bogus lengths early
+            if len > data.len() / 32 {
+                return None;
+            }
+            Some(AbiValue::Array(decode_params(&vec![(**inner).clone(); len], &data[32..])?))
+        }
+        _ => decode_static(ty, data),
+    }
+}
+
+fn word(data: &[u8], offset: usize) -> Option<&[u8]> {
+    data.get(offset..offset.checked_add(32)?)
+}
+
+/// Approvals in the top half of the uint256 range are treated as "unlimited"
+/// (MAX_UINT and friends).
+pub fn is_unlimited(amount: U256) -> bool {
+    amount >= U256::MAX >> 1
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn word(hex: &str) -> String {
+        format!("{:0>64}", hex)
+    }
+
+    #[test]
+    fn decodes_bundled_calldata() {
+        let input = format!(
+            "0xa9059cbb{}{}",
+            word("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"),
+            word("de0b6b3a7640000")
+        );
+        let call = decode_calldata(&input).unwrap();
+        assert_eq!(call.selector, "0xa9059cbb");
+        assert_eq!(call.name, "transfer");
+        assert_eq!(
+            call.params[0].as_address(),
+            Some("0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb")
+        );
+        assert_eq!(call.params[1].as_uint(), Some(U256::exp10(18)));
+    }
+
+    #[test]
+    fn decodes_dynamic_params() {
+        let signature = "setName(string,uint256[])";
+        let hello = format!("{:0<64}", hex::encode("hello"));
+        let input = [
+            function_selector(signature),
+            // Offsets of the string and the array, then each one's length and items
+            word("40"),
+            word("80"),
+            word("5"),
+            hello,
+            word("2"),
+            word("1"),
+            word("2"),
+        ]
+        .concat();
+        let call = decode_calldata_as(&input, signature).unwrap();
+        let params: Vec<Value> = call.params.iter().map(to_json).collect();
+        assert_eq!(params, vec![json!("hello"), json!(["1", "2"])]);
+    }
+
+    #[test]
+    fn rejects_unknown_or_truncated_calldata() {
+        assert!(decode_calldata("0xdeadbeef").is_none());
+        assert!(decode_calldata("0xa9059cbb").is_none());
+        assert!(decode_calldata("0x").is_none());
+        let input = format!("0xa9059cbb{}", word("1"));
+        assert!(decode_calldata_as(&input, "approve(address,uint256)").is_none());
+    }
+
+    #[test]
+    fn renders_negative_ints_with_their_sign() {
+        let minus_one = AbiValue::Int(U256::MAX);
+        assert_eq!(to_json(&minus_one), json!("-1"));
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::models::{
+    DecodeRequest, DecodeResponse, DecodedCallInfo, DecodedEventInfo, DecodedParam, ProvidedLog,
+    SignatureSource,
+};
+use crate::services::decode::{self, AbiValue};
+use crate::services::explorer::{Abi, ExplorerClient};
+use crate::services::selectors;
+use crate::util::keccak256;
+
+// Contracts whose ABI is fetched for one tx; logs of any further ones are
+// decoded with the bundled tables only
+const MAX_ABI_CONTRACTS: usize = 10;
+
+/// Standalone calldata/log decoding for `/decode`; no RPC, explorer or LLM.
+/// Signatures are tried in order: the request's `signature` (calldata only),
+/// its `abi`, then the bundled tables. What can't be decoded comes back raw.
+pub fn decode(request: &DecodeRequest) -> Result<DecodeResponse, String> {
+    let abi = request.abi.as_deref().unwrap_or_default();
+    let call = match &request.data {
+        Some(data) => {
+            decode::decode_hex(data).ok_or("data must be hex")?;
+            Some(decode_call(data, request.signature.as_deref(), abi, SignatureSource::Abi))
+        }
+        None => None,
+    };
+    if call.is_none() && request.logs.is_empty() {
+        return Err("Nothing to decode; send `data` and/or `logs`".to_string());
+    }
+    let events = request
+        .logs
+        .iter()
+        .map(|log| decode_event(log, abi, SignatureSource::Abi))
+        .collect();
+    Ok(DecodeResponse { call, events })
+}
+
+/// A tx's top-level call and logs, decoded with the verified ABI of the
+/// contract each belongs to where the explorer has one, else the bundled
+/// tables. A proxy's calls and events are decoded with its implementation's
+/// ABI. ABIs are fetched concurrently, and cached by the explorer client.
+pub async fn decode_tx(
+    explorer: &ExplorerClient,
+    tx_details: &Value,
+) -> (Option<DecodedCallInfo>, Vec<DecodedEventInfo>) {
+    let to = tx_details["to"].as_str().map(str::to_lowercase);
+    let implementation = tx_details["proxy"]["implementation"].as_str().map(str::to_lowercase);
+    let input = tx_details["input"].as_str().filter(|input| input.len() >= 10);
+    let logs: Vec<ProvidedLog> = tx_details["logs"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|log| serde_json::from_value(log.clone()).ok())
+        .collect();
+
+    // The contract whose ABI describes `address`
+    let abi_address = |address: &str| {
+        let address = address.to_lowercase();
+        match &implementation {
+            Some(logic) if to.as_ref() == Some(&address) => logic.clone(),
+            _ => address,
+        }
+    };
+    let mut seen = HashSet::new();
+    let addresses: Vec<String> = input
+        .and(to.as_deref())
+        .into_iter()
+        .chain(logs.iter().map(|log| log.address.as_str()))
+        .map(abi_address)
+        .filter(|address| seen.insert(address.clone()))
+        .take(MAX_ABI_CONTRACTS)
+        .collect();
+    let fetches = addresses
+        .into_iter()
+        .map(|address| async move { (explorer.abi(&address).await, address) });
+    let abis: HashMap<String, Abi> = futures::future::join_all(fetches)
+        .await
+        .into_iter()
+        .filter_map(|(abi, address)| Some((address, abi?)))
+        .collect();
+    let abi_of = |address: &str| {
+        abis.get(&abi_address(address))
+            .map(|abi| abi.as_slice())
+            .unwrap_or_default()
+    };
+
+    let call = input
+        .zip(to.as_deref())
+        .map(|(input, to)| decode_call(input, None, abi_of(to), SignatureSource::Explorer));
+    let events = logs
+        .iter()
+        .map(|log| decode_event(log, abi_of(&log.address), SignatureSource::Explorer))
+        .collect();
+    (call, events)
+}
+
+fn decode_call(
+    data: &str,
+    signature: Option<&str>,
+    abi: &[Value],
+    abi_source: SignatureSource,
+) -> DecodedCallInfo {
+    let selector = data.get(..10).map(str::to_lowercase);
+    let from_abi = selector.as_deref().and_then(|selector| {
+        abi.iter()
+            .filter(|entry| entry["type"] == "function")
+            .filter_map(decode::abi_signature)
+            .find(|sig| decode::function_selector(sig) == selector)
+    });
+    let candidates = [
+        signature.map(|sig| (sig.to_string(), SignatureSource::Signature)),
+        from_abi.map(|sig| (sig, abi_source)),
+        selector
+            .as_deref()
+            .and_then(selectors::function_signature)
+            .map(|sig| (sig.to_string(), SignatureSource::Bundled)),
+    ];
+
+    for (sig, source) in candidates.into_iter().flatten() {
+        if let Some(call) = decode::decode_calldata_as(data, &sig) {
+            return DecodedCallInfo {
+                selector,
+                params: Some(params(&sig, &call.params)),
+                signature: Some(sig),
+                signature_source: Some(source),
+                raw: None,
+            };
+        }
+    }
+    DecodedCallInfo {
+        raw: Some(format!("0x{}", data.get(10..).unwrap_or_default())),
+        selector,
+        signature: None,
+        signature_source: None,
+        params: None,
+    }
+}
+
+fn decode_event(log: &ProvidedLog, abi: &[Value], abi_source: SignatureSource) -> DecodedEventInfo {
+    let topic0 = log.topics.first().map(|t| t.to_lowercase());
+    let mut event = DecodedEventInfo {
+        address: log.address.clone(),
+        name: None,
+        signature: None,
+        signature_source: None,
+        indexed_inferred: false,
+        params: None,
+        topics: log.topics.clone(),
+        data: log.data.clone(),
+    };
+    let Some(topic0) = topic0 else {
+        return event;
+    };
+
+    // ABI event entries say exactly which inputs are indexed
+    let from_abi = abi
+        .iter()
+        .filter(|entry| entry["type"] == "event")
+        .find_map(|entry| {
+            let sig = decode::abi_signature(entry)?;
+            let topic = format!("0x{}", hex::encode(keccak256(sig.as_bytes())));
+            let indexed = entry["inputs"]
+                .as_array()?
+                .iter()
+                .map(|input| input["indexed"] == true)
+                .collect::<Vec<_>>();
+            (topic == topic0).then_some((sig, indexed))
+        });
+
+    let (sig, indexed, source) = match from_abi {
+        Some((sig, indexed)) => (sig, indexed, abi_source),
+        None => {
+            let Some(sig) = selectors::event_signature(&topic0) else {
+                return event;
+            };
+            let count = decode::signature_types(sig).len();
+            let indexed = match selectors::event_indexed(&topic0) {
+                Some(positions) => (0..count).map(|i| positions.contains(&i)).collect(),
+                None => {
+                    // Solidity convention for standard events: the leading params are indexed
+                    event.indexed_inferred = true;
+                    (0..count).map(|i| i < log.topics.len() - 1).collect()
+                }
+            };
+            (sig.to_string(), indexed, SignatureSource::Bundled)
+        }
+    };
+
+    event.name = sig.split_once('(').map(|(name, _)| name.to_string());
+    event.params = decode::decode_log(&sig, &indexed, &log.topics, &log.data)
+        .map(|values| params(&sig, &values));
+    event.signature = Some(sig);
+    event.signature_source = Some(source);
+    event
+}
+
+fn params(signature: &str, values: &[AbiValue]) -> Vec<DecodedParam> {
+    decode::signature_types(signature)
+        .into_iter()
+        .zip(values)
+        .map(|(ty, value)| DecodedParam {
+            ty,
+            value: decode::to_json(value),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    const VAULT: &str = "0x7a017a017a017a017a017a017a017a017a017a01";
+    const LOGIC: &str = "0x10a1c10a1c10a1c10a1c10a1c10a1c10a1c10a1c";
+    const TOKEN: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const USER: &str = "0x000000000000000000000000000000000000beef";
+
+    // A vault contract that isn't in the bundled tables
+    fn vault_abi() -> Value {
+        json!([
+            {
+                "type": "function",
+                "name": "lockUp",
+                "inputs": [{ "name": "amount", "type": "uint256" }]
+            },
+            {
+                "type": "event",
+                "name": "Staked",
+                "inputs": [
+                    { "name": "amount", "type": "uint256", "indexed": false },
+                    { "name": "user", "type": "address", "indexed": true }
+                ]
+            }
+        ])
+    }
+
+    fn word(hex: &str) -> String {
+        format!("{:0>64}", hex.trim_start_matches("0x"))
+    }
+
+    fn staked_topic() -> String {
+        format!("0x{}", hex::encode(keccak256(b"Staked(uint256,address)")))
+    }
+
+    // Only `abi_holder` has a verified ABI
+    async fn explorer(abi_holder: &'static str) -> ExplorerClient {
+        testing::explorer(move |query| {
+            if query.get("action").map(String::as_str) == Some("getabi")
+                && query.get("address").map(String::as_str) == Some(abi_holder)
+            {
+                json!({ "status": "1", "result": vault_abi().to_string() })
+            } else {
+                json!({ "status": "0", "result": "Contract source code not verified" })
+            }
+        })
+        .await
+    }
+
+    fn tx() -> Value {
+        json!({
+            "to": VAULT,
+            "input": format!("{}{}", decode::function_selector("lockUp(uint256)"), word("64")),
+            "logs": [
+                {
+                    "address": VAULT,
+                    "topics": [staked_topic(), format!("0x{}", word(USER))],
+                    "data": format!("0x{}", word("64"))
+                },
+                {
+                    "address": TOKEN,
+                    "topics": [
+                        "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef",
+                        format!("0x{}", word(USER)),
+                        format!("0x{}", word(VAULT))
+                    ],
+                    "data": format!("0x{}", word("64"))
+                }
+            ]
+        })
+    }
+
+    #[tokio::test]
+    async fn decodes_with_the_verified_abi_and_falls_back_to_bundled() {
+        let (call, events) = decode_tx(&explorer(VAULT).await, &tx()).await;
+
+        let call = call.unwrap();
+        assert_eq!(call.signature.as_deref(), Some("lockUp(uint256)"));
+        assert!(matches!(call.signature_source, Some(SignatureSource::Explorer)));
+        assert_eq!(call.params.unwrap()[0].value, json!("100"));
+
+        // The ABI marks the second input indexed, which the convention wouldn't guess
+        let staked = &events[0];
+        assert_eq!(staked.name.as_deref(), Some("Staked"));
+        assert!(matches!(staked.signature_source, Some(SignatureSource::Explorer)));
+        assert!(!staked.indexed_inferred);
+        let params = staked.params.as_ref().unwrap();
+        assert_eq!(params[0].value, json!("100"));
+        assert_eq!(params[1].value, json!(USER));
+
+        let transfer = &events[1];
+        assert_eq!(transfer.name.as_deref(), Some("Transfer"));
+        assert!(matches!(transfer.signature_source, Some(SignatureSource::Bundled)));
+        assert!(transfer.indexed_inferred);
+    }
+
+    #[tokio::test]
+    async fn decodes_a_proxy_with_its_implementation_abi() {
+        let mut tx = tx();
+        tx["proxy"] = json!({ "implementation": LOGIC });
+        let (call, events) = decode_tx(&explorer(LOGIC).await, &tx).await;
+
+        assert!(matches!(call.unwrap().signature_source, Some(SignatureSource::Explorer)));
+        assert_eq!(events[0].name.as_deref(), Some("Staked"));
+    }
+
+    #[tokio::test]
+    async fn leaves_unknown_calls_and_events_raw() {
+        let (call, events) = decode_tx(&explorer(LOGIC).await, &tx()).await;
+
+        let call = call.unwrap();
+        assert!(call.signature.is_none() && call.params.is_none());
+        assert_eq!(call.raw.as_deref(), Some(format!("0x{}", word("64")).as_str()));
+        assert!(events[0].name.is_none() && events[0].params.is_none());
+        assert_eq!(events[0].topics[0], staked_topic());
+    }
+}
@@ -0,0 +1,290 @@
+use primitive_types::U256;
+
+use crate::services::decode::{self, AbiValue};
+
+// EntryPoint bundle entry points, by selector
+const HANDLE_OPS_V06: &str = "0x1fad948c";
+const HANDLE_OPS_V07: &str = "0x765e827f";
+const HANDLE_AGGREGATED_OPS_V06: &str = "0x4b1d7cf5";
+
+/// One UserOperation from an EntryPoint bundle.
+#[derive(Debug, Clone)]
+pub struct UserOp {
+    pub sender: String,
+    pub nonce: U256,
+    // Non-empty when this op also deploys the account
+    pub init_code: Vec<u8>,
+    pub call_data: String,
+    // First 20 bytes of `paymasterAndData`; `None` when the account pays its own gas
+    pub paymaster: Option<String>,
+}
+
+/// A call the account makes on the op's behalf.
+#[derive(Debug, Clone)]
+pub struct AccountCall {
+    pub to: String,
+    pub value: U256,
+    pub data: String,
+}
+
+/// Decodes the UserOperations of an EntryPoint `handleOps` (v0.6 or v0.7) or
+/// `handleAggregatedOps` call; `None` for anything else.
+pub fn decode_bundle(input: &str) -> Option<Vec<UserOp>> {
+    let call = decode::decode_calldata(input)?;
+    let ops: Vec<&AbiValue> = match call.selector.as_str() {
+        HANDLE_OPS_V06 | HANDLE_OPS_V07 => items(call.params.first()?)?.iter().collect(),
+        // (UserOperation[], aggregator, signature) per aggregator
+        HANDLE_AGGREGATED_OPS_V06 => {
+            let mut ops = Vec::new();
+            for group in items(call.params.first()?)? {
+                let AbiValue::Tuple(fields) = group else {
+                    return None;
+                };
+                ops.extend(items(fields.first()?)?);
+            }
+            ops
+        }
+        _ => return None,
+    };
+    ops.into_iter().map(user_op).collect()
+}
+
+// v0.6: (sender, nonce, initCode, callData, 5 gas fields, paymasterAndData, signature)
+// v0.7 packs the gas fields into 3, but the fields read here sit in the same places
+fn user_op(op: &AbiValue) -> Option<UserOp> {
+    let AbiValue::Tuple(fields) = op else {
+        return None;
+    };
+    let [sender, nonce, AbiValue::Bytes(init_code), AbiValue::Bytes(call_data), ..] =
+        fields.as_slice()
+    else {
+        return None;
+    };
+    let AbiValue::Bytes(paymaster_and_data) = fields.get(fields.len().checked_sub(2)?)? else {
+        return None;
+    };
+
+    Some(UserOp {
+        sender: sender.as_address()?.to_lowercase(),
+        nonce: nonce.as_uint()?,
+        init_code: init_code.clone(),
+        call_data: format!("0x{}", hex::encode(call_data)),
+        paymaster: paymaster_and_data
+            .get(..20)
+            .map(|address| format!("0x{}", hex::encode(address))),
+    })
+}
+
+/// The calls in a smart account's `execute`/`executeBatch` callData (the
+/// SimpleAccount-style interface most accounts share). Empty when the callData
+/// is anything else.
+pub fn account_calls(call_data: &str) -> Vec<AccountCall> {
+    let Some(call) = decode::decode_calldata(call_data) else {
+        return Vec::new();
+    };
+    let calls = match call.name.as_str() {
+        "execute" => execute(&call.params),
+        "executeBatch" => execute_batch(&call.params),
+        _ => None,
+    };
+    calls.unwrap_or_default()
+}
+
+// execute(dest, value, func)
+fn execute(p: &[AbiValue]) -> Option<Vec<AccountCall>> {
+    let [to, value, AbiValue::Bytes(data)] = p else {
+        return None;
+    };
+    Some(vec![account_call(to, value.as_uint()?, data)?])
+}
+
+// executeBatch(dest[], func[]) or executeBatch(dest[], value[], func[])
+fn execute_batch(p: &[AbiValue]) -> Option<Vec<AccountCall>> {
+    let (targets, values, datas) = match p {
+        [targets, datas] => (items(targets)?, None, items(datas)?),
+        [targets, values, datas] => (items(targets)?, Some(items(values)?), items(datas)?),
+        _ => return None,
+    };
+    if datas.len() != targets.len() || values.is_some_and(|v| v.len() != targets.len()) {
+        return None;
+    }
+    targets
+        .iter()
+        .zip(datas)
+        .enumerate()
+        .map(|(i, (to, data))| {
+            let value = match values {
+                Some(values) => values[i].as_uint()?,
+                None => U256::zero(),
+            };
+            account_call(to, value, bytes(data)?)
+        })
+        .collect()
+}
+
+fn account_call(to: &AbiValue, value: U256, data: &[u8]) -> Option<AccountCall> {
+    Some(AccountCall {
+        to: to.as_address()?.to_lowercase(),
+        value,
+        data: format!("0x{}", hex::encode(data)),
+    })
+}
+
+fn items(value: &AbiValue) -> Option<&[AbiValue]> {
+    match value {
+        AbiValue::Array(items) => Some(items),
+        _ => None,
+    }
+}
+
+fn bytes(value: &AbiValue) -> Option<&[u8]> {
+    match value {
+        AbiValue::Bytes(bytes) => Some(bytes),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "0xacc0acc0acc0acc0acc0acc0acc0acc0acc0acc0";
+    const PAYMASTER: &str = "0x9a9a9a9a9a9a9a9a9a9a9a9a9a9a9a9a9a9a9a9a";
+    const TOKEN: &str = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    const BENEFICIARY: &str = "0x0000000000000000000000000000000000000b0b";
+
+    // Just enough of the ABI encoding for EntryPoint calls
+    enum Abi {
+        Word(String),
+        Bytes(Vec<u8>),
+        Tuple(Vec<Abi>),
+        Array(Vec<Abi>),
+    }
+
+    fn uint(value: u64) -> Abi {
+        Abi::Word(format!("{:064x}", value))
+    }
+
+    fn address(address: &str) -> Abi {
+        Abi::Word(format!("{:0>64}", address.trim_start_matches("0x")))
+    }
+
+    fn dynamic(value: &Abi) -> bool {
+        match value {
+            Abi::Word(_) => false,
+            Abi::Bytes(_) | Abi::Array(_) => true,
+            Abi::Tuple(items) => items.iter().any(dynamic),
+        }
+    }
+
+    fn encode(value: &Abi) -> String {
+        match value {
+            Abi::Word(word) => word.clone(),
+            Abi::Bytes(data) => {
+                let padded = data.len().div_ceil(32) * 64;
+                format!("{:064x}{:0<padded$}", data.len(), hex::encode(data))
+            }
+            Abi::Tuple(items) => encode_all(items),
+            Abi::Array(items) => format!("{:064x}{}", items.len(), encode_all(items)),
+        }
+    }
+
+    // Static items inline, dynamic ones as offsets into the tail
+    fn encode_all(items: &[Abi]) -> String {
+        let encoded: Vec<String> = items.iter().map(encode).collect();
+        let head_len: usize = items
+            .iter()
+            .zip(&encoded)
+            .map(|(item, e)| if dynamic(item) { 32 } else { e.len() / 2 })
+            .sum();
+        let (mut head, mut tail) = (String::new(), String::new());
+        for (item, e) in items.iter().zip(encoded) {
+            if dynamic(item) {
+                head.push_str(&format!("{:064x}", head_len + tail.len() / 2));
+                tail.push_str(&e);
+            } else {
+                head.push_str(&e);
+            }
+        }
+        head + &tail
+    }
+
+    fn calldata(selector: &str, params: Vec<Abi>) -> Vec<u8> {
+        let selector = selector.trim_start_matches("0x");
+        hex::decode(format!("{}{}", selector, encode_all(&params))).unwrap()
+    }
+
+    // A v0.6 UserOperation
+    fn user_op(nonce: u64, init_code: Vec<u8>, call_data: Vec<u8>, paymaster: Option<&str>) -> Abi {
+        let paymaster_and_data = paymaster
+            .map(|p| [hex::decode(&p[2..]).unwrap(), vec![0xee; 8]].concat())
+            .unwrap_or_default();
+        Abi::Tuple(vec![
+            address(ACCOUNT),
+            uint(nonce),
+            Abi::Bytes(init_code),
+            Abi::Bytes(call_data),
+            uint(100_000),
+            uint(200_000),
+            uint(50_000),
+            uint(30_000_000_000),
+            uint(1_000_000_000),
+            Abi::Bytes(paymaster_and_data),
+            Abi::Bytes(vec![0x11; 65]),
+        ])
+    }
+
+    fn handle_ops(ops: Vec<Abi>) -> String {
+        let input = calldata(HANDLE_OPS_V06, vec![Abi::Array(ops), address(BENEFICIARY)]);
+        format!("0x{}", hex::encode(input))
+    }
+
+    #[test]
+    fn decodes_the_ops_of_a_handle_ops_bundle() {
+        // execute(TOKEN, 0, transfer(...)), sponsored, and a batch that also deploys the account
+        let transfer = calldata("0xa9059cbb", vec![address(BENEFICIARY), uint(5)]);
+        let execute = calldata(
+            "0xb61d27f6",
+            vec![address(TOKEN), uint(0), Abi::Bytes(transfer.clone())],
+        );
+        let batch = calldata(
+            "0x47e1da2a",
+            vec![
+                Abi::Array(vec![address(TOKEN), address(BENEFICIARY)]),
+                Abi::Array(vec![uint(0), uint(1_000)]),
+                Abi::Array(vec![Abi::Bytes(transfer.clone()), Abi::Bytes(Vec::new())]),
+            ],
+        );
+        let input = handle_ops(vec![
+            user_op(7, Vec::new(), execute, Some(PAYMASTER)),
+            user_op(8, vec![0xfa; 40], batch, None),
+        ]);
+
+        let ops = decode_bundle(&input).unwrap();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].sender, ACCOUNT);
+        assert_eq!(ops[0].nonce, U256::from(7));
+        assert!(ops[0].init_code.is_empty());
+        assert_eq!(ops[0].paymaster.as_deref(), Some(PAYMASTER));
+        assert_eq!(ops[1].nonce, U256::from(8));
+        assert_eq!(ops[1].init_code.len(), 40);
+        assert_eq!(ops[1].paymaster, None);
+
+        let calls = account_calls(&ops[0].call_data);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].to, TOKEN);
+        assert_eq!(calls[0].data, format!("0x{}", hex::encode(&transfer)));
+
+        let calls = account_calls(&ops[1].call_data);
+        assert_eq!(calls.len(), 2);
+        assert_eq!((calls[1].to.as_str(), calls[1].value), (BENEFICIARY, U256::from(1_000)));
+        assert_eq!(calls[1].data, "0x");
+    }
+
+    #[test]
+    fn ignores_other_calls() {
+        let transfer = calldata("0xa9059cbb", vec![address(BENEFICIARY), uint(5)]);
+        assert!(decode_bundle(&format!("0x{}", hex::encode(transfer))).is_none());
+        assert!(account_calls("0x").is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::models::ContractInfo;
+use crate::services::decode;
+
+#[derive(Debug, Error)]
+pub enum ExplorerError {
+    #[error("Explorer request failed: {0}")]
+    RequestFailed(String),
+    #[error("Unexpected explorer response: {0}")]
+    BadResponse(String),
+}
+
+#[derive(Debug, Clone)]
+struct Verification {
+    verified: bool,
+    name: Option<String>,
+    // Red flags found in the verified source (see `SOURCE_RED_FLAGS`)
+    flags: Vec<String>,
+}
+
+/// What a contract's verified source says about it.
+#[derive(Debug, Clone)]
+pub struct SourceReview {
+    pub verified: bool,
+    pub flags: Vec<String>,
+}
+
+// Source patterns (matched case-insensitively) typical of scam tokens, with the
+// finding reported for each. Owner-controlled knobs are legitimate in many
+// tokens, so these are hints, not verdicts.
+const SOURCE_RED_FLAGS: &[(&[&str], &str)] = &[
+    (&["blacklist", "isbot", "_bots["], "Owner can blacklist addresses from transferring"),
+    (&["setfee", "settax", "updatefee", "setsellfee"], "Owner can change transfer fees"),
+    (&["tradingopen", "enabletrading", "tradingenabled"], "Trading can be switched off by the owner"),
+    (&["maxtxamount", "maxwallet", "setmaxtx"], "Transaction or wallet size is capped"),
+    (&["function mint("], "Supply can be minted after launch"),
+    (&["whennotpaused", "function pause("], "Transfers can be paused"),
+];
+
+// One contract's verified JSON ABI entries; empty when it isn't verified
+pub type Abi = Arc<Vec<Value>>;
+
+/// Etherscan-compatible explorer client. Verification results and ABIs are
+/// cached per address since a contract's source can't become unverified.
+pub struct ExplorerClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    cache: Mutex<HashMap<String, Verification>>,
+    abi_cache: Mutex<HashMap<String, Abi>>,
+}
+
+impl ExplorerClient {
+    pub fn new(http: reqwest::Client, base_url: String, api_key: Option<String>) -> Self {
+        Self {
+            http,
+            base_url,
+            api_key,
+            cache: Mutex::new(HashMap::new()),
+            abi_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Signature of `selector` according to the verified ABI of `address`.
+    /// Unverified contracts and failed lookups give `None`.
+    pub async fn function_signature(&self, address: &str, selector: &str) -> Option<String> {
+        let selector = selector.to_lowercase();
+        self.abi(address)
+            .await?
+            .iter()
+            .filter(|entry| entry["type"] == "function")
+            .filter_map(decode::abi_signature)
+            .find(|sig| decode::function_selector(sig) == selector)
+    }
+
+    /// The verified ABI of `address` (empty when unverified). Failed lookups
+    /// give `None` and aren't cached.
+    pub async fn abi(&self, address: &str) -> Option<Abi> {
+        let key = address.to_lowercase();
+        if let Some(abi) = self.abi_cache.lock().unwrap().get(&key) {
+            return Some(abi.clone());
+        }
+        if !is_address(address) {
+            return None;
+        }
+        match self.fetch_abi(address).await {
+            Ok(abi) => {
+                let abi = Arc::new(abi);
+                self.abi_cache.lock().unwrap().insert(key, abi.clone());
+                Some(abi)
+            }
+            Err(e) => {
+                tracing::warn!("ABI lookup failed for {}: {}", address, e);
+                None
+            }
+        }
+    }
+
+    /// Verification status and source red flags for one contract.
+    /// A failed lookup is reported as unverified.
+    pub async fn source_review(&self, address: &str) -> SourceReview {
+        match self.verification(address).await {
+            Ok(v) => SourceReview { verified: v.verified, flags: v.flags },
+            Err(e) => {
+                tracing::warn!("Contract verification lookup failed for {}: {}", address, e);
+                SourceReview { verified: false, flags: Vec::new() }
+            }
+        }
+    }
+
+    /// Looks up verification status for every contract counterparty of the tx.
+    /// Lookups that fail are reported as unverified but not cached.
+    pub async fn verify_counterparties(&self, tx_details: &Value) -> Vec<ContractInfo> {
+        let mut contracts = Vec::new();
+        for address in counterparty_contracts(tx_details) {
+            let verification = match self.verification(&address).await {
+                Ok(v) => v,
+                Err(e) => {
+                    tracing::warn!("Contract verification lookup failed for {}: {}", address, e);
+                    Verification { verified: false, name: None, flags: Vec::new() }
+                }
+            };
+            contracts.push(ContractInfo {
+                address,
+                verified: verification.verified,
+                name: verification.name,
+                deployment_block: None,
+                contract_age_days: None,
+                labels: Vec::new(),
+            });
+        }
+        contracts
+    }
+
+    async fn verification(&self, address: &str) -> Result<Verification, ExplorerError> {
+        let key = address.to_lowercase();
+        if let Some(hit) = self.cache.lock().unwrap().get(&key) {
+            return Ok(hit.clone());
+        }
+
+        // Malformed addresses (e.g. mocked data) can't be looked up
+        if !is_address(address) {
+            return Ok(Verification { verified: false, name: None, flags: Vec::new() });
+        }
+
+        self.fetch_verification(address).await.inspect(|verification| {
+            self.cache.lock().unwrap().insert(key, verification.clone());
+        })
+    }
+
+    #[tracing::instrument(
+        name = "explorer.getsourcecode",
+        skip(self),
+        fields(provider = %self.base_url, latency_ms = tracing::field::Empty)
+    )]
+    async fn fetch_verification(&self, address: &str) -> Result<Verification, ExplorerError> {
+        let started = Instant::now();
+        let body = self.get("getsourcecode", address).await?;
+
+        let entry = body["result"]
+            .get(0)
+            .ok_or_else(|| ExplorerError::BadResponse(body["result"].to_string()))?;
+
+        let source = entry["SourceCode"].as_str().unwrap_or("");
+        let verified = !source.is_empty();
+        let flags = red_flags(source);
+        let name = entry["ContractName"]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(str::to_string);
+
+        tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        Ok(Verification { verified, name, flags })
+    }
+
+    #[tracing::instrument(
+        name = "explorer.getabi",
+        skip(self),
+        fields(provider = %self.base_url, latency_ms = tracing::field::Empty)
+    )]
+    async fn fetch_abi(&self, address: &str) -> Result<Vec<Value>, ExplorerError> {
+        let started = Instant::now();
+        let body = self.get("getabi", address).await?;
+
+        // Unverified contracts come back as status "0" with a message instead of an ABI
+        let abi = match body["result"].as_str() {
+            Some(abi) if body["status"] == "1" => serde_json::from_str(abi)
+                .map_err(|e| ExplorerError::BadResponse(e.to_string()))?,
+            _ => Vec::new(),
+        };
+
+        tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        Ok(abi)
+    }
+
+    /// Up to `limit` normal txs sent or received by `address`, oldest first
+    /// when `oldest_first`, else newest first. Each is the explorer's entry
+    /// (`hash`, `from`, `to`, `value` in wei as a decimal string, ...).
+    pub async fn transactions(
+        &self,
+        address: &str,
+        limit: usize,
+        oldest_first: bool,
+    ) -> Result<Vec<Value>, ExplorerError> {
+        let sort = if oldest_first { "asc" } else { "desc" };
+        let limit = limit.to_string();
+        self.account_list(&[("action", "txlist"), ("address", address), ("sort", sort)], &limit)
+            .await
+    }
+
+    /// Up to `limit` of the latest ERC-721 transfers of `collection` to or
+    /// from `address`, newest first (`hash`, `from`, `to`, `tokenID`, ...).
+    pub async fn nft_transfers(
+        &self,
+        address: &str,
+        collection: &str,
+        limit: usize,
+    ) -> Result<Vec<Value>, ExplorerError> {
+        let limit = limit.to_string();
+        let query = [
+            ("action", "tokennfttx"),
+            ("address", address),
+            ("contractaddress", collection),
+            ("sort", "desc"),
+        ];
+        self.account_list(&query, &limit).await
+    }
+
+    #[tracing::instrument(
+        name = "explorer.account",
+        skip(self, limit),
+        fields(provider = %self.base_url, latency_ms = tracing::field::Empty)
+    )]
+    async fn account_list(
+        &self,
+        query: &[(&str, &str)],
+        limit: &str,
+    ) -> Result<Vec<Value>, ExplorerError> {
+        let started = Instant::now();
+        let mut query = query.to_vec();
+        query.extend([("module", "account"), ("page", "1"), ("offset", limit)]);
+        let body = self.query(query).await?;
+
+        // An address with no history comes back as status "0" with an empty list
+        let entries = match body["result"].as_array() {
+            Some(entries) => entries.clone(),
+            None => return Err(ExplorerError::BadResponse(body["result"].to_string())),
+        };
+
+        tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        Ok(entries)
+    }
+
+    async fn get(&self, action: &str, address: &str) -> Result<Value, ExplorerError> {
+        self.query(vec![("module", "contract"), ("action", action), ("address", address)])
+            .await
+    }
+
+    async fn query(&self, mut query: Vec<(&str, &str)>) -> Result<Value, ExplorerError> {
+        if let Some(api_key) = &self.api_key {
+            query.push(("apikey", api_key));
+        }
+
+        self.http
+            .get(&self.base_url)
+            .query(&query)
+            .send()
+            .await
+            .map_err(|e| ExplorerError::RequestFailed(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| ExplorerError::BadResponse(e.to_string()))
+    }
+}
+
+// Contract counterparties: every log emitter, plus `to` when the tx carries calldata
+// (a bare value transfer to an EOA shouldn't count as an unverified contract).
+fn counterparty_contracts(tx_details: &Value) -> Vec<String> {
+    let mut addresses: Vec<String> = Vec::new();
+
+    let has_calldata = tx_details["input"]
+        .as_str()
+        .is_some_and(|input| input.len() > 2);
+    if has_calldata && let Some(to) = tx_details["to"].as_str() {
+        addresses.push(to.to_string());
+    }
+
+    for log in tx_details["logs"].as_array().unwrap_or(&vec![]) {
+        if let Some(address) = log["address"].as_str() {
+            addresses.push(address.to_string());
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    addresses.retain(|a| seen.insert(a.to_lowercase()));
+    addresses
+}
+
+fn red_flags(source: &str) -> Vec<String> {
+    let source = source.to_lowercase();
+    SOURCE_RED_FLAGS
+        .iter()
+        .filter(|(patterns, _)| patterns.iter().any(|p| source.contains(p)))
+        .map(|(_, finding)| finding.to_string())
+        .collect()
+}
+
+fn is_address(s: &str) -> bool {
+    s.len() == 42
+        && s.starts_with("0x")
+        && s[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,157 @@
+use serde_json::Value;
+
+use crate::models::FailureReason;
+
+// A failed tx that burned at least this share of its gas limit ran out of gas.
+// An explicit REVERT refunds what's left, so reverts land well below it.
+const OUT_OF_GAS_USED_RATIO: f64 = 0.99;
+
+/// Why a failed tx failed; `None` unless its status is "failed".
+///
+/// The call trace (deep mode) says so outright. Without one, a failure that
+/// used (nearly) all of its gas is taken as out-of-gas, and one that left gas
+/// unused as a revert.
+pub fn classify(tx_details: &Value) -> Option<FailureReason> {
+    if tx_details["status"] != "failed" {
+        return None;
+    }
+
+    let error = tx_details["error"].as_str().unwrap_or("").to_lowercase();
+    if error.contains("out of gas") {
+        return Some(FailureReason::OutOfGas);
+    }
+    if error.contains("revert") || tx_details["revert_reason"].is_string() {
+        return Some(FailureReason::Reverted);
+    }
+
+    let gas_used = tx_details["gas_used"].as_u64();
+    let gas_limit = tx_details["gas_limit"].as_u64().filter(|limit| *limit > 0);
+    Some(match (gas_used, gas_limit) {
+        (Some(used), Some(limit)) if used as f64 >= limit as f64 * OUT_OF_GAS_USED_RATIO => {
+            FailureReason::OutOfGas
+        }
+        (Some(_), Some(_)) => FailureReason::Reverted,
+        _ => FailureReason::Unknown,
+    })
+}
+
+/// One sentence on the failure, for the heuristic explanation.
+pub fn describe(reason: FailureReason, tx_details: &Value) -> String {
+    let gas = match (tx_details["gas_used"].as_u64(), tx_details["gas_limit"].as_u64()) {
+        (Some(used), Some(limit)) => format!(" (used {} of its {} gas limit)", used, limit),
+        _ => String::new(),
+    };
+    match reason {
+        FailureReason::OutOfGas => format!(
+            "The transaction failed because it ran out of gas{}; resending it with a higher \
+             gas limit may succeed.",
+            gas
+        ),
+        FailureReason::Reverted => match tx_details["revert_reason"].as_str() {
+            Some(revert) => format!(
+                "The transaction failed because a contract reverted it: \"{}\"{}.",
+                revert, gas
+            ),
+            None => format!("The transaction failed because a contract reverted it{}.", gas),
+        },
+        FailureReason::Unknown => {
+            "The transaction failed; the data available doesn't show why.".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, MockChain, RECIPIENT, SENDER, TRANSFER_HASH};
+
+    // Normalized details of a failed tx with a 100k gas limit
+    fn failed(gas_used: Option<u64>) -> Value {
+        let mut tx = json!({ "status": "failed", "gas_limit": 100_000 });
+        if let Some(used) = gas_used {
+            tx["gas_used"] = json!(used);
+        }
+        tx
+    }
+
+    #[test]
+    fn all_gas_used_is_out_of_gas() {
+        let tx = failed(Some(99_500));
+        assert_eq!(classify(&tx), Some(FailureReason::OutOfGas));
+        assert!(describe(FailureReason::OutOfGas, &tx).contains("used 99500 of its 100000 gas"));
+    }
+
+    #[test]
+    fn gas_left_over_is_a_revert() {
+        let mut tx = failed(Some(43_000));
+        assert_eq!(classify(&tx), Some(FailureReason::Reverted));
+
+        tx["revert_reason"] = json!("Too little received");
+        let explanation = describe(FailureReason::Reverted, &tx);
+        assert!(explanation.contains("\"Too little received\""), "{}", explanation);
+    }
+
+    #[test]
+    fn without_gas_figures_the_reason_is_unknown() {
+        assert_eq!(classify(&failed(None)), Some(FailureReason::Unknown));
+        let mut tx = failed(Some(43_000));
+        tx["gas_limit"] = json!(0);
+        assert_eq!(classify(&tx), Some(FailureReason::Unknown));
+    }
+
+    #[test]
+    fn the_trace_error_wins_over_gas_figures() {
+        let mut tx = failed(Some(43_000));
+        tx["error"] = json!("out of gas");
+        assert_eq!(classify(&tx), Some(FailureReason::OutOfGas));
+        let mut tx = failed(Some(100_000));
+        tx["error"] = json!("execution reverted");
+        assert_eq!(classify(&tx), Some(FailureReason::Reverted));
+    }
+
+    #[test]
+    fn successful_txs_have_no_failure_reason() {
+        assert_eq!(classify(&json!({ "status": "success", "gas_used": 100_000 })), None);
+        assert_eq!(classify(&json!({ "status": "pending" })), None);
+    }
+
+    // The failure_reason of an analysis of a tx with a 100k gas limit (none
+    // when `gas_limit` is false) and a failed receipt that used `gas_used`
+    async fn analyzed(gas_limit: bool, gas_used: u64) -> Value {
+        let mut tx = json!({
+            "hash": TRANSFER_HASH,
+            "from": SENDER,
+            "to": RECIPIENT,
+            "value": "0x0",
+            "input": "0x38ed1739",
+            "gas": "0x186a0",
+            "gasPrice": "0x3b9aca00",
+            "blockNumber": "0x1312d00",
+        });
+        if !gas_limit {
+            tx.as_object_mut().unwrap().remove("gas");
+        }
+        let receipt = json!({
+            "status": "0x0",
+            "gasUsed": format!("0x{:x}", gas_used),
+            "blockHash": format!("0x{}", "f".repeat(64)),
+            "logs": [],
+        });
+        let chain = MockChain::transfer()
+            .with_params("eth_getTransactionByHash", json!([TRANSFER_HASH]), tx)
+            .with_params("eth_getTransactionReceipt", json!([TRANSFER_HASH]), receipt);
+        let state = testing::state(chain, None);
+        let request = testing::post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+        let (_, body) = testing::send(&state, request).await;
+        body["failure_reason"].clone()
+    }
+
+    #[tokio::test]
+    async fn classifies_failed_receipts() {
+        assert_eq!(analyzed(true, 100_000).await, "OutOfGas");
+        assert_eq!(analyzed(true, 43_000).await, "Reverted");
+        assert_eq!(analyzed(false, 43_000).await, "Unknown");
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::models::{Congestion, GasContext, GasPriceVsCurrent};
+use crate::network::Network;
+use crate::services::blockchain::parse_u64;
+use crate::services::rpc::ChainClient;
+
+// Current prices are reused for about a mainnet block
+const CACHE_TTL: Duration = Duration::from_secs(12);
+
+// Only txs this recent (or still pending) are compared with current prices;
+// for older ones, what the network costs now says nothing about the tx
+const MAX_CONFIRMATIONS: u64 = 50;
+
+// Within this share of the current price either way counts as at current
+const AT_CURRENT_BAND: f64 = 0.1;
+
+// Latest-block utilization (gas used / limit) above which the network counts
+// as moderately / highly congested. EIP-1559 targets half-full blocks.
+const MODERATE_UTILIZATION: f64 = 0.5;
+const HIGH_UTILIZATION: f64 = 0.9;
+
+const WEI_PER_GWEI: f64 = 1e9;
+
+#[derive(Debug, Clone, Copy)]
+struct Current {
+    // eth_gasPrice: the base fee plus a typical tip
+    gas_price: u64,
+    // Unset on networks without EIP-1559
+    base_fee: Option<u64>,
+    utilization: f64,
+}
+
+/// Each network's current gas price and how full its latest block is, cached
+/// briefly, to put a recent or pending tx's gas price in context.
+#[derive(Default)]
+pub struct GasPriceCache {
+    cache: Mutex<HashMap<Network, (Instant, Current)>>,
+}
+
+impl GasPriceCache {
+    /// The tx's gas price against the network's current one. `None` for txs
+    /// older than MAX_CONFIRMATIONS, without a gas price, or when the current
+    /// price can't be read.
+    pub async fn context(
+        &self,
+        network: Network,
+        rpc: &dyn ChainClient,
+        tx_details: &Value,
+    ) -> Option<GasContext> {
+        let tx_gas_price = tx_details["gas_price"].as_u64().filter(|p| *p > 0)?;
+        if tx_details["confirmations"].as_u64().is_some_and(|c| c > MAX_CONFIRMATIONS) {
+            return None;
+        }
+        let current = self.current(network, rpc).await?;
+        Some(compare(tx_gas_price, current))
+    }
+
+    async fn current(&self, network: Network, rpc: &dyn ChainClient) -> Option<Current> {
+        if let Some((fetched, current)) = self.cache.lock().unwrap().get(&network)
+            && fetched.elapsed() < CACHE_TTL
+        {
+            return Some(*current);
+        }
+
+        let calls = [
+            ("eth_gasPrice", json!([])),
+            ("eth_getBlockByNumber", json!(["latest", false])),
+        ];
+        let mut results = rpc.batch(&calls).await.into_iter();
+        let (Some(gas_price), Some(block)) = (results.next(), results.next()) else {
+            return None;
+        };
+        let (gas_price, block) = match (gas_price, block) {
+            (Ok(gas_price), Ok(block)) => (gas_price, block),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!("Current gas price lookup failed on {}: {}", network, e);
+                return None;
+            }
+        };
+
+        let gas_limit = parse_u64(&block["gasLimit"]);
+        let current = Current {
+            gas_price: parse_u64(&gas_price),
+            base_fee: (!block["baseFeePerGas"].is_null())
+                .then(|| parse_u64(&block["baseFeePerGas"])),
+            utilization: if gas_limit > 0 {
+                parse_u64(&block["gasUsed"]) as f64 / gas_limit as f64
+            } else {
+                0.0
+            },
+        };
+        self.cache.lock().unwrap().insert(network, (Instant::now(), current));
+        Some(current)
+    }
+}
+
+fn compare(tx_gas_price: u64, current: Current) -> GasContext {
+    let ratio = tx_gas_price as f64 / current.gas_price.max(1) as f64;
+    let vs_current = if ratio > 1.0 + AT_CURRENT_BAND {
+        GasPriceVsCurrent::Above
+    } else if ratio < 1.0 - AT_CURRENT_BAND {
+        GasPriceVsCurrent::Below
+    } else {
+        GasPriceVsCurrent::AtCurrent
+    };
+    let network_congestion = if current.utilization > HIGH_UTILIZATION {
+        Congestion::High
+    } else if current.utilization > MODERATE_UTILIZATION {
+        Congestion::Moderate
+    } else {
+        Congestion::Low
+    };
+    GasContext {
+        tx_gas_price_gwei: gwei(tx_gas_price),
+        current_gas_price_gwei: gwei(current.gas_price),
+        current_base_fee_gwei: current.base_fee.map(gwei),
+        ratio: (ratio * 100.0).round() / 100.0,
+        vs_current,
+        network_congestion,
+        latest_block_utilization: (current.utilization * 100.0).round() / 100.0,
+    }
+}
+
+fn gwei(wei: u64) -> f64 {
+    (wei as f64 / WEI_PER_GWEI * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::rpc::RpcError;
+    use crate::testing::MockChain;
+
+    // A node where gas costs 20 gwei (a 15 gwei base fee) and the latest
+    // block is `used` million of 30 million gas full
+    fn node(used: u64) -> MockChain {
+        MockChain::default().with("eth_gasPrice", json!("0x4a817c800")).with(
+            "eth_getBlockByNumber",
+            json!({
+                "gasLimit": "0x1c9c380",
+                "gasUsed": format!("0x{:x}", used * 1_000_000),
+                "baseFeePerGas": "0x37e11d600",
+            }),
+        )
+    }
+
+    async fn context(rpc: &MockChain, gas_price_gwei: u64) -> Option<GasContext> {
+        let tx_details = json!({
+            "gas_price": gas_price_gwei * 1_000_000_000,
+            "confirmations": 3,
+        });
+        GasPriceCache::default()
+            .context(Network::EthereumMainnet, rpc, &tx_details)
+            .await
+    }
+
+    #[tokio::test]
+    async fn compares_the_tx_price_with_the_current_one() {
+        let rpc = node(29);
+        let gas = context(&rpc, 60).await.unwrap();
+        assert_eq!(gas.tx_gas_price_gwei, 60.0);
+        assert_eq!(gas.current_gas_price_gwei, 20.0);
+        assert_eq!(gas.current_base_fee_gwei, Some(15.0));
+        assert_eq!(gas.ratio, 3.0);
+        assert_eq!(gas.vs_current, GasPriceVsCurrent::Above);
+        assert_eq!(gas.network_congestion, Congestion::High);
+        assert_eq!(gas.latest_block_utilization, 0.97);
+
+        let gas = context(&node(18), 21).await.unwrap();
+        assert_eq!(gas.ratio, 1.05);
+        assert_eq!(gas.vs_current, GasPriceVsCurrent::AtCurrent);
+        assert_eq!(gas.network_congestion, Congestion::Moderate);
+
+        let gas = context(&node(6), 10).await.unwrap();
+        assert_eq!(gas.ratio, 0.5);
+        assert_eq!(gas.vs_current, GasPriceVsCurrent::Below);
+        assert_eq!(gas.network_congestion, Congestion::Low);
+    }
+
+    #[tokio::test]
+    async fn old_or_unpriced_txs_get_no_context() {
+        let cache = GasPriceCache::default();
+        let rpc = node(15);
+        let old = json!({ "gas_price": 20_000_000_000u64, "confirmations": 51 });
+        let unpriced = json!({ "confirmations": 3 });
+        for tx_details in [old, unpriced] {
+            let gas = cache.context(Network::EthereumMainnet, &rpc, &tx_details).await;
+            assert!(gas.is_none(), "{}", tx_details);
+        }
+        // Nor when the current price can't be read
+        assert!(context(&MockChain::failing(RpcError::Http(503)), 20).await.is_none());
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::network::Network;
+
+#[derive(Debug, Error)]
+pub enum LabelError {
+    #[error("Labels request failed: {0}")]
+    RequestFailed(String),
+    #[error("Unexpected labels response: {0}")]
+    BadResponse(String),
+}
+
+/// A source of address labels ("Binance 14", "Uniswap V3: Router"), e.g.
+/// Etherscan's or Arkham's label APIs. An address without labels is `Ok(vec![])`.
+pub trait LabelProvider: Send + Sync {
+    fn labels<'a>(
+        &'a self,
+        network: Network,
+        address: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, LabelError>>;
+}
+
+/// Labels from a JSON HTTP API. `url` may contain `{address}` and
+/// `{chain_id}` placeholders; the response is an array of labels, or an
+/// object with a `labels` or `tags` array. Entries are strings or objects
+/// with a `label` or `name`.
+pub struct HttpLabelProvider {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl HttpLabelProvider {
+    pub fn new(http: reqwest::Client, url: String, api_key: Option<String>) -> Self {
+        Self { http, url, api_key }
+    }
+
+    #[tracing::instrument(
+        name = "labels.fetch",
+        skip(self),
+        fields(latency_ms = tracing::field::Empty)
+    )]
+    async fn fetch(&self, network: Network, address: &str) -> Result<Vec<String>, LabelError> {
+        let started = Instant::now();
+        let url = self
+            .url
+            .replace("{address}", address)
+            .replace("{chain_id}", &network.chain_id().to_string());
+        let mut request = self.http.get(url);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| LabelError::RequestFailed(e.to_string()))?;
+        // Providers answer 404 for addresses they have nothing on
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body: Value = response
+            .error_for_status()
+            .map_err(|e| LabelError::RequestFailed(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| LabelError::BadResponse(e.to_string()))?;
+
+        tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        parse_labels(&body)
+    }
+}
+
+impl LabelProvider for HttpLabelProvider {
+    fn labels<'a>(
+        &'a self,
+        network: Network,
+        address: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, LabelError>> {
+        Box::pin(self.fetch(network, address))
+    }
+}
+
+fn parse_labels(body: &Value) -> Result<Vec<String>, LabelError> {
+    let entries = body
+        .as_array()
+        .or_else(|| body["labels"].as_array())
+        .or_else(|| body["tags"].as_array())
+        .ok_or_else(|| LabelError::BadResponse(body.to_string()))?;
+    Ok(entries
+        .iter()
+        .filter_map(|entry| {
+            entry
+                .as_str()
+                .or_else(|| entry["label"].as_str())
+                .or_else(|| entry["name"].as_str())
+        })
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+// (network, lowercase address) -> when fetched, labels
+type LabelCache = HashMap<(Network, String), (Instant, Vec<String>)>;
+
+/// Cached front of a `LabelProvider`. Labels rarely change, so entries live
+/// for a long TTL; failed lookups give no labels and aren't cached.
+pub struct AddressLabels {
+    provider: Box<dyn LabelProvider>,
+    ttl: Duration,
+    cache: Mutex<LabelCache>,
+}
+
+impl AddressLabels {
+    pub fn new(provider: Box<dyn LabelProvider>, ttl: Duration) -> Self {
+        Self {
+            provider,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Labels of each address that has any, in input order. Lookups run
+    /// concurrently.
+    pub async fn lookup(
+        &self,
+        network: Network,
+        addresses: &[String],
+    ) -> Vec<(String, Vec<String>)> {
+        let lookups = addresses.iter().map(|address| async move {
+            (address.clone(), self.labels(network, address).await)
+        });
+        futures::future::join_all(lookups)
+            .await
+            .into_iter()
+            .filter(|(_, labels)| !labels.is_empty())
+            .collect()
+    }
+
+    async fn labels(&self, network: Network, address: &str) -> Vec<String> {
+        // Malformed addresses (e.g. mocked data) can't be looked up
+        if !is_address(address) {
+            return Vec::new();
+        }
+        let key = (network, address.to_lowercase());
+        if let Some((fetched, labels)) = self.cache.lock().unwrap().get(&key)
+            && fetched.elapsed() < self.ttl
+        {
+            return labels.clone();
+        }
+
+        match self.provider.labels(network, &key.1).await {
+            Ok(labels) => {
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(key, (Instant::now(), labels.clone()));
+                labels
+            }
+            Err(e) => {
+                tracing::warn!("Label lookup failed for {}: {}", address, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn is_address(s: &str) -> bool {
+    s.len() == 42 && s.starts_with("0x") && s[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
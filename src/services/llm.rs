@@ -0,0 +1,249 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+
+use crate::services::ai::AiError;
+
+/// A model provider: completions for the analyses, embeddings for similarity
+/// search. `LlmClient` is the HTTP implementation; tests and tools can put a
+/// recorded or scripted provider in its place (see `state::Services`).
+pub trait LlmProvider: Send + Sync {
+    // Default model, used unless a request picks another allowed one
+    fn model(&self) -> &str;
+
+    /// Sends a single-turn prompt to `model` and returns the raw message
+    /// content, which should be a JSON object, in at most `max_tokens`.
+    /// Without a `temperature`, the provider's default applies.
+    fn complete<'a>(
+        &'a self,
+        model: &'a str,
+        prompt: &'a str,
+        temperature: Option<f32>,
+        max_tokens: u32,
+    ) -> BoxFuture<'a, Result<String, AiError>>;
+
+    fn embed<'a>(&'a self, model: &'a str, input: &'a str) -> BoxFuture<'a, Result<Vec<f32>, AiError>>;
+}
+
+/// OpenAI-compatible chat completions client.
+pub struct LlmClient {
+    http: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    model: String,
+}
+
+impl LlmClient {
+    pub fn new(http: reqwest::Client, api_key: String, base_url: String, model: String) -> Self {
+        Self {
+            http,
+            api_key,
+            base_url,
+            model,
+        }
+    }
+
+    // The model is asked for a JSON object (response_format) so the caller can parse it
+    #[tracing::instrument(
+        name = "llm.complete",
+        skip_all,
+        fields(provider = %self.base_url, model = %model, latency_ms = tracing::field::Empty)
+    )]
+    async fn chat_completion(
+        &self,
+        model: &str,
+        prompt: &str,
+        temperature: Option<f32>,
+        max_tokens: u32,
+    ) -> Result<String, AiError> {
+        let started = Instant::now();
+        let mut request = json!({
+            "model": model,
+            "messages": [
+                {"role": "user", "content": prompt}
+            ],
+            "max_tokens": max_tokens,
+            "response_format": {"type": "json_object"}
+        });
+        if let Some(temperature) = temperature {
+            request["temperature"] = json!(temperature);
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url.trim_end_matches('/')))
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| AiError::LlmCallFailed(e.to_string()))?;
+
+        let status = response.status();
+        let json: Value = response
+            .json()
+            .await
+            .map_err(|e| AiError::LlmCallFailed(e.to_string()))?;
+        if is_quota_error(status, &json) {
+            return Err(AiError::QuotaExhausted(format!("{}: {}", status, json["error"])));
+        }
+        if !status.is_success() {
+            return Err(AiError::LlmCallFailed(format!("{}: {}", status, json["error"])));
+        }
+
+        tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        json["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AiError::LlmCallFailed("response had no message content".to_string()))
+    }
+
+    #[tracing::instrument(
+        name = "llm.embed",
+        skip_all,
+        fields(provider = %self.base_url, model = %model, latency_ms = tracing::field::Empty)
+    )]
+    async fn embedding(&self, model: &str, input: &str) -> Result<Vec<f32>, AiError> {
+        let started = Instant::now();
+        let response = self
+            .http
+            .post(format!("{}/embeddings", self.base_url.trim_end_matches('/')))
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "model": model, "input": input }))
+            .send()
+            .await
+            .map_err(|e| AiError::LlmCallFailed(e.to_string()))?;
+
+        let status = response.status();
+        let json: Value = response
+            .json()
+            .await
+            .map_err(|e| AiError::LlmCallFailed(e.to_string()))?;
+        if !status.is_success() {
+            return Err(AiError::LlmCallFailed(format!("{}: {}", status, json["error"])));
+        }
+
+        tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        json["data"][0]["embedding"]
+            .as_array()
+            .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+            .filter(|vector: &Vec<f32>| !vector.is_empty())
+            .ok_or_else(|| AiError::LlmCallFailed("response had no embedding".to_string()))
+    }
+}
+
+impl LlmProvider for LlmClient {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn complete<'a>(
+        &'a self,
+        model: &'a str,
+        prompt: &'a str,
+        temperature: Option<f32>,
+        max_tokens: u32,
+    ) -> BoxFuture<'a, Result<String, AiError>> {
+        Box::pin(self.chat_completion(model, prompt, temperature, max_tokens))
+    }
+
+    /// Embeds `input` with the provider's embeddings endpoint.
+    fn embed<'a>(&'a self, model: &'a str, input: &'a str) -> BoxFuture<'a, Result<Vec<f32>, AiError>> {
+        Box::pin(self.embedding(model, input))
+    }
+}
+
+// 402, or OpenAI's 429 with `insufficient_quota` (plain 429s are rate limits,
+// which pass)
+fn is_quota_error(status: reqwest::StatusCode, body: &Value) -> bool {
+    let error = &body["error"];
+    status == reqwest::StatusCode::PAYMENT_REQUIRED
+        || (status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            && (error["code"] == "insufficient_quota" || error["type"] == "insufficient_quota"))
+}
+
+/// LLM use shared by the items of one batch. Once it's exhausted, because
+/// the batch used up its `BATCH_LLM_BUDGET` or the provider reported its
+/// quota gone, the remaining items get heuristics only instead of each
+/// failing an LLM call in turn.
+pub struct LlmBudget {
+    // Items that may still use the LLM; `None` = no cap
+    remaining: Mutex<Option<usize>>,
+    exhausted: AtomicBool,
+}
+
+impl LlmBudget {
+    pub fn new(items: Option<usize>) -> Self {
+        Self {
+            remaining: Mutex::new(items),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Claims LLM use for one item; false (and exhausted from then on) once
+    /// nothing is left.
+    pub fn take(&self) -> bool {
+        if self.exhausted() {
+            return false;
+        }
+        let mut remaining = self.remaining.lock().unwrap();
+        match remaining.as_mut() {
+            Some(0) => {
+                self.exhausted.store(true, Ordering::Relaxed);
+                false
+            }
+            Some(n) => {
+                *n -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Whether an item has had to go without the LLM for lack of budget.
+    pub fn exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
+
+/// A provider that exhausts `budget` when the provider it wraps runs out of
+/// quota.
+pub struct Budgeted<'a> {
+    pub inner: &'a dyn LlmProvider,
+    pub budget: &'a LlmBudget,
+}
+
+impl Budgeted<'_> {
+    fn observe<T>(&self, result: Result<T, AiError>) -> Result<T, AiError> {
+        if let Err(AiError::QuotaExhausted(e)) = &result {
+            tracing::warn!("LLM quota exhausted; the rest of the batch is heuristic-only: {}", e);
+            self.budget.exhausted.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl LlmProvider for Budgeted<'_> {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn complete<'a>(
+        &'a self,
+        model: &'a str,
+        prompt: &'a str,
+        temperature: Option<f32>,
+        max_tokens: u32,
+    ) -> BoxFuture<'a, Result<String, AiError>> {
+        Box::pin(async move {
+            let result = self.inner.complete(model, prompt, temperature, max_tokens).await;
+            self.observe(result)
+        })
+    }
+
+    fn embed<'a>(&'a self, model: &'a str, input: &'a str) -> BoxFuture<'a, Result<Vec<f32>, AiError>> {
+        Box::pin(async move { self.observe(self.inner.embed(model, input).await) })
+    }
+}
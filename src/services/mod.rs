@@ -1,2 +1,29 @@
 pub mod blockchain;
+pub mod bridge;
+pub mod bundle;
+pub mod categories;
+pub mod contract_age;
 pub mod ai;
+pub mod balances;
+pub mod decode;
+pub mod decoder;
+pub mod erc4337;
+pub mod explorer;
+pub mod failure;
+pub mod gas;
+pub mod injection;
+pub mod labels;
+pub mod llm;
+pub mod nft;
+pub mod permit;
+pub mod proxy;
+pub mod recommend;
+pub mod risk;
+pub mod rpc;
+pub mod safe;
+pub mod selectors;
+pub mod similarity;
+pub mod swaps;
+pub mod throttle;
+pub mod token_safety;
+pub mod tokens;
@@ -0,0 +1,327 @@
+use primitive_types::U256;
+use serde_json::Value;
+
+use crate::models::WashTradeCheck;
+use crate::services::decode;
+use crate::services::explorer::ExplorerClient;
+
+// Sales checked per tx; each costs four explorer lookups
+const MAX_SALES: usize = 3;
+
+// Earliest txs of a party searched for the one that first funded it
+const EARLY_TXS: usize = 20;
+
+// Latest txs of the buyer searched for native funds moving between the parties
+const RECENT_TXS: usize = 100;
+
+// Latest transfers of the collection to or from the buyer searched for the
+// token's earlier trips between the parties
+const RECENT_NFT_TRANSFERS: usize = 100;
+
+/// An ERC-721 token changing hands for payment in the tx. Addresses lowercase.
+pub struct NftSale {
+    pub collection: String,
+    pub token_id: U256,
+    pub seller: String,
+    pub buyer: String,
+}
+
+/// ERC-721 transfers in the tx that look like sales: neither mints nor burns,
+/// in a tx that pays with native value or an ERC-20 transfer from the buyer.
+pub fn sales(tx_details: &Value) -> Vec<NftSale> {
+    let logs = tx_details["logs"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let transfers: Vec<(String, String, String, &Value)> = logs
+        .iter()
+        .filter_map(|log| {
+            let topics = log["topics"].as_array()?;
+            if topics.first()?.as_str().and_then(decode::event_name) != Some("Transfer") {
+                return None;
+            }
+            Some((
+                log["address"].as_str()?.to_lowercase(),
+                topics.get(1)?.as_str().and_then(topic_address)?,
+                topics.get(2)?.as_str().and_then(topic_address)?,
+                log,
+            ))
+        })
+        .collect();
+    let paid_natively = tx_details["value"]["wei"].as_str().is_some_and(|wei| wei != "0");
+
+    transfers
+        .iter()
+        // ERC-721 Transfer has a fourth (tokenId) topic; ERC-20's has three
+        .filter_map(|(collection, from, to, log)| {
+            let token_id = log["topics"].get(3)?.as_str()?;
+            let token_id = U256::from_str_radix(token_id.strip_prefix("0x")?, 16).ok()?;
+            Some((collection, from, to, token_id))
+        })
+        .filter(|(_, from, to, _)| *from != ZERO_ADDRESS && *to != ZERO_ADDRESS)
+        .filter(|(_, _, buyer, _)| {
+            paid_natively
+                || transfers.iter().any(|(_, from, _, log)| {
+                    from == *buyer && log["topics"].as_array().is_some_and(|t| t.len() == 3)
+                })
+        })
+        .map(|(collection, seller, buyer, token_id)| NftSale {
+            collection: collection.clone(),
+            token_id,
+            seller: seller.clone(),
+            buyer: buyer.clone(),
+        })
+        .collect()
+}
+
+/// Checks each NFT sale in the tx for wash trading, from the buyer's and
+/// seller's history on the explorer: a shared first funder (or one funding
+/// the other), native funds moving between them, and the token going back
+/// and forth between them before. Failed lookups count as no sign.
+pub async fn wash_trades(explorer: &ExplorerClient, tx_details: &Value) -> Vec<WashTradeCheck> {
+    let tx_hash = tx_details["hash"].as_str().unwrap_or("").to_lowercase();
+    let mut checks = Vec::new();
+    for sale in sales(tx_details).into_iter().take(MAX_SALES) {
+        checks.push(check_sale(explorer, &tx_hash, sale).await);
+    }
+    checks
+}
+
+async fn check_sale(explorer: &ExplorerClient, tx_hash: &str, sale: NftSale) -> WashTradeCheck {
+    let mut signals = Vec::new();
+    let NftSale { collection, token_id, seller, buyer } = sale;
+    let between = |entry: &Value| {
+        let (from, to) = (lowercase(&entry["from"]), lowercase(&entry["to"]));
+        (from == buyer && to == seller) || (from == seller && to == buyer)
+    };
+
+    let seller_funding = first_funding(explorer, &seller).await;
+    let buyer_funding = first_funding(explorer, &buyer).await;
+    match (&seller_funding, &buyer_funding) {
+        (_, Some((funder, _))) if *funder == seller => {
+            signals.push("the seller first funded the buyer".to_string())
+        }
+        (Some((funder, _)), _) if *funder == buyer => {
+            signals.push("the buyer first funded the seller".to_string())
+        }
+        (Some((a, _)), Some((b, _))) if a == b => {
+            signals.push(format!("both were first funded by {}", a))
+        }
+        _ => {}
+    }
+
+    // Besides the funding already counted above
+    let counted: Vec<&str> = [tx_hash]
+        .into_iter()
+        .chain(seller_funding.iter().chain(&buyer_funding).map(|(_, hash)| hash.as_str()))
+        .collect();
+    let recent = explorer
+        .transactions(&buyer, RECENT_TXS, false)
+        .await
+        .inspect_err(|e| tracing::warn!("Tx history lookup failed for {}: {}", buyer, e))
+        .unwrap_or_default();
+    let circular = recent
+        .iter()
+        .filter(|tx| !counted.contains(&lowercase(&tx["hash"]).as_str()))
+        .filter(|tx| tx["value"].as_str().is_some_and(|wei| wei != "0") && between(tx))
+        .count();
+    if circular > 0 {
+        signals.push(format!("{} recent native transfer(s) between them", circular));
+    }
+
+    let token = token_id.to_string();
+    let transfers = explorer
+        .nft_transfers(&buyer, &collection, RECENT_NFT_TRANSFERS)
+        .await
+        .inspect_err(|e| tracing::warn!("NFT transfer lookup failed for {}: {}", buyer, e))
+        .unwrap_or_default();
+    let trips = transfers
+        .iter()
+        .filter(|transfer| lowercase(&transfer["hash"]) != tx_hash)
+        .filter(|transfer| transfer["tokenID"].as_str() == Some(token.as_str()) && between(transfer))
+        .count();
+    if trips > 0 {
+        signals.push(format!("the token went between them {} time(s) before", trips));
+    }
+
+    WashTradeCheck {
+        likely: trips > 0 || signals.len() >= 2,
+        collection,
+        token_id: token,
+        seller,
+        buyer,
+        signals,
+    }
+}
+
+/// One line on a likely wash trade, for the heuristic explanation.
+pub fn describe(check: &WashTradeCheck) -> String {
+    format!(
+        "The sale of token #{} of {} from {} to {} looks like a wash trade: {}.",
+        check.token_id,
+        check.collection,
+        check.seller,
+        check.buyer,
+        check.signals.join("; ")
+    )
+}
+
+// (funder, tx hash) of the first native transfer `address` received
+async fn first_funding(explorer: &ExplorerClient, address: &str) -> Option<(String, String)> {
+    let early = explorer
+        .transactions(address, EARLY_TXS, true)
+        .await
+        .inspect_err(|e| tracing::warn!("Tx history lookup failed for {}: {}", address, e))
+        .ok()?;
+    early
+        .iter()
+        .find(|tx| {
+            lowercase(&tx["to"]) == address && tx["value"].as_str().is_some_and(|wei| wei != "0")
+        })
+        .map(|tx| (lowercase(&tx["from"]), lowercase(&tx["hash"])))
+}
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+fn lowercase(value: &Value) -> String {
+    value.as_str().unwrap_or("").to_lowercase()
+}
+
+fn topic_address(topic: &str) -> Option<String> {
+    let hex = topic.strip_prefix("0x")?;
+    (hex.len() == 64).then(|| format!("0x{}", &hex[24..]).to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    const COLLECTION: &str = "0xc011ec7104c011ec7104c011ec7104c011ec7104";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const SELLER: &str = "0x5e11e25e11e25e11e25e11e25e11e25e11e25e11";
+    const BUYER: &str = "0xb0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0";
+    const FUNDER: &str = "0xf00df00df00df00df00df00df00df00df00df00d";
+    const TRANSFER: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+    fn topic(hex: &str) -> String {
+        format!("0x{:0>64}", hex.trim_start_matches("0x"))
+    }
+
+    fn nft_transfer(from: &str, to: &str, token_id: &str) -> Value {
+        json!({
+            "address": COLLECTION,
+            "topics": [TRANSFER, topic(from), topic(to), topic(token_id)],
+            "data": "0x"
+        })
+    }
+
+    fn erc20_transfer(from: &str, to: &str) -> Value {
+        json!({
+            "address": WETH,
+            "topics": [TRANSFER, topic(from), topic(to)],
+            "data": topic("de0b6b3a7640000")
+        })
+    }
+
+    fn sale_tx(wei: &str, logs: Vec<Value>) -> Value {
+        json!({ "hash": "0xsale", "value": { "wei": wei }, "logs": logs })
+    }
+
+    #[test]
+    fn finds_paid_transfers() {
+        let native = sale_tx("1000", vec![nft_transfer(SELLER, BUYER, "2a")]);
+        let sales = sales(&native);
+        assert_eq!(sales.len(), 1);
+        assert_eq!(sales[0].token_id, U256::from(42));
+        assert_eq!((sales[0].seller.as_str(), sales[0].buyer.as_str()), (SELLER, BUYER));
+
+        let logs = vec![erc20_transfer(BUYER, SELLER), nft_transfer(SELLER, BUYER, "2a")];
+        assert_eq!(super::sales(&sale_tx("0", logs)).len(), 1);
+    }
+
+    #[test]
+    fn skips_mints_burns_and_gifts() {
+        let mint = sale_tx("1000", vec![nft_transfer(ZERO_ADDRESS, BUYER, "1")]);
+        let burn = sale_tx("1000", vec![nft_transfer(SELLER, ZERO_ADDRESS, "1")]);
+        let gift = sale_tx("0", vec![nft_transfer(SELLER, BUYER, "1")]);
+        // Paid by someone other than the recipient
+        let paid_by_other = sale_tx(
+            "0",
+            vec![erc20_transfer(FUNDER, SELLER), nft_transfer(SELLER, BUYER, "1")],
+        );
+        for tx in [mint, burn, gift, paid_by_other] {
+            assert!(sales(&tx).is_empty(), "{}", tx);
+        }
+    }
+
+    // History where both parties were first funded by FUNDER, native funds
+    // went from the seller to the buyer, and the token went back and forth
+    fn suspicious_history(query: &HashMap<String, String>) -> Value {
+        let get = |key: &str| query.get(key).map(String::as_str).unwrap_or("");
+        let result = match (get("action"), get("sort")) {
+            ("txlist", "asc") => json!([
+                { "hash": format!("0xfund{}", get("address")), "from": FUNDER,
+                  "to": get("address"), "value": "1" }
+            ]),
+            ("txlist", "desc") => json!([
+                { "hash": "0xcircle", "from": SELLER, "to": BUYER, "value": "5" },
+                { "hash": "0xsale", "from": BUYER, "to": COLLECTION, "value": "1000" }
+            ]),
+            ("tokennfttx", _) => json!([
+                { "hash": "0xsale", "from": SELLER, "to": BUYER, "tokenID": "42" },
+                { "hash": "0xearlier", "from": BUYER, "to": SELLER, "tokenID": "42" },
+                { "hash": "0xother", "from": BUYER, "to": SELLER, "tokenID": "7" }
+            ]),
+            _ => json!([]),
+        };
+        json!({ "status": "1", "result": result })
+    }
+
+    #[tokio::test]
+    async fn flags_related_parties_trading_a_token_back_and_forth() {
+        let explorer = testing::explorer(suspicious_history).await;
+        let tx = sale_tx("1000", vec![nft_transfer(SELLER, BUYER, "2a")]);
+        let checks = wash_trades(&explorer, &tx).await;
+
+        assert_eq!(checks.len(), 1);
+        let check = &checks[0];
+        assert!(check.likely);
+        assert_eq!(check.token_id, "42");
+        assert_eq!(
+            check.signals,
+            vec![
+                format!("both were first funded by {}", FUNDER),
+                "1 recent native transfer(s) between them".to_string(),
+                "the token went between them 1 time(s) before".to_string(),
+            ]
+        );
+        assert!(describe(check).contains("looks like a wash trade"));
+    }
+
+    #[tokio::test]
+    async fn a_shared_funder_alone_is_not_likely() {
+        let explorer = testing::explorer(|query| match query.get("sort").map(String::as_str) {
+            Some("asc") => suspicious_history(query),
+            _ => json!({ "status": "0", "result": [] }),
+        })
+        .await;
+        let tx = sale_tx("1000", vec![nft_transfer(SELLER, BUYER, "2a")]);
+        let check = &wash_trades(&explorer, &tx).await[0];
+
+        assert_eq!(check.signals.len(), 1);
+        assert!(!check.likely);
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_count_as_no_sign() {
+        let explorer =
+            testing::explorer(|_| json!({ "status": "0", "result": "Max rate limit" })).await;
+        let tx = sale_tx("1000", vec![nft_transfer(SELLER, BUYER, "2a")]);
+        let check = &wash_trades(&explorer, &tx).await[0];
+
+        assert!(check.signals.is_empty());
+        assert!(!check.likely);
+    }
+}
@@ -0,0 +1,199 @@
+use primitive_types::U256;
+
+use crate::models::PermitInfo;
+use crate::services::decode::{self, AbiValue};
+use crate::services::risk::Call;
+
+/// Decodes gasless approvals (EIP-2612, DAI-style, or Permit2) from one call.
+/// A Permit2 batch grants several allowances at once, hence the `Vec`.
+pub fn decode_permits(call: &Call) -> Vec<PermitInfo> {
+    let Some(decoded) = decode::decode_calldata(call.input) else {
+        return Vec::new();
+    };
+
+    let token = call.to.to_lowercase();
+    let permits = match decoded.selector.as_str() {
+        "0xd505accf" => eip2612(&decoded.params, token),
+        "0x8fcbaf0c" => dai(&decoded.params, token),
+        "0x2b67b570" | "0x2a2d80d1" => permit2(&decoded.params),
+        _ => None,
+    };
+    permits.unwrap_or_default()
+}
+
+// permit(owner, spender, value, deadline, v, r, s)
+fn eip2612(p: &[AbiValue], token: String) -> Option<Vec<PermitInfo>> {
+    let value = p.get(2)?.as_uint()?;
+    Some(vec![PermitInfo {
+        kind: "eip2612".to_string(),
+        token,
+        owner: address(p.first()?)?,
+        spender: address(p.get(1)?)?,
+        value: value.to_string(),
+        unlimited: decode::is_unlimited(value),
+        deadline: timestamp(p.get(3)?.as_uint()?),
+    }])
+}
+
+// permit(holder, spender, nonce, expiry, allowed, v, r, s): grants MAX_UINT
+// when `allowed` and revokes otherwise; an expiry of 0 never expires
+fn dai(p: &[AbiValue], token: String) -> Option<Vec<PermitInfo>> {
+    let allowed = matches!(p.get(4)?, AbiValue::Bool(true));
+    let expiry = p.get(3)?.as_uint()?;
+    Some(vec![PermitInfo {
+        kind: "dai".to_string(),
+        token,
+        owner: address(p.first()?)?,
+        spender: address(p.get(1)?)?,
+        value: if allowed { U256::MAX } else { U256::zero() }.to_string(),
+        unlimited: allowed,
+        deadline: if expiry.is_zero() { None } else { timestamp(expiry) },
+    }])
+}
+
+// permit(owner, (details, spender, sigDeadline), signature) where details is
+// (token, amount, expiration, nonce), or an array of them for the batch form
+fn permit2(p: &[AbiValue]) -> Option<Vec<PermitInfo>> {
+    let owner = address(p.first()?)?;
+    let AbiValue::Tuple(permit) = p.get(1)? else {
+        return None;
+    };
+    let spender = address(permit.get(1)?)?;
+    let details = match permit.first()? {
+        AbiValue::Array(items) => items.as_slice(),
+        single => std::slice::from_ref(single),
+    };
+
+    details
+        .iter()
+        .map(|d| {
+            let AbiValue::Tuple(d) = d else {
+                return None;
+            };
+            let amount = d.get(1)?.as_uint()?;
+            Some(PermitInfo {
+                kind: "permit2".to_string(),
+                token: address(d.first()?)?,
+                owner: owner.clone(),
+                spender: spender.clone(),
+                value: amount.to_string(),
+                // Amounts are uint160, so "unlimited" is the top half of that range
+                unlimited: amount >= U256::one() << 159,
+                // The allowance's own expiration, which outlives the signature deadline
+                deadline: timestamp(d.get(2)?.as_uint()?),
+            })
+        })
+        .collect()
+}
+
+fn address(value: &AbiValue) -> Option<String> {
+    value.as_address().map(str::to_lowercase)
+}
+
+// Deadlines past u64 (e.g. MAX_UINT) are treated as never expiring
+fn timestamp(value: U256) -> Option<u64> {
+    u64::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: &str = "0x1111111111111111111111111111111111111111";
+    const SPENDER: &str = "0x2222222222222222222222222222222222222222";
+    const TOKEN: &str = "0x3333333333333333333333333333333333333333";
+
+    fn word(hex: &str) -> String {
+        format!("{:0>64}", hex.trim_start_matches("0x"))
+    }
+
+    fn decode(to: &str, input: &str) -> Vec<PermitInfo> {
+        decode_permits(&Call { from: OWNER, to, input })
+    }
+
+    #[test]
+    fn decodes_an_eip2612_permit() {
+        // permit(owner, spender, 1e18, deadline 0x6553f100, v, r, s)
+        let input = format!(
+            "0xd505accf{}{}{}{}{}{}{}",
+            word(OWNER),
+            word(SPENDER),
+            word("de0b6b3a7640000"),
+            word("6553f100"),
+            word("1b"),
+            word(&"a".repeat(64)),
+            word(&"b".repeat(64)),
+        );
+        let permits = decode(TOKEN, &input);
+        assert_eq!(permits.len(), 1);
+        let permit = &permits[0];
+        assert_eq!(permit.kind, "eip2612");
+        assert_eq!(permit.token, TOKEN);
+        assert_eq!(permit.owner, OWNER);
+        assert_eq!(permit.spender, SPENDER);
+        assert_eq!(permit.value, "1000000000000000000");
+        assert!(!permit.unlimited);
+        assert_eq!(permit.deadline, Some(0x6553f100));
+    }
+
+    #[test]
+    fn decodes_a_dai_permit_without_expiry() {
+        // permit(holder, spender, nonce, expiry 0, allowed, v, r, s)
+        let input = format!(
+            "0x8fcbaf0c{}{}{}{}{}{}{}{}",
+            word(OWNER),
+            word(SPENDER),
+            word("0"),
+            word("0"),
+            word("1"),
+            word("1c"),
+            word(&"a".repeat(64)),
+            word(&"b".repeat(64)),
+        );
+        let permits = decode(TOKEN, &input);
+        assert_eq!(permits.len(), 1);
+        assert_eq!(permits[0].kind, "dai");
+        assert_eq!(permits[0].value, U256::MAX.to_string());
+        assert!(permits[0].unlimited);
+        assert_eq!(permits[0].deadline, None);
+    }
+
+    #[test]
+    fn decodes_a_permit2_single_permit() {
+        let permit2 = "0x000000000022d473030f116ddee9f6b43ac78ba3";
+        // permit(owner, ((token, amount, expiration, nonce), spender, sigDeadline), signature)
+        // with an amount of uint160 max and a 65-byte signature
+        let input = format!(
+            "0x2b67b570{}{}{}{}{}{}{}{}{}{}{}{:0<64}",
+            word(OWNER),
+            word(TOKEN),
+            word(&"f".repeat(40)),
+            word("6553f100"),
+            word("0"),
+            word(SPENDER),
+            word("6553f100"),
+            word("100"),
+            word("41"),
+            "a".repeat(64),
+            "b".repeat(64),
+            "1b",
+        );
+        let permits = decode(permit2, &input);
+        assert_eq!(permits.len(), 1);
+        let permit = &permits[0];
+        assert_eq!(permit.kind, "permit2");
+        // The token comes from the permit's details, not the called contract
+        assert_eq!(permit.token, TOKEN);
+        assert_eq!(permit.owner, OWNER);
+        assert_eq!(permit.spender, SPENDER);
+        assert!(permit.unlimited);
+        assert_eq!(permit.deadline, Some(0x6553f100));
+    }
+
+    #[test]
+    fn ignores_other_calls() {
+        let input = format!("0xa9059cbb{}{}", word(SPENDER), word("1"));
+        assert!(decode(TOKEN, &input).is_empty());
+        assert!(decode(TOKEN, "0x").is_empty());
+    }
+}
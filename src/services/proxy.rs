@@ -0,0 +1,53 @@
+use serde_json::json;
+
+use crate::services::rpc::{ChainClient, RpcError};
+
+// bytes32(uint256(keccak256("eip1967.proxy.implementation")) - 1)
+const EIP1967_IMPLEMENTATION_SLOT: &str =
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+
+// Proxies can point at proxies; stop following after this many hops
+const MAX_PROXY_DEPTH: usize = 3;
+
+/// Follows EIP-1967 implementation slots from `address` to the contract that
+/// actually holds the logic. Returns `None` when `address` isn't a proxy.
+///
+/// The slot is read at the latest block, so for an upgraded proxy this is the
+/// current implementation rather than the one at the tx's block.
+pub async fn resolve_implementation(
+    rpc: &dyn ChainClient,
+    address: &str,
+) -> Result<Option<String>, RpcError> {
+    let mut current = address.to_lowercase();
+    let mut implementation = None;
+
+    for _ in 0..MAX_PROXY_DEPTH {
+        match read_implementation_slot(rpc, &current).await? {
+            Some(next) if next != current => {
+                implementation = Some(next.clone());
+                current = next;
+            }
+            _ => break,
+        }
+    }
+    Ok(implementation)
+}
+
+async fn read_implementation_slot(rpc: &dyn ChainClient, address: &str) -> Result<Option<String>, RpcError> {
+    let word = rpc
+        .call(
+            "eth_getStorageAt",
+            json!([address, EIP1967_IMPLEMENTATION_SLOT, "latest"]),
+        )
+        .await?;
+    let word = word
+        .as_str()
+        .ok_or_else(|| RpcError::BadResponse(word.to_string()))?
+        .trim_start_matches("0x");
+
+    // The address sits in the low 20 bytes; an all-zero slot means "not a proxy"
+    if word.len() < 40 || word.chars().all(|c| c == '0') {
+        return Ok(None);
+    }
+    Ok(Some(format!("0x{}", &word[word.len() - 40..].to_lowercase())))
+}
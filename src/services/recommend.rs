@@ -0,0 +1,222 @@
+use serde_json::Value;
+
+use crate::models::{Recommendation, RecommendationKind};
+use crate::network::Network;
+use crate::services::risk::RiskSignal;
+
+const REPORT_URL: &str = "https://www.chainabuse.com/report";
+
+// The LLM's suggestions are capped, and each one cut to this length
+const MAX_LLM_RECOMMENDATIONS: usize = 3;
+const MAX_LLM_RECOMMENDATION_LEN: usize = 200;
+
+/// Next steps for the risk signals that fired, in signal order. Signals that
+/// call for the same step (say, two risky approvals) share one.
+pub fn for_signals(
+    network: Network,
+    signals: &[RiskSignal],
+    tx_details: &Value,
+) -> Vec<Recommendation> {
+    // The sender's approvals, all in one place
+    let revoke_url = tx_details["from"].as_str().map(|from| {
+        format!("https://revoke.cash/address/{}?chainId={}", from, network.chain_id())
+    });
+
+    let mut recommendations = Vec::new();
+    for signal in signals {
+        let steps: &[(RecommendationKind, &str, Option<&str>)] = match signal.code {
+            "DRAIN_PATTERN" => &[
+                (
+                    RecommendationKind::RevokeApproval,
+                    "Revoke the token approvals granted in this tx right away: the approved \
+                     spender moved tokens out as soon as it could",
+                    revoke_url.as_deref(),
+                ),
+                (
+                    RecommendationKind::ReportAddress,
+                    "Report the spender as a wallet drainer, so wallets and exchanges can \
+                     warn others",
+                    Some(REPORT_URL),
+                ),
+            ],
+            "RISKY_PERMIT" => &[(
+                RecommendationKind::RevokeApproval,
+                "Revoke the permit's allowance unless you trust the spender; permits are \
+                 signed off-chain, so check every signature request before signing",
+                revoke_url.as_deref(),
+            )],
+            "TOKEN_SAFETY" => &[(
+                RecommendationKind::AvoidToken,
+                "Don't buy more of this token: it may not be sellable, or only at a steep tax",
+                None,
+            )],
+            "NEW_CONTRACT" => &[(
+                RecommendationKind::VerifyContract,
+                "Check the newly deployed contract's source, audits, and team before \
+                 interacting with it again",
+                None,
+            )],
+            "PROMPT_INJECTION" => &[(
+                RecommendationKind::Caution,
+                "Ignore any instructions in the tx's on-chain text: it's a \
+                 social-engineering attempt",
+                None,
+            )],
+            "COMPLEX_CALL_TREE" => &[(
+                RecommendationKind::ReviewTransaction,
+                "Simulate the tx in a trace viewer before repeating it; its call tree was \
+                 too large to analyze in full",
+                None,
+            )],
+            "HIGH_PRICE_IMPACT" => &[(
+                RecommendationKind::Caution,
+                "Split large trades or use a deeper pool or an aggregator, and set a tight \
+                 slippage limit: this swap lost several percent to price impact",
+                None,
+            )],
+            "UNRECONCILED_BALANCE" => &[(
+                RecommendationKind::ReviewTransaction,
+                "Check the token for transfer fees, rebasing or hidden transfers before \
+                 trading it again: balances moved differently from what the tx's transfers say",
+                None,
+            )],
+            "LIKELY_WASH_TRADE" => &[(
+                RecommendationKind::Caution,
+                "Don't read this sale's price as market demand: buyer and seller look \
+                 related, so check the collection's volume for more trades between them",
+                None,
+            )],
+            _ => &[],
+        };
+        for (kind, message, action_url) in steps {
+            let recommendation = Recommendation {
+                kind: *kind,
+                message: message.to_string(),
+                action_url: action_url.map(str::to_string),
+                rule: Some(signal.code.to_string()),
+            };
+            if !recommendations.iter().any(|r: &Recommendation| {
+                r.kind == recommendation.kind && r.action_url == recommendation.action_url
+            }) {
+                recommendations.push(recommendation);
+            }
+        }
+    }
+    recommendations
+}
+
+/// Adds the LLM's suggested next steps after the rules' ones. Suggestions are
+/// free text, so they carry no link.
+pub fn add_llm(recommendations: &mut Vec<Recommendation>, suggested: Vec<String>) {
+    let suggested = suggested
+        .into_iter()
+        .map(|text| text.trim().chars().take(MAX_LLM_RECOMMENDATION_LEN).collect::<String>())
+        .filter(|text| !text.is_empty())
+        .take(MAX_LLM_RECOMMENDATIONS);
+    for message in suggested {
+        if !recommendations.iter().any(|r| r.message.eq_ignore_ascii_case(&message)) {
+            recommendations.push(Recommendation {
+                kind: RecommendationKind::General,
+                message,
+                action_url: None,
+                rule: None,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::services::risk::Severity;
+    use crate::testing::SENDER;
+
+    fn signal(code: &'static str) -> RiskSignal {
+        RiskSignal {
+            code,
+            severity: Severity::High,
+            detail: String::new(),
+        }
+    }
+
+    fn steps(network: Network, codes: &[&'static str]) -> Vec<(RecommendationKind, String)> {
+        let signals: Vec<RiskSignal> = codes.iter().copied().map(signal).collect();
+        for_signals(network, &signals, &json!({ "from": SENDER }))
+            .into_iter()
+            .map(|r| (r.kind, r.rule.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn each_rule_calls_for_its_step() {
+        let expected = [
+            ("RISKY_PERMIT", RecommendationKind::RevokeApproval),
+            ("TOKEN_SAFETY", RecommendationKind::AvoidToken),
+            ("NEW_CONTRACT", RecommendationKind::VerifyContract),
+            ("PROMPT_INJECTION", RecommendationKind::Caution),
+            ("COMPLEX_CALL_TREE", RecommendationKind::ReviewTransaction),
+            ("HIGH_PRICE_IMPACT", RecommendationKind::Caution),
+            ("UNRECONCILED_BALANCE", RecommendationKind::ReviewTransaction),
+            ("LIKELY_WASH_TRADE", RecommendationKind::Caution),
+        ];
+        for (code, kind) in expected {
+            assert_eq!(
+                steps(Network::EthereumMainnet, &[code]),
+                [(kind, code.to_string())],
+                "{}",
+                code
+            );
+        }
+        // Rules without a step, like the plain risk reasons, add nothing
+        assert!(steps(Network::EthereumMainnet, &["HIGH_VALUE"]).is_empty());
+    }
+
+    #[test]
+    fn a_drain_calls_for_a_revoke_and_a_report() {
+        let signals = [signal("DRAIN_PATTERN")];
+        let recommendations =
+            for_signals(Network::PolygonMainnet, &signals, &json!({ "from": SENDER }));
+        let kinds: Vec<_> = recommendations.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            [RecommendationKind::RevokeApproval, RecommendationKind::ReportAddress]
+        );
+        let revoke_url = format!("https://revoke.cash/address/{}?chainId=137", SENDER);
+        assert_eq!(recommendations[0].action_url.as_deref(), Some(revoke_url.as_str()));
+        assert_eq!(recommendations[1].action_url.as_deref(), Some(REPORT_URL));
+    }
+
+    #[test]
+    fn signals_calling_for_the_same_step_share_it() {
+        let steps = steps(Network::EthereumMainnet, &["DRAIN_PATTERN", "RISKY_PERMIT"]);
+        let revokes = steps
+            .iter()
+            .filter(|(kind, _)| *kind == RecommendationKind::RevokeApproval)
+            .count();
+        assert_eq!(revokes, 1);
+        assert_eq!(steps[0].1, "DRAIN_PATTERN");
+    }
+
+    #[test]
+    fn llm_suggestions_follow_the_rules_steps() {
+        let signals = [signal("TOKEN_SAFETY")];
+        let mut recommendations =
+            for_signals(Network::EthereumMainnet, &signals, &json!({ "from": SENDER }));
+        let rule_step = recommendations[0].message.to_uppercase();
+        let suggested = vec![
+            "  ".to_string(),
+            rule_step,
+            "x".repeat(300),
+            "Check the token's holders".to_string(),
+            "One too many".to_string(),
+        ];
+        add_llm(&mut recommendations, suggested);
+
+        let added = &recommendations[1..];
+        let messages: Vec<&str> = added.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, [&"x".repeat(200), "Check the token's holders"]);
+        assert!(added.iter().all(|r| r.kind == RecommendationKind::General && r.rule.is_none()));
+    }
+}
@@ -0,0 +1,618 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::models::PermitInfo;
+use crate::services::decode::{self, AbiValue};
+use crate::services::{injection, permit};
+use crate::store::unix_now;
+use crate::util::fnv1a;
+
+/// Risk score at or above which an analysis counts as high risk (`Severity::High`).
+pub const HIGH_RISK_SCORE: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(dead_code)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// Minimum risk score implied by a signal of this severity.
+    pub fn score_floor(self) -> f32 {
+        match self {
+            Severity::Low => 0.2,
+            Severity::Medium => 0.5,
+            Severity::High => HIGH_RISK_SCORE,
+            Severity::Critical => 0.95,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RiskSignal {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+impl RiskSignal {
+    pub fn reason(&self) -> String {
+        format!("{}: {}", self.code, self.detail)
+    }
+}
+
+// What a rule gets to look at
+struct RuleInput<'a> {
+    tx: &'a Value,
+    calls: Vec<Call<'a>>,
+}
+
+struct Rule {
+    code: &'static str,
+    // Bump when a rule's logic or thresholds change so `rules_hash` changes too
+    revision: u32,
+    check: fn(&RuleInput) -> Vec<RiskSignal>,
+}
+
+// The active rule set, in evaluation order
+const RULES: &[Rule] = &[
+    Rule {
+        code: "DRAIN_PATTERN",
+        revision: 1,
+        check: drain_pattern,
+    },
+    Rule {
+        code: "RISKY_PERMIT",
+        revision: 2,
+        check: risky_permit,
+    },
+    Rule {
+        code: "TOKEN_SAFETY",
+        revision: 1,
+        check: token_safety,
+    },
+    Rule {
+        code: "NEW_CONTRACT",
+        revision: 1,
+        check: new_contract,
+    },
+    Rule {
+        code: "PROMPT_INJECTION",
+        revision: 1,
+        check: prompt_injection,
+    },
+    Rule {
+        code: "COMPLEX_CALL_TREE",
+        revision: 1,
+        check: complex_call_tree,
+    },
+    Rule {
+        code: "HIGH_PRICE_IMPACT",
+        revision: 1,
+        check: high_price_impact,
+    },
+    Rule {
+        code: "UNRECONCILED_BALANCE",
+        revision: 1,
+        check: unreconciled_balance,
+    },
+    Rule {
+        code: "LIKELY_WASH_TRADE",
+        revision: 1,
+        check: likely_wash_trade,
+    },
+];
+
+/// Number of rules in the active rule set.
+pub const RULE_COUNT: usize = RULES.len();
+
+// Transfer taxes above this are worth a warning even when selling still works
+const HIGH_TAX_PCT: f64 = 10.0;
+
+// Counterparty contracts younger than this are flagged
+const NEW_CONTRACT_DAYS: u64 = 7;
+
+// Permits valid for longer than this are "far-future"
+const FAR_FUTURE_PERMIT_SECS: u64 = 30 * 24 * 3600;
+
+// Swaps executing this much below the pool's spot price (fee included) are flagged
+const HIGH_PRICE_IMPACT_PCT: f64 = 5.0;
+
+/// Runs every rule against the tx and returns the signals that fired.
+pub fn evaluate(tx_details: &Value) -> Vec<RiskSignal> {
+    let input = RuleInput {
+        tx: tx_details,
+        calls: call_sequence(tx_details),
+    };
+
+    RULES.iter().flat_map(|rule| (rule.check)(&input)).collect()
+}
+
+/// Short fingerprint of the active rule set (codes, revisions, severity floors),
+/// identifying which rules produced a stored result.
+pub fn rules_hash() -> String {
+    let mut fingerprint = String::new();
+    for rule in RULES {
+        fingerprint.push_str(&format!("{}@{};", rule.code, rule.revision));
+    }
+    for severity in [Severity::Low, Severity::Medium, Severity::High, Severity::Critical] {
+        fingerprint.push_str(&format!("{:?}={};", severity, severity.score_floor()));
+    }
+    format!("{:08x}", fnv1a(fingerprint.as_bytes()) as u32)
+}
+
+/// Raises the base score to the floor of the most severe signal.
+pub fn score(base: f32, signals: &[RiskSignal]) -> f32 {
+    signals
+        .iter()
+        .map(|s| s.severity.score_floor())
+        .fold(base, f32::max)
+}
+
+pub struct Call<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub input: &'a str,
+}
+
+/// Every permit granted anywhere in the tx's call sequence.
+pub fn permits(tx_details: &Value) -> Vec<PermitInfo> {
+    call_sequence(tx_details)
+        .iter()
+        .flat_map(permit::decode_permits)
+        .collect()
+}
+
+// Internal calls in execution order when a trace is available,
+// otherwise just the top-level call.
+fn call_sequence(tx_details: &Value) -> Vec<Call<'_>> {
+    match tx_details["calls"].as_array() {
+        Some(calls) => calls
+            .iter()
+            .map(|c| Call {
+                from: field(c, "from"),
+                to: field(c, "to"),
+                input: field(c, "input"),
+            })
+            .collect(),
+        None => {
+            let mut calls = vec![Call {
+                from: field(tx_details, "from"),
+                to: field(tx_details, "to"),
+                input: field(tx_details, "input"),
+            }];
+            // Without a trace, calls wrapped by (possibly nested) Safes are still
+            // known from the calldata
+            let mut multisig = &tx_details["multisig"];
+            while multisig.is_object() {
+                calls.push(Call {
+                    from: field(multisig, "safe"),
+                    to: field(multisig, "to"),
+                    input: field(multisig, "data"),
+                });
+                multisig = &multisig["inner"]["multisig"];
+            }
+            // Likewise what each ERC-4337 UserOperation has its account do
+            for op in tx_details["user_operations"].as_array().into_iter().flatten() {
+                match op["calls"].as_array() {
+                    Some(account_calls) => calls.extend(account_calls.iter().map(|c| Call {
+                        from: field(op, "sender"),
+                        to: field(c, "to"),
+                        input: field(c, "data"),
+                    })),
+                    None => calls.push(Call {
+                        from: field(tx_details, "to"),
+                        to: field(op, "sender"),
+                        input: field(op, "call_data"),
+                    }),
+                }
+            }
+            calls
+        }
+    }
+}
+
+fn field<'a>(v: &'a Value, key: &str) -> &'a str {
+    v[key].as_str().unwrap_or("")
+}
+
+// Wallet drainer: an unlimited `approve(spender)` on a token followed by a
+// `transferFrom` issued by that same spender on the same token.
+fn drain_pattern(input: &RuleInput) -> Vec<RiskSignal> {
+    // (token, spender) for every unlimited approval seen so far
+    let mut approvals: HashSet<(String, String)> = HashSet::new();
+    let mut signals = Vec::new();
+
+    for call in &input.calls {
+        let Some(decoded) = decode::decode_calldata(call.input) else {
+            continue;
+        };
+        let token = call.to.to_lowercase();
+
+        match decoded.name.as_str() {
+            "approve" => {
+                let spender = decoded.params.first().and_then(AbiValue::as_address);
+                let amount = decoded.params.get(1).and_then(AbiValue::as_uint);
+                if let (Some(spender), Some(amount)) = (spender, amount)
+                    && decode::is_unlimited(amount)
+                {
+                    approvals.insert((token, spender.to_lowercase()));
+                }
+            }
+            "transferFrom" => {
+                let spender = call.from.to_lowercase();
+                if approvals.remove(&(token.clone(), spender.clone())) {
+                    signals.push(RiskSignal {
+                        code: "DRAIN_PATTERN",
+                        severity: Severity::Critical,
+                        detail: format!(
+                            "unlimited approval of token {} to spender {} immediately followed by transferFrom from that spender",
+                            token, spender
+                        ),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    signals
+}
+
+// Phishing favours gasless approvals: an unlimited permit, or one usable (or
+// granting an allowance) far into the future, is elevated risk. "Far" is
+// measured from the tx's block, so the verdict on a mined tx never changes;
+// from now for a pending one. When the block's time couldn't be read, only a
+// permit that never expires counts.
+fn risky_permit(input: &RuleInput) -> Vec<RiskSignal> {
+    let since = match (input.tx["block_timestamp"].as_u64(), input.tx["block_number"].as_u64()) {
+        (Some(timestamp), _) => Some(timestamp),
+        (None, None) => Some(unix_now().max(0) as u64),
+        (None, Some(_)) => None,
+    };
+    let horizon = since.map(|since| since.saturating_add(FAR_FUTURE_PERMIT_SECS));
+
+    input
+        .calls
+        .iter()
+        .flat_map(permit::decode_permits)
+        .filter_map(|p| {
+            let far_future = match (p.deadline, horizon) {
+                (None, _) => true,
+                (Some(deadline), Some(horizon)) => deadline > horizon,
+                (Some(_), None) => false,
+            };
+            let expiry = match p.deadline {
+                Some(d) => format!("expiring at {}", d),
+                None => "that never expires".to_string(),
+            };
+            let (severity, what) = match (p.unlimited, far_future) {
+                (true, _) => (Severity::High, "unlimited"),
+                (false, true) => (Severity::Medium, "long-lived"),
+                (false, false) => return None,
+            };
+            Some(RiskSignal {
+                code: "RISKY_PERMIT",
+                severity,
+                detail: format!(
+                    "{} {} permit on token {} to spender {} {}",
+                    what, p.kind, p.token, p.spender, expiry
+                ),
+            })
+        })
+        .collect()
+}
+
+// Bought tokens that can't be sold (or only at a ruinous tax) are High risk;
+// a steep but survivable tax is Medium. Reads the deep-mode `token_safety` checks.
+fn token_safety(input: &RuleInput) -> Vec<RiskSignal> {
+    input.tx["token_safety"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|t| {
+            let token = field(t, "token");
+            let tax = t["transfer_tax_pct"].as_f64().unwrap_or(0.0);
+            let (severity, detail) = if t["likely_honeypot"].as_bool() == Some(true) {
+                let why = t["findings"]
+                    .as_array()
+                    .map(|f| f.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("; "))
+                    .unwrap_or_default();
+                (Severity::High, format!("token {} is a likely honeypot ({})", token, why))
+            } else if tax > HIGH_TAX_PCT {
+                (Severity::Medium, format!("token {} takes a {:.2}% transfer tax", token, tax))
+            } else {
+                return None;
+            };
+            Some(RiskSignal { code: "TOKEN_SAFETY", severity, detail })
+        })
+        .collect()
+}
+
+// Freshly deployed counterparties are where most rug pulls and drainers live.
+// Reads the deployment ages the pipeline adds to the tx's `contracts`.
+fn new_contract(input: &RuleInput) -> Vec<RiskSignal> {
+    input.tx["contracts"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|c| {
+            let age = c["contract_age_days"].as_u64().filter(|&d| d < NEW_CONTRACT_DAYS)?;
+            Some(RiskSignal {
+                code: "NEW_CONTRACT",
+                severity: Severity::Medium,
+                detail: format!(
+                    "contract {} was deployed {} day(s) ago (block {})",
+                    field(c, "address"),
+                    age,
+                    c["deployment_block"]
+                ),
+            })
+        })
+        .collect()
+}
+
+// A call tree past the trace limits (TRACE_MAX_DEPTH, TRACE_MAX_CALLS) is
+// far beyond what ordinary contracts produce: it's built to exhaust whatever
+// decodes it, or to bury what the tx really does. Reads the `trace_truncated`
+// marker left by the fetch.
+fn complex_call_tree(input: &RuleInput) -> Vec<RiskSignal> {
+    let truncated = &input.tx["trace_truncated"];
+    let mut exceeded = Vec::new();
+    if let Some(depth) = truncated["max_depth"].as_u64() {
+        exceeded.push(format!("{} levels deep", depth));
+    }
+    if let Some(calls) = truncated["max_calls"].as_u64() {
+        exceeded.push(format!("{} calls", calls));
+    }
+    if exceeded.is_empty() {
+        return Vec::new();
+    }
+    vec![RiskSignal {
+        code: "COMPLEX_CALL_TREE",
+        severity: Severity::Medium,
+        detail: format!(
+            "internal call tree exceeds {}; possible DoS or obfuscation, and only \
+             the calls within the limits were analyzed",
+            exceeded.join(" and ")
+        ),
+    }]
+}
+
+// A swap that moved its pool's price this far paid well over the odds: the
+// pool is too shallow for the trade, or someone traded ahead of it. Reads the
+// priced `swaps`.
+fn high_price_impact(input: &RuleInput) -> Vec<RiskSignal> {
+    input.tx["swaps"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|swap| {
+            let impact = swap["price_impact_pct"]
+                .as_f64()
+                .filter(|&pct| pct >= HIGH_PRICE_IMPACT_PCT)?;
+            Some(RiskSignal {
+                code: "HIGH_PRICE_IMPACT",
+                severity: Severity::Medium,
+                detail: format!(
+                    "swap of {} for {} on pool {} executed {:.2}% below the pool's price",
+                    field(swap, "token_in"),
+                    field(swap, "token_out"),
+                    field(swap, "pool"),
+                    impact
+                ),
+            })
+        })
+        .collect()
+}
+
+// A balance that moved differently from what the tx's transfers say: a
+// transfer fee or rebase the logs don't show, or a transfer that emitted no
+// event. Reads the `balance_changes` checks.
+fn unreconciled_balance(input: &RuleInput) -> Vec<RiskSignal> {
+    input.tx["balance_changes"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter(|change| change["reconciled"] == false)
+        .map(|change| {
+            let asset = change["symbol"]
+                .as_str()
+                .or_else(|| change["token"].as_str())
+                .unwrap_or("native");
+            RiskSignal {
+                code: "UNRECONCILED_BALANCE",
+                severity: Severity::Medium,
+                detail: format!(
+                    "{} balance of {} changed by {} base units where the tx's transfers \
+                     account for {}",
+                    asset,
+                    field(change, "address"),
+                    field(change, "actual_change"),
+                    field(change, "expected_change")
+                ),
+            }
+        })
+        .collect()
+}
+
+// An NFT sold between parties that look like one: the price says nothing
+// about demand, and is usually there to fake volume or farm marketplace
+// rewards. Reads the `wash_trades` checks.
+fn likely_wash_trade(input: &RuleInput) -> Vec<RiskSignal> {
+    input.tx["wash_trades"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter(|check| check["likely"] == true)
+        .map(|check| {
+            let signals: Vec<&str> = check["signals"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Value::as_str)
+                .collect();
+            RiskSignal {
+                code: "LIKELY_WASH_TRADE",
+                severity: Severity::Medium,
+                detail: format!(
+                    "sale of token #{} of {} from {} to {} looks like a wash trade: {}",
+                    field(check, "token_id"),
+                    field(check, "collection"),
+                    field(check, "seller"),
+                    field(check, "buyer"),
+                    signals.join("; ")
+                ),
+            }
+        })
+        .collect()
+}
+
+// Instruction-like text in on-chain data is aimed at an AI analyzer (or at a
+// human reading a wallet prompt), never at the chain. Either way it's a
+// social-engineering attempt worth flagging.
+fn prompt_injection(input: &RuleInput) -> Vec<RiskSignal> {
+    let paths = injection::scan(input.tx);
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    vec![RiskSignal {
+        code: "PROMPT_INJECTION",
+        severity: Severity::Medium,
+        detail: format!(
+            "instruction-like text in {}; possible prompt-injection or social-engineering attempt",
+            paths.join(", ")
+        ),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const VICTIM: &str = "0x1111111111111111111111111111111111111111";
+    const DRAINER: &str = "0x2222222222222222222222222222222222222222";
+    const TOKEN: &str = "0x3333333333333333333333333333333333333333";
+
+    fn word(hex: &str) -> String {
+        format!("{:0>64}", hex.trim_start_matches("0x"))
+    }
+
+    fn approve(spender: &str, amount: &str) -> String {
+        format!("0x095ea7b3{}{}", word(spender), word(amount))
+    }
+
+    fn transfer_from(from: &str, to: &str) -> String {
+        format!("0x23b872dd{}{}{}", word(from), word(to), word("de0b6b3a7640000"))
+    }
+
+    fn traced(calls: &[(&str, &str, String)]) -> Value {
+        let calls: Vec<Value> = calls
+            .iter()
+            .map(|(from, to, input)| json!({ "from": from, "to": to, "input": input }))
+            .collect();
+        json!({ "from": VICTIM, "to": TOKEN, "calls": calls })
+    }
+
+    fn drains(tx: &Value) -> Vec<RiskSignal> {
+        evaluate(tx)
+            .into_iter()
+            .filter(|s| s.code == "DRAIN_PATTERN")
+            .collect()
+    }
+
+    #[test]
+    fn flags_unlimited_approval_then_transfer_from_by_the_spender() {
+        let tx = traced(&[
+            (VICTIM, TOKEN, approve(DRAINER, &"f".repeat(64))),
+            (DRAINER, TOKEN, transfer_from(VICTIM, DRAINER)),
+        ]);
+        let signals = drains(&tx);
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].severity, Severity::Critical);
+        assert!(signals[0].detail.contains(DRAINER));
+        assert_eq!(score(0.1, &signals), Severity::Critical.score_floor());
+    }
+
+    #[test]
+    fn ignores_limited_approvals() {
+        let tx = traced(&[
+            (VICTIM, TOKEN, approve(DRAINER, "de0b6b3a7640000")),
+            (DRAINER, TOKEN, transfer_from(VICTIM, DRAINER)),
+        ]);
+        assert!(drains(&tx).is_empty());
+    }
+
+    #[test]
+    fn ignores_transfer_from_by_someone_else() {
+        let other = "0x4444444444444444444444444444444444444444";
+        let tx = traced(&[
+            (VICTIM, TOKEN, approve(DRAINER, &"f".repeat(64))),
+            (other, TOKEN, transfer_from(VICTIM, other)),
+        ]);
+        assert!(drains(&tx).is_empty());
+    }
+
+    #[test]
+    fn ignores_transfer_from_before_the_approval() {
+        let tx = traced(&[
+            (DRAINER, TOKEN, transfer_from(VICTIM, DRAINER)),
+            (VICTIM, TOKEN, approve(DRAINER, &"f".repeat(64))),
+        ]);
+        assert!(drains(&tx).is_empty());
+    }
+
+    // An EIP-2612 permit of 1e18 expiring at `deadline`, sent by the victim
+    fn permit_tx(deadline: u64, block: Value) -> Value {
+        permit_until(&format!("{:x}", deadline), block)
+    }
+
+    fn permit_until(deadline: &str, block: Value) -> Value {
+        let input = format!(
+            "0xd505accf{}{}{}{}{}{}{}",
+            word(VICTIM),
+            word(DRAINER),
+            word("de0b6b3a7640000"),
+            word(deadline),
+            word("1b"),
+            "a".repeat(64),
+            "b".repeat(64),
+        );
+        let mut tx = json!({ "from": VICTIM, "to": TOKEN, "input": input });
+        for (key, value) in block.as_object().unwrap() {
+            tx[key] = value.clone();
+        }
+        tx
+    }
+
+    fn risky_permits(tx: &Value) -> usize {
+        evaluate(tx).iter().filter(|s| s.code == "RISKY_PERMIT").count()
+    }
+
+    #[test]
+    fn measures_permit_deadlines_from_the_block_time() {
+        // Mined in 2020: a year-long permit was far-future then, a week-long one wasn't,
+        // however long ago that is now
+        let mined = json!({ "block_number": 11_000_000, "block_timestamp": 1_600_000_000 });
+        let week = 7 * 24 * 3600;
+        assert_eq!(risky_permits(&permit_tx(1_600_000_000 + 52 * week, mined.clone())), 1);
+        assert_eq!(risky_permits(&permit_tx(1_600_000_000 + week, mined)), 0);
+
+        // A pending tx is measured from now
+        let now = unix_now() as u64;
+        assert_eq!(risky_permits(&permit_tx(now + 52 * week, json!({}))), 1);
+        assert_eq!(risky_permits(&permit_tx(now + week, json!({}))), 0);
+
+        // Without the block's time, only a permit that never expires counts
+        let unknown = json!({ "block_number": 11_000_000 });
+        assert_eq!(risky_permits(&permit_tx(now + 52 * week, unknown.clone())), 0);
+        assert_eq!(risky_permits(&permit_until(&"f".repeat(64), unknown)), 1);
+    }
+}
@@ -0,0 +1,735 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::services::throttle::Throttle;
+
+// Providers commonly cap batch arrays around 100 entries
+const MAX_BATCH_LEN: usize = 100;
+
+// A rate-limited request is retried after the provider's back-off this many times
+const MAX_RATE_LIMIT_RETRIES: usize = 2;
+
+// Consecutive transient failures after which an endpoint is benched
+const BENCH_AFTER_FAILURES: u32 = 3;
+
+// How long a benched endpoint sits out before it's tried again
+const BENCH_DURATION: Duration = Duration::from_secs(30);
+
+// Deepest JSON nesting parsed from a response, below serde_json's recursion
+// limit of 128. A callTracer frame nests two levels per call, so deep-mode
+// traces past this are cut short; the trace limits are far shallower anyway.
+const MAX_RESPONSE_DEPTH: usize = 100;
+
+#[derive(Debug, Clone, Error)]
+pub enum RpcError {
+    #[error("transport error: {0}")]
+    Transport(String),
+    #[error("HTTP status {0}")]
+    Http(u16),
+    #[error("rate limited by provider")]
+    RateLimited,
+    #[error("node returned error {code}: {message}")]
+    Node { code: i64, message: String },
+    #[error("malformed response: {0}")]
+    BadResponse(String),
+    #[error("batch requests unsupported: {0}")]
+    BatchUnsupported(String),
+}
+
+impl RpcError {
+    /// Whether the endpoint, rather than the request, is at fault, so another
+    /// endpoint may well succeed. Node errors (reverts, unknown blocks) and
+    /// other 4xx come from the request and would fail anywhere. So does a
+    /// body that doesn't parse: the tx's data can make it odd, and counting
+    /// it would let a few crafted txs bench every endpoint.
+    fn is_transient(&self) -> bool {
+        match self {
+            RpcError::Transport(_) | RpcError::RateLimited => true,
+            RpcError::Http(status) => *status >= 500 || matches!(status, 401 | 403 | 408 | 429),
+            RpcError::Node { .. } | RpcError::BadResponse(_) | RpcError::BatchUnsupported(_) => {
+                false
+            }
+        }
+    }
+}
+
+/// A node's JSON-RPC interface, as the analyses use it. `RpcClient` talks to
+/// real endpoints; tests and tools can put anything answering the same calls
+/// in its place (see `state::Services`).
+pub trait ChainClient: Send + Sync {
+    fn call<'a>(&'a self, method: &'a str, params: Value) -> BoxFuture<'a, Result<Value, RpcError>>;
+
+    /// One result per call, in input order.
+    fn batch<'a>(
+        &'a self,
+        calls: &'a [(&'a str, Value)],
+    ) -> BoxFuture<'a, Vec<Result<Value, RpcError>>>;
+
+    /// Per-endpoint health for `/metrics`; nothing for clients without endpoints.
+    fn health(&self) -> Vec<EndpointHealth> {
+        Vec::new()
+    }
+
+    fn failovers_total(&self) -> u64 {
+        0
+    }
+}
+
+/// Health of one endpoint, as reported on `/metrics`.
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+    // Position in the network's RPC_URL list (URLs often embed API keys)
+    pub index: usize,
+    pub benched: bool,
+    pub failures_total: u64,
+    // Current spacing between requests after rate limiting (0 = unthrottled)
+    pub throttle_delay: Duration,
+    pub rate_limited_total: u64,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    benched_until: Option<Instant>,
+}
+
+struct Endpoint {
+    url: String,
+    throttle: Throttle,
+    health: Mutex<Health>,
+    failures: AtomicU64,
+}
+
+impl Endpoint {
+    fn benched(&self) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .benched_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn on_success(&self) {
+        *self.health.lock().unwrap() = Health::default();
+    }
+
+    // A failure past the threshold benches the endpoint again, so one that
+    // comes back from the bench still failing goes straight back on it
+    fn on_failure(&self, index: usize, error: &RpcError) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= BENCH_AFTER_FAILURES {
+            tracing::warn!(
+                "RPC endpoint {} benched for {:?} after {} consecutive failures (last: {})",
+                index,
+                BENCH_DURATION,
+                health.consecutive_failures,
+                error
+            );
+            let until = Instant::now() + BENCH_DURATION;
+            health.benched_until = health.benched_until.max(Some(until));
+        }
+    }
+
+    // For as long as the provider asked us to stay away
+    fn bench_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut health = self.health.lock().unwrap();
+        health.benched_until = health.benched_until.max(Some(until));
+    }
+}
+
+/// Minimal JSON-RPC 2.0 client over HTTP, with failover across a network's
+/// endpoints. Each request tries the endpoints in configured order, moving
+/// on to the next on errors that are the endpoint's fault; endpoints that
+/// keep failing are benched for a while. When every endpoint is benched, they
+/// are all tried anyway rather than failing the request outright.
+pub struct RpcClient {
+    endpoints: Vec<Endpoint>,
+    client: reqwest::Client,
+    // Cleared the first time an endpoint answers a batch array as unsupported
+    batch_supported: AtomicBool,
+    failovers: AtomicU64,
+}
+
+impl RpcClient {
+    /// `urls` in order of preference; must not be empty.
+    pub fn new(urls: Vec<String>, client: reqwest::Client) -> Self {
+        Self {
+            endpoints: urls
+                .into_iter()
+                .map(|url| Endpoint {
+                    url,
+                    throttle: Throttle::new(),
+                    health: Mutex::new(Health::default()),
+                    failures: AtomicU64::new(0),
+                })
+                .collect(),
+            client,
+            batch_supported: AtomicBool::new(true),
+            failovers: AtomicU64::new(0),
+        }
+    }
+
+    fn endpoint_health(&self) -> Vec<EndpointHealth> {
+        self.endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, endpoint)| EndpointHealth {
+                index,
+                benched: endpoint.benched(),
+                failures_total: endpoint.failures.load(Ordering::Relaxed),
+                throttle_delay: endpoint.throttle.delay(),
+                rate_limited_total: endpoint.throttle.rate_limited_total(),
+            })
+            .collect()
+    }
+
+    async fn send(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1
+        });
+
+        let response = self.post(&request).await?;
+        parse_result(&response)
+    }
+
+    // Sends the calls as JSON-RPC batch arrays (responses are matched back by
+    // id). Falls back to one request per call if the provider rejects the
+    // batch; only a provider that says it doesn't do batches is never sent
+    // one again.
+    async fn send_all(&self, calls: &[(&str, Value)]) -> Vec<Result<Value, RpcError>> {
+        if self.batch_supported.load(Ordering::Relaxed) {
+            let mut results = Vec::with_capacity(calls.len());
+            for chunk in calls.chunks(MAX_BATCH_LEN) {
+                match self.send_batch(chunk).await {
+                    Ok(chunk_results) => results.extend(chunk_results),
+                    Err(e @ RpcError::BatchUnsupported(_)) if results.is_empty() => {
+                        tracing::warn!(
+                            "RPC provider doesn't take batch requests ({}); using individual calls",
+                            e
+                        );
+                        self.batch_supported.store(false, Ordering::Relaxed);
+                        break;
+                    }
+                    // Any other 4xx or a malformed reply (an auth hiccup, a
+                    // truncated body) only sends this request's calls singly;
+                    // network errors and 5xx are transient and just fail the chunk.
+                    Err(e @ (RpcError::Http(400..=499) | RpcError::BadResponse(_)))
+                        if results.is_empty() =>
+                    {
+                        tracing::debug!(
+                            "Batch request failed ({}); retrying as individual calls",
+                            e
+                        );
+                        break;
+                    }
+                    Err(e) => results.extend(chunk.iter().map(|_| Err(e.clone()))),
+                }
+            }
+            if results.len() == calls.len() {
+                return results;
+            }
+        }
+
+        futures::future::join_all(
+            calls
+                .iter()
+                .map(|(method, params)| self.send(method, params.clone())),
+        )
+        .await
+    }
+
+    async fn send_batch(&self, calls: &[(&str, Value)]) -> Result<Vec<Result<Value, RpcError>>, RpcError> {
+        let request: Vec<Value> = calls
+            .iter()
+            .enumerate()
+            .map(|(id, (method, params))| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": params,
+                    "id": id
+                })
+            })
+            .collect();
+
+        let response = self.post(&Value::Array(request)).await?;
+        let Value::Array(entries) = response else {
+            // One error object answering the whole array: the provider doesn't do batches
+            if let Some(error) = response.get("error") {
+                let message = error["message"].as_str().unwrap_or_default();
+                return Err(RpcError::BatchUnsupported(message.to_string()));
+            }
+            return Err(RpcError::BadResponse(response.to_string()));
+        };
+
+        let mut results: Vec<Result<Value, RpcError>> = calls
+            .iter()
+            .map(|_| Err(RpcError::BadResponse("missing from batch response".to_string())))
+            .collect();
+        for entry in entries {
+            if let Some(slot) = entry["id"].as_u64().and_then(|id| results.get_mut(id as usize)) {
+                *slot = parse_result(&entry);
+            }
+        }
+        Ok(results)
+    }
+
+    // Healthy endpoints first, then benched ones as a last resort, each group
+    // in configured order
+    fn candidates(&self) -> Vec<usize> {
+        let (healthy, benched): (Vec<usize>, Vec<usize>) =
+            (0..self.endpoints.len()).partition(|&i| !self.endpoints[i].benched());
+        healthy.into_iter().chain(benched).collect()
+    }
+
+    async fn post(&self, body: &Value) -> Result<Value, RpcError> {
+        let mut last_error = RpcError::Transport("no RPC endpoints configured".to_string());
+        for (attempt, index) in self.candidates().into_iter().enumerate() {
+            if attempt > 0 {
+                self.failovers.fetch_add(1, Ordering::Relaxed);
+                tracing::info!("Failing over to RPC endpoint {} ({})", index, last_error);
+            }
+            let endpoint = &self.endpoints[index];
+            match self.post_to(index, endpoint, body).await {
+                Ok(response) => {
+                    endpoint.on_success();
+                    return Ok(response);
+                }
+                Err(e) if e.is_transient() => {
+                    endpoint.on_failure(index, &e);
+                    last_error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error)
+    }
+
+    // Every request goes through the endpoint's throttle; 429s are retried
+    // only after the throttle has backed off, so retries never hammer the
+    // provider. One that benched the endpoint goes to the next one instead.
+    async fn post_to(&self, index: usize, endpoint: &Endpoint, body: &Value) -> Result<Value, RpcError> {
+        let mut attempt = 0;
+        loop {
+            endpoint.throttle.wait().await;
+            match self.post_once(index, endpoint, body).await {
+                Err(RpcError::RateLimited)
+                    if attempt < MAX_RATE_LIMIT_RETRIES && !endpoint.benched() =>
+                {
+                    attempt += 1
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // Errors name the endpoint by index: reqwest's own messages include the
+    // URL, and these reach API clients
+    async fn post_once(&self, index: usize, endpoint: &Endpoint, body: &Value) -> Result<Value, RpcError> {
+        let response = self
+            .client
+            .post(&endpoint.url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| RpcError::Transport(format!("endpoint {}: {}", index, e.without_url())))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_retry_after);
+            tracing::warn!(
+                "RPC endpoint {} rate limited us (Retry-After: {:?})",
+                index,
+                retry_after
+            );
+            // Too long to wait out: sit the endpoint out so requests go elsewhere
+            if !endpoint.throttle.on_rate_limited(retry_after)
+                && let Some(retry_after) = retry_after
+            {
+                endpoint.bench_for(retry_after);
+            }
+            return Err(RpcError::RateLimited);
+        }
+        if !status.is_success() {
+            return Err(RpcError::Http(status.as_u16()));
+        }
+        endpoint.throttle.on_success();
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| RpcError::Transport(format!("endpoint {}: {}", index, e.without_url())))?;
+        serde_json::from_slice(&prune_nesting(&body, MAX_RESPONSE_DEPTH))
+            .map_err(|e| RpcError::BadResponse(format!("endpoint {}: {}", index, e)))
+    }
+}
+
+impl ChainClient for RpcClient {
+    fn call<'a>(&'a self, method: &'a str, params: Value) -> BoxFuture<'a, Result<Value, RpcError>> {
+        Box::pin(self.send(method, params))
+    }
+
+    fn batch<'a>(
+        &'a self,
+        calls: &'a [(&'a str, Value)],
+    ) -> BoxFuture<'a, Vec<Result<Value, RpcError>>> {
+        Box::pin(self.send_all(calls))
+    }
+
+    fn health(&self) -> Vec<EndpointHealth> {
+        self.endpoint_health()
+    }
+
+    /// Requests that moved on to another endpoint after one failed.
+    fn failovers_total(&self) -> u64 {
+        self.failovers.load(Ordering::Relaxed)
+    }
+}
+
+// Retry-After in either of its forms: delay seconds, or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+// `body` with every array or object nested deeper than `max_depth` replaced by
+// `null`, so a deep call trace parses instead of hitting serde_json's
+// recursion limit. One pass over the bytes, without recursion.
+fn prune_nesting(body: &[u8], max_depth: usize) -> Cow<'_, [u8]> {
+    let mut pruned = Vec::new();
+    let mut depth = 0;
+    // Depth of the container being left out, while inside one
+    let mut skipping: Option<usize> = None;
+    let (mut in_string, mut escaped) = (false, false);
+    for (i, &byte) in body.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+        } else {
+            match byte {
+                b'"' => in_string = true,
+                b'{' | b'[' => {
+                    depth += 1;
+                    if depth > max_depth && skipping.is_none() {
+                        if pruned.is_empty() {
+                            pruned.extend_from_slice(&body[..i]);
+                        }
+                        pruned.extend_from_slice(b"null");
+                        skipping = Some(depth);
+                    }
+                }
+                b'}' | b']' => {
+                    let closed = depth;
+                    depth = depth.saturating_sub(1);
+                    if skipping == Some(closed) {
+                        skipping = None;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if skipping.is_none() && !pruned.is_empty() {
+            pruned.push(byte);
+        }
+    }
+    if pruned.is_empty() { Cow::Borrowed(body) } else { Cow::Owned(pruned) }
+}
+
+fn parse_result(response: &Value) -> Result<Value, RpcError> {
+    if let Some(error) = response.get("error") {
+        return Err(RpcError::Node {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+
+    response
+        .get("result")
+        .cloned()
+        .ok_or_else(|| RpcError::BadResponse(response.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    use axum::http::StatusCode;
+    use axum::http::header::RETRY_AFTER;
+    use axum::routing::post;
+
+    use super::*;
+    use crate::network::Network;
+    use crate::state::{AppState, Services};
+    use crate::store::Store;
+    use crate::testing;
+
+    // An endpoint answering every request with `status`, counting the requests
+    async fn failing_endpoint(status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = axum::Router::new().route(
+            "/",
+            post(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async move { (status, "down") }
+            }),
+        );
+        (testing::serve(app).await, hits)
+    }
+
+    async fn healthy_endpoint() -> String {
+        let app = axum::Router::new()
+            .route("/", post(|| async { r#"{"jsonrpc":"2.0","id":1,"result":"0x10"}"# }));
+        testing::serve(app).await
+    }
+
+    // A callTracer frame with `depth` levels of single nested calls below it
+    fn deep_trace(depth: usize) -> String {
+        let open = r#"{"type":"CALL","calls":["#.repeat(depth);
+        format!(r#"{}{{"type":"CALL"}}{}"#, open, "]}".repeat(depth))
+    }
+
+    #[test]
+    fn prunes_nesting_past_the_limit() {
+        let body = br#"{"a":[1,{"b":"[{\"}"}],"c":{"d":[[2]]}}"#;
+        assert!(matches!(prune_nesting(body, 4), Cow::Borrowed(_)));
+        assert_eq!(&*prune_nesting(body, 2), br#"{"a":[1,null],"c":{"d":null}}"#);
+    }
+
+    #[tokio::test]
+    async fn deep_traces_parse_without_failing_the_endpoint() {
+        let body = format!(r#"{{"jsonrpc":"2.0","id":1,"result":{}}}"#, deep_trace(1000));
+        let app = axum::Router::new().route("/", post(|| async { body }));
+        let client = RpcClient::new(vec![testing::serve(app).await], reqwest::Client::new());
+        let trace = client.call("debug_traceTransaction", json!([])).await.unwrap();
+
+        // Frames down to the parse limit are kept; the rest is cut off as null
+        let mut frame = &trace;
+        let mut frames = 1;
+        while let Some(child) = frame["calls"].get(0) {
+            frame = child;
+            frames += 1;
+        }
+        assert_eq!(frames, (MAX_RESPONSE_DEPTH - 2) / 2 + 1);
+        assert_eq!(frame.get("calls"), Some(&Value::Null));
+        assert_eq!(client.health()[0].failures_total, 0);
+    }
+
+    #[tokio::test]
+    async fn unparseable_bodies_do_not_count_against_endpoints() {
+        let app = axum::Router::new().route("/", post(|| async { "{\"result\": [" }));
+        let url = testing::serve(app).await;
+        let client = RpcClient::new(vec![url.clone(), url], reqwest::Client::new());
+        for _ in 0..BENCH_AFTER_FAILURES {
+            let result = client.call("eth_blockNumber", json!([])).await;
+            assert!(matches!(result, Err(RpcError::BadResponse(_))), "{:?}", result);
+        }
+
+        assert_eq!(client.failovers_total(), 0);
+        assert!(client.health().iter().all(|h| h.failures_total == 0 && !h.benched));
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_benches_an_endpoint_that_keeps_failing() {
+        let (down, hits) = failing_endpoint(StatusCode::BAD_GATEWAY).await;
+        let urls = vec![down, healthy_endpoint().await];
+        let network = Network::EthereumMainnet;
+        let mut rpc_clients: HashMap<Network, Box<dyn ChainClient>> = HashMap::new();
+        rpc_clients.insert(network, Box::new(RpcClient::new(urls, reqwest::Client::new())));
+        let services = Services {
+            rpc_clients,
+            archive_rpc_clients: HashMap::new(),
+            llm: None,
+            emitter: None,
+        };
+        let store = Store::open(":memory:").unwrap();
+        let state =
+            AppState::with_services(testing::config(), store, reqwest::Client::new(), services);
+        let client = state.rpc(network).unwrap();
+
+        for _ in 0..BENCH_AFTER_FAILURES {
+            assert_eq!(client.call("eth_blockNumber", json!([])).await.unwrap(), "0x10");
+        }
+        let health = client.health();
+        assert!(health[0].benched);
+        assert_eq!(health[0].failures_total, 3);
+        assert!(!health[1].benched);
+        assert_eq!(health[1].failures_total, 0);
+        assert_eq!(client.failovers_total(), 3);
+
+        // Benched, so later requests go straight to the healthy endpoint
+        assert_eq!(client.call("eth_blockNumber", json!([])).await.unwrap(), "0x10");
+        assert_eq!(hits.load(Ordering::Relaxed), 3);
+        assert_eq!(client.failovers_total(), 3);
+
+        let (_, metrics) = testing::send(&state, testing::get("/metrics")).await;
+        let metrics = metrics.as_str().unwrap();
+        let network = network.as_str();
+        for line in [
+            format!("analyzer_rpc_failovers_total{{network=\"{}\"}} 3", network),
+            format!("analyzer_rpc_endpoint_up{{network=\"{}\",endpoint=\"0\"}} 0", network),
+            format!("analyzer_rpc_endpoint_up{{network=\"{}\",endpoint=\"1\"}} 1", network),
+            format!(
+                "analyzer_rpc_endpoint_failures_total{{network=\"{}\",endpoint=\"0\"}} 3",
+                network
+            ),
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{} not in\n{}", line, metrics);
+        }
+    }
+
+    #[tokio::test]
+    async fn request_errors_do_not_fail_over() {
+        let (bad, hits) = failing_endpoint(StatusCode::BAD_REQUEST).await;
+        let client = RpcClient::new(vec![bad, healthy_endpoint().await], reqwest::Client::new());
+        let result = client.call("eth_blockNumber", json!([])).await;
+        assert!(matches!(result, Err(RpcError::Http(400))), "{:?}", result);
+        assert_eq!(hits.load(Ordering::Relaxed), 1);
+        assert_eq!(client.failovers_total(), 0);
+        assert_eq!(client.health()[0].failures_total, 0);
+    }
+
+    // A node that records each request body. It answers a single call with its
+    // method name; a batch array, in reverse order, when `batches` is set and
+    // with one error for the whole array otherwise.
+    async fn recording_node(batches: bool) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let requests: Arc<Mutex<Vec<Value>>> = Arc::default();
+        let recorded = requests.clone();
+        let answer =
+            |call: &Value| json!({ "jsonrpc": "2.0", "id": call["id"], "result": call["method"] });
+        let app = axum::Router::new().route(
+            "/",
+            post(move |axum::Json(body): axum::Json<Value>| {
+                recorded.lock().unwrap().push(body.clone());
+                async move {
+                    let response = match &body {
+                        Value::Array(calls) if batches => {
+                            Value::Array(calls.iter().rev().map(answer).collect())
+                        }
+                        Value::Array(_) => json!({
+                            "jsonrpc": "2.0",
+                            "id": null,
+                            "error": { "code": -32600, "message": "batch requests not supported" }
+                        }),
+                        call => answer(call),
+                    };
+                    axum::Json(response)
+                }
+            }),
+        );
+        (testing::serve(app).await, requests)
+    }
+
+    fn calls() -> Vec<(&'static str, Value)> {
+        vec![
+            ("eth_getTransactionByHash", json!(["0x01"])),
+            ("eth_getTransactionReceipt", json!(["0x01"])),
+            ("eth_blockNumber", json!([])),
+        ]
+    }
+
+    #[tokio::test]
+    async fn batches_calls_into_one_request() {
+        let (url, requests) = recording_node(true).await;
+        let client = RpcClient::new(vec![url], reqwest::Client::new());
+        let results = client.batch(&calls()).await;
+
+        // Matched back by id despite the reversed answer
+        let methods: Vec<Value> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            methods,
+            vec!["eth_getTransactionByHash", "eth_getTransactionReceipt", "eth_blockNumber"]
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let expected: Vec<Value> = calls()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (method, params))| {
+                json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id })
+            })
+            .collect();
+        assert_eq!(requests[0], Value::Array(expected));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_single_calls_for_nodes_without_batches() {
+        let (url, requests) = recording_node(false).await;
+        let client = RpcClient::new(vec![url], reqwest::Client::new());
+        for _ in 0..2 {
+            let results = client.batch(&calls()).await;
+            assert!(results.iter().all(Result::is_ok), "{:?}", results);
+            assert_eq!(results[2].as_ref().unwrap(), "eth_blockNumber");
+        }
+
+        // One rejected batch, then three single calls per round
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1 + 2 * 3);
+        assert!(requests[0].is_array());
+        assert!(requests[1..].iter().all(Value::is_object));
+        assert_eq!(client.health()[0].failures_total, 0);
+    }
+
+    #[test]
+    fn reads_retry_after_as_seconds_or_a_date() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        let in_a_minute = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(61));
+        let wait = parse_retry_after(&in_a_minute).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(61), "{:?}", wait);
+        let past = httpdate::fmt_http_date(SystemTime::UNIX_EPOCH);
+        assert_eq!(parse_retry_after(&past), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn long_retry_after_fails_over_instead_of_waiting() {
+        let limited = axum::Router::new().route(
+            "/",
+            post(|| async {
+                (StatusCode::TOO_MANY_REQUESTS, [(RETRY_AFTER, "86400")], "slow down")
+            }),
+        );
+        let urls = vec![testing::serve(limited).await, healthy_endpoint().await];
+        let client = RpcClient::new(urls, reqwest::Client::new());
+
+        let started = Instant::now();
+        for _ in 0..2 {
+            let head = client.call("eth_blockNumber", json!([])).await.unwrap();
+            assert_eq!(head, "0x10");
+        }
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let health = client.health();
+        assert!(health[0].benched);
+        // Benched after the first 429, so the second request went straight on
+        assert_eq!(health[0].rate_limited_total, 1);
+        assert_eq!(client.failovers_total(), 1);
+    }
+}
@@ -0,0 +1,204 @@
+use primitive_types::U256;
+use serde_json::json;
+
+use crate::services::decode::{self, AbiValue};
+use crate::services::rpc::ChainClient;
+
+const EXEC_TRANSACTION: &str = "execTransaction";
+const GET_THRESHOLD: &str = "0xe75235b8";
+const GET_OWNERS: &str = "0xa0e67e2b";
+
+// Safe signatures are packed r (32) | s (32) | v (1)
+const SIGNATURE_LEN: usize = 65;
+
+/// The operation a Safe `execTransaction` call wraps.
+#[derive(Debug, Clone)]
+pub struct SafeExec {
+    pub to: String,
+    pub value: U256,
+    pub data: String,
+    // "call" or "delegatecall"
+    pub operation: &'static str,
+    pub signature_count: usize,
+    // Owners named by the signatures themselves (see `signers`)
+    pub signers: Vec<String>,
+}
+
+/// Decodes Safe `execTransaction` calldata; `None` for anything else.
+pub fn decode_exec(input: &str) -> Option<SafeExec> {
+    let call = decode::decode_calldata(input)?;
+    if call.name != EXEC_TRANSACTION {
+        return None;
+    }
+    let [to, value, data, operation, .., signatures] = call.params.as_slice() else {
+        return None;
+    };
+    let (AbiValue::Bytes(data), AbiValue::Bytes(signatures)) = (data, signatures) else {
+        return None;
+    };
+
+    Some(SafeExec {
+        to: to.as_address()?.to_lowercase(),
+        value: value.as_uint()?,
+        data: format!("0x{}", hex::encode(data)),
+        operation: if operation.as_uint()? == U256::one() { "delegatecall" } else { "call" },
+        signature_count: signatures.len() / SIGNATURE_LEN,
+        signers: signers(signatures),
+    })
+}
+
+// Contract signatures (v = 0) and pre-approved hashes (v = 1) carry the owner
+// in `r`. ECDSA signatures would need the Safe tx hash to recover, so they're
+// only counted.
+fn signers(signatures: &[u8]) -> Vec<String> {
+    signatures
+        .chunks_exact(SIGNATURE_LEN)
+        .filter(|sig| sig[64] <= 1)
+        .map(|sig| format!("0x{}", hex::encode(&sig[12..32])))
+        .collect()
+}
+
+/// Current signing threshold and owners of the Safe, read at the tx's block.
+/// Either is `None` when the call fails (e.g. `safe` isn't actually a Safe).
+pub async fn owners(
+    rpc: &dyn ChainClient,
+    safe: &str,
+    block: Option<u64>,
+) -> (Option<u64>, Option<Vec<String>>) {
+    let block = block.map_or("latest".to_string(), |n| format!("0x{:x}", n));
+    let read = |selector: &'static str| {
+        let block = block.clone();
+        async move {
+            let ret = rpc
+                .call("eth_call", json!([{ "to": safe, "data": selector }, block]))
+                .await
+                .inspect_err(|e| tracing::warn!("Safe {} call on {} failed: {}", selector, safe, e))
+                .ok()?;
+            ret.as_str().map(str::to_string)
+        }
+    };
+
+    let threshold = read(GET_THRESHOLD)
+        .await
+        .and_then(|ret| decode::decode_output("uint256", &ret))
+        .and_then(|v| v.first().and_then(AbiValue::as_uint))
+        .and_then(|t| u64::try_from(t).ok());
+    let owners = read(GET_OWNERS)
+        .await
+        .and_then(|ret| decode::decode_output("address[]", &ret))
+        .and_then(|v| match v.into_iter().next() {
+            Some(AbiValue::Array(items)) => items
+                .iter()
+                .map(|a| a.as_address().map(str::to_lowercase))
+                .collect(),
+            _ => None,
+        });
+    (threshold, owners)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::MockChain;
+
+    const SAFE: &str = "0x5afe5afe5afe5afe5afe5afe5afe5afe5afe5afe";
+    const OWNER: &str = "0x0000000000000000000000000000000000000a11";
+    const TARGET: &str = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn word(value: impl std::fmt::LowerHex) -> String {
+        format!("{:064x}", value)
+    }
+
+    fn address(address: &str) -> String {
+        format!("{:0>64}", address.trim_start_matches("0x"))
+    }
+
+    // Length-prefixed and right-padded, as ABI `bytes`
+    fn bytes(data: &[u8]) -> String {
+        let padded = data.len().div_ceil(32) * 32;
+        format!("{}{:0<width$}", word(data.len()), hex::encode(data), width = padded * 2)
+    }
+
+    fn exec_transaction(data: &[u8], operation: u8, signatures: &[u8]) -> String {
+        let data = bytes(data);
+        let head = 10 * 32;
+        let signatures_offset = head + data.len() / 2;
+        [
+            "0x6a761202".to_string(),
+            address(TARGET),
+            word(7u8),
+            word(head),
+            word(operation),
+            word(0u8),
+            word(0u8),
+            word(0u8),
+            word(0u8),
+            word(0u8),
+            word(signatures_offset),
+            data,
+            bytes(signatures),
+        ]
+        .concat()
+    }
+
+    // A contract signature by OWNER (v = 0), then an ECDSA one (v = 27)
+    fn signatures() -> Vec<u8> {
+        let mut contract = hex::decode(address(OWNER)).unwrap();
+        contract.extend([0u8; 32]);
+        contract.push(0);
+        let mut ecdsa = vec![0x11u8; 64];
+        ecdsa.push(27);
+        [contract, ecdsa].concat()
+    }
+
+    #[test]
+    fn decodes_the_wrapped_call_and_its_signers() {
+        let input = exec_transaction(&[0xa9, 0x05, 0x9c, 0xbb], 0, &signatures());
+        let exec = decode_exec(&input).unwrap();
+
+        assert_eq!(exec.to, TARGET);
+        assert_eq!(exec.value, U256::from(7));
+        assert_eq!(exec.data, "0xa9059cbb");
+        assert_eq!(exec.operation, "call");
+        assert_eq!(exec.signature_count, 2);
+        assert_eq!(exec.signers, vec![OWNER.to_string()]);
+    }
+
+    #[test]
+    fn flags_delegatecalls() {
+        let input = exec_transaction(&[], 1, &signatures());
+        assert_eq!(decode_exec(&input).unwrap().operation, "delegatecall");
+    }
+
+    #[test]
+    fn ignores_other_calls() {
+        let transfer = format!("0xa9059cbb{}{}", address(TARGET), word(1u8));
+        assert!(decode_exec(&transfer).is_none());
+        assert!(decode_exec("0x").is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_threshold_and_owners_at_the_block() {
+        let owners_list = [word(0x20u8), word(1u8), address(OWNER)].concat();
+        let chain = MockChain::default()
+            .with_params(
+                "eth_call",
+                json!([{ "to": SAFE, "data": GET_THRESHOLD }, "0x10"]),
+                json!(format!("0x{}", word(2u8))),
+            )
+            .with_params(
+                "eth_call",
+                json!([{ "to": SAFE, "data": GET_OWNERS }, "0x10"]),
+                json!(format!("0x{}", owners_list)),
+            );
+
+        let read = owners(&chain, SAFE, Some(16)).await;
+        assert_eq!(read, (Some(2), Some(vec![OWNER.to_string()])));
+
+        // Not a Safe: the calls come back empty
+        let read = owners(&MockChain::default(), SAFE, None).await;
+        assert_eq!(read, (None, None));
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// Curated function selectors / event topics compiled into the binary, so common
+// calls and logs decode offline with no signature-directory lookups.
+static FUNCTIONS: LazyLock<HashMap<String, &'static str>> =
+    LazyLock::new(|| parse(include_str!("../../data/function_selectors.tsv")));
+static EVENTS: LazyLock<HashMap<String, &'static str>> =
+    LazyLock::new(|| parse(EVENT_TABLE));
+static EVENT_INDEXED: LazyLock<HashMap<String, Vec<usize>>> = LazyLock::new(|| {
+    rows(EVENT_TABLE)
+        .filter_map(|row| Some((row.0.to_lowercase(), row.2?)))
+        .map(|(topic, positions)| {
+            let positions = positions.split(',').filter_map(|p| p.trim().parse().ok());
+            (topic, positions.collect())
+        })
+        .collect()
+});
+type Row = (&'static str, &'static str, Option<&'static str>);
+const EVENT_TABLE: &str = include_str!("../../data/event_signatures.tsv");
+
+/// Canonical signature for a 4-byte selector ("0x095ea7b3").
+pub fn function_signature(selector: &str) -> Option<&'static str> {
+    FUNCTIONS.get(&selector.to_lowercase()).copied()
+}
+
+/// Canonical signature for an event topic0.
+pub fn event_signature(topic0: &str) -> Option<&'static str> {
+    EVENTS.get(&topic0.to_lowercase()).copied()
+}
+
+/// Positions of an event's indexed params, when the table lists them because
+/// they aren't simply the leading params (Uniswap V2 `Swap` indexes 0 and 5).
+pub fn event_indexed(topic0: &str) -> Option<&'static [usize]> {
+    EVENT_INDEXED.get(&topic0.to_lowercase()).map(Vec::as_slice)
+}
+
+fn parse(table: &'static str) -> HashMap<String, &'static str> {
+    rows(table)
+        .map(|(selector, signature, _)| (selector.to_lowercase(), signature))
+        .collect()
+}
+
+// "<hex>\t<signature>[\t<extra>]" per line; blank lines and '#' comments are skipped
+fn rows(table: &'static str) -> impl Iterator<Item = Row> {
+    table
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split('\t').map(str::trim);
+            Some((fields.next()?, fields.next()?, fields.next()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::keccak256;
+
+    #[test]
+    fn every_bundled_selector_matches_its_signature() {
+        for (selector, signature, _) in rows(include_str!("../../data/function_selectors.tsv")) {
+            let expected = format!("0x{}", hex::encode(&keccak256(signature.as_bytes())[..4]));
+            assert_eq!(selector.to_lowercase(), expected, "{}", signature);
+        }
+    }
+
+    #[test]
+    fn every_bundled_topic_matches_its_signature() {
+        for (topic, signature, _) in rows(EVENT_TABLE) {
+            let expected = format!("0x{}", hex::encode(keccak256(signature.as_bytes())));
+            assert_eq!(topic.to_lowercase(), expected, "{}", signature);
+        }
+    }
+
+    #[test]
+    fn looks_up_selectors_and_topics_in_any_case() {
+        assert_eq!(function_signature("0xA9059CBB"), Some("transfer(address,uint256)"));
+        assert_eq!(
+            event_signature("0xDDF252AD1BE2C89B69C2B068FC378DAA952BA7F163C4A11628F55A4DF523B3EF"),
+            Some("Transfer(address,address,uint256)")
+        );
+        assert_eq!(function_signature("0xdeadbeef"), None);
+    }
+
+    #[test]
+    fn lists_indexed_positions_only_where_given() {
+        let v2_swap = "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822";
+        assert_eq!(event_indexed(v2_swap), Some(&[0, 5][..]));
+        let transfer = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+        assert_eq!(event_indexed(transfer), None);
+    }
+}
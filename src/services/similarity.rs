@@ -0,0 +1,68 @@
+use crate::models::AnalyzeTxResponse;
+use crate::state::AppState;
+
+/// Whether stored analyses get embedded: an LLM is configured and
+/// EMBEDDINGS_ENABLED is on.
+pub fn enabled(state: &AppState) -> bool {
+    state.llm.is_some() && state.config.embeddings_enabled
+}
+
+/// Embeds the stored analysis `analysis_id` so `/similar` can find it.
+/// Best effort, like the history write it follows.
+pub async fn embed_analysis(state: &AppState, analysis_id: i64, analysis: &AnalyzeTxResponse) {
+    let Some(llm) = state.llm.as_ref().filter(|_| enabled(state)) else {
+        return;
+    };
+    let model = &state.config.embedding_model;
+    let vector = match llm.embed(model, &features(analysis)).await {
+        Ok(vector) => vector,
+        Err(e) => {
+            tracing::warn!("Failed to embed analysis of {}: {}", analysis.tx_hash, e);
+            return;
+        }
+    };
+    if let Err(e) = state
+        .store
+        .record_embedding(analysis_id, analysis.network, &analysis.tx_hash, model, &vector)
+        .await
+    {
+        tracing::warn!("Failed to store embedding for {}: {}", analysis.tx_hash, e);
+    }
+}
+
+// What a tx "looks like": its decoded shape and findings rather than its
+// hash, amounts, or free-text explanation, so alike txs land close together
+fn features(analysis: &AnalyzeTxResponse) -> String {
+    let mut lines = vec![
+        format!("type: {}", analysis.tx_type),
+        format!("protocol: {}", analysis.protocol.as_deref().unwrap_or("unknown")),
+        format!("method: {}", analysis.method.as_deref().unwrap_or("unknown")),
+        format!("risk score: {:.2}", analysis.risk_score),
+    ];
+    lines.extend(analysis.risk_reasons.iter().map(|r| format!("finding: {}", r)));
+    lines.extend(
+        analysis
+            .permits
+            .iter()
+            .map(|p| format!("permit: {}{}", p.kind, if p.unlimited { " (unlimited)" } else { "" })),
+    );
+    lines.extend(analysis.contracts.iter().map(|c| {
+        format!(
+            "contract: {} ({})",
+            c.name.as_deref().unwrap_or("unnamed"),
+            if c.verified { "verified" } else { "unverified" }
+        )
+    }));
+    lines.join("\n")
+}
+
+/// Cosine similarity; 0 for mismatched or zero-length vectors.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 { 0.0 } else { dot / denom }
+}
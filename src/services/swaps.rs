@@ -0,0 +1,345 @@
+use std::collections::{BTreeSet, HashMap};
+
+use primitive_types::U256;
+use serde_json::{json, Value};
+
+use crate::models::SwapInfo;
+use crate::network::Network;
+use crate::services::decode;
+use crate::services::rpc::ChainClient;
+use crate::services::selectors;
+use crate::services::tokens::TokenMetadataCache;
+
+// Swaps priced per tx; each pool costs two eth_calls (three without a Sync log)
+const MAX_SWAPS: usize = 5;
+
+const SWAP_EVENT: &str = "Swap(address,uint256,uint256,uint256,uint256,address)";
+const SYNC_EVENT: &str = "Sync(uint112,uint112)";
+
+// token0(), token1(), getReserves()
+const TOKEN0_SELECTOR: &str = "0x0dfe1681";
+const TOKEN1_SELECTOR: &str = "0xd21220a7";
+const GET_RESERVES_SELECTOR: &str = "0x0902f1ac";
+
+struct PoolSwap {
+    pool: String,
+    amount0_in: U256,
+    amount1_in: U256,
+    amount0_out: U256,
+    amount1_out: U256,
+    // (reserve0, reserve1) just before the swap, from the Sync the pool emits
+    // with it; read from the node instead when there's none
+    reserves: Option<(U256, U256)>,
+}
+
+/// The Uniswap V2-style swaps in the tx's logs, priced against their pool's
+/// reserves just before each swap. Those come from the pool's own `Sync` log
+/// (its reserves after the swap, less what the swap moved), which also
+/// accounts for earlier swaps on the pool in the same block; a pool without
+/// one is asked for `getReserves()` at the previous block, which needs
+/// `historical`. Each pool's tokens are read with `token0()`/`token1()`.
+pub async fn assess(
+    network: Network,
+    rpc: &dyn ChainClient,
+    historical: Option<&dyn ChainClient>,
+    tokens: &TokenMetadataCache,
+    tx_details: &Value,
+) -> Vec<SwapInfo> {
+    let mut swaps = pool_swaps(tx_details);
+    swaps.truncate(MAX_SWAPS);
+    if swaps.is_empty() {
+        return Vec::new();
+    }
+
+    let pools: Vec<&str> = swaps
+        .iter()
+        .map(|swap| swap.pool.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let pair_tokens = pair_tokens(rpc, &pools).await;
+    let parent_reserves = match (historical, tx_details["block_number"].as_u64()) {
+        (Some(historical), Some(block)) if block > 0 => {
+            let unsynced: Vec<&str> = swaps
+                .iter()
+                .filter(|swap| swap.reserves.is_none())
+                .map(|swap| swap.pool.as_str())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            reserves_at(historical, &unsynced, block - 1).await
+        }
+        _ => HashMap::new(),
+    };
+    tokens
+        .load(network, rpc, pair_tokens.values().flat_map(|(t0, t1)| [t0.as_str(), t1.as_str()]))
+        .await;
+
+    swaps
+        .into_iter()
+        .filter_map(|swap| {
+            let (token0, token1) = pair_tokens.get(&swap.pool)?;
+            let reserves = swap.reserves.or_else(|| parent_reserves.get(&swap.pool).copied());
+            // Whichever side went in; flash swaps paying back in the same token aren't priced
+            let (token_in, token_out, amount_in, amount_out, reserves) =
+                if !swap.amount0_in.is_zero() && !swap.amount1_out.is_zero() {
+                    (token0, token1, swap.amount0_in, swap.amount1_out, reserves)
+                } else if !swap.amount1_in.is_zero() && !swap.amount0_out.is_zero() {
+                    let flipped = reserves.map(|(r0, r1)| (r1, r0));
+                    (token1, token0, swap.amount1_in, swap.amount0_out, flipped)
+                } else {
+                    return None;
+                };
+            let amounts = (amount_in, amount_out);
+            Some(price(network, tokens, swap.pool, token_in, token_out, amounts, reserves))
+        })
+        .collect()
+}
+
+// `amounts` and `reserves` are (in, out) in base units
+fn price(
+    network: Network,
+    tokens: &TokenMetadataCache,
+    pool: String,
+    token_in: &str,
+    token_out: &str,
+    amounts: (U256, U256),
+    reserves: Option<(U256, U256)>,
+) -> SwapInfo {
+    let amount_in = tokens.amount(network, token_in, amounts.0);
+    let amount_out = tokens.amount(network, token_out, amounts.1);
+    // Base units to whole tokens, from token_out per token_in
+    let scale = amount_in
+        .decimals
+        .zip(amount_out.decimals)
+        .map(|(d_in, d_out)| 10f64.powi(d_in as i32 - d_out as i32));
+    let ratio = |(x_in, x_out): (U256, U256)| to_f64(x_out) / to_f64(x_in);
+
+    let execution = ratio(amounts);
+    let spot = reserves.filter(|(r_in, r_out)| !r_in.is_zero() && !r_out.is_zero()).map(ratio);
+    SwapInfo {
+        pool,
+        token_in: token_in.to_string(),
+        token_out: token_out.to_string(),
+        amount_in,
+        amount_out,
+        effective_price: scale.map(|scale| execution * scale),
+        spot_price: spot.zip(scale).map(|(spot, scale)| spot * scale),
+        price_impact_pct: spot.map(|spot| ((1.0 - execution / spot) * 10_000.0).round() / 100.0),
+    }
+}
+
+// V2 `Swap` logs in log order, each with the reserves of its pool's latest
+// `Sync` before it. The pair emits Sync with the reserves after the swap, so
+// undoing the swap's amounts gives the ones before.
+fn pool_swaps(tx_details: &Value) -> Vec<PoolSwap> {
+    let logs = tx_details["logs"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let mut synced: HashMap<String, (U256, U256)> = HashMap::new();
+    let mut swaps = Vec::new();
+    for log in logs {
+        let Some(pool) = log["address"].as_str().map(str::to_lowercase) else {
+            continue;
+        };
+        let event = log["topics"][0].as_str().and_then(selectors::event_signature);
+        let data = log["data"].as_str().unwrap_or("0x");
+        match event {
+            Some(SYNC_EVENT) => {
+                if let Some(&[r0, r1]) = uints(data, 2).as_deref() {
+                    synced.insert(pool, (r0, r1));
+                }
+            }
+            Some(SWAP_EVENT) => {
+                let Some(&[in0, in1, out0, out1]) = uints(data, 4).as_deref() else {
+                    continue;
+                };
+                let reserves = synced.remove(&pool).and_then(|(r0, r1)| {
+                    Some((
+                        r0.checked_add(out0)?.checked_sub(in0)?,
+                        r1.checked_add(out1)?.checked_sub(in1)?,
+                    ))
+                });
+                swaps.push(PoolSwap {
+                    pool,
+                    amount0_in: in0,
+                    amount1_in: in1,
+                    amount0_out: out0,
+                    amount1_out: out1,
+                    reserves,
+                });
+            }
+            _ => {}
+        }
+    }
+    swaps
+}
+
+// `count` leading uint words of ABI-encoded data
+fn uints(data: &str, count: usize) -> Option<Vec<U256>> {
+    let types = vec!["uint256"; count].join(",");
+    let values = decode::decode_output(&types, data)?;
+    values.iter().map(|value| value.as_uint()).collect()
+}
+
+// pool -> (token0, token1), lowercase. Pools that don't answer are left out.
+async fn pair_tokens(rpc: &dyn ChainClient, pools: &[&str]) -> HashMap<String, (String, String)> {
+    let calls: Vec<(&str, Value)> = pools
+        .iter()
+        .flat_map(|pool| {
+            [TOKEN0_SELECTOR, TOKEN1_SELECTOR]
+                .map(|data| ("eth_call", json!([{ "to": pool, "data": data }, "latest"])))
+        })
+        .collect();
+    let mut results = rpc.batch(&calls).await.into_iter();
+
+    let mut tokens = HashMap::new();
+    for pool in pools {
+        let (Some(token0), Some(token1)) = (results.next(), results.next()) else {
+            break;
+        };
+        let address = |result: Result<Value, _>| {
+            let output = result.ok()?;
+            match decode::decode_output("address", output.as_str()?)?.first()? {
+                decode::AbiValue::Address(a) => Some(a.to_lowercase()),
+                _ => None,
+            }
+        };
+        if let (Some(token0), Some(token1)) = (address(token0), address(token1)) {
+            tokens.insert(pool.to_string(), (token0, token1));
+        }
+    }
+    tokens
+}
+
+// pool -> (reserve0, reserve1) at the end of `block`
+async fn reserves_at(
+    rpc: &dyn ChainClient,
+    pools: &[&str],
+    block: u64,
+) -> HashMap<String, (U256, U256)> {
+    if pools.is_empty() {
+        return HashMap::new();
+    }
+    let block = format!("0x{:x}", block);
+    let calls: Vec<(&str, Value)> = pools
+        .iter()
+        .map(|pool| ("eth_call", json!([{ "to": pool, "data": GET_RESERVES_SELECTOR }, block])))
+        .collect();
+    let results = rpc.batch(&calls).await;
+
+    pools
+        .iter()
+        .zip(results)
+        .filter_map(|(pool, result)| {
+            let output = result.ok()?;
+            // (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
+            match uints(output.as_str()?, 3).as_deref() {
+                Some(&[r0, r1, _]) => Some((pool.to_string(), (r0, r1))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Reserves and amounts can exceed 128 bits; prices only need f64 precision
+fn to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, limb| acc * 18_446_744_073_709_551_616.0 + *limb as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::risk;
+    use crate::testing::MockChain;
+    use crate::util::keccak256;
+
+    const POOL: &str = "0xcccccccccccccccccccccccccccccccccccccccc";
+    const WETH: &str = "0xdddddddddddddddddddddddddddddddddddddddd";
+    const USDC: &str = "0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee";
+    const ETHER: u128 = 1_000_000_000_000_000_000;
+    const DOLLAR: u128 = 1_000_000;
+
+    fn words(values: &[u128]) -> String {
+        let words: Vec<String> = values.iter().map(|v| format!("{:064x}", v)).collect();
+        format!("0x{}", words.concat())
+    }
+
+    fn log(event: &str, indexed: usize, data: &[u128]) -> Value {
+        let topic0 = format!("0x{}", hex::encode(keccak256(event.as_bytes())));
+        let sender = format!("0x{:0>64}", "a".repeat(40));
+        let mut topics = vec![topic0];
+        topics.extend(std::iter::repeat_n(sender, indexed));
+        json!({ "address": POOL, "topics": topics, "data": words(data) })
+    }
+
+    fn close(price: Option<f64>, expected: f64) -> bool {
+        price.is_some_and(|price| (price - expected).abs() < 1e-6)
+    }
+
+    fn call(to: &str, data: &str) -> Value {
+        json!([{ "to": to, "data": data }, "latest"])
+    }
+
+    // A WETH/USDC pool, with the tokens' decimals and symbols
+    fn node() -> MockChain {
+        let address = |token: &str| format!("0x{:0>64}", &token[2..]);
+        let symbol = |symbol: &str| format!("0x{:0<64}", hex::encode(symbol));
+        MockChain::default()
+            .with_params("eth_call", call(POOL, TOKEN0_SELECTOR), json!(address(WETH)))
+            .with_params("eth_call", call(POOL, TOKEN1_SELECTOR), json!(address(USDC)))
+            .with_params("eth_call", call(WETH, "0x313ce567"), json!(words(&[18])))
+            .with_params("eth_call", call(WETH, "0x95d89b41"), json!(symbol("WETH")))
+            .with_params("eth_call", call(USDC, "0x313ce567"), json!(words(&[6])))
+            .with_params("eth_call", call(USDC, "0x95d89b41"), json!(symbol("USDC")))
+    }
+
+    // `weth_in` WETH sold for `usdc_out` USDC against a pool holding 100 WETH
+    // and 200,000 USDC (a price of 2,000), with the pool's Sync when `synced`
+    async fn swap(weth_in: u128, usdc_out: u128, synced: bool) -> SwapInfo {
+        let mut logs = Vec::new();
+        if synced {
+            let reserves = [(100 + weth_in) * ETHER, (200_000 - usdc_out) * DOLLAR];
+            logs.push(log(SYNC_EVENT, 0, &reserves));
+        }
+        logs.push(log(SWAP_EVENT, 2, &[weth_in * ETHER, 0, 0, usdc_out * DOLLAR]));
+        let tx_details = json!({ "block_number": 20_000_000, "logs": logs });
+
+        let tokens = TokenMetadataCache::default();
+        let rpc = node();
+        let mut swaps = assess(Network::EthereumMainnet, &rpc, None, &tokens, &tx_details).await;
+        assert_eq!(swaps.len(), 1);
+        swaps.remove(0)
+    }
+
+    #[tokio::test]
+    async fn prices_a_swap_against_the_reserves_before_it() {
+        let swap = swap(10, 18_000, true).await;
+        assert_eq!(swap.pool, POOL);
+        assert_eq!((swap.token_in.as_str(), swap.token_out.as_str()), (WETH, USDC));
+        assert_eq!(swap.amount_in.formatted.as_deref(), Some("10"));
+        assert_eq!(swap.amount_out.symbol.as_deref(), Some("USDC"));
+        assert!(close(swap.spot_price, 2_000.0));
+        assert!(close(swap.effective_price, 1_800.0));
+        assert_eq!(swap.price_impact_pct, Some(10.0));
+
+        let signals = risk::evaluate(&json!({ "swaps": [swap] }));
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].code, "HIGH_PRICE_IMPACT");
+        assert!(signals[0].detail.ends_with("executed 10.00% below the pool's price"));
+    }
+
+    #[tokio::test]
+    async fn small_swaps_pay_little_impact() {
+        let swap = swap(1, 1_990, true).await;
+        assert!(close(swap.effective_price, 1_990.0));
+        assert_eq!(swap.price_impact_pct, Some(0.5));
+        assert!(risk::evaluate(&json!({ "swaps": [swap] })).is_empty());
+    }
+
+    #[tokio::test]
+    async fn without_a_sync_or_archive_node_the_impact_is_unknown() {
+        let swap = swap(10, 18_000, false).await;
+        assert!(close(swap.effective_price, 1_800.0));
+        assert_eq!(swap.spot_price, None);
+        assert_eq!(swap.price_impact_pct, None);
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Spacing added after the first 429, doubled on each further one
+const MIN_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+// Each success shrinks the spacing by this factor until it drops below MIN_DELAY
+const RECOVERY_FACTOR: f64 = 0.9;
+
+struct State {
+    // Minimum spacing between requests; zero while the provider is happy
+    delay: Duration,
+    // Earliest time the next request may go out
+    next_slot: Instant,
+}
+
+/// Adaptive request pacing for one RPC provider: 429s multiplicatively slow
+/// requests down (honoring `Retry-After`), and each success speeds them back
+/// up a little.
+pub struct Throttle {
+    state: Mutex<State>,
+    rate_limited: AtomicU64,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                delay: Duration::ZERO,
+                next_slot: Instant::now(),
+            }),
+            rate_limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Waits for this request's slot. Slots are handed out in order, `delay` apart.
+    pub async fn wait(&self) {
+        let slot = {
+            let mut state = self.state.lock().unwrap();
+            let slot = state.next_slot.max(Instant::now());
+            state.next_slot = slot + state.delay;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    pub fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.delay.is_zero() {
+            return;
+        }
+        let delay = state.delay.mul_f64(RECOVERY_FACTOR);
+        state.delay = if delay < MIN_DELAY { Duration::ZERO } else { delay };
+    }
+
+    /// Backs off after a 429: doubles the spacing and pauses every request
+    /// until `retry_after` (or one spacing interval) has passed. A
+    /// `retry_after` past the longest spacing isn't waited out: returns false,
+    /// and requests should go to another provider instead.
+    pub fn on_rate_limited(&self, retry_after: Option<Duration>) -> bool {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        state.delay = (state.delay * 2).clamp(MIN_DELAY, MAX_DELAY);
+        let honored = retry_after.is_none_or(|wait| wait <= MAX_DELAY);
+        let resume = Instant::now() + retry_after.filter(|_| honored).unwrap_or(state.delay);
+        state.next_slot = state.next_slot.max(resume);
+        honored
+    }
+
+    /// Current spacing between requests (0 = unthrottled).
+    pub fn delay(&self) -> Duration {
+        self.state.lock().unwrap().delay
+    }
+
+    pub fn rate_limited_total(&self) -> u64 {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paused_for(throttle: &Throttle) -> Duration {
+        throttle.state.lock().unwrap().next_slot.saturating_duration_since(Instant::now())
+    }
+
+    #[test]
+    fn backs_off_and_recovers() {
+        let throttle = Throttle::new();
+        assert!(throttle.on_rate_limited(None));
+        assert!(throttle.on_rate_limited(None));
+        assert_eq!(throttle.delay(), MIN_DELAY * 2);
+        assert_eq!(throttle.rate_limited_total(), 2);
+
+        for _ in 0..20 {
+            throttle.on_success();
+        }
+        assert_eq!(throttle.delay(), Duration::ZERO);
+    }
+
+    #[test]
+    fn honors_retry_after_up_to_the_longest_spacing() {
+        let throttle = Throttle::new();
+        assert!(throttle.on_rate_limited(Some(Duration::from_secs(2))));
+        assert!(paused_for(&throttle) > Duration::from_secs(1));
+
+        // A day-long Retry-After doesn't hold the endpoint's requests
+        let throttle = Throttle::new();
+        assert!(!throttle.on_rate_limited(Some(Duration::from_secs(86_400))));
+        assert!(paused_for(&throttle) <= MIN_DELAY);
+    }
+}
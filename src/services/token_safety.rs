@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashSet};
+
+use primitive_types::{U256, U512};
+use serde_json::{Value, json};
+
+use crate::models::TokenSafety;
+use crate::services::decode;
+use crate::services::explorer::ExplorerClient;
+use crate::services::rpc::{ChainClient, RpcError};
+
+// Tokens checked per tx; each costs an eth_call and an explorer lookup
+const MAX_TOKENS: usize = 3;
+
+// A transfer tax at or above this makes selling pointless
+const HONEYPOT_TAX_PCT: f64 = 50.0;
+
+const TRANSFER_SELECTOR: &str = "a9059cbb";
+
+/// Honeypot checks for the tokens bought in a swap: bought tokens are the
+/// ERC-20s sent out of a pool that emitted a `Swap`. Each is checked for a
+/// transfer tax (seen in the tx's own logs), a simulated sell back to the
+/// pool, and red flags in its verified source.
+pub async fn assess(
+    rpc: Option<&dyn ChainClient>,
+    explorer: &ExplorerClient,
+    tx_details: &Value,
+) -> Vec<TokenSafety> {
+    let mut report = Vec::new();
+    for bought in bought_tokens(tx_details).into_iter().take(MAX_TOKENS) {
+        report.push(check_token(rpc, explorer, tx_details, bought).await);
+    }
+    report
+}
+
+struct Bought {
+    token: String,
+    pool: String,
+    buyer: String,
+    // Amount the buyer actually received
+    received: U256,
+    // Everything that left the pool, including any tax taken on the way
+    sent: U256,
+}
+
+async fn check_token(
+    rpc: Option<&dyn ChainClient>,
+    explorer: &ExplorerClient,
+    tx_details: &Value,
+    bought: Bought,
+) -> TokenSafety {
+    let mut findings = Vec::new();
+
+    let transfer_tax_pct = (bought.sent > bought.received && !bought.sent.is_zero()).then(|| {
+        // In 512 bits, so amounts near the top of the uint256 range don't overflow
+        let taxed = (bought.sent - bought.received).full_mul(U256::from(10_000))
+            / U512::from(bought.sent);
+        taxed.low_u64() as f64 / 100.0
+    });
+    if let Some(tax) = transfer_tax_pct {
+        findings.push(format!("{:.2}% of the bought amount was withheld as a transfer tax", tax));
+    }
+
+    let sell_simulation = match rpc {
+        Some(rpc) => Some(simulate_sell(rpc, tx_details, &bought).await),
+        None => None,
+    };
+    match sell_simulation.as_deref() {
+        Some("reverted") => findings.push("Selling back to the pool reverts".to_string()),
+        Some("returned_false") => findings.push("Selling back to the pool returns false".to_string()),
+        _ => {}
+    }
+
+    let source = explorer.source_review(&bought.token).await;
+    if !source.verified {
+        findings.push("Token source is not verified".to_string());
+    }
+    findings.extend(source.flags);
+
+    let likely_honeypot = matches!(sell_simulation.as_deref(), Some("reverted" | "returned_false"))
+        || transfer_tax_pct.is_some_and(|t| t >= HONEYPOT_TAX_PCT);
+
+    TokenSafety {
+        token: bought.token,
+        verified: source.verified,
+        sell_simulation,
+        transfer_tax_pct,
+        likely_honeypot,
+        findings,
+    }
+}
+
+// `transfer(pool, received)` from the buyer, against the state right after the
+// tx's block: the cheapest stand-in for a sell, and what honeypots block.
+async fn simulate_sell(rpc: &dyn ChainClient, tx_details: &Value, bought: &Bought) -> String {
+    let data = format!(
+        "0x{}{:0>64}{:064x}",
+        TRANSFER_SELECTOR,
+        bought.pool.trim_start_matches("0x"),
+        bought.received
+    );
+    let block = match tx_details["block_number"].as_u64() {
+        Some(n) => format!("0x{:x}", n),
+        None => "latest".to_string(),
+    };
+    let call = json!({ "from": bought.buyer, "to": bought.token, "data": data });
+
+    match rpc.call("eth_call", json!([call, block])).await {
+        // Tokens returning nothing (USDT-style) or `true` both count as success
+        Ok(Value::String(ret)) => {
+            let ret = ret.trim_start_matches("0x");
+            if !ret.is_empty() && ret.trim_start_matches('0').is_empty() {
+                "returned_false".to_string()
+            } else {
+                "passed".to_string()
+            }
+        }
+        Ok(other) => {
+            tracing::warn!("Unexpected eth_call result for {}: {}", bought.token, other);
+            "inconclusive".to_string()
+        }
+        Err(RpcError::Node { .. }) => "reverted".to_string(),
+        Err(e) => {
+            tracing::warn!("Sell simulation failed for {}: {}", bought.token, e);
+            "inconclusive".to_string()
+        }
+    }
+}
+
+fn bought_tokens(tx_details: &Value) -> Vec<Bought> {
+    let logs = tx_details["logs"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let pools: HashSet<String> = logs
+        .iter()
+        .filter(|log| log["topics"][0].as_str().and_then(decode::event_name) == Some("Swap"))
+        .filter_map(|log| log["address"].as_str().map(str::to_lowercase))
+        .collect();
+    if pools.is_empty() {
+        return Vec::new();
+    }
+    let sender = tx_details["from"].as_str().unwrap_or("").to_lowercase();
+
+    // token -> (pool, recipient -> amount) for ERC-20 transfers out of a pool.
+    // ERC-721 Transfer has the same topic0 but a fourth (tokenId) topic.
+    let mut outflows: BTreeMap<String, (String, BTreeMap<String, U256>)> = BTreeMap::new();
+    for log in logs {
+        let topics = log["topics"].as_array().map(Vec::as_slice).unwrap_or_default();
+        if topics.len() != 3 || topics[0].as_str().and_then(decode::event_name) != Some("Transfer") {
+            continue;
+        }
+        let (Some(token), Some(from), Some(to)) = (
+            log["address"].as_str(),
+            topics[1].as_str().and_then(topic_address),
+            topics[2].as_str().and_then(topic_address),
+        ) else {
+            continue;
+        };
+        if !pools.contains(&from) {
+            continue;
+        }
+        let amount = log["data"]
+            .as_str()
+            .and_then(decode::decode_hex)
+            .filter(|d| d.len() >= 32)
+            .map(|d| U256::from_big_endian(&d[..32]))
+            .unwrap_or_default();
+
+        let (_, recipients) = outflows
+            .entry(token.to_lowercase())
+            .or_insert_with(|| (from, BTreeMap::new()));
+        let total = recipients.entry(to).or_default();
+        *total = total.saturating_add(amount);
+    }
+
+    outflows
+        .into_iter()
+        .filter_map(|(token, (pool, recipients))| {
+            let sent = recipients.values().fold(U256::zero(), |a, b| a.saturating_add(*b));
+            // The buyer is the tx sender when it received the token, otherwise
+            // the largest recipient (e.g. a router forwarding to someone else)
+            let (buyer, received) = recipients
+                .get_key_value(&sender)
+                .or_else(|| recipients.iter().max_by_key(|(_, amount)| **amount))
+                .map(|(buyer, amount)| (buyer.clone(), *amount))?;
+            // Only passing through on a multi-hop route, not bought
+            if pools.contains(&buyer) {
+                return None;
+            }
+            Some(Bought { token, pool, buyer, received, sent })
+        })
+        .collect()
+}
+
+fn topic_address(topic: &str) -> Option<String> {
+    let hex = topic.strip_prefix("0x")?;
+    (hex.len() == 64).then(|| format!("0x{}", &hex[24..]).to_lowercase())
+}
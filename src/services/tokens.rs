@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use primitive_types::U256;
+use serde_json::{json, Value};
+
+use crate::models::{Amount, TokenTransfer};
+use crate::network::Network;
+use crate::services::blockchain::format_units;
+use crate::services::decode::{self, AbiValue};
+use crate::services::rpc::{ChainClient, RpcError};
+
+// decimals() and symbol()
+const DECIMALS_SELECTOR: &str = "0x313ce567";
+const SYMBOL_SELECTOR: &str = "0x95d89b41";
+
+// Longer symbols are cut; they reach the prompt like any other on-chain text
+const MAX_SYMBOL_LEN: usize = 32;
+
+#[derive(Debug, Clone, Default)]
+struct Metadata {
+    decimals: Option<u8>,
+    symbol: Option<String>,
+}
+
+/// ERC-20 decimals and symbols, read from the token contracts. Cached per
+/// network and address, since neither changes after deployment. A token
+/// that doesn't implement them is cached as unknown too; a failed request
+/// isn't cached, so it's retried on the next analysis.
+#[derive(Default)]
+pub struct TokenMetadataCache {
+    cache: Mutex<HashMap<(Network, String), Metadata>>,
+}
+
+impl TokenMetadataCache {
+    /// ERC-20 transfers in the tx's logs, in log order. Metadata of tokens not
+    /// cached yet is read in one batch request; without `rpc` it stays unknown,
+    /// and amounts are given in base units only.
+    pub async fn transfers(
+        &self,
+        network: Network,
+        rpc: Option<&dyn ChainClient>,
+        tx_details: &Value,
+    ) -> Vec<TokenTransfer> {
+        let transfers = transfer_logs(tx_details);
+        if let Some(rpc) = rpc {
+            self.load(network, rpc, transfers.iter().map(|(token, ..)| token.as_str())).await;
+        }
+        transfers
+            .into_iter()
+            .map(|(token, from, to, raw)| TokenTransfer {
+                amount: self.amount(network, &token, raw),
+                token,
+                from,
+                to,
+            })
+            .collect()
+    }
+
+    /// Reads the metadata of whichever of `tokens` isn't cached yet, in one
+    /// batch request.
+    pub async fn load<'a>(
+        &self,
+        network: Network,
+        rpc: &dyn ChainClient,
+        tokens: impl IntoIterator<Item = &'a str>,
+    ) {
+        let mut missing: Vec<&str> = Vec::new();
+        {
+            let cache = self.cache.lock().unwrap();
+            for token in tokens {
+                let key = (network, token.to_string());
+                if !cache.contains_key(&key) && !missing.contains(&token) {
+                    missing.push(token);
+                }
+            }
+        }
+        self.fetch(network, rpc, &missing).await;
+    }
+
+    /// `raw` base units of `token`, scaled by its decimals if they're cached.
+    pub fn amount(&self, network: Network, token: &str, raw: U256) -> Amount {
+        let (symbol, decimals) = self.metadata(network, token);
+        Amount {
+            wei: raw.to_string(),
+            formatted: decimals.map(|d| format_units(raw, d)),
+            symbol,
+            decimals,
+        }
+    }
+
+    /// Symbol and decimals of `token`, as far as they're cached.
+    pub fn metadata(&self, network: Network, token: &str) -> (Option<String>, Option<u8>) {
+        let metadata = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&(network, token.to_string()))
+            .cloned()
+            .unwrap_or_default();
+        (metadata.symbol, metadata.decimals)
+    }
+
+    async fn fetch(&self, network: Network, rpc: &dyn ChainClient, tokens: &[&str]) {
+        if tokens.is_empty() {
+            return;
+        }
+        let calls: Vec<(&str, Value)> = tokens
+            .iter()
+            .flat_map(|token| {
+                [DECIMALS_SELECTOR, SYMBOL_SELECTOR]
+                    .map(|data| ("eth_call", json!([{ "to": token, "data": data }, "latest"])))
+            })
+            .collect();
+        let mut results = rpc.batch(&calls).await.into_iter();
+
+        let mut cache = self.cache.lock().unwrap();
+        for token in tokens {
+            let (Some(decimals), Some(symbol)) = (results.next(), results.next()) else {
+                break;
+            };
+            // Only a node's answer (a value, or a revert) says anything about the token
+            if [&decimals, &symbol].iter().any(|r| matches!(r, Err(e) if !is_revert(e))) {
+                tracing::warn!("Token metadata lookup failed for {}", token);
+                continue;
+            }
+            let metadata = Metadata {
+                decimals: decimals.ok().as_ref().and_then(Value::as_str).and_then(parse_decimals),
+                symbol: symbol.ok().as_ref().and_then(Value::as_str).and_then(parse_symbol),
+            };
+            cache.insert((network, token.to_string()), metadata);
+        }
+    }
+}
+
+fn is_revert(error: &RpcError) -> bool {
+    matches!(error, RpcError::Node { .. })
+}
+
+/// (token, from, to, amount) of each ERC-20 Transfer log, addresses lowercase.
+/// ERC-721 Transfer has the same topic0 but a fourth (tokenId) topic.
+pub fn transfer_logs(tx_details: &Value) -> Vec<(String, String, String, U256)> {
+    let logs = tx_details["logs"].as_array().map(Vec::as_slice).unwrap_or_default();
+    logs.iter()
+        .filter_map(|log| {
+            let topics = log["topics"].as_array()?;
+            if topics.len() != 3
+                || topics[0].as_str().and_then(decode::event_name) != Some("Transfer")
+            {
+                return None;
+            }
+            let amount = log["data"]
+                .as_str()
+                .and_then(decode::decode_hex)
+                .filter(|d| d.len() >= 32)
+                .map(|d| U256::from_big_endian(&d[..32]))?;
+            Some((
+                log["address"].as_str()?.to_lowercase(),
+                topics[1].as_str().and_then(topic_address)?,
+                topics[2].as_str().and_then(topic_address)?,
+                amount,
+            ))
+        })
+        .collect()
+}
+
+// uint8, though some tokens return it as a full uint256 word
+fn parse_decimals(output: &str) -> Option<u8> {
+    let decimals = decode::decode_output("uint256", output)?.first()?.as_uint()?;
+    (decimals <= U256::from(u8::MAX)).then(|| decimals.as_u32() as u8)
+}
+
+// A string, or a bytes32 for early tokens such as MKR
+fn parse_symbol(output: &str) -> Option<String> {
+    let symbol = match decode::decode_output("string", output).as_deref() {
+        Some([AbiValue::String(s)]) => s.clone(),
+        _ => {
+            let bytes = decode::decode_hex(output).filter(|b| b.len() == 32)?;
+            String::from_utf8_lossy(&bytes).trim_end_matches('\0').to_string()
+        }
+    };
+    let symbol: String = symbol
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_SYMBOL_LEN)
+        .collect();
+    let symbol = symbol.trim();
+    (!symbol.is_empty()).then(|| symbol.to_string())
+}
+
+fn topic_address(topic: &str) -> Option<String> {
+    let hex = topic.strip_prefix("0x")?;
+    (hex.len() == 64).then(|| format!("0x{}", &hex[24..]).to_lowercase())
+}
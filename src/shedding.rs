@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+// Bound on the samples kept, however busy the window gets
+const MAX_SAMPLES: usize = 10_000;
+
+// The status is recomputed at most this often (it sorts the window)
+const STATUS_REFRESH: Duration = Duration::from_secs(1);
+
+/// When to shed: either threshold (unset = not checked) exceeded over the
+/// window, once it holds at least `min_samples` analyses.
+#[derive(Debug, Clone, Copy)]
+pub struct ShedThresholds {
+    pub p99_latency: Option<Duration>,
+    pub error_rate: Option<f64>,
+    pub window: Duration,
+    pub min_samples: usize,
+}
+
+/// Load over the recent window, as reported by `/health/ready`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShedStatus {
+    pub shedding: bool,
+    pub samples: usize,
+    pub p99_latency_ms: Option<u64>,
+    pub error_rate: Option<f64>,
+}
+
+struct Sample {
+    at: Instant,
+    latency: Duration,
+    failed: bool,
+}
+
+/// Adaptive load shedding: tracks the latency and outcome of recent analyses
+/// and, while either is over its threshold, tells handlers to turn away
+/// requests that aren't marked high priority. Shed requests aren't recorded,
+/// so the window recovers from the traffic still served (or, with none,
+/// from samples ageing out).
+pub struct LoadShedder {
+    thresholds: Option<ShedThresholds>,
+    samples: Mutex<VecDeque<Sample>>,
+    status: Mutex<Option<(Instant, ShedStatus)>>,
+}
+
+impl LoadShedder {
+    /// Never sheds when neither threshold is set.
+    pub fn new(thresholds: ShedThresholds) -> Self {
+        let enabled = thresholds.p99_latency.is_some() || thresholds.error_rate.is_some();
+        Self {
+            thresholds: enabled.then_some(thresholds),
+            samples: Mutex::new(VecDeque::new()),
+            status: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, latency: Duration, failed: bool) {
+        if self.thresholds.is_none() {
+            return;
+        }
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(Sample {
+            at: Instant::now(),
+            latency,
+            failed,
+        });
+    }
+
+    pub fn shedding(&self) -> bool {
+        self.status().shedding
+    }
+
+    pub fn status(&self) -> ShedStatus {
+        let Some(thresholds) = self.thresholds else {
+            return ShedStatus::default();
+        };
+        let mut cached = self.status.lock().unwrap();
+        if let Some((at, status)) = cached.as_ref()
+            && at.elapsed() < STATUS_REFRESH
+        {
+            return status.clone();
+        }
+        let status = self.compute(&thresholds);
+        *cached = Some((Instant::now(), status.clone()));
+        status
+    }
+
+    fn compute(&self, thresholds: &ShedThresholds) -> ShedStatus {
+        let mut samples = self.samples.lock().unwrap();
+        while samples
+            .front()
+            .is_some_and(|s| s.at.elapsed() > thresholds.window)
+        {
+            samples.pop_front();
+        }
+        if samples.is_empty() {
+            return ShedStatus::default();
+        }
+
+        let mut latencies: Vec<Duration> = samples.iter().map(|s| s.latency).collect();
+        latencies.sort();
+        let p99 = latencies[(latencies.len() * 99).div_ceil(100) - 1];
+        let error_rate =
+            samples.iter().filter(|s| s.failed).count() as f64 / samples.len() as f64;
+
+        let overloaded = thresholds.p99_latency.is_some_and(|max| p99 > max)
+            || thresholds.error_rate.is_some_and(|max| error_rate > max);
+        ShedStatus {
+            shedding: overloaded && samples.len() >= thresholds.min_samples,
+            samples: samples.len(),
+            p99_latency_ms: Some(p99.as_millis() as u64),
+            error_rate: Some(error_rate),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::alerts::HighRiskMonitor;
+use crate::config::{Config, NetworkConfig};
+use crate::emitter::{self, AnalysisEmitter, EmitQueue};
+use crate::history::HistoryQueue;
+use crate::jobs::JobQueue;
+use crate::limits::NetworkLimits;
+use crate::metrics::Metrics;
+use crate::network::Network;
+use crate::redact::Redactor;
+use crate::shedding::LoadShedder;
+use crate::services::contract_age::DeploymentCache;
+use crate::services::explorer::ExplorerClient;
+use crate::services::gas::GasPriceCache;
+use crate::services::labels::{AddressLabels, HttpLabelProvider};
+use crate::services::llm::{LlmClient, LlmProvider};
+use crate::services::risk;
+use crate::services::rpc::{ChainClient, RpcClient};
+use crate::services::tokens::TokenMetadataCache;
+use crate::store::Store;
+
+pub struct AppState {
+    pub config: Config,
+    // Shared by every outbound call (clones share the connection pool)
+    pub http: reqwest::Client,
+    // One per network, each against its own chain's explorer; see `explorer`
+    pub explorers: HashMap<Network, ExplorerClient>,
+    // Counterparty tags; set when LABELS_API_URL is configured
+    pub labels: Option<AddressLabels>,
+    pub rpc_clients: HashMap<Network, Box<dyn ChainClient>>,
+    // Separate archive endpoints (ARCHIVE_RPC_URL_<NETWORK>); see `archive_rpc`
+    pub archive_rpc_clients: HashMap<Network, Box<dyn ChainClient>>,
+    pub llm: Option<Box<dyn LlmProvider>>,
+    // Set when EMITTER_BACKEND is configured (and compiled in)
+    pub emitter: Option<EmitQueue>,
+    pub deployments: DeploymentCache,
+    pub tokens: TokenMetadataCache,
+    pub gas_prices: GasPriceCache,
+    pub limits: NetworkLimits,
+    pub shedder: LoadShedder,
+    pub metrics: Metrics,
+    pub high_risk: HighRiskMonitor,
+    pub store: Store,
+    // Analyses waiting to be written to `store`; see `history::start_writer`
+    pub history: HistoryQueue,
+    pub jobs: JobQueue,
+    // Set when ADDRESS_REDACTION is on; applied before results are stored or returned
+    pub redactor: Option<Redactor>,
+    // Fingerprint of the active risk rules, computed once at startup
+    pub rules_hash: String,
+}
+
+pub type SharedState = Arc<AppState>;
+
+/// The chain and model clients behind the analyses. Built from the config
+/// for the server; tests can inject mocks through `AppState::with_services`.
+pub struct Services {
+    // Networks without a client are served from mocked data
+    pub rpc_clients: HashMap<Network, Box<dyn ChainClient>>,
+    pub archive_rpc_clients: HashMap<Network, Box<dyn ChainClient>>,
+    pub llm: Option<Box<dyn LlmProvider>>,
+    pub emitter: Option<Box<dyn AnalysisEmitter>>,
+}
+
+impl Services {
+    pub fn from_config(config: &Config, http: &reqwest::Client) -> Self {
+        let clients = |urls: fn(&NetworkConfig) -> &Vec<String>| {
+            config
+                .networks
+                .iter()
+                .filter(|(_, net)| !urls(net).is_empty())
+                .map(|(network, net)| {
+                    let client: Box<dyn ChainClient> =
+                        Box::new(RpcClient::new(urls(net).clone(), http.clone()));
+                    (*network, client)
+                })
+                .collect()
+        };
+        let llm = config.llm_api_key.clone().map(|api_key| {
+            let client: Box<dyn LlmProvider> = Box::new(LlmClient::new(
+                http.clone(),
+                api_key,
+                config.llm_base_url.clone(),
+                config.llm_model.clone(),
+            ));
+            client
+        });
+        // A misconfigured emitter shouldn't keep the analyzer from serving
+        let emitter = emitter::from_config(config).unwrap_or_else(|e| {
+            tracing::error!("Analysis emitter disabled: {}", e);
+            None
+        });
+        Self {
+            rpc_clients: clients(|net| &net.rpc_urls),
+            archive_rpc_clients: clients(|net| &net.archive_rpc_urls),
+            llm,
+            emitter,
+        }
+    }
+}
+
+impl AppState {
+    pub fn new(config: Config, store: Store) -> SharedState {
+        let http = config.http_client();
+        let services = Services::from_config(&config, &http);
+        Self::with_services(config, store, http, services)
+    }
+
+    /// State around the given chain and model clients, with everything else
+    /// (limits, caches, the job queue) built from `config` as usual.
+    pub fn with_services(
+        config: Config,
+        store: Store,
+        http: reqwest::Client,
+        services: Services,
+    ) -> SharedState {
+        let Services {
+            rpc_clients,
+            archive_rpc_clients,
+            llm,
+            emitter,
+        } = services;
+        let emitter = emitter.map(|e| EmitQueue::new(e, config.emitter_queue_capacity));
+        let limits = NetworkLimits::new(
+            &config.networks,
+            Duration::from_millis(config.concurrency_wait_ms),
+        );
+        let shedder = LoadShedder::new(config.shed_thresholds());
+        let high_risk = HighRiskMonitor::new(
+            Duration::from_secs(config.high_risk_window_secs),
+            config.high_risk_alert_threshold,
+            config.alert_webhook_url.clone().map(|url| (http.clone(), url)),
+        );
+        let labels = config.labels_api_url.clone().map(|url| {
+            let provider = HttpLabelProvider::new(http.clone(), url, config.labels_api_key.clone());
+            AddressLabels::new(
+                Box::new(provider),
+                Duration::from_secs(config.labels_cache_ttl_secs),
+            )
+        });
+        let explorers = config
+            .networks
+            .iter()
+            .map(|(&network, settings)| {
+                let explorer = ExplorerClient::new(
+                    http.clone(),
+                    settings.explorer_api_url.clone(),
+                    settings.explorer_api_key.clone(),
+                );
+                (network, explorer)
+            })
+            .collect();
+
+        let history = HistoryQueue::new(
+            config.history_queue_capacity,
+            config.history_batch_size,
+            config.history_queue_full,
+        );
+        let jobs = JobQueue::new(config.job_queue_capacity);
+        // A config main would refuse (see `Config::errors`) redacts nothing
+        let redactor = Redactor::from_config(&config).ok().flatten();
+
+        Arc::new(Self {
+            config,
+            http,
+            explorers,
+            labels,
+            rpc_clients,
+            archive_rpc_clients,
+            llm,
+            emitter,
+            deployments: DeploymentCache::default(),
+            tokens: TokenMetadataCache::default(),
+            gas_prices: GasPriceCache::default(),
+            limits,
+            shedder,
+            metrics: Metrics::default(),
+            high_risk,
+            store,
+            history,
+            jobs,
+            redactor,
+            rules_hash: risk::rules_hash(),
+        })
+    }
+
+    /// The network's node client; `None` means mocked data.
+    pub fn rpc(&self, network: Network) -> Option<&dyn ChainClient> {
+        self.rpc_clients.get(&network).map(Box::as_ref)
+    }
+
+    /// Client for reads of state at any block: the dedicated archive endpoint,
+    /// else the main one if it's marked archival. `None` means only a full node
+    /// (or no node) is available.
+    pub fn archive_rpc(&self, network: Network) -> Option<&dyn ChainClient> {
+        self.archive_rpc_clients.get(&network).map(Box::as_ref).or_else(|| {
+            let archival = self
+                .config
+                .networks
+                .get(&network)
+                .is_some_and(|net| net.rpc_archival);
+            self.rpc(network).filter(|_| archival)
+        })
+    }
+
+    /// The network's explorer client. Every network has one, built from its
+    /// config.
+    pub fn explorer(&self, network: Network) -> &ExplorerClient {
+        &self.explorers[&network]
+    }
+}
@@ -0,0 +1,954 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use thiserror::Error;
+
+use crate::models::{
+    AnalysisRecord, AnalyzeTxRequest, AnalyzeTxResponse, FeedbackRecord, FeedbackRequest, JobKind,
+    JobProgress, JobRecord, JobStatus,
+};
+use crate::network::Network;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("store task failed: {0}")]
+    Task(String),
+}
+
+// Outbox row states
+const OUTBOX_PENDING: &str = "pending";
+const OUTBOX_DELIVERED: &str = "delivered";
+const OUTBOX_DEAD: &str = "dead";
+
+/// A queued webhook delivery; `payload_json` is the analysis as stored.
+#[derive(Debug)]
+pub struct Delivery {
+    pub id: i64,
+    pub payload_json: String,
+    pub attempts: u32,
+}
+
+/// A stored analysis embedding, with enough of the analysis to list it.
+#[derive(Debug)]
+pub struct StoredEmbedding {
+    pub analysis_id: i64,
+    pub tx_hash: String,
+    pub tx_type: String,
+    pub risk_score: f32,
+    pub vector: Vec<f32>,
+}
+
+/// A stored analysis of a tx that wasn't final yet, awaiting a re-check.
+#[derive(Debug)]
+pub struct ProvisionalAnalysis {
+    pub analysis_id: i64,
+    pub network: String,
+    pub tx_hash: String,
+    pub block_hash: String,
+    // Unknown for analyses tracked before the block number was stored
+    pub block_number: Option<u64>,
+}
+
+/// The latest stored analysis of a tx, as a history re-analysis sees it.
+#[derive(Debug)]
+pub struct HistoryTarget {
+    pub analysis_id: i64,
+    pub network: String,
+    pub tx_hash: String,
+    pub tx_type: String,
+    pub risk_score: f32,
+}
+
+/// The latest stored analysis of a tx, as `/stats/protocols` aggregates it.
+#[derive(Debug)]
+pub struct ProtocolSample {
+    pub network: String,
+    pub protocol: Option<String>,
+    // Native value in wei, as a decimal string
+    pub value_wei: Option<String>,
+    pub risk_score: f32,
+}
+
+/// SQLite-backed analysis history. rusqlite is blocking, so every query runs
+/// on the blocking pool behind a shared connection.
+#[derive(Clone)]
+pub struct Store {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Store {
+    pub fn open(path: &str) -> Result<Self, StoreError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS analyses (
+                 id               INTEGER PRIMARY KEY AUTOINCREMENT,
+                 tx_hash          TEXT NOT NULL,
+                 network          TEXT NOT NULL,
+                 analyzer_version TEXT NOT NULL,
+                 tx_type          TEXT NOT NULL,
+                 risk_score       REAL NOT NULL,
+                 response_json    TEXT NOT NULL,
+                 created_at       INTEGER NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_analyses_tx ON analyses (network, tx_hash);
+             CREATE INDEX IF NOT EXISTS idx_analyses_created ON analyses (created_at);
+             CREATE TABLE IF NOT EXISTS feedback (
+                 id            INTEGER PRIMARY KEY AUTOINCREMENT,
+                 analysis_id   INTEGER NOT NULL REFERENCES analyses (id),
+                 field         TEXT NOT NULL,
+                 correct_value TEXT NOT NULL,
+                 comment       TEXT,
+                 created_at    INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS jobs (
+                 id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                 tx_hash     TEXT NOT NULL,
+                 network     TEXT NOT NULL,
+                 status      TEXT NOT NULL,
+                 result_json TEXT,
+                 error       TEXT,
+                 created_at  INTEGER NOT NULL,
+                 updated_at  INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS outbox (
+                 id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                 analysis_id     INTEGER NOT NULL REFERENCES analyses (id),
+                 payload_json    TEXT NOT NULL,
+                 status          TEXT NOT NULL,
+                 attempts        INTEGER NOT NULL DEFAULT 0,
+                 next_attempt_at INTEGER NOT NULL,
+                 last_error      TEXT,
+                 created_at      INTEGER NOT NULL,
+                 delivered_at    INTEGER
+             );
+             CREATE INDEX IF NOT EXISTS idx_outbox_due ON outbox (status, next_attempt_at);
+             CREATE TABLE IF NOT EXISTS embeddings (
+                 analysis_id INTEGER PRIMARY KEY REFERENCES analyses (id),
+                 network     TEXT NOT NULL,
+                 tx_hash     TEXT NOT NULL,
+                 model       TEXT NOT NULL,
+                 vector      BLOB NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_embeddings_tx ON embeddings (network, model, tx_hash);
+             CREATE TABLE IF NOT EXISTS provisional (
+                 analysis_id  INTEGER PRIMARY KEY REFERENCES analyses (id),
+                 network      TEXT NOT NULL,
+                 tx_hash      TEXT NOT NULL,
+                 block_hash   TEXT NOT NULL,
+                 block_number INTEGER
+             );
+             CREATE TABLE IF NOT EXISTS reorged (
+                 analysis_id INTEGER PRIMARY KEY REFERENCES analyses (id),
+                 reorged_at  INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS job_progress (
+                 job_id    INTEGER PRIMARY KEY REFERENCES jobs (id),
+                 total     INTEGER NOT NULL,
+                 processed INTEGER NOT NULL DEFAULT 0,
+                 changed   INTEGER NOT NULL DEFAULT 0,
+                 failed    INTEGER NOT NULL DEFAULT 0
+             );",
+        )?;
+        add_column(&conn, "provisional", "block_number", "INTEGER")?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Stores the analyses in one transaction and returns their ids, in order.
+    /// Each one with `notify` set also gets a webhook delivery queued in the
+    /// outbox, in the same transaction, so it survives a crash once recorded.
+    pub async fn record_analyses(
+        &self,
+        analyses: &[(&AnalyzeTxResponse, bool)],
+    ) -> Result<Vec<i64>, StoreError> {
+        let rows = analyses
+            .iter()
+            .map(|(analysis, notify)| {
+                let response_json = serde_json::to_string(analysis)?;
+                Ok((
+                    analysis.tx_hash.clone(),
+                    analysis.network.to_string(),
+                    analysis.analyzer_version.clone(),
+                    analysis.tx_type.clone(),
+                    analysis.risk_score,
+                    response_json,
+                    *notify,
+                ))
+            })
+            .collect::<Result<Vec<_>, StoreError>>()?;
+
+        self.with_conn(move |conn| {
+            let now = unix_now();
+            let tx = conn.unchecked_transaction()?;
+            let mut ids = Vec::with_capacity(rows.len());
+            for (tx_hash, network, analyzer_version, tx_type, risk_score, response_json, notify) in
+                rows
+            {
+                tx.execute(
+                    "INSERT INTO analyses
+                         (tx_hash, network, analyzer_version, tx_type, risk_score, response_json, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![tx_hash, network, analyzer_version, tx_type, risk_score, response_json, now],
+                )?;
+                let id = tx.last_insert_rowid();
+                if notify {
+                    tx.execute(
+                        "INSERT INTO outbox (analysis_id, payload_json, status, next_attempt_at, created_at)
+                         VALUES (?1, ?2, ?3, ?4, ?4)",
+                        params![id, response_json, OUTBOX_PENDING, now],
+                    )?;
+                }
+                ids.push(id);
+            }
+            tx.commit()?;
+            Ok(ids)
+        })
+        .await
+    }
+
+    /// Every stored analysis of the tx, oldest first.
+    pub async fn list_analyses(
+        &self,
+        network: Network,
+        tx_hash: &str,
+    ) -> Result<Vec<AnalysisRecord>, StoreError> {
+        let network = network.to_string();
+        let tx_hash = tx_hash.to_string();
+
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT a.id, a.analyzer_version, a.response_json, a.created_at, r.reorged_at
+                 FROM analyses a LEFT JOIN reorged r ON r.analysis_id = a.id
+                 WHERE a.network = ?1 AND a.tx_hash = ?2
+                 ORDER BY a.id",
+            )?;
+            let rows = stmt
+                .query_map(params![network, tx_hash], |r| {
+                    Ok((r.get(0)?, r.get(1)?, r.get::<_, String>(2)?, r.get(3)?, r.get(4)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            rows.into_iter()
+                .map(|(id, analyzer_version, json, created_at, reorged_at)| {
+                    Ok(AnalysisRecord {
+                        id,
+                        analyzer_version,
+                        created_at,
+                        reorged_at,
+                        analysis: serde_json::from_str(&json)?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn record_embedding(
+        &self,
+        analysis_id: i64,
+        network: Network,
+        tx_hash: &str,
+        model: &str,
+        vector: &[f32],
+    ) -> Result<(), StoreError> {
+        let row = (network.to_string(), tx_hash.to_string(), model.to_string());
+        let blob: Vec<u8> = vector.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO embeddings (analysis_id, network, tx_hash, model, vector)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![analysis_id, row.0, row.1, row.2, blob],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The latest embedding (by `model`) of every analyzed tx on the network,
+    /// with the analysis it was computed from.
+    pub async fn latest_embeddings(
+        &self,
+        network: Network,
+        model: &str,
+    ) -> Result<Vec<StoredEmbedding>, StoreError> {
+        let network = network.to_string();
+        let model = model.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT e.analysis_id, e.tx_hash, a.tx_type, a.risk_score, e.vector
+                 FROM embeddings e JOIN analyses a ON a.id = e.analysis_id
+                 WHERE e.network = ?1 AND e.model = ?2
+                   AND e.analysis_id = (SELECT MAX(analysis_id) FROM embeddings
+                                        WHERE network = e.network AND model = e.model
+                                          AND tx_hash = e.tx_hash
+                                          AND analysis_id NOT IN
+                                              (SELECT analysis_id FROM reorged))",
+            )?;
+            let embeddings = stmt
+                .query_map(params![network, model], |r| {
+                    let blob: Vec<u8> = r.get(4)?;
+                    Ok(StoredEmbedding {
+                        analysis_id: r.get(0)?,
+                        tx_hash: r.get(1)?,
+                        tx_type: r.get(2)?,
+                        risk_score: r.get(3)?,
+                        vector: blob
+                            .chunks_exact(4)
+                            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                            .collect(),
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(embeddings)
+        })
+        .await
+    }
+
+    /// Queues a provisional analysis for re-checking against the chain.
+    pub async fn track_provisional(
+        &self,
+        analysis_id: i64,
+        network: Network,
+        tx_hash: &str,
+        block_hash: &str,
+        block_number: u64,
+    ) -> Result<(), StoreError> {
+        let row = (network.to_string(), tx_hash.to_string(), block_hash.to_string());
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO provisional
+                     (analysis_id, network, tx_hash, block_hash, block_number)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![analysis_id, row.0, row.1, row.2, block_number as i64],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn provisional_analyses(&self) -> Result<Vec<ProvisionalAnalysis>, StoreError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT analysis_id, network, tx_hash, block_hash, block_number FROM provisional
+                 ORDER BY analysis_id",
+            )?;
+            let pending = stmt
+                .query_map([], |r| {
+                    Ok(ProvisionalAnalysis {
+                        analysis_id: r.get(0)?,
+                        network: r.get(1)?,
+                        tx_hash: r.get(2)?,
+                        block_hash: r.get(3)?,
+                        block_number: r.get::<_, Option<i64>>(4)?.map(|n| n as u64),
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(pending)
+        })
+        .await
+    }
+
+    /// The tx is final in the block it was analyzed in: the stored analysis
+    /// stops being provisional.
+    pub async fn finalize_analysis(
+        &self,
+        analysis_id: i64,
+        confirmations: u64,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let json: Option<String> = tx
+                .query_row(
+                    "SELECT response_json FROM analyses WHERE id = ?1",
+                    params![analysis_id],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            if let Some(json) = json {
+                let mut analysis: AnalyzeTxResponse = serde_json::from_str(&json)?;
+                analysis.provisional = false;
+                analysis.confirmations = Some(confirmations);
+                tx.execute(
+                    "UPDATE analyses SET response_json = ?2 WHERE id = ?1",
+                    params![analysis_id, serde_json::to_string(&analysis)?],
+                )?;
+            }
+            tx.execute("DELETE FROM provisional WHERE analysis_id = ?1", params![analysis_id])?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The tx was reorged out of (or moved from) the block it was analyzed in.
+    /// The analysis is marked as reorged rather than deleted, so its feedback
+    /// is kept; it no longer counts as the tx's latest (history re-analysis,
+    /// similarity, stats, new feedback).
+    pub async fn invalidate_analysis(&self, analysis_id: i64) -> Result<(), StoreError> {
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM provisional WHERE analysis_id = ?1", params![analysis_id])?;
+            tx.execute(
+                "INSERT OR IGNORE INTO reorged (analysis_id, reorged_at) VALUES (?1, ?2)",
+                params![analysis_id, unix_now()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Up to `limit` txs whose latest analysis has an id in `(after, until]`,
+    /// by that id. Txs the client sent the data for (`/analyze_logs`) are
+    /// left out, since that data isn't stored.
+    pub async fn history_targets(
+        &self,
+        after: i64,
+        until: i64,
+        limit: usize,
+    ) -> Result<Vec<HistoryTarget>, StoreError> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, network, tx_hash, tx_type, risk_score FROM analyses
+                 WHERE id IN (SELECT MAX(id) FROM analyses
+                              WHERE id NOT IN (SELECT analysis_id FROM reorged)
+                              GROUP BY network, tx_hash)
+                   AND id > ?1 AND id <= ?2
+                   AND json_extract(response_json, '$.source') IS NOT 'client_provided'
+                 ORDER BY id LIMIT ?3",
+            )?;
+            let rows = stmt
+                .query_map(params![after, until, limit as i64], |r| {
+                    Ok(HistoryTarget {
+                        analysis_id: r.get(0)?,
+                        network: r.get(1)?,
+                        tx_hash: r.get(2)?,
+                        tx_type: r.get(3)?,
+                        risk_score: r.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })
+        .await
+    }
+
+    /// The latest analysis of each tx analyzed since `since` (unix seconds),
+    /// optionally on one network. A tx re-analyzed in the window counts once,
+    /// as of its latest analysis.
+    pub async fn protocol_samples(
+        &self,
+        network: Option<Network>,
+        since: i64,
+    ) -> Result<Vec<ProtocolSample>, StoreError> {
+        let network = network.map(|n| n.as_str().to_string());
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT network, json_extract(response_json, '$.protocol'),
+                        json_extract(response_json, '$.value.wei'), risk_score
+                 FROM analyses
+                 WHERE id IN (SELECT MAX(id) FROM analyses
+                              WHERE created_at >= ?1
+                                AND id NOT IN (SELECT analysis_id FROM reorged)
+                              GROUP BY network, tx_hash)
+                   AND (?2 IS NULL OR network = ?2)",
+            )?;
+            let rows = stmt
+                .query_map(params![since, network], |r| {
+                    Ok(ProtocolSample {
+                        network: r.get(0)?,
+                        protocol: r.get(1)?,
+                        value_wei: r.get(2)?,
+                        risk_score: r.get(3)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })
+        .await
+    }
+
+    /// The highest analysis id and how many txs `history_targets` covers up
+    /// to it.
+    pub async fn history_extent(&self) -> Result<(i64, u64), StoreError> {
+        self.with_conn(|conn| {
+            let until: i64 =
+                conn.query_row("SELECT COALESCE(MAX(id), 0) FROM analyses", [], |r| r.get(0))?;
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM analyses
+                 WHERE id IN (SELECT MAX(id) FROM analyses
+                              WHERE id NOT IN (SELECT analysis_id FROM reorged)
+                              GROUP BY network, tx_hash)
+                   AND json_extract(response_json, '$.source') IS NOT 'client_provided'",
+                [],
+                |r| r.get(0),
+            )?;
+            Ok((until, count as u64))
+        })
+        .await
+    }
+
+    /// Overwrites a stored analysis with a re-analysis of the same tx, keeping
+    /// its id (and so its feedback and embedding).
+    pub async fn replace_analysis(
+        &self,
+        analysis_id: i64,
+        analysis: &AnalyzeTxResponse,
+    ) -> Result<(), StoreError> {
+        let response_json = serde_json::to_string(analysis)?;
+        let row = (
+            analysis.analyzer_version.clone(),
+            analysis.tx_type.clone(),
+            analysis.risk_score,
+        );
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE analyses
+                 SET analyzer_version = ?2, tx_type = ?3, risk_score = ?4, response_json = ?5
+                 WHERE id = ?1",
+                params![analysis_id, row.0, row.1, row.2, response_json],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stores a correction against the most recent analysis of the tx.
+    /// Returns `(feedback_id, analysis_id)`, or `None` when the tx was never analyzed.
+    pub async fn record_feedback(
+        &self,
+        feedback: &FeedbackRequest,
+    ) -> Result<Option<(i64, i64)>, StoreError> {
+        let tx_hash = feedback.tx_hash.clone();
+        let network = feedback.network.to_string();
+        let row = (
+            feedback.field.clone(),
+            feedback.correct_value.clone(),
+            feedback.comment.clone(),
+        );
+
+        self.with_conn(move |conn| {
+            let analysis_id: Option<i64> = conn
+                .query_row(
+                    "SELECT id FROM analyses WHERE network = ?1 AND tx_hash = ?2
+                       AND id NOT IN (SELECT analysis_id FROM reorged)
+                     ORDER BY id DESC LIMIT 1",
+                    params![network, tx_hash],
+                    |r| r.get(0),
+                )
+                .optional()?;
+            let Some(analysis_id) = analysis_id else {
+                return Ok(None);
+            };
+
+            conn.execute(
+                "INSERT INTO feedback (analysis_id, field, correct_value, comment, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![analysis_id, row.0, row.1, row.2, unix_now()],
+            )?;
+            Ok(Some((conn.last_insert_rowid(), analysis_id)))
+        })
+        .await
+    }
+
+    /// All collected feedback, oldest first, joined with the analysis it refers to.
+    pub async fn list_feedback(&self) -> Result<Vec<FeedbackRecord>, StoreError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT f.id, f.analysis_id, a.tx_hash, a.network, a.analyzer_version,
+                        f.field, f.correct_value, f.comment, f.created_at
+                 FROM feedback f JOIN analyses a ON a.id = f.analysis_id
+                 ORDER BY f.id",
+            )?;
+            let records = stmt
+                .query_map([], |r| {
+                    Ok(FeedbackRecord {
+                        id: r.get(0)?,
+                        analysis_id: r.get(1)?,
+                        tx_hash: r.get(2)?,
+                        network: r.get(3)?,
+                        analyzer_version: r.get(4)?,
+                        field: r.get(5)?,
+                        correct_value: r.get(6)?,
+                        comment: r.get(7)?,
+                        created_at: r.get(8)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(records)
+        })
+        .await
+    }
+
+    /// Records a new job in the `queued` state and returns its id.
+    pub async fn create_job(&self, request: &AnalyzeTxRequest) -> Result<i64, StoreError> {
+        let tx_hash = request.tx_hash.clone();
+        let network = request.network().to_string();
+
+        self.with_conn(move |conn| {
+            let now = unix_now();
+            conn.execute(
+                "INSERT INTO jobs (tx_hash, network, status, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)",
+                params![tx_hash, network, JobStatus::Queued.as_str(), now],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+    }
+
+    /// Records a history re-analysis job over `total` txs, already `running`,
+    /// and returns its id.
+    pub async fn create_reanalysis_job(&self, total: u64) -> Result<i64, StoreError> {
+        self.with_conn(move |conn| {
+            let now = unix_now();
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT INTO jobs (tx_hash, network, status, created_at, updated_at)
+                 VALUES ('', '', ?1, ?2, ?2)",
+                params![JobStatus::Running.as_str(), now],
+            )?;
+            let id = tx.last_insert_rowid();
+            tx.execute(
+                "INSERT INTO job_progress (job_id, total) VALUES (?1, ?2)",
+                params![id, total as i64],
+            )?;
+            tx.commit()?;
+            Ok(id)
+        })
+        .await
+    }
+
+    pub async fn update_job_progress(
+        &self,
+        id: i64,
+        progress: JobProgress,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "UPDATE job_progress SET processed = ?2, changed = ?3, failed = ?4
+                 WHERE job_id = ?1",
+                params![
+                    id,
+                    progress.processed as i64,
+                    progress.changed as i64,
+                    progress.failed as i64
+                ],
+            )?;
+            tx.execute(
+                "UPDATE jobs SET updated_at = ?2 WHERE id = ?1",
+                params![id, unix_now()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn update_job(
+        &self,
+        id: i64,
+        status: JobStatus,
+        result: Option<&AnalyzeTxResponse>,
+        error: Option<String>,
+    ) -> Result<(), StoreError> {
+        let result_json = result.map(serde_json::to_string).transpose()?;
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE jobs SET status = ?2, result_json = ?3, error = ?4, updated_at = ?5
+                 WHERE id = ?1",
+                params![id, status.as_str(), result_json, error, unix_now()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_job(&self, id: i64) -> Result<Option<JobRecord>, StoreError> {
+        self.with_conn(move |conn| {
+            let row = conn
+                .query_row(
+                    "SELECT tx_hash, network, status, result_json, error, created_at, updated_at
+                     FROM jobs WHERE id = ?1",
+                    params![id],
+                    |r| {
+                        Ok((
+                            r.get::<_, String>(0)?,
+                            r.get::<_, String>(1)?,
+                            r.get::<_, String>(2)?,
+                            r.get::<_, Option<String>>(3)?,
+                            r.get(4)?,
+                            r.get(5)?,
+                            r.get(6)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            let Some((tx_hash, network, status, result_json, error, created_at, updated_at)) = row
+            else {
+                return Ok(None);
+            };
+            // Only history re-analyses track progress
+            let progress = conn
+                .query_row(
+                    "SELECT total, processed, changed, failed FROM job_progress WHERE job_id = ?1",
+                    params![id],
+                    |r| {
+                        Ok(JobProgress {
+                            total: r.get::<_, i64>(0)? as u64,
+                            processed: r.get::<_, i64>(1)? as u64,
+                            changed: r.get::<_, i64>(2)? as u64,
+                            failed: r.get::<_, i64>(3)? as u64,
+                        })
+                    },
+                )
+                .optional()?;
+
+            Ok(Some(JobRecord {
+                job_id: id,
+                kind: if progress.is_some() {
+                    JobKind::ReanalyzeHistory
+                } else {
+                    JobKind::Analysis
+                },
+                status: JobStatus::parse(&status).unwrap_or(JobStatus::Failed),
+                network,
+                tx_hash,
+                created_at,
+                updated_at,
+                result: result_json.as_deref().map(serde_json::from_str).transpose()?,
+                error,
+                progress,
+            }))
+        })
+        .await
+    }
+
+    /// The queue lives in memory, so jobs still pending when the process
+    /// stopped will never run; marks them failed. Returns how many there were.
+    pub async fn fail_interrupted_jobs(&self) -> Result<usize, StoreError> {
+        self.with_conn(|conn| {
+            let n = conn.execute(
+                "UPDATE jobs SET status = ?1, error = 'Interrupted by a restart', updated_at = ?2
+                 WHERE status IN (?3, ?4)",
+                params![
+                    JobStatus::Failed.as_str(),
+                    unix_now(),
+                    JobStatus::Queued.as_str(),
+                    JobStatus::Running.as_str()
+                ],
+            )?;
+            Ok(n)
+        })
+        .await
+    }
+
+    /// Pending outbox rows whose next attempt is due, oldest first.
+    pub async fn due_deliveries(&self, limit: usize) -> Result<Vec<Delivery>, StoreError> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, payload_json, attempts FROM outbox
+                 WHERE status = ?1 AND next_attempt_at <= ?2
+                 ORDER BY id LIMIT ?3",
+            )?;
+            let deliveries = stmt
+                .query_map(params![OUTBOX_PENDING, unix_now(), limit as i64], |r| {
+                    Ok(Delivery {
+                        id: r.get(0)?,
+                        payload_json: r.get(1)?,
+                        attempts: r.get(2)?,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(deliveries)
+        })
+        .await
+    }
+
+    pub async fn mark_delivered(&self, id: i64) -> Result<(), StoreError> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE outbox SET status = ?2, attempts = attempts + 1, delivered_at = ?3
+                 WHERE id = ?1",
+                params![id, OUTBOX_DELIVERED, unix_now()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records a failed attempt: retried at `retry_at`, or dead-lettered when `None`.
+    pub async fn mark_failed(
+        &self,
+        id: i64,
+        error: String,
+        retry_at: Option<i64>,
+    ) -> Result<(), StoreError> {
+        self.with_conn(move |conn| {
+            let status = if retry_at.is_some() { OUTBOX_PENDING } else { OUTBOX_DEAD };
+            conn.execute(
+                "UPDATE outbox SET status = ?2, attempts = attempts + 1, next_attempt_at = ?3,
+                        last_error = ?4
+                 WHERE id = ?1",
+                params![id, status, retry_at.unwrap_or_else(unix_now), error],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// `(pending, dead_lettered)` outbox row counts.
+    pub async fn outbox_counts(&self) -> Result<(u64, u64), StoreError> {
+        self.with_conn(|conn| {
+            let count = |status: &str| {
+                conn.query_row(
+                    "SELECT COUNT(*) FROM outbox WHERE status = ?1",
+                    params![status],
+                    |r| r.get::<_, i64>(0),
+                )
+            };
+            Ok((count(OUTBOX_PENDING)? as u64, count(OUTBOX_DEAD)? as u64))
+        })
+        .await
+    }
+
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, StoreError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap()))
+            .await
+            .map_err(|e| StoreError::Task(e.to_string()))?
+    }
+}
+
+// For columns added after their table shipped: CREATE TABLE IF NOT EXISTS
+// leaves an existing table as it was
+fn add_column(conn: &Connection, table: &str, column: &str, decl: &str) -> rusqlite::Result<()> {
+    let exists = conn
+        .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))?
+        .exists(params![column])?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))?;
+    }
+    Ok(())
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    // An analysis of `tx_hash` on `network`, attributed to `protocol`
+    async fn analysis(
+        tx_hash: &str,
+        network: Network,
+        protocol: Option<&str>,
+        risk_score: f32,
+    ) -> AnalyzeTxResponse {
+        let mut analysis = testing::analysis(tx_hash).await;
+        analysis.network = network;
+        analysis.protocol = protocol.map(str::to_string);
+        analysis.risk_score = risk_score;
+        analysis
+    }
+
+    #[tokio::test]
+    async fn opens_a_history_from_before_block_numbers_were_tracked() {
+        let path = std::env::temp_dir().join(format!("store-test-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        Connection::open(path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE provisional (
+                     analysis_id INTEGER PRIMARY KEY,
+                     network     TEXT NOT NULL,
+                     tx_hash     TEXT NOT NULL,
+                     block_hash  TEXT NOT NULL
+                 );
+                 INSERT INTO provisional VALUES (1, 'ethereum-mainnet', '0x01', '0x02');",
+            )
+            .unwrap();
+
+        let store = Store::open(path).unwrap();
+        store.track_provisional(2, Network::EthereumMainnet, "0x03", "0x04", 7).await.unwrap();
+        let pending = store.provisional_analyses().await.unwrap();
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+
+        let numbers: Vec<Option<u64>> = pending.iter().map(|p| p.block_number).collect();
+        assert_eq!(numbers, [None, Some(7)]);
+    }
+
+    #[tokio::test]
+    async fn protocol_samples_cover_the_latest_analysis_of_each_tx_in_the_window() {
+        let store = Store::open(":memory:").unwrap();
+        let eth = Network::EthereumMainnet;
+        let analyses = [
+            analysis("0x01", eth, Some("Uniswap V2"), 0.2).await,
+            analysis("0x01", eth, Some("Uniswap V2"), 0.8).await,
+            analysis("0x02", eth, None, 0.1).await,
+            analysis("0x03", Network::PolygonMainnet, Some("Aave"), 0.5).await,
+            analysis("0x04", eth, Some("Curve"), 0.3).await,
+            analysis("0x05", eth, Some("Balancer"), 0.3).await,
+        ];
+        let records: Vec<_> = analyses.iter().map(|a| (a, false)).collect();
+        let ids = store.record_analyses(&records).await.unwrap();
+        // 0x04 was analyzed two days ago; 0x05 was reorged out
+        let old = unix_now() - 2 * 24 * 3600;
+        let id = ids[4];
+        store
+            .with_conn(move |conn| {
+                conn.execute(
+                    "UPDATE analyses SET created_at = ?1 WHERE id = ?2",
+                    params![old, id],
+                )?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        store.invalidate_analysis(ids[5]).await.unwrap();
+
+        let since = unix_now() - 24 * 3600;
+        let mut samples = store.protocol_samples(None, since).await.unwrap();
+        samples.sort_by(|a, b| a.protocol.cmp(&b.protocol));
+        let seen: Vec<_> = samples
+            .iter()
+            .map(|s| (s.network.as_str(), s.protocol.as_deref(), s.risk_score))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                ("ethereum-mainnet", None, 0.1),
+                ("polygon-mainnet", Some("Aave"), 0.5),
+                ("ethereum-mainnet", Some("Uniswap V2"), 0.8),
+            ]
+        );
+        assert_eq!(samples[2].value_wei.as_deref(), Some("1000000000000000000"));
+
+        let polygon = store.protocol_samples(Some(Network::PolygonMainnet), since).await.unwrap();
+        assert_eq!(polygon.len(), 1);
+        assert_eq!(polygon[0].protocol.as_deref(), Some("Aave"));
+        // A wider window takes in the older analysis
+        let week = store.protocol_samples(None, since - 6 * 24 * 3600).await.unwrap();
+        assert_eq!(week.len(), 4);
+    }
+}
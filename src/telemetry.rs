@@ -0,0 +1,58 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::redact::{RedactingStdout, Redactor};
+
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Sets up fmt logging, plus OTLP span export when an endpoint is configured.
+/// With a `redactor`, addresses are redacted in log output.
+/// The returned provider must be shut down on exit to flush pending spans.
+pub fn init(otlp_endpoint: Option<&str>, redactor: Option<Redactor>) -> Option<SdkTracerProvider> {
+    let provider = otlp_endpoint.and_then(|endpoint| match build_provider(endpoint) {
+        Ok(provider) => Some(provider),
+        Err(e) => {
+            eprintln!("Failed to set up OTLP exporter for {}: {}", endpoint, e);
+            None
+        }
+    });
+
+    let otel_layer = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(SERVICE_NAME)));
+
+    let writer = match redactor {
+        Some(redactor) => BoxMakeWriter::new(RedactingStdout(redactor)),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
+        .with(otel_layer)
+        .init();
+
+    if let Some(endpoint) = otlp_endpoint.filter(|_| provider.is_some()) {
+        tracing::info!("Exporting traces via OTLP to {}", endpoint);
+    }
+
+    provider
+}
+
+fn build_provider(endpoint: &str) -> Result<SdkTracerProvider, opentelemetry_otlp::ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build())
+}
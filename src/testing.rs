@@ -0,0 +1,328 @@
+// Mocks and helpers for in-process tests: a scripted chain and model behind
+// the real state and router
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::Query;
+use axum::http::{Request, StatusCode};
+use futures::future::BoxFuture;
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+use crate::config::Config;
+use crate::history;
+use crate::models::AnalyzeTxResponse;
+use crate::network::Network;
+use crate::routes;
+use crate::services::ai::AiError;
+use crate::services::explorer::ExplorerClient;
+use crate::services::llm::LlmProvider;
+use crate::services::rpc::{ChainClient, RpcError};
+use crate::state::{AppState, Services, SharedState};
+use crate::store::Store;
+
+pub const TRANSFER_HASH: &str =
+    "0x1111111111111111111111111111111111111111111111111111111111111111";
+pub const SENDER: &str = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+pub const RECIPIENT: &str = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+/// Answers calls by method, or by method and params when those were given.
+/// Anything else gets `null`, as a node answers for an unknown tx; with
+/// `failing`, every call fails instead. With `slow`, each answer takes that
+/// long.
+#[derive(Default)]
+pub struct MockChain {
+    by_method: HashMap<String, Value>,
+    by_params: HashMap<(String, String), Value>,
+    failure: Option<RpcError>,
+    delay: Option<Duration>,
+}
+
+impl MockChain {
+    pub fn with(mut self, method: &str, result: Value) -> Self {
+        self.by_method.insert(method.to_string(), result);
+        self
+    }
+
+    pub fn with_params(mut self, method: &str, params: Value, result: Value) -> Self {
+        self.by_params.insert((method.to_string(), params.to_string()), result);
+        self
+    }
+
+    pub fn slow(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn failing(error: RpcError) -> Self {
+        Self {
+            failure: Some(error),
+            ..Self::default()
+        }
+    }
+
+    /// A mined 1 ETH transfer from `SENDER` to `RECIPIENT` under `TRANSFER_HASH`.
+    pub fn transfer() -> Self {
+        Self::default()
+            .with_params(
+                "eth_getTransactionByHash",
+                json!([TRANSFER_HASH]),
+                json!({
+                    "hash": TRANSFER_HASH,
+                    "from": SENDER,
+                    "to": RECIPIENT,
+                    "value": "0xde0b6b3a7640000",
+                    "input": "0x",
+                    "gas": "0x5208",
+                    "gasPrice": "0x3b9aca00",
+                    "blockNumber": "0x1312d00"
+                }),
+            )
+            .with_params(
+                "eth_getTransactionReceipt",
+                json!([TRANSFER_HASH]),
+                json!({
+                    "status": "0x1",
+                    "gasUsed": "0x5208",
+                    "blockHash": format!("0x{}", "f".repeat(64)),
+                    "logs": []
+                }),
+            )
+            .with("eth_blockNumber", json!("0x1312d40"))
+    }
+
+    async fn answer(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        if let Some(error) = &self.failure {
+            return Err(error.clone());
+        }
+        Ok(self
+            .by_params
+            .get(&(method.to_string(), params.to_string()))
+            .or_else(|| self.by_method.get(method))
+            .cloned()
+            .unwrap_or(Value::Null))
+    }
+}
+
+impl ChainClient for MockChain {
+    fn call<'a>(&'a self, method: &'a str, params: Value) -> BoxFuture<'a, Result<Value, RpcError>> {
+        Box::pin(async move { self.answer(method, &params).await })
+    }
+
+    fn batch<'a>(
+        &'a self,
+        calls: &'a [(&'a str, Value)],
+    ) -> BoxFuture<'a, Vec<Result<Value, RpcError>>> {
+        Box::pin(futures::future::join_all(
+            calls.iter().map(|(method, params)| self.answer(method, params)),
+        ))
+    }
+}
+
+/// Answers every completion with the same verdict, or with each of several in
+/// turn; with `quota`, only that many, and the rest fail as the provider's
+/// quota being used up. The prompts
+/// it got and its embedding calls are shared with clones of the handles, so a
+/// test can keep them after handing the mock to the state.
+pub struct MockLlm {
+    verdicts: Result<Vec<Value>, String>,
+    answered: AtomicUsize,
+    quota: Option<AtomicUsize>,
+    pub prompts: Arc<Mutex<Vec<String>>>,
+    pub embeddings: Arc<AtomicUsize>,
+}
+
+impl MockLlm {
+    pub fn answering(verdict: Value) -> Self {
+        Self::answering_in_turn(vec![verdict])
+    }
+
+    /// Completions get `verdicts` in order, starting over after the last.
+    pub fn answering_in_turn(verdicts: Vec<Value>) -> Self {
+        Self {
+            verdicts: Ok(verdicts),
+            answered: AtomicUsize::new(0),
+            quota: None,
+            prompts: Arc::default(),
+            embeddings: Arc::default(),
+        }
+    }
+
+    /// A low-risk TRANSFER verdict.
+    pub fn transfer() -> Self {
+        Self::answering(json!({
+            "tx_type": "TRANSFER",
+            "protocol": null,
+            "risk_score": 0.1,
+            "risk_reasons": [],
+            "explanation": "A plain ETH transfer."
+        }))
+    }
+
+    pub fn failing(message: &str) -> Self {
+        Self {
+            verdicts: Err(message.to_string()),
+            ..Self::answering(Value::Null)
+        }
+    }
+
+    pub fn quota(mut self, completions: usize) -> Self {
+        self.quota = Some(AtomicUsize::new(completions));
+        self
+    }
+}
+
+impl LlmProvider for MockLlm {
+    fn model(&self) -> &str {
+        "mock-model"
+    }
+
+    fn complete<'a>(
+        &'a self,
+        _model: &'a str,
+        prompt: &'a str,
+        _temperature: Option<f32>,
+        _max_tokens: u32,
+    ) -> BoxFuture<'a, Result<String, AiError>> {
+        Box::pin(async move {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            let used_up = |quota: &AtomicUsize| {
+                quota
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_err()
+            };
+            if self.quota.as_ref().is_some_and(used_up) {
+                return Err(AiError::QuotaExhausted("mock quota used up".to_string()));
+            }
+            let turn = self.answered.fetch_add(1, Ordering::SeqCst);
+            self.verdicts
+                .as_ref()
+                .map(|verdicts| verdicts[turn % verdicts.len()].to_string())
+                .map_err(|e| AiError::LlmCallFailed(e.clone()))
+        })
+    }
+
+    fn embed<'a>(
+        &'a self,
+        _model: &'a str,
+        _input: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<f32>, AiError>> {
+        Box::pin(async move {
+            self.embeddings.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![1.0, 0.0, 0.0])
+        })
+    }
+}
+
+/// Serves `app` on a local port for the rest of the test; its base URL.
+pub async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    url
+}
+
+/// An explorer client against a local server that answers each API call
+/// (its query params) with `answer`'s body.
+pub async fn explorer(
+    answer: impl Fn(&HashMap<String, String>) -> Value + Clone + Send + Sync + 'static,
+) -> ExplorerClient {
+    let app = axum::Router::new().route(
+        "/api",
+        axum::routing::get(move |query: Query<HashMap<String, String>>| async move {
+            axum::Json(answer(&query.0))
+        }),
+    );
+    let url = format!("{}/api", serve(app).await);
+    ExplorerClient::new(reqwest::Client::new(), url, None)
+}
+
+/// The env's config with every outbound integration off, so a test only
+/// talks to the mocks it injects.
+pub fn config() -> Config {
+    let mut config = Config::from_env();
+    config.default_network = Some(Network::EthereumMainnet);
+    for network in config.networks.values_mut() {
+        // Connection refused right away rather than a real explorer
+        network.explorer_api_url = "http://127.0.0.1:9".to_string();
+        network.explorer_api_key = None;
+    }
+    config.labels_api_url = None;
+    config.llm_api_key = None;
+    config.llm_allowed_models = Vec::new();
+    config.llm_sample_rate = 1.0;
+    config.embeddings_enabled = false;
+    config.webhook_url = None;
+    config.alert_webhook_url = None;
+    config.admin_api_key = None;
+    config.response_deadline_ms = None;
+    config
+}
+
+/// State over `chain` (for the default network) and `llm`, with an in-memory
+/// store and the history writer running.
+pub fn state_with(config: Config, chain: MockChain, llm: Option<MockLlm>) -> SharedState {
+    let state = state_without_writer(config, chain, llm);
+    history::start_writer(&state);
+    state
+}
+
+/// `state_with`, but nothing drains the history queue until the test starts
+/// the writer.
+pub fn state_without_writer(config: Config, chain: MockChain, llm: Option<MockLlm>) -> SharedState {
+    let network = config.default_network.unwrap_or(Network::EthereumMainnet);
+    let mut rpc_clients: HashMap<Network, Box<dyn ChainClient>> = HashMap::new();
+    rpc_clients.insert(network, Box::new(chain));
+    let services = Services {
+        rpc_clients,
+        archive_rpc_clients: HashMap::new(),
+        llm: llm.map(|llm| Box::new(llm) as Box<dyn LlmProvider>),
+        emitter: None,
+    };
+    let store = Store::open(":memory:").expect("failed to open history store");
+    AppState::with_services(config, store, reqwest::Client::new(), services)
+}
+
+pub fn state(chain: MockChain, llm: Option<MockLlm>) -> SharedState {
+    state_with(config(), chain, llm)
+}
+
+/// A heuristic analysis of `MockChain::transfer`, relabeled as `tx_hash`.
+pub async fn analysis(tx_hash: &str) -> AnalyzeTxResponse {
+    let state = state(MockChain::transfer(), None);
+    let request = post_json("/analyze_tx", json!({ "tx_hash": TRANSFER_HASH }));
+    let (status, body) = send(&state, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let mut analysis: AnalyzeTxResponse = serde_json::from_value(body).unwrap();
+    analysis.tx_hash = tx_hash.to_string();
+    analysis
+}
+
+/// Sends `request` through the router over `state`; the body as JSON, or
+/// as a JSON string when it isn't JSON.
+pub async fn send(state: &SharedState, request: Request<Body>) -> (StatusCode, Value) {
+    let response = routes::router(state.clone()).oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+    (status, body)
+}
+
+pub fn post_json(uri: &str, body: Value) -> Request<Body> {
+    Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+pub fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
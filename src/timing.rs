@@ -0,0 +1,71 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::{StageTiming, Timing};
+
+/// Wall-clock time of each stage of one analysis, for `include_timing` and
+/// the slow-request log (SLOW_REQUEST_MS).
+pub struct Timings {
+    started: Instant,
+    stages: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl Timings {
+    /// Starts the clock for the total.
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            stages: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, stage: &'static str, elapsed: Duration) {
+        self.stages.lock().unwrap().push((stage, elapsed));
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The stages so far, in the order they finished.
+    pub fn summary(&self) -> Timing {
+        Timing {
+            total_ms: self.elapsed().as_millis() as u64,
+            stages: self
+                .stages
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(stage, elapsed)| StageTiming {
+                    stage: stage.to_string(),
+                    ms: elapsed.as_millis() as u64,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Runs `work`, recording its time under `stage` when there are `timings`.
+pub async fn timed<T>(
+    timings: Option<&Timings>,
+    stage: &'static str,
+    work: impl Future<Output = T>,
+) -> T {
+    let started = Instant::now();
+    let result = work.await;
+    if let Some(timings) = timings {
+        timings.record(stage, started.elapsed());
+    }
+    result
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}ms total", self.total_ms)?;
+        for stage in &self.stages {
+            write!(f, ", {} {}ms", stage.stage, stage.ms)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use tiny_keccak::{Hasher, Keccak};
+
+/// FNV-1a 64-bit. Used where a hash must be stable across builds and
+/// processes (std's `DefaultHasher` makes no such guarantee).
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf29ce484222325u64, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut out = [0u8; 32];
+    hasher.update(bytes);
+    hasher.finalize(&mut out);
+    out
+}
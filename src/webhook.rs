@@ -0,0 +1,102 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::state::SharedState;
+use crate::store::{Delivery, unix_now};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const BATCH_SIZE: usize = 50;
+// Retry backoff doubles from the base up to the cap
+const BACKOFF_BASE_SECS: i64 = 5;
+const BACKOFF_MAX_SECS: i64 = 3600;
+
+/// Spawns the outbox dispatcher when WEBHOOK_URL is set. Rows are only marked
+/// delivered after a 2xx, so a crash mid-delivery means a retry, not a loss;
+/// receivers should dedupe on the `X-Delivery-Id` header.
+pub fn start_dispatcher(state: &SharedState) {
+    let Some(url) = state.config.webhook_url.clone() else {
+        return;
+    };
+    let state = state.clone();
+    let http = state.http.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match state.store.due_deliveries(BATCH_SIZE).await {
+                Ok(due) => {
+                    for delivery in due {
+                        dispatch(&state, &http, &url, delivery).await;
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to read webhook outbox: {}", e),
+            }
+            match state.store.outbox_counts().await {
+                Ok((pending, dead)) => {
+                    state.metrics.webhook_outbox_depth.store(pending, Ordering::Relaxed);
+                    state.metrics.webhook_dead_letters.store(dead, Ordering::Relaxed);
+                }
+                Err(e) => tracing::warn!("Failed to count webhook outbox: {}", e),
+            }
+        }
+    });
+}
+
+async fn dispatch(state: &SharedState, http: &reqwest::Client, url: &str, delivery: Delivery) {
+    let outcome = match send(http, url, &delivery).await {
+        Ok(()) => state.store.mark_delivered(delivery.id).await,
+        Err(error) => {
+            let attempts = delivery.attempts + 1;
+            let retry_at = (attempts < state.config.webhook_max_attempts).then(|| {
+                let backoff = BACKOFF_BASE_SECS
+                    .saturating_mul(1 << delivery.attempts.min(20))
+                    .min(BACKOFF_MAX_SECS);
+                unix_now() + backoff
+            });
+            match retry_at {
+                Some(_) => tracing::warn!(
+                    "Webhook delivery {} failed (attempt {}): {}",
+                    delivery.id,
+                    attempts,
+                    error
+                ),
+                None => tracing::error!(
+                    "Webhook delivery {} dead-lettered after {} attempts: {}",
+                    delivery.id,
+                    attempts,
+                    error
+                ),
+            }
+            state.store.mark_failed(delivery.id, error, retry_at).await
+        }
+    };
+    if let Err(e) = outcome {
+        tracing::warn!("Failed to update webhook delivery {}: {}", delivery.id, e);
+    }
+}
+
+async fn send(http: &reqwest::Client, url: &str, delivery: &Delivery) -> Result<(), String> {
+    let analysis: Value =
+        serde_json::from_str(&delivery.payload_json).map_err(|e| e.to_string())?;
+    let body = json!({
+        "delivery_id": delivery.id,
+        "event": "high_risk_analysis",
+        "analysis": analysis,
+    });
+    let response = http
+        .post(url)
+        .header("X-Delivery-Id", delivery.id.to_string())
+        .timeout(DELIVERY_TIMEOUT)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("receiver returned {}", response.status()))
+    }
+}